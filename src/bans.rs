@@ -0,0 +1,253 @@
+//! Analysis of the peers banned by the peerset, grouped by reason and banning node.
+//!
+//! Both peerset backends are recognized:
+//!
+//! - litep2p: `peer banned, disconnecting, reason: <reason>`
+//! - libp2p: `Report <peer>: -2147483648 to -2147483648. Banned, disconnecting. Reason: <reason>`
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    collector::{self, Collector},
+    output::{escape, Render},
+    parse::LogLine,
+    BansConfig,
+};
+
+/// Loki filter selecting the ban lines of both peerset backends.
+const QUERY_FILTER: &str = "|~ `(?i)banned, disconnecting`";
+
+/// Marker of the litep2p ban lines, followed by the reason.
+const LITEP2P_MARKER: &str = "banned, disconnecting, reason:";
+
+/// Marker of the libp2p ban lines.
+const LIBP2P_MARKER: &str = "Banned, disconnecting.";
+
+/// The reason of the bans whose reason could not be extracted.
+const UNKNOWN_REASON: &str = "unknown";
+
+/// Extract the reason of a ban line, `None` if the line does not report a ban.
+pub fn ban_reason(line: &str) -> Option<&str> {
+    let reason = if let Some((_, reason)) = line.split_once(LITEP2P_MARKER) {
+        reason
+    } else if line.contains(LIBP2P_MARKER) {
+        line.rsplit_once("Reason:")
+            .map(|(_, reason)| reason.trim_end().trim_end_matches(LIBP2P_MARKER))
+            .unwrap_or_default()
+    } else {
+        return None;
+    };
+
+    let reason = reason.trim().trim_end_matches('.').trim();
+    Some(if reason.is_empty() {
+        UNKNOWN_REASON
+    } else {
+        reason
+    })
+}
+
+/// The number of bans of a single reason or node.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BanCount {
+    /// The ban reason or the banning node.
+    pub name: String,
+    /// The number of bans.
+    pub count: usize,
+    /// The number of bans per hour over the time window, when it could be determined.
+    pub rate: Option<f64>,
+}
+
+/// The report of the bans.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct BansReport {
+    /// The number of bans.
+    pub total: usize,
+    /// The timestamp of the first ban, when it could be parsed.
+    pub start: Option<chrono::NaiveDateTime>,
+    /// The timestamp of the last ban, when it could be parsed.
+    pub end: Option<chrono::NaiveDateTime>,
+    /// The number of bans per hour over the time window, when it could be determined.
+    pub rate: Option<f64>,
+    /// The bans per reason, the most frequent reasons first.
+    pub reasons: Vec<BanCount>,
+    /// The bans per banning node, the most banning nodes first.
+    pub nodes: Vec<BanCount>,
+}
+
+impl BansReport {
+    /// The time window and the overall rate of the bans.
+    fn window(&self) -> String {
+        match (self.start, self.end, self.rate) {
+            (Some(start), Some(end), Some(rate)) => {
+                format!("from {start} to {end} ({})", format_rate(Some(rate)))
+            }
+            _ => "without timestamps".to_string(),
+        }
+    }
+}
+
+impl Render for BansReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "Bans: {} {}", self.total, self.window())?;
+
+        for (name, counts) in [("Reason", &self.reasons), ("Node", &self.nodes)] {
+            if counts.is_empty() {
+                continue;
+            }
+
+            writeln!(out)?;
+            writeln!(out, "{0: <80} | {1: <10} | Rate", name, "Count")?;
+            for count in counts {
+                writeln!(
+                    out,
+                    "{0: <80} | {1: <10} | {2}",
+                    count.name,
+                    count.count,
+                    format_rate(count.rate)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "Bans: {} {}", self.total, self.window())?;
+
+        for (name, counts) in [("Reason", &self.reasons), ("Node", &self.nodes)] {
+            if counts.is_empty() {
+                continue;
+            }
+
+            writeln!(out)?;
+            writeln!(out, "| {name} | Count | Rate |")?;
+            writeln!(out, "|---|---|---|")?;
+            for count in counts {
+                writeln!(
+                    out,
+                    "| {} | {} | {} |",
+                    escape(&count.name),
+                    count.count,
+                    format_rate(count.rate)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The rate of the bans (ie `12.50/h`).
+fn format_rate(rate: Option<f64>) -> String {
+    rate.map(|rate| format!("{rate:.2}/h"))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Collects the bans of the processed lines.
+#[derive(Default)]
+struct Bans {
+    total: usize,
+    reasons: BTreeMap<String, usize>,
+    nodes: BTreeMap<String, usize>,
+    start: Option<chrono::NaiveDateTime>,
+    end: Option<chrono::NaiveDateTime>,
+}
+
+impl Collector for Bans {
+    type Item<'a> = &'a str;
+    type Report = BansReport;
+
+    const QUERY_FILTER: &'static str = QUERY_FILTER;
+
+    fn select<'a>(&self, line: &'a LogLine) -> Option<&'a str> {
+        ban_reason(&line.message)
+    }
+
+    fn record(
+        &mut self,
+        node: Option<&str>,
+        datetime: Option<chrono::NaiveDateTime>,
+        reason: &str,
+    ) {
+        self.total += 1;
+        *self.reasons.entry(reason.to_string()).or_default() += 1;
+        if let Some(node) = node {
+            *self.nodes.entry(node.to_string()).or_default() += 1;
+        }
+        if let Some(datetime) = datetime {
+            self.start = Some(self.start.map_or(datetime, |start| start.min(datetime)));
+            self.end = Some(self.end.map_or(datetime, |end| end.max(datetime)));
+        }
+    }
+
+    fn report(self) -> BansReport {
+        // The window is at least a minute, such that a burst of bans does not
+        // produce an absurd rate.
+        let hours = match (self.start, self.end) {
+            (Some(start), Some(end)) => Some((end - start).num_seconds().max(60) as f64 / 3600.0),
+            _ => None,
+        };
+        let counts = |counts: BTreeMap<String, usize>| {
+            let mut counts: Vec<_> = counts
+                .into_iter()
+                .map(|(name, count)| BanCount {
+                    name,
+                    count,
+                    rate: hours.map(|hours| count as f64 / hours),
+                })
+                .collect();
+            counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+            counts
+        };
+
+        BansReport {
+            total: self.total,
+            start: self.start,
+            end: self.end,
+            rate: hours.map(|hours| self.total as f64 / hours),
+            reasons: counts(self.reasons),
+            nodes: counts(self.nodes),
+        }
+    }
+}
+
+/// Report the bans of the provided files or Loki query.
+pub fn run(opts: BansConfig) -> Result<BansReport, Box<dyn std::error::Error>> {
+    log::info!("Running bans query");
+    collector::run(&opts.config, Bans::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::tests::collect;
+
+    #[test]
+    fn test_bans() {
+        assert_eq!(
+            ban_reason(
+                "peer PeerId(\"12D3KooW\") banned, disconnecting, reason: Invalid justification"
+            ),
+            Some("Invalid justification")
+        );
+        assert_eq!(
+            ban_reason("Report 12D3KooW: -2147483648 to -2147483648. Banned, disconnecting. Reason: Same block request multiple times"),
+            Some("Same block request multiple times")
+        );
+        assert_eq!(ban_reason("Failed to dial peer 12D3KooW"), None);
+
+        let lines = "\
+2024-03-29 16:00:00.000  WARN tokio-runtime-worker peerset: Report A: -2147483648 to -2147483648. Banned, disconnecting. Reason: Same block request multiple times
+2024-03-29 16:30:00.000  WARN tokio-runtime-worker peerset: Report B: -2147483648 to -2147483648. Banned, disconnecting. Reason: Same block request multiple times
+2024-03-29 17:00:00.000  WARN tokio-runtime-worker peerset: Report C: -2147483648 to -2147483648. Banned, disconnecting. Reason: Bad block announcement
+2024-03-29 17:00:00.000  WARN tokio-runtime-worker sync: Failed to dial peer";
+        let report = collect(Bans::default(), &[("alice", lines)]);
+        assert_eq!(report.total, 3);
+        assert_eq!(report.rate, Some(3.0));
+        assert_eq!(report.reasons[0].name, "Same block request multiple times");
+        assert_eq!(report.reasons[0].count, 2);
+        assert_eq!(report.reasons[0].rate, Some(2.0));
+        assert_eq!(report.nodes[0].name, "alice");
+        assert_eq!(report.nodes[0].count, 3);
+    }
+}
@@ -0,0 +1,150 @@
+//! Attribute log statements to the commit that last touched them via `git blame`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::fetch_git::RegexDetails;
+
+/// The commit that introduced or last touched a log statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribution {
+    /// The commit hash.
+    pub commit: String,
+    /// The author of the commit.
+    pub author: String,
+    /// The first line of the commit message.
+    pub summary: String,
+    /// The pull request number extracted from the summary (ie `Fix peerset (#1234)`).
+    pub pr: Option<u64>,
+}
+
+impl std::fmt::Display for Attribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let commit = &self.commit[..self.commit.len().min(10)];
+        match self.pr {
+            Some(pr) => write!(f, "{} {} (#{})", commit, self.author, pr),
+            None => write!(f, "{} {}", commit, self.author),
+        }
+    }
+}
+
+impl Attribution {
+    /// Parse the output of `git blame --porcelain` for a single line.
+    fn from_porcelain(output: &str) -> Option<Self> {
+        let mut lines = output.lines();
+        let commit = lines.next()?.split_whitespace().next()?.to_string();
+
+        let mut author = None;
+        let mut summary = None;
+        for line in lines {
+            if let Some(rest) = line.strip_prefix("author ") {
+                author = Some(rest.to_string());
+            } else if let Some(rest) = line.strip_prefix("summary ") {
+                summary = Some(rest.to_string());
+            }
+        }
+        let summary = summary.unwrap_or_default();
+
+        let pr = summary
+            .rsplit_once("(#")
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .and_then(|(number, _)| number.parse().ok());
+
+        Some(Attribution {
+            commit,
+            author: author.unwrap_or_default(),
+            summary,
+            pr,
+        })
+    }
+}
+
+/// Blame log statements using local clones of the regex repositories.
+pub struct Blame {
+    /// Local clones indexed by the repository name.
+    clones: HashMap<String, PathBuf>,
+    /// Cached attributions indexed by (repo, file, line).
+    cache: HashMap<(String, String, usize), Option<Attribution>>,
+}
+
+impl Blame {
+    /// Construct the blame from `<repo-name>=<path>` arguments.
+    pub fn new(args: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let clones = args
+            .iter()
+            .map(|arg| {
+                let (name, path) = arg.split_once('=').ok_or_else(|| {
+                    format!("Invalid blame argument {arg:?}, expected <repo>=<path>")
+                })?;
+                Ok((name.to_string(), PathBuf::from(path)))
+            })
+            .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+
+        Ok(Blame {
+            clones,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Attribute the log statement to the commit that last touched it.
+    ///
+    /// Returns `None` if the repository has no local clone or git blame failed.
+    pub fn attribute(&mut self, details: &RegexDetails) -> Option<&Attribution> {
+        let key = (details.repo.clone(), details.file.clone(), details.line);
+        if !self.cache.contains_key(&key) {
+            let attribution = self.run_blame(details);
+            self.cache.insert(key.clone(), attribution);
+        }
+
+        self.cache
+            .get(&key)
+            .and_then(|attribution| attribution.as_ref())
+    }
+
+    fn run_blame(&self, details: &RegexDetails) -> Option<Attribution> {
+        let path = self.clones.get(&details.repo)?;
+        let range = format!("{},{}", details.line, details.line);
+
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(path)
+            .args(["blame", "--porcelain", "-L", &range, "--", &details.file])
+            .output()
+            .map_err(|err| log::warn!("Failed to run git blame: {err}"))
+            .ok()?;
+
+        if !output.status.success() {
+            log::debug!(
+                "git blame failed for {}:{}: {}",
+                details.file,
+                details.line,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            return None;
+        }
+
+        Attribution::from_porcelain(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_porcelain() {
+        let output = "1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e 10 10 1
+author Alice
+author-mail <alice@parity.io>
+author-time 1714000000
+summary litep2p: Ban peers on invalid requests (#4321)
+filename substrate/client/network/src/peerset.rs
+\t\twarn!(target: LOG_TARGET, \"{peer:?} banned, disconnecting, reason: {}\", reason);
+";
+
+        let attribution = Attribution::from_porcelain(output).unwrap();
+        assert_eq!(attribution.author, "Alice");
+        assert_eq!(attribution.pr, Some(4321));
+        assert_eq!(attribution.to_string(), "1b2c3d4e5f Alice (#4321)");
+    }
+}
@@ -0,0 +1,477 @@
+//! Persistent cache of the chunked query results.
+//!
+//! Each chunk is identified by its query string (which includes the time range).
+//! The cache records a checksum and the number of lines of every fetched chunk,
+//! such that retried or resumed runs can detect whether the data of a previously
+//! fetched chunk changed on the Loki side (ie evicted by retention).
+//!
+//! The results are stored zstd compressed next to the manifest, and can be reused
+//! instead of querying Loki again for chunks that are already complete. The results
+//! older than [`MAX_AGE`] are evicted, then the oldest ones until the cache fits its
+//! maximum size.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+
+/// The name of the manifest file within the cache directory.
+const MANIFEST: &str = "manifest.json";
+
+/// The compression level of the stored results.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// The default maximum size of the stored results, in MiB.
+pub const DEFAULT_MAX_SIZE: u64 = 2048;
+
+/// The stored results fetched longer ago are evicted.
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The default directory of the cache (ie `~/.cache/sub-triage-logs`).
+pub fn default_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("sub-triage-logs"))
+}
+
+/// Information about a previously fetched chunk.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChunkRecord {
+    /// The query of the chunk.
+    pub query: String,
+    /// Sha256 of the chunk result.
+    pub checksum: String,
+    /// The number of lines of the chunk result.
+    pub lines: usize,
+    /// When the chunk was last fetched.
+    pub fetched_at: String,
+    /// The size of the stored result, in bytes.
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// The outcome of verifying a chunk against the previous fetch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// The chunk was never fetched before.
+    New,
+    /// The chunk is identical to the previous fetch.
+    Unchanged,
+    /// The chunk contains fewer lines than the previous fetch.
+    ///
+    /// This happens when Loki evicted part of the historical data.
+    Truncated { previous: usize, current: usize },
+    /// The chunk content changed without losing lines (ie late ingestion).
+    Changed { previous: usize, current: usize },
+}
+
+/// Persistent cache of the query results.
+pub struct QueryCache {
+    /// The directory of the cache.
+    dir: PathBuf,
+    /// The records of the fetched chunks indexed by key.
+    records: HashMap<String, ChunkRecord>,
+    /// Serve the stored results instead of running the queries again.
+    reuse: bool,
+    /// The maximum size of the stored results, in bytes.
+    max_size: u64,
+}
+
+impl QueryCache {
+    /// Open the cache from the provided directory, creating it if needed.
+    pub fn open(dir: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&dir)?;
+
+        let manifest = dir.join(MANIFEST);
+        let records = if manifest.exists() {
+            serde_json::from_slice(&std::fs::read(&manifest)?)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(QueryCache {
+            dir,
+            records,
+            reuse: false,
+            max_size: DEFAULT_MAX_SIZE * 1024 * 1024,
+        })
+    }
+
+    /// Set the maximum size of the stored results, in bytes.
+    ///
+    /// Default: [`DEFAULT_MAX_SIZE`] MiB.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Serve the stored results of complete chunks instead of running the queries again.
+    pub fn reuse(mut self, reuse: bool) -> Self {
+        self.reuse = reuse;
+        self
+    }
+
+    /// The stored result of the provided query, if it can be reused.
+    ///
+    /// Chunks ending in the future are never reused, since Loki may still ingest
+    /// lines for them. Results not matching the recorded checksum are ignored.
+    pub fn cached(&self, query: &str) -> Option<Vec<u8>> {
+        if !self.reuse || !is_complete(query) {
+            return None;
+        }
+
+        let key = Self::key(query);
+        let record = self.records.get(&key)?;
+        let compressed = std::fs::read(self.result_path(&key)).ok()?;
+        let result = zstd::decode_all(&compressed[..])
+            .map_err(|err| log::warn!("Failed to decompress cached result: {err}"))
+            .ok()?;
+
+        if hex_digest(&result) != record.checksum {
+            log::warn!("Ignoring corrupted cached result for query: {query}");
+            return None;
+        }
+
+        log::info!("Using cached result ({} lines)", record.lines);
+        Some(result)
+    }
+
+    /// Check if the stored result of the provided query can be reused.
+    pub fn is_cached(&self, query: &str) -> bool {
+        self.reuse && is_complete(query) && self.records.contains_key(&Self::key(query))
+    }
+
+    /// The path of the stored result.
+    fn result_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.zst"))
+    }
+
+    /// The key of the provided query.
+    pub fn key(query: &str) -> String {
+        hex_digest(query.as_bytes())
+    }
+
+    /// Record the result of a chunk and verify it against the previous fetch.
+    pub fn record(
+        &mut self,
+        query: &str,
+        result: &[u8],
+    ) -> Result<Verification, Box<dyn std::error::Error>> {
+        let mut writer = self.writer(query)?;
+        writer.write_all(result)?;
+        self.finish(query, writer)
+    }
+
+    /// Start recording the result of a chunk while it is streamed.
+    ///
+    /// The result is only stored once the recording is finished with [`Self::finish`].
+    pub fn writer(&self, query: &str) -> Result<ChunkWriter, Box<dyn std::error::Error>> {
+        let tmp = self
+            .result_path(&Self::key(query))
+            .with_extension("zst.tmp");
+        let encoder = zstd::Encoder::new(std::fs::File::create(&tmp)?, COMPRESSION_LEVEL)?;
+
+        Ok(ChunkWriter {
+            tmp,
+            encoder: Some(encoder),
+            hasher: Sha256::new(),
+            lines: 0,
+            empty_line: true,
+        })
+    }
+
+    /// Store the recorded result of a chunk and verify it against the previous fetch.
+    pub fn finish(
+        &mut self,
+        query: &str,
+        mut writer: ChunkWriter,
+    ) -> Result<Verification, Box<dyn std::error::Error>> {
+        let key = Self::key(query);
+        if let Some(encoder) = writer.encoder.take() {
+            encoder.finish()?;
+        }
+        let path = self.result_path(&key);
+        std::fs::rename(&writer.tmp, &path)?;
+
+        let record = ChunkRecord {
+            query: query.to_string(),
+            checksum: format!("{:x}", std::mem::take(&mut writer.hasher).finalize()),
+            lines: writer.lines + usize::from(!writer.empty_line),
+            fetched_at: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            size: std::fs::metadata(&path)?.len(),
+        };
+
+        let verification = match self.records.get(&Self::key(query)) {
+            None => Verification::New,
+            Some(previous) if previous.checksum == record.checksum => Verification::Unchanged,
+            Some(previous) if previous.lines > record.lines => Verification::Truncated {
+                previous: previous.lines,
+                current: record.lines,
+            },
+            Some(previous) => Verification::Changed {
+                previous: previous.lines,
+                current: record.lines,
+            },
+        };
+
+        match &verification {
+            Verification::New => log::debug!("Recorded new chunk for query: {query}"),
+            Verification::Unchanged => log::info!("Chunk verified against the previous fetch"),
+            Verification::Truncated { previous, current } => log::warn!(
+                "Chunk returned {current} lines, previously {previous}: historical data has been partially evicted for query: {query}"
+            ),
+            Verification::Changed { previous, current } => log::info!(
+                "Chunk changed since the previous fetch ({previous} -> {current} lines) for query: {query}"
+            ),
+        }
+
+        self.records.insert(key, record);
+        self.evict(MAX_AGE);
+        self.save()?;
+
+        Ok(verification)
+    }
+
+    /// Store the results recorded by the parts of a chunk (ie its split queries), in order,
+    /// and verify them against the previous fetch.
+    pub fn finish_parts(
+        &mut self,
+        query: &str,
+        mut parts: Vec<ChunkWriter>,
+    ) -> Result<Verification, Box<dyn std::error::Error>> {
+        if parts.len() == 1 {
+            if let Some(part) = parts.pop() {
+                return self.finish(query, part);
+            }
+        }
+
+        let mut writer = self.writer(query)?;
+        for mut part in parts {
+            if let Some(encoder) = part.encoder.take() {
+                encoder.finish()?;
+            }
+            let copied = std::fs::File::open(&part.tmp)
+                .and_then(zstd::Decoder::new)
+                .and_then(|mut decoder| std::io::copy(&mut decoder, &mut writer));
+            std::fs::remove_file(&part.tmp)?;
+            copied?;
+            // The lines of consecutive parts are not joined.
+            if !part.empty_line {
+                writer.write_all(b"\n")?;
+            }
+        }
+        self.finish(query, writer)
+    }
+
+    /// Remove the results fetched before the maximum age, then the oldest results until
+    /// the cache fits its maximum size.
+    fn evict(&mut self, max_age: Duration) {
+        let expired = (chrono::Utc::now() - max_age)
+            .format("%Y-%m-%dT%H:%M:%SZ")
+            .to_string();
+        // The fetch times sort chronologically.
+        let mut records: Vec<_> = self
+            .records
+            .iter()
+            .map(|(key, record)| (record.fetched_at.clone(), key.clone(), record.size))
+            .collect();
+        records.sort();
+
+        let mut size: u64 = records.iter().map(|(_, _, size)| size).sum();
+        for (fetched_at, key, record_size) in records {
+            if fetched_at > expired && size <= self.max_size {
+                break;
+            }
+
+            log::debug!("Evicting the cached result fetched at {fetched_at}");
+            if let Err(err) = std::fs::remove_file(self.result_path(&key)) {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to evict the cached result {key}: {err}");
+                    continue;
+                }
+            }
+            self.records.remove(&key);
+            size -= record_size;
+        }
+    }
+
+    /// Persist the manifest, such that interrupted runs keep the records.
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let manifest = self.dir.join(MANIFEST);
+        std::fs::write(manifest, serde_json::to_vec_pretty(&self.records)?)?;
+        Ok(())
+    }
+}
+
+/// Records the result of a chunk while it is streamed, compressing and hashing it.
+pub struct ChunkWriter {
+    /// The temporary file of the result, until the recording is finished.
+    tmp: PathBuf,
+    encoder: Option<zstd::Encoder<'static, std::fs::File>>,
+    hasher: Sha256,
+    /// The number of non-empty lines.
+    lines: usize,
+    /// The current line is empty so far.
+    empty_line: bool,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let encoder = self
+            .encoder
+            .as_mut()
+            .ok_or_else(|| std::io::Error::other("The recording is finished"))?;
+        let written = encoder.write(buf)?;
+
+        let buf = &buf[..written];
+        self.hasher.update(buf);
+        for byte in buf {
+            if *byte == b'\n' {
+                self.lines += usize::from(!self.empty_line);
+                self.empty_line = true;
+            } else {
+                self.empty_line = false;
+            }
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.encoder {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ChunkWriter {
+    fn drop(&mut self) {
+        // The results of the interrupted recordings are not stored.
+        if self.encoder.is_some() {
+            let _ = std::fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Records the bytes read from the reader, if a writer is provided.
+pub struct Recorded<'a, R> {
+    reader: R,
+    writer: Option<&'a mut ChunkWriter>,
+}
+
+impl<'a, R> Recorded<'a, R> {
+    pub fn new(reader: R, writer: Option<&'a mut ChunkWriter>) -> Self {
+        Recorded { reader, writer }
+    }
+}
+
+impl<R: Read> Read for Recorded<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        if let Some(writer) = &mut self.writer {
+            writer.write_all(&buf[..read])?;
+        }
+        Ok(read)
+    }
+}
+
+/// Check if the time range of the query ended (ie `--to="2024-03-29T17:00:00Z"`).
+fn is_complete(query: &str) -> bool {
+    query
+        .split_once("--to=\"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .and_then(|(to, _)| chrono::DateTime::parse_from_rfc3339(to).ok())
+        .map(|to| to < chrono::Utc::now())
+        .unwrap_or_default()
+}
+
+/// Hex encoded sha256 digest.
+fn hex_digest(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verification() {
+        let dir = std::env::temp_dir().join(format!("sub-triage-cache-{}", std::process::id()));
+        let query = "logcli query --from=\"2024-03-29T16:00:00Z\" --to=\"2024-03-29T17:00:00Z\"";
+
+        let mut cache = QueryCache::open(dir.clone()).unwrap();
+        assert_eq!(
+            cache.record(query, b"a\nb\nc\n").unwrap(),
+            Verification::New
+        );
+        assert_eq!(
+            cache.record(query, b"a\nb\nc\n").unwrap(),
+            Verification::Unchanged
+        );
+
+        // The records are persisted across runs.
+        let mut cache = QueryCache::open(dir.clone()).unwrap().reuse(true);
+        assert_eq!(cache.cached(query).unwrap(), b"a\nb\nc\n");
+
+        assert_eq!(
+            cache.record(query, b"b\nc\n").unwrap(),
+            Verification::Truncated {
+                previous: 3,
+                current: 2
+            }
+        );
+
+        // The parts of a split chunk are stored as a single result.
+        let parts = ["first", "second"].map(|part| {
+            let mut writer = cache.writer(part).unwrap();
+            writer.write_all(part.as_bytes()).unwrap();
+            writer
+        });
+        cache.finish_parts(query, parts.into()).unwrap();
+        assert_eq!(cache.cached(query).unwrap(), b"first\nsecond\n");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_eviction() {
+        let dir = std::env::temp_dir().join(format!("sub-triage-eviction-{}", std::process::id()));
+        let query = |hour: u32| {
+            format!(
+                "logcli query --from=\"2024-03-29T{hour:02}:00:00Z\" --to=\"2024-03-29T{:02}:00:00Z\"",
+                hour + 1
+            )
+        };
+
+        let mut cache = QueryCache::open(dir.clone()).unwrap().reuse(true);
+        cache.record(&query(0), b"a\n").unwrap();
+        cache.record(&query(1), b"b\n").unwrap();
+        let size = cache.records.values().map(|record| record.size).sum();
+        assert!(size > 0);
+
+        // The oldest results are evicted to fit the maximum size.
+        cache
+            .records
+            .get_mut(&QueryCache::key(&query(0)))
+            .unwrap()
+            .fetched_at = "2024-03-29T00:00:00Z".to_string();
+        let mut cache = cache.max_size(size);
+        cache.record(&query(2), b"c\n").unwrap();
+        assert!(cache.cached(&query(0)).is_none());
+        assert!(!cache.result_path(&QueryCache::key(&query(0))).exists());
+        assert_eq!(cache.cached(&query(1)).unwrap(), b"b\n");
+        assert_eq!(cache.cached(&query(2)).unwrap(), b"c\n");
+
+        // The expired results are evicted regardless of the size.
+        cache.evict(Duration::ZERO);
+        assert!(cache.records.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+//! Checkpoints of the chunked query runs, such that interrupted runs can be resumed.
+//!
+//! The checkpoint of a run starts with its resolved time range, followed by the matches of
+//! every completed chunk, appended as the chunks complete. The file is named after the
+//! options of the command rather than its queries, whose times move with `--last-day`.
+//! Re-running the same command with `--resume` queries the time range of the interrupted
+//! run, and skips the completed chunks instead of querying and matching them again.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{cache::QueryCache, matcher::Matches};
+
+/// The compression level of the checkpoints.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// The checkpoints not updated for this long are removed.
+pub const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The extension of the checkpoint files.
+const EXTENSION: &str = "ckpt";
+
+/// The state of an interrupted run.
+#[derive(Debug, Default)]
+pub struct Checkpoint {
+    /// The resolved time range of the run.
+    pub range: Option<(String, String)>,
+    /// The keys of the completed chunks.
+    pub completed: HashSet<String>,
+    /// The matches of the completed chunks of every chain.
+    pub chains: HashMap<String, Matches>,
+}
+
+impl Checkpoint {
+    /// Check if the chunk of the provided query is completed.
+    pub fn is_completed(&self, query: &str) -> bool {
+        self.completed.contains(&QueryCache::key(query))
+    }
+
+    /// Take the matches of the completed chunks of the chain.
+    pub fn take_chain(&mut self, chain: &str) -> Matches {
+        self.chains.remove(chain).unwrap_or_default()
+    }
+}
+
+/// A record of the checkpoint file.
+#[derive(serde::Deserialize)]
+enum Record {
+    /// The start of the run.
+    Start { range: Option<(String, String)> },
+    /// The matches of a completed chunk.
+    Chunk {
+        chain: String,
+        key: String,
+        matches: Box<Matches>,
+    },
+}
+
+/// A record being persisted, borrowing the matches of the run.
+#[derive(serde::Serialize)]
+enum RecordRef<'a> {
+    Start {
+        range: Option<(&'a str, &'a str)>,
+    },
+    Chunk {
+        chain: &'a str,
+        key: String,
+        matches: &'a Matches,
+    },
+}
+
+/// The file of the checkpoints of a run.
+#[derive(Debug, Clone)]
+pub struct CheckpointFile {
+    path: PathBuf,
+}
+
+impl CheckpointFile {
+    /// The checkpoint file of the run of the provided command, within the directory.
+    ///
+    /// The command is identified by its options, excluding the resolved times.
+    pub fn new(dir: &Path, command: &str) -> Self {
+        let key = QueryCache::key(command);
+        CheckpointFile {
+            path: dir.join(format!("{key}.{EXTENSION}")),
+        }
+    }
+
+    /// Remove the checkpoints of the directory not updated for the provided duration,
+    /// ie left behind by interrupted runs that were never resumed.
+    pub fn prune(dir: &Path, max_age: Duration) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let stale = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > max_age);
+            if stale {
+                log::info!("Removing the stale checkpoint {}", path.display());
+                if let Err(err) = std::fs::remove_file(&path) {
+                    log::warn!("Failed to remove {}: {err}", path.display());
+                }
+            }
+        }
+    }
+
+    /// Load the checkpoint of an interrupted run, if any.
+    ///
+    /// A record cut by an interruption while being appended is ignored, its chunk is
+    /// processed again.
+    pub fn load(&self) -> Result<Option<Checkpoint>, Box<dyn std::error::Error>> {
+        let mut file = match std::fs::File::open(&self.path) {
+            Ok(file) => std::io::BufReader::new(file),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let invalid = |err: &dyn std::fmt::Display| {
+            format!("Invalid checkpoint {}: {err}", self.path.display())
+        };
+
+        let mut checkpoint = Checkpoint::default();
+        loop {
+            // Every record is a compressed frame, prefixed by its length.
+            let mut len = [0; 4];
+            match file.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            }
+            let mut frame = vec![0; u32::from_le_bytes(len) as usize];
+            if file.read_exact(&mut frame).is_err() {
+                log::warn!("Ignoring the last record of the checkpoint, cut while saved");
+                break;
+            }
+
+            let record = serde_json::from_slice(&zstd::decode_all(&frame[..])?)
+                .map_err(|err| invalid(&err))?;
+            match record {
+                Record::Start { range } => checkpoint.range = range,
+                Record::Chunk {
+                    chain,
+                    key,
+                    matches,
+                } => {
+                    checkpoint.completed.insert(key);
+                    checkpoint.chains.entry(chain).or_default().merge(*matches);
+                }
+            }
+        }
+
+        Ok(Some(checkpoint))
+    }
+
+    /// Start the checkpoint of a new run of the resolved time range, replacing the
+    /// checkpoint of a previous run.
+    pub fn start(&self, range: Option<(&str, &str)>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::File::create(&self.path)?;
+        self.append(&RecordRef::Start { range })
+    }
+
+    /// Persist the matches of a completed chunk of the chain.
+    ///
+    /// Only the matches of the chunk are appended, the checkpoint grows with the chunks.
+    pub fn complete(
+        &self,
+        chain: &str,
+        query: &str,
+        matches: &Matches,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.append(&RecordRef::Chunk {
+            chain,
+            key: QueryCache::key(query),
+            matches,
+        })
+    }
+
+    fn append(&self, record: &RecordRef) -> Result<(), Box<dyn std::error::Error>> {
+        let frame = zstd::encode_all(&serde_json::to_vec(record)?[..], COMPRESSION_LEVEL)?;
+        let len = u32::try_from(frame.len()).map_err(|_| "Checkpoint record too large")?;
+
+        // A single write, such that a record is either complete or cut at the end.
+        let mut bytes = len.to_le_bytes().to_vec();
+        bytes.extend(frame);
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.path)?
+            .write_all(&bytes)?;
+
+        Ok(())
+    }
+
+    /// Remove the checkpoint of a completed run.
+    pub fn remove(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match std::fs::remove_file(&self.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::Matcher;
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("checkpoint-{}", std::process::id()));
+        let file = CheckpointFile::new(&dir, "warn-err --last-day");
+        assert!(file.load().unwrap().is_none());
+
+        let matcher = Matcher::new(
+            vec![(
+                regex::Regex::new("Slow").unwrap(),
+                crate::fetch_git::RegexDetails {
+                    repo: "extra".to_string(),
+                    file: "extra.yaml".to_string(),
+                    start: 0,
+                    end: 0,
+                    line: 1,
+                    column: 1,
+                    permalink: String::new(),
+                    description: None,
+                    target: None,
+                    owners: Vec::new(),
+                    ty: "warn".to_string(),
+                },
+            )],
+            Vec::new(),
+        );
+        let (mut first, mut second) = (Matches::default(), Matches::default());
+        matcher.process_lines(["Slow", "Slow", "Unknown"].into_iter(), &mut first);
+        matcher.process_lines(["Slow"].into_iter(), &mut second);
+
+        file.start(Some(("2024-03-29T16:00:00Z", "2024-03-30T16:00:00Z")))
+            .unwrap();
+        file.complete("versi", "first", &first).unwrap();
+        file.complete("versi", "second", &second).unwrap();
+        // A record cut by an interruption.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&file.path)
+            .unwrap()
+            .write_all(&[100, 0, 0, 0, 1])
+            .unwrap();
+
+        let mut loaded = file.load().unwrap().unwrap();
+        assert_eq!(
+            loaded.range,
+            Some((
+                "2024-03-29T16:00:00Z".to_string(),
+                "2024-03-30T16:00:00Z".to_string()
+            ))
+        );
+        assert!(loaded.is_completed("first") && loaded.is_completed("second"));
+        assert!(!loaded.is_completed("third"));
+        let matches = loaded.take_chain("versi");
+        assert_eq!(matches.total, 4);
+        assert_eq!(matches.unknown_lines, vec!["Unknown"]);
+        assert_eq!(matches.found_lines.values().next().unwrap().count(), 3);
+        assert_eq!(loaded.take_chain("kusama").total, 0);
+
+        // A new run replaces the checkpoint.
+        file.start(None).unwrap();
+        assert!(file.load().unwrap().unwrap().completed.is_empty());
+
+        CheckpointFile::prune(&dir, Duration::ZERO);
+        assert!(file.load().unwrap().is_none());
+        file.remove().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
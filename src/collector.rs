@@ -0,0 +1,230 @@
+//! The line collection shared by the dedicated subcommands (ie `bans` or `imports`).
+//!
+//! Every collector selects its lines and records the fields extracted from them, while
+//! the empty lines, the node attribution and the duplicates of the overlapping query
+//! chunks are handled once by the [`Collection`].
+
+use chrono::NaiveDateTime;
+
+use crate::{dedup, matcher::Matcher, parse::LogLine, stats::Stats, Config};
+
+/// Collects the fields of the lines of a dedicated subcommand into its report.
+pub(crate) trait Collector {
+    /// The fields extracted from a selected line (ie the reason of a ban).
+    type Item<'a>;
+
+    /// The report of the recorded lines.
+    type Report;
+
+    /// Loki filter selecting the lines of the collector.
+    const QUERY_FILTER: &'static str;
+
+    /// Extract the fields of the parsed line, `None` if the line is not collected.
+    fn select<'a>(&self, line: &'a LogLine) -> Option<Self::Item<'a>>;
+
+    /// Record the fields of a selected line of the node.
+    fn record(&mut self, node: Option<&str>, datetime: Option<NaiveDateTime>, item: Self::Item<'_>);
+
+    /// Build the report of the recorded lines.
+    fn report(self) -> Self::Report;
+}
+
+/// Feeds the processed lines to a collector.
+pub(crate) struct Collection<C> {
+    collector: C,
+    /// Extracts the node of the lines.
+    matcher: Matcher,
+    /// Drop the lines returned twice by overlapping query chunks.
+    drop_duplicates: bool,
+    fingerprints: dedup::LineFingerprints,
+}
+
+impl<C: Collector> Collection<C> {
+    pub(crate) fn new(collector: C, matcher: Matcher, drop_duplicates: bool) -> Self {
+        Collection {
+            collector,
+            matcher,
+            drop_duplicates,
+            fingerprints: Default::default(),
+        }
+    }
+
+    /// Record the selected lines of the provided lines, attributing them to the source
+    /// if they carry no node information.
+    ///
+    /// Returns the number of processed and selected lines.
+    pub(crate) fn process(
+        &mut self,
+        source: Option<&str>,
+        lines: &str,
+        stats: &mut Stats,
+    ) -> (usize, usize) {
+        let (mut processed, mut selected) = (0, 0);
+        for line in lines.lines() {
+            processed += 1;
+            if line.is_empty() {
+                stats.empty_lines += 1;
+                continue;
+            }
+
+            let (node, content) = self.matcher.split_node(line);
+            let node = node.or(source);
+            let parsed = LogLine::parse(content);
+            let Some(item) = self.collector.select(&parsed) else {
+                stats.unknown += 1;
+                continue;
+            };
+
+            let datetime = parsed.datetime();
+            if let (true, Some(datetime)) = (self.drop_duplicates, datetime) {
+                if !self.fingerprints.insert(datetime, node, content) {
+                    stats.duplicates += 1;
+                    continue;
+                }
+            }
+
+            stats.total += 1;
+            selected += 1;
+            self.collector.record(node, datetime, item);
+        }
+
+        (processed, selected)
+    }
+
+    /// Build the report of the recorded lines.
+    pub(crate) fn report(self) -> C::Report {
+        self.collector.report()
+    }
+}
+
+/// Collect the lines of the provided files or Loki query into the report of the collector.
+pub(crate) fn run<C: Collector>(
+    opts: &Config,
+    collector: C,
+) -> Result<C::Report, Box<dyn std::error::Error>> {
+    let mut stats = Stats::new();
+
+    // Overlapping query chunks can return the same lines twice.
+    let mut collection = Collection::new(
+        collector,
+        Matcher::new(Vec::new(), Vec::new()).with_node_prefix(opts.node_prefix()?),
+        opts.is_query(),
+    );
+    opts.process_sources(C::QUERY_FILTER, &mut |source, lines| {
+        collection.process(source, lines, &mut stats)
+    })?;
+
+    Ok(collection.report())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Collect the lines of every node into the report of the collector.
+    pub(crate) fn collect<C: Collector>(collector: C, nodes: &[(&str, &str)]) -> C::Report {
+        let mut collection =
+            Collection::new(collector, Matcher::new(Vec::new(), Vec::new()), false);
+        for (node, lines) in nodes {
+            collection.process(Some(node), lines, &mut Stats::new());
+        }
+        collection.report()
+    }
+
+    /// Records the ban lines.
+    #[derive(Default)]
+    struct Messages(Vec<(Option<String>, Option<NaiveDateTime>, String)>);
+
+    impl Collector for Messages {
+        type Item<'a> = &'a str;
+        type Report = Vec<(Option<String>, Option<NaiveDateTime>, String)>;
+
+        const QUERY_FILTER: &'static str = "|= `Banned`";
+
+        fn select<'a>(&self, line: &'a LogLine) -> Option<&'a str> {
+            line.message.contains("Banned").then_some(&*line.message)
+        }
+
+        fn record(&mut self, node: Option<&str>, datetime: Option<NaiveDateTime>, message: &str) {
+            self.0
+                .push((node.map(str::to_string), datetime, message.to_string()));
+        }
+
+        fn report(self) -> Self::Report {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_collection() {
+        let lines = "\
+[bob] 2024-03-29 16:00:00.000  WARN tokio-runtime-worker peerset: Report A: -2147483648 to -2147483648. Banned, disconnecting.
+[bob] 2024-03-29 16:00:00.000  WARN tokio-runtime-worker peerset: Report A: -2147483648 to -2147483648. Banned, disconnecting.
+
+2024-03-29 16:00:00.000  WARN tokio-runtime-worker peerset: Report A: -2147483648 to -2147483648. Banned, disconnecting.
+2024-03-29 16:00:01.000  INFO tokio-runtime-worker substrate: 💤 Idle (8 peers)
+Report B: -2147483648 to -2147483648. Banned, disconnecting.
+Report B: -2147483648 to -2147483648. Banned, disconnecting.";
+        let matcher = || {
+            Matcher::new(Vec::new(), Vec::new())
+                .with_node_prefix(Some(regex::Regex::new(r"^\[(?P<node>\w+)\] ").unwrap()))
+        };
+
+        // The duplicates are dropped, unless they have no timestamp.
+        let mut stats = Stats::new();
+        let mut collection = Collection::new(Messages::default(), matcher(), true);
+        assert_eq!(collection.process(Some("alice"), lines, &mut stats), (7, 4));
+        assert_eq!(
+            (
+                stats.total,
+                stats.empty_lines,
+                stats.unknown,
+                stats.duplicates
+            ),
+            (4, 1, 1, 1)
+        );
+        // The lines without node prefix are attributed to the source.
+        let records = collection.report();
+        let nodes: Vec<_> = records.iter().map(|(node, ..)| node.as_deref()).collect();
+        assert_eq!(
+            nodes,
+            [Some("bob"), Some("alice"), Some("alice"), Some("alice")]
+        );
+        assert_eq!(
+            records[0].1.map(|datetime| datetime.to_string()),
+            Some("2024-03-29 16:00:00".to_string())
+        );
+        assert_eq!(records[2].1, None);
+
+        let mut stats = Stats::new();
+        let mut collection = Collection::new(Messages::default(), matcher(), false);
+        assert_eq!(collection.process(None, lines, &mut stats), (7, 5));
+        assert_eq!(stats.duplicates, 0);
+        assert_eq!(collection.report()[2].0, None);
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir().join(format!("collector-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let line = "2024-03-29 16:00:00.000  WARN tokio-runtime-worker peerset: Report A: -2147483648 to -2147483648. Banned, disconnecting.";
+        std::fs::write(dir.join("alice.log"), format!("{line}\n{line}\n")).unwrap();
+        std::fs::write(dir.join("bob.log"), line).unwrap();
+
+        // The lines of the local files are attributed to their file, and never dropped.
+        let opts = <Config as clap::Parser>::parse_from([
+            "warn-err",
+            "--file-glob",
+            &dir.join("*.log").to_string_lossy(),
+        ]);
+        let records = run(&opts, Messages::default()).unwrap();
+        let mut nodes: Vec<_> = records.iter().map(|(node, ..)| node.as_deref()).collect();
+        nodes.sort();
+        assert_eq!(
+            nodes,
+            [Some("alice.log"), Some("alice.log"), Some("bob.log")]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
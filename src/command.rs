@@ -0,0 +1,183 @@
+//! The commands of the queries, spawned without a shell.
+//!
+//! The queries are command lines quoted like the POSIX shell (ie `logcli query
+//! '{chain="versi"}' --from="2024-03-29T16:00:00Z"`), such that the dry runs print them and
+//! the cache keys them as is. They are split into their program and arguments here, and the
+//! program is spawned directly: `sh` is not required, ie on Windows workstations. The command
+//! lines using other shell syntax (ie pipes or redirections) are rejected.
+
+use std::{iter::Peekable, process::Command, str::Chars};
+
+/// Split the command line into its words, like the POSIX shell.
+///
+/// Supports the single and double quotes, the backslash escapes and the `$NAME` and
+/// `${NAME}` variables, resolved with the provided function (empty when unset). The
+/// expanded variables are not split into words. The other shell syntax is an error.
+pub fn split(line: &str, var: impl Fn(&str) -> Option<String>) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    // The current word, `None` between the words such that `''` is an empty word.
+    let mut word: Option<String> = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ' ' | '\t' | '\n' => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => word.push(ch),
+                        None => return Err("Unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.peek() {
+                            Some(&ch @ ('"' | '\\' | '$' | '`')) => {
+                                chars.next();
+                                word.push(ch);
+                            }
+                            _ => word.push('\\'),
+                        },
+                        Some('$') => expand(&mut chars, word, &var)?,
+                        Some('`') => return Err("Unsupported command substitution".to_string()),
+                        Some(ch) => word.push(ch),
+                        None => return Err("Unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // Line continuation.
+                Some('\n') => {}
+                Some(ch) => word.get_or_insert_with(String::new).push(ch),
+                None => return Err("Trailing backslash".to_string()),
+            },
+            '$' => expand(&mut chars, word.get_or_insert_with(String::new), &var)?,
+            '|' | '&' | ';' | '<' | '>' | '(' | ')' | '`' => {
+                return Err(format!("Unsupported shell syntax {ch:?}"));
+            }
+            ch => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// Expand the variable following a `$` into the word.
+fn expand(
+    chars: &mut Peekable<Chars>,
+    word: &mut String,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<(), String> {
+    let mut name = String::new();
+    if chars.next_if_eq(&'{').is_some() {
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(ch) => name.push(ch),
+                None => return Err("Unterminated variable".to_string()),
+            }
+        }
+    } else {
+        while let Some(ch) = chars.next_if(|ch| ch.is_ascii_alphanumeric() || *ch == '_') {
+            name.push(ch);
+        }
+        if name.is_empty() {
+            word.push('$');
+            return Ok(());
+        }
+    }
+
+    word.push_str(&var(&name).unwrap_or_default());
+    Ok(())
+}
+
+/// The command running the command line with the provided environment variables.
+///
+/// The variables of the command line are resolved from the provided variables, then from
+/// the environment. The command lines that cannot be split are an error.
+pub fn command<'a>(
+    line: &str,
+    envs: impl IntoIterator<Item = (&'static str, &'a str)>,
+) -> Result<Command, String> {
+    let envs: Vec<_> = envs.into_iter().collect();
+    let var = |name: &str| {
+        envs.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+            .or_else(|| std::env::var(name).ok())
+    };
+
+    let words = split(line, var).map_err(|err| format!("Invalid command {line:?}: {err}"))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err("Empty command".to_string());
+    };
+
+    let mut command = Command::new(program);
+    command.args(args).envs(envs);
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::shell_quote;
+
+    #[test]
+    fn test_split() {
+        let var = |name: &str| (name == "ES_API_KEY").then(|| "secret".to_string());
+
+        assert_eq!(
+            split(
+                r#"logcli query --from="2024-03-29T16:00:00Z" '{chain="versi"} != `Error`' --batch 5000"#,
+                var
+            )
+            .unwrap(),
+            [
+                "logcli",
+                "query",
+                "--from=2024-03-29T16:00:00Z",
+                r#"{chain="versi"} != `Error`"#,
+                "--batch",
+                "5000"
+            ]
+        );
+        assert_eq!(
+            split(
+                &format!("curl --data {} ''", shell_quote(r#"{"it's": "\n"}"#)),
+                var
+            )
+            .unwrap(),
+            ["curl", "--data", r#"{"it's": "\n"}"#, ""]
+        );
+        assert_eq!(
+            split(
+                r#"curl --header "Authorization: ApiKey ${ES_API_KEY}" $UNSET\ x $"#,
+                var
+            )
+            .unwrap(),
+            [
+                "curl",
+                "--header",
+                "Authorization: ApiKey secret",
+                " x",
+                "$"
+            ]
+        );
+
+        assert!(split("printf 'a'; exit 1", var).is_err());
+        assert!(split("logcli query 'unterminated", var).is_err());
+
+        assert!(command("printf 'a'; exit 1", []).is_err());
+        assert!(command(" ", []).is_err());
+        let command = command("logcli query \"$LOKI_ORG_ID\"", [("LOKI_ORG_ID", "1")]).unwrap();
+        assert_eq!(command.get_program(), "logcli");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["query", "1"]);
+    }
+}
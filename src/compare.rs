@@ -0,0 +1,268 @@
+//! Comparison of the patterns of two chains over the same time window.
+//!
+//! The first chain is the network under test, the second chain is the reference
+//! network (ie `--chain versi-networking --chain kusama`).
+
+use std::fmt::Write;
+
+use crate::{
+    output::{escape, Render},
+    report::TriageReport,
+    CompareConfig, Config, TriageSession,
+};
+
+/// The counts of a single pattern on both chains.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PatternDelta {
+    /// The pattern.
+    pub pattern: String,
+    /// The level of the log statement (ie `warn`, `error`).
+    pub level: String,
+    /// The number of lines matched on the chain under test.
+    pub count: usize,
+    /// The number of lines matched on the reference chain.
+    pub reference: usize,
+    /// The difference between the chain under test and the reference chain.
+    pub delta: i64,
+}
+
+impl PatternDelta {
+    /// The pattern is only matched on one of the chains.
+    fn specific(&self) -> bool {
+        self.count == 0 || self.reference == 0
+    }
+}
+
+/// The report comparing the patterns of two chains.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CompareReport {
+    /// The chain under test.
+    pub chain: String,
+    /// The reference chain.
+    pub reference: String,
+    /// The counts of every pattern, the largest deltas first.
+    pub patterns: Vec<PatternDelta>,
+}
+
+impl CompareReport {
+    /// Build the comparison of the patterns of a multi-chain report.
+    pub fn new(report: &TriageReport, chain: &str, reference: &str) -> Self {
+        let count = |counts: &std::collections::BTreeMap<String, usize>, chain: &str| {
+            counts.get(chain).copied().unwrap_or_default()
+        };
+        let mut patterns: Vec<_> = report
+            .patterns
+            .iter()
+            .map(|pattern| {
+                let (count, reference) = (
+                    count(&pattern.chains, chain),
+                    count(&pattern.chains, reference),
+                );
+                PatternDelta {
+                    pattern: pattern.pattern.clone(),
+                    level: pattern.level.clone(),
+                    count,
+                    reference,
+                    delta: count as i64 - reference as i64,
+                }
+            })
+            .collect();
+        patterns.sort_by(|a, b| {
+            b.delta
+                .abs()
+                .cmp(&a.delta.abs())
+                .then_with(|| b.delta.cmp(&a.delta))
+                .then_with(|| a.pattern.cmp(&b.pattern))
+        });
+
+        CompareReport {
+            chain: chain.to_string(),
+            reference: reference.to_string(),
+            patterns,
+        }
+    }
+
+    /// Drop the patterns matched less than `min_count` times on both chains, and keep the
+    /// `top` largest deltas.
+    pub fn select(&mut self, min_count: usize, top: Option<usize>) {
+        self.patterns
+            .retain(|pattern| pattern.count + pattern.reference >= min_count);
+        if let Some(top) = top {
+            self.patterns.truncate(top);
+        }
+    }
+
+    /// The number of patterns only matched on one of the chains.
+    fn specific(&self) -> usize {
+        self.patterns
+            .iter()
+            .filter(|pattern| pattern.specific())
+            .count()
+    }
+}
+
+impl Render for CompareReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Compare: {} against {} ({} patterns, {} only on one chain)",
+            self.chain,
+            self.reference,
+            self.patterns.len(),
+            self.specific()
+        )?;
+        if self.patterns.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{0: <25} | {1: <25} | {2: <10} | {3: <10} | Pattern",
+            self.chain, self.reference, "Delta", "Level"
+        )?;
+        for pattern in &self.patterns {
+            writeln!(
+                out,
+                "{0: <25} | {1: <25} | {2: <10} | {3: <10} | {4}",
+                pattern.count,
+                pattern.reference,
+                format_delta(pattern.delta),
+                pattern.level,
+                pattern.pattern
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Compare: {} against {} ({} patterns, {} only on one chain)",
+            self.chain,
+            self.reference,
+            self.patterns.len(),
+            self.specific()
+        )?;
+        if self.patterns.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "| {} | {} | Delta | Level | Pattern |",
+            self.chain, self.reference
+        )?;
+        writeln!(out, "|---|---|---|---|---|")?;
+        for pattern in &self.patterns {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | `{}` |",
+                pattern.count,
+                pattern.reference,
+                format_delta(pattern.delta),
+                pattern.level,
+                escape(&pattern.pattern)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The delta with its sign (ie `+12`, `-3`, `0`).
+fn format_delta(delta: i64) -> String {
+    if delta > 0 {
+        format!("+{delta}")
+    } else {
+        delta.to_string()
+    }
+}
+
+/// Compare the patterns of the two chains over the same time window.
+pub async fn run(opts: CompareConfig) -> Result<CompareReport, Box<dyn std::error::Error>> {
+    log::info!("Running compare query");
+    let opts = opts.config;
+    let [chain, reference] = opts.chain.as_slice() else {
+        return Err(
+            "The compare subcommand requires two chains (ie `--chain versi,kusama`)".into(),
+        );
+    };
+    if !opts.is_query() {
+        return Err(
+            "The compare subcommand queries the chains, local files are not supported".into(),
+        );
+    }
+
+    let (chain, reference) = (chain.clone(), reference.clone());
+    // The patterns are limited after the comparison, such that the largest deltas are kept
+    // rather than the most frequent patterns.
+    let (min_count, top) = (opts.min_count, opts.top);
+    let report = TriageSession::new(Config {
+        min_count: 0,
+        top: None,
+        ..opts
+    })
+    .run()
+    .await?;
+
+    let mut compare = CompareReport::new(&report, &chain, &reference);
+    compare.select(min_count, top);
+    Ok(compare)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{matcher::Matches, output::OutputFormat, report::PatternReport};
+
+    #[test]
+    fn test_compare() {
+        let mut report = TriageReport::new(&Matches::default(), None, false, None);
+        let pattern = |name: &str, counts: &[(&str, usize)]| {
+            counts
+                .iter()
+                .fold(PatternReport::new(name, 0), |pattern, (chain, count)| {
+                    pattern.chain(chain, *count)
+                })
+        };
+        report.patterns = vec![
+            pattern("Failed to dial .*", &[("versi", 10), ("kusama", 12)]),
+            pattern("Slow block import", &[("versi", 40)]),
+            pattern("Invalid justification", &[("kusama", 5)]),
+            pattern("Idle", &[("versi", 1), ("kusama", 1)]),
+        ];
+
+        let mut compare = CompareReport::new(&report, "versi", "kusama");
+        let deltas: Vec<_> = compare
+            .patterns
+            .iter()
+            .map(|pattern| (pattern.pattern.as_str(), pattern.delta))
+            .collect();
+        assert_eq!(
+            deltas,
+            vec![
+                ("Slow block import", 40),
+                ("Invalid justification", -5),
+                ("Failed to dial .*", -2),
+                ("Idle", 0)
+            ]
+        );
+        assert_eq!(compare.specific(), 2);
+
+        // The patterns are limited by their largest delta, rather than their count.
+        compare.select(6, Some(2));
+        let patterns: Vec<_> = compare
+            .patterns
+            .iter()
+            .map(|pattern| pattern.pattern.as_str())
+            .collect();
+        assert_eq!(patterns, vec!["Slow block import", "Failed to dial .*"]);
+        assert!(compare
+            .render(OutputFormat::Text)
+            .unwrap()
+            .contains("40                        | 0                         | +40"));
+    }
+}
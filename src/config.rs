@@ -0,0 +1,1151 @@
+//! The options of the triage commands.
+
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::{
+    cache, checkpoint, elasticsearch, fetch_git, grafana, histogram, input, journald, k8s, level,
+    output, parse, progress, query, report, samples, tarball, template,
+};
+
+/// The interval of the repeated runs of `--watch` and `serve`, when not provided.
+const DEFAULT_RUN_INTERVAL: chrono::Duration = chrono::Duration::hours(1);
+
+/// Group the warnings and errors by the log statements that produced them.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct Config {
+    /// The address of the Loki instance.
+    #[clap(long, default_value = "127.0.0.1:10700")]
+    pub address: String,
+
+    /// Optionally provide a file for parsing instead of querying the Loki instance.
+    ///
+    /// When a directory is provided, all the files of the directory are processed.
+    /// Use `-` to read from the standard input. When omitted and the standard input
+    /// is piped, the lines are read from the standard input.
+    #[clap(long)]
+    pub file: Option<String>,
+
+    /// Glob of log files to process instead of querying the Loki instance
+    /// (ie `logs/*.log`).
+    ///
+    /// The files are processed in parallel and each line is attributed to its
+    /// file, unless the line carries node information.
+    #[clap(long, conflicts_with = "file")]
+    pub file_glob: Option<String>,
+
+    /// A zombienet output directory, whose node log files are processed instead of
+    /// querying the Loki instance.
+    ///
+    /// The logs are discovered from both the `logs/<node>.log` (zombienet) and the
+    /// `<node>/<node>.log` (zombienet-sdk) layouts, and every line is attributed to its node.
+    #[clap(long, conflicts_with_all = ["file", "file_glob"])]
+    pub zombienet_dir: Option<PathBuf>,
+
+    /// Regex extracting the node from the prefix of each line, for files that
+    /// interleave the lines of multiple nodes.
+    ///
+    /// The regex must contain a `node` named capture group, for example
+    /// `^\[(?P<node>[^\]]+)\]\s*` for lines like `[alice] 2024-03-29 ...`.
+    #[clap(long)]
+    pub node_prefix: Option<String>,
+
+    /// Keep the ANSI escape sequences (ie colors) of the local files.
+    ///
+    /// The sequences of the logs captured from terminals are stripped by default, since
+    /// they break the level filters and the patterns.
+    #[clap(long)]
+    pub keep_ansi: bool,
+
+    /// The maximum number of files processed in parallel in directory mode.
+    ///
+    /// Defaults to the number of available CPUs.
+    #[clap(long)]
+    pub jobs: Option<usize>,
+
+    /// The chains to query.
+    ///
+    /// Can be repeated or provided as a comma-separated list, in which case the
+    /// report contains the number of matches per chain.
+    #[clap(long, value_delimiter = ',', default_value = "versi-networking")]
+    pub chain: Vec<String>,
+
+    /// The nodes to query, as regexes (ie `validator-.*`).
+    ///
+    /// Can be repeated or provided as a comma-separated list, in which case the lines of
+    /// any of the nodes are queried. Every node is queried by default.
+    #[clap(long, value_delimiter = ',')]
+    pub node: Vec<String>,
+
+    /// The nodes to leave out of the queries, as regexes (ie the canary nodes).
+    ///
+    /// Can be repeated or provided as a comma-separated list.
+    #[clap(long, value_delimiter = ',')]
+    pub exclude_node: Vec<String>,
+
+    /// Provide the info for the last 24 hours.
+    #[clap(long)]
+    pub last_day: bool,
+
+    /// The start time of the query.
+    ///
+    /// The format is "YYYY-MM-DDTHH:MM:SSZ". RFC 3339 with an offset
+    /// (ie "2024-05-01T10:00:00+02:00"), dates (ie "2024-05-01") and UNIX timestamps in
+    /// seconds or milliseconds are also accepted, and normalized to UTC.
+    #[clap(long, value_parser = query::parse_time_arg)]
+    pub start_time: Option<String>,
+
+    /// The end time of the query, in the formats of `--start-time`.
+    #[clap(long, value_parser = query::parse_time_arg)]
+    pub end_time: Option<String>,
+
+    /// Optionally provide an organization ID.
+    #[clap(long)]
+    pub org_id: Option<String>,
+
+    /// The bearer token of the Loki instance.
+    ///
+    /// Passed to `logcli` through its environment, never printed with the queries.
+    #[clap(
+        long,
+        env = "LOKI_BEARER_TOKEN",
+        hide_env_values = true,
+        conflicts_with = "username"
+    )]
+    pub auth_token: Option<String>,
+
+    /// The username of the basic authentication of the Loki instance.
+    #[clap(long, env = "LOKI_USERNAME", requires = "password")]
+    pub username: Option<String>,
+
+    /// The password of the basic authentication of the Loki instance.
+    #[clap(
+        long,
+        env = "LOKI_PASSWORD",
+        hide_env_values = true,
+        requires = "username"
+    )]
+    pub password: Option<String>,
+
+    /// The CA certificate verifying the TLS certificate of the Loki instance.
+    #[clap(long)]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Skip the verification of the TLS certificate of the Loki instance.
+    #[clap(long)]
+    pub insecure_skip_verify: bool,
+
+    /// Extra label matcher of the Loki stream selector, ie `namespace=versi` or `pod=~"validator-.*"`.
+    ///
+    /// Supports the `=`, `!=`, `=~` and `!~` operators. Can be provided multiple times.
+    #[clap(long = "label", value_name = "LABEL", value_parser = query::parse_label)]
+    pub labels: Vec<query::Label>,
+
+    /// Exclude the lines containing this substring from the queries, besides the common
+    /// errors (ie `Error while dialing`).
+    ///
+    /// Can be provided multiple times.
+    #[clap(long, value_parser = query::parse_excluded_line)]
+    pub exclude_line: Vec<String>,
+
+    /// File of substrings excluding the lines containing them from the queries, one per line.
+    ///
+    /// Empty lines and lines starting with `#` are skipped.
+    #[clap(long)]
+    pub exclude_line_file: Option<PathBuf>,
+
+    /// List the lines excluded from the queries in the report header.
+    #[clap(long)]
+    pub show_exclusions: bool,
+
+    /// The source of the lines when no file is provided.
+    ///
+    /// The `k8s` source reads the logs of the pods of `--selector` (or of the `--node` pod)
+    /// with `kubectl logs`, from `--start-time` until now (`--end-time` is not supported).
+    /// The `journald` source reads the logs of the `--unit` systemd units with `journalctl`.
+    /// The `elasticsearch` source searches the `--es-index` indices of the `--es-url`
+    /// cluster with `curl`.
+    #[clap(long, value_enum, default_value_t = query::Source::Loki)]
+    pub source: query::Source,
+
+    /// The namespace of the pods, with `--source k8s`.
+    #[clap(long)]
+    pub namespace: Option<String>,
+
+    /// The label selector of the pods, with `--source k8s` (ie `app=polkadot`).
+    #[clap(long)]
+    pub selector: Option<String>,
+
+    /// Only read the logs of this container, with `--source k8s`.
+    #[clap(long)]
+    pub container: Option<String>,
+
+    /// The systemd unit of the node, with `--source journald` (ie `polkadot.service`).
+    ///
+    /// Can be provided multiple times.
+    #[clap(long)]
+    pub unit: Vec<String>,
+
+    /// The URL of the cluster, with `--source elasticsearch`.
+    ///
+    /// The API key is read from the `ES_API_KEY` environment variable, if set.
+    #[clap(long, default_value = elasticsearch::DEFAULT_URL)]
+    pub es_url: String,
+
+    /// The index pattern of the logs, with `--source elasticsearch`.
+    #[clap(long, default_value = elasticsearch::DEFAULT_INDEX)]
+    pub es_index: String,
+
+    /// The field of the log line, with `--source elasticsearch`.
+    #[clap(long, default_value = "message")]
+    pub es_message_field: String,
+
+    /// The field of the log level, with `--source elasticsearch`.
+    #[clap(long, default_value = "level")]
+    pub es_level_field: String,
+
+    /// The field of the timestamp, with `--source elasticsearch`.
+    #[clap(long, default_value = "@timestamp")]
+    pub es_timestamp_field: String,
+
+    /// The field of the chain, with `--source elasticsearch`.
+    ///
+    /// The chains are not filtered if not provided.
+    #[clap(long)]
+    pub es_chain_field: Option<String>,
+
+    /// The field of the node filtered by `--node`, with `--source elasticsearch`.
+    #[clap(long)]
+    pub es_node_field: Option<String>,
+
+    /// Skip building the regexes.
+    #[clap(long)]
+    pub skip_regex_build: bool,
+
+    /// Build the regexes from a repository.
+    #[clap(long, default_value = "https://github.com/paritytech/polkadot-sdk/")]
+    pub regex_repo: Option<String>,
+
+    /// Only build the regexes from the source files of `--regex-repo` under this path prefix
+    /// (ie `substrate/client/network`).
+    ///
+    /// Can be provided multiple times. Every source file is included if not provided.
+    #[clap(long, value_name = "PREFIX")]
+    pub regex_include_path: Vec<String>,
+
+    /// Skip the source files of `--regex-repo` under this path prefix (ie `substrate/frame`).
+    ///
+    /// Can be provided multiple times, and takes precedence over `--regex-include-path`.
+    #[clap(long, value_name = "PREFIX")]
+    pub regex_exclude_path: Vec<String>,
+
+    /// The token fetching the private regex repositories, on GitHub or GitHub Enterprise.
+    ///
+    /// The repositories of GitHub Enterprise are provided with `--regex-repo` (ie
+    /// `https://ghe.example.com/org/node/`), their API is served from `/api/v3`.
+    #[clap(long, env = "GITHUB_TOKEN", hide_env_values = true)]
+    pub github_token: Option<String>,
+
+    /// Build the regexes from the branch, tag (ie `polkadot-v1.16.0`) or commit SHA.
+    ///
+    /// The report records the commit of the regexes, and the permalinks point at it,
+    /// such that the reports stay reproducible as the branch moves.
+    #[clap(long, alias = "regex-ref", default_value = "master")]
+    pub regex_branch: Option<String>,
+
+    /// The logging macros whose log lines are turned into regexes.
+    ///
+    /// Macros are also found behind a path (ie `tracing::warn!`, `gum::warn!`).
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = fetch_git::DEFAULT_LOG_MACROS
+    )]
+    pub log_macros: Vec<String>,
+
+    /// YAML file of extra regexes, for log lines of dependencies that are not fetched.
+    ///
+    /// Every entry provides a `pattern`, a `level` (`warn` or `error`) and a `label`.
+    /// The regexes are appended to the ones built from the source code, and are also
+    /// used with `--skip-regex-build`.
+    #[clap(long)]
+    pub extra_regex_file: Option<PathBuf>,
+
+    /// The triaged log levels, as `LEVEL` or `LEVEL:TARGET` (ie `INFO:sync`).
+    ///
+    /// Can be provided multiple times, defaults to `WARN` and `ERROR`.
+    #[clap(
+        long = "level",
+        value_name = "LEVEL[:TARGET]",
+        value_parser = level::parse_level,
+        default_values = ["WARN", "ERROR"]
+    )]
+    pub levels: Vec<level::LevelFilter>,
+
+    /// Provide the raw lines from the query.
+    #[clap(long)]
+    pub raw: bool,
+
+    /// Only keep and print N example lines per pattern.
+    ///
+    /// The first and last N lines are kept, with N random lines in between,
+    /// such that the memory stays bounded for patterns with many lines.
+    #[clap(long, value_name = "N", conflicts_with = "raw")]
+    pub samples: Option<usize>,
+
+    /// Only keep the first N lines per pattern with `--raw` and `--dump-dir`, the number of
+    /// dropped lines being reported. 0 keeps every line.
+    #[clap(long, value_name = "N", default_value_t = samples::DEFAULT_MAX_LINES)]
+    pub max_lines: usize,
+
+    /// The format of the printed report.
+    #[clap(long, value_enum, default_value_t)]
+    pub format: output::OutputFormat,
+
+    /// Write the report to the file instead of the standard output.
+    #[clap(long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Write the report to the directory in every format (`report.txt`, `report.md` and
+    /// `report.json`), instead of the standard output.
+    #[clap(long, value_name = "DIR", conflicts_with = "output")]
+    pub output_dir: Option<PathBuf>,
+
+    /// Render the report with the handlebars template of the file, instead of `--format`.
+    ///
+    /// The template receives the report with the fields of `--format json`.
+    #[clap(long, value_name = "FILE", conflicts_with = "output_dir")]
+    pub template: Option<PathBuf>,
+
+    /// Disable the colors of the text report.
+    ///
+    /// The report is only colored when printed to a terminal, and `NO_COLOR` is unset.
+    #[clap(long)]
+    pub no_color: bool,
+
+    /// The order of the patterns in the report.
+    #[clap(long, value_enum, default_value_t = report::SortOrder::Count)]
+    pub sort: report::SortOrder,
+
+    /// Only report the first N patterns.
+    #[clap(long)]
+    pub top: Option<usize>,
+
+    /// Only report the patterns matched at least N times.
+    #[clap(long, default_value_t = 1)]
+    pub min_count: usize,
+
+    /// Aggregate the pattern counts in an extra table (ie per log target, subsystem or
+    /// mentioned peer).
+    #[clap(long, value_enum)]
+    pub group_by: Option<report::GroupBy>,
+
+    /// YAML file mapping the source file prefixes to subsystems, extending the built-in
+    /// subsystems of `polkadot-sdk` and `litep2p`.
+    ///
+    /// The longest matching prefix wins, the prefixes of the file take precedence over the
+    /// built-in prefixes.
+    #[clap(long)]
+    pub subsystem_file: Option<PathBuf>,
+
+    /// Print the lines that could not be matched against any pattern.
+    #[clap(long)]
+    pub show_unknown: bool,
+
+    /// Write the matched lines of every pattern to its own file of the directory, instead of
+    /// printing them with `--raw`.
+    ///
+    /// The unknown lines are written to `unknown.log`, and the files of the patterns are
+    /// listed in `index.json`. With `--samples`, only the samples are written.
+    #[clap(long, value_name = "DIR")]
+    pub dump_dir: Option<PathBuf>,
+
+    /// Compress the files of `--dump-dir` with gzip.
+    #[clap(long, requires = "dump_dir")]
+    pub dump_compress: bool,
+
+    /// List the source log statements whose regexes never matched, and the statements
+    /// skipped during extraction, grouped by file.
+    ///
+    /// This finds the extraction bugs and the dead log lines.
+    #[clap(long)]
+    pub coverage_report: bool,
+
+    /// Report how long after the start of the window, or after its last restart, every node
+    /// printed its first error, and the pattern of the error.
+    ///
+    /// The restarts are detected from the version printed by the nodes at startup.
+    #[clap(long)]
+    pub time_to_first_error: bool,
+
+    /// Detect the session changes (new epochs, authority sets and eras), and flag the patterns
+    /// whose lines cluster around them.
+    ///
+    /// The changes are also marked below the `--histogram` header.
+    #[clap(long)]
+    pub session_changes: bool,
+
+    /// Cluster the unknown lines by message and print the closest patterns of the most
+    /// frequent clusters.
+    ///
+    /// This points at the log statements whose regex extraction failed.
+    #[clap(long)]
+    pub suggest_patterns: bool,
+
+    /// YAML file annotating the known issues, mapping pattern substrings to
+    /// a status (`known`, `triaged`, `fixed`), an owner and a tracking issue.
+    #[clap(long)]
+    pub known_issues: Option<PathBuf>,
+
+    /// Hide the patterns marked as `known` in the known issues file.
+    #[clap(long, requires = "known_issues")]
+    pub hide_known: bool,
+
+    /// YAML file of the accepted patterns, mapping pattern substrings to a reason, a
+    /// tracking link and an expiry date.
+    ///
+    /// The suppressed patterns are hidden from the report until their expiry date, the
+    /// expired suppressions are listed at the top of the report.
+    #[clap(long)]
+    pub suppressions: Option<PathBuf>,
+
+    /// Flag the patterns matched more than N times, listed in a needs attention
+    /// section at the top of the report.
+    #[clap(long, value_name = "N")]
+    pub highlight_threshold: Option<usize>,
+
+    /// YAML file of per-pattern thresholds, mapping pattern substrings to a threshold
+    /// overriding `--highlight-threshold`.
+    #[clap(long)]
+    pub threshold_file: Option<PathBuf>,
+
+    /// Exclude the patterns matching this regex from the report.
+    ///
+    /// Can be provided multiple times, ie for noisy but accepted patterns.
+    #[clap(long)]
+    pub ignore_pattern: Vec<String>,
+
+    /// File of regexes excluding the matching patterns, one per line.
+    ///
+    /// Empty lines and lines starting with `#` are skipped.
+    #[clap(long)]
+    pub ignore_file: Option<PathBuf>,
+
+    /// Only report the patterns matching this regex.
+    ///
+    /// Can be provided multiple times, ie when investigating a single issue.
+    #[clap(long)]
+    pub only_pattern: Vec<String>,
+
+    /// Only count the lines of the `--count-pattern` regexes with LogQL metric queries,
+    /// instead of fetching the lines.
+    ///
+    /// A cheap health check of the chains before committing to a full log download.
+    #[clap(long, requires = "count_pattern", conflicts_with_all = ["file", "file_glob", "zombienet_dir"])]
+    pub count_only: bool,
+
+    /// Repeat the triage every `--interval`, and only report the patterns that are new or
+    /// growing compared to the previous run.
+    ///
+    /// The first run reports every pattern, as a baseline of the continuous triage.
+    #[clap(long, conflicts_with = "count_only")]
+    pub watch: bool,
+
+    /// The interval of the repeated runs of `--watch` and `serve` (ie `30m`), defaults to `1h`.
+    ///
+    /// Without `--start-time` and `--last-day`, every run queries the last interval of logs.
+    #[clap(long, value_parser = histogram::parse_interval)]
+    pub interval: Option<chrono::Duration>,
+
+    /// A regex whose lines are counted by `--count-only`.
+    ///
+    /// Can be provided multiple times (ie `--count-pattern 'panicked at'`).
+    #[clap(long, requires = "count_only")]
+    pub count_pattern: Vec<String>,
+
+    /// Group the pattern counts by the version of the nodes, detected from the
+    /// version printed at startup.
+    #[clap(long)]
+    pub by_version: bool,
+
+    /// Show the occurrences of every pattern per time bucket of the provided
+    /// interval (ie `10m`, `1h`).
+    #[clap(long, value_parser = histogram::parse_interval)]
+    pub histogram: Option<chrono::Duration>,
+
+    /// Flag the patterns whose count in a time bucket exceeds N times their median
+    /// count per bucket, listing the spike windows.
+    ///
+    /// Spikes are detected over the `--histogram` interval, 5 minutes by default.
+    #[clap(long, value_name = "N")]
+    pub spike_factor: Option<f64>,
+
+    /// Attribute matched log statements to the commit that last touched them.
+    ///
+    /// Expects a local clone of the regex repository as `<repo-name>=<path>`,
+    /// for example `polkadot-sdk=../polkadot-sdk`. Can be provided multiple times.
+    #[clap(long)]
+    pub blame: Vec<String>,
+
+    /// Directory of the persistent query cache.
+    ///
+    /// Stores the result and a checksum of every fetched chunk, such that retried or
+    /// resumed runs detect chunks whose historical data was partially evicted by Loki.
+    /// The tarballs of the regex repositories are also cached, and only downloaded again
+    /// when their branch changed. Defaults to `~/.cache/sub-triage-logs`.
+    #[clap(long)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Print an intermediate report on stderr after every query chunk, with the top
+    /// patterns and the number of unknown lines so far.
+    #[clap(long)]
+    pub incremental: bool,
+
+    /// How the progress of the run is reported on stderr.
+    ///
+    /// Defaults to a progress bar when stderr is a terminal.
+    #[clap(long, value_enum)]
+    pub progress: Option<progress::ProgressMode>,
+
+    /// Record the pattern counts of the run in a history store, ie `sqlite://triage.db`.
+    ///
+    /// The evolution of a pattern across runs is printed by the `trend` command.
+    #[clap(long)]
+    pub store: Option<String>,
+
+    /// The maximum number of lines of a single Loki query.
+    ///
+    /// Queries hitting the limit are split into smaller time ranges and queried again,
+    /// such that no lines are lost.
+    #[clap(long, default_value_t = query::DEFAULT_LIMIT)]
+    pub limit: usize,
+
+    /// The maximum number of retries of a failed query.
+    ///
+    /// Only transient failures (ie timeouts, 5xx responses) are retried, with
+    /// exponential backoff.
+    #[clap(long, default_value_t = query::DEFAULT_RETRIES)]
+    pub retries: u32,
+
+    /// Reuse the cached results of complete chunks instead of querying Loki again.
+    #[clap(long)]
+    pub use_cache: bool,
+
+    /// Disable the query cache and the tarball cache.
+    #[clap(long, conflicts_with = "use_cache")]
+    pub no_cache: bool,
+
+    /// The maximum size of the query cache, in MiB.
+    ///
+    /// The oldest results are evicted when the cache grows larger, and the results fetched
+    /// more than 30 days ago regardless.
+    #[clap(long, default_value_t = cache::DEFAULT_MAX_SIZE)]
+    pub cache_max_size: u64,
+
+    /// Resume an interrupted run of the same command, skipping the chunks already
+    /// processed.
+    ///
+    /// The completed chunks and the matches so far are checkpointed after every chunk,
+    /// in the `checkpoints` directory of the cache.
+    #[clap(long, conflicts_with = "dry_run")]
+    pub resume: bool,
+
+    /// Print the queries instead of running them, without reporting.
+    ///
+    /// The output of every query is redirected to a file named after the query, such
+    /// that the outputs can be captured elsewhere and read with `--from-query-output`.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Read the outputs of the queries printed by `--dry-run` from this directory,
+    /// instead of running the queries.
+    #[clap(long, conflicts_with = "dry_run")]
+    pub from_query_output: Option<PathBuf>,
+
+    /// Push annotations of the detected panics and error spikes to this Grafana instance.
+    ///
+    /// Spikes are detected over the `--histogram` interval, 5 minutes by default, as
+    /// exceeding `--spike-factor` times the median count, 5 by default.
+    #[clap(long)]
+    pub grafana_url: Option<String>,
+
+    /// The API token of the Grafana service account creating the annotations.
+    #[clap(long, env = "GRAFANA_TOKEN", hide_env_values = true)]
+    pub grafana_token: Option<String>,
+
+    /// The UID of the dashboard of the annotations, organization wide if not provided.
+    #[clap(long)]
+    pub grafana_dashboard: Option<String>,
+
+    /// Link every pattern of the report to its lines in the Explore page of this Grafana
+    /// instance (ie `https://grafana.example.com`).
+    ///
+    /// The links query the chains, node and labels over the time range of the run.
+    #[clap(long, requires = "explore_datasource")]
+    pub explore_url: Option<String>,
+
+    /// The UID of the Loki datasource of the `--explore-url` links.
+    #[clap(long, requires = "explore_url")]
+    pub explore_datasource: Option<String>,
+}
+
+/// Report the peers banned by the peerset, grouped by ban reason and banning node.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct BansConfig {
+    #[clap(flatten)]
+    pub config: Config,
+}
+
+/// Compare the patterns of two chains over the same time window.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct CompareConfig {
+    #[clap(flatten)]
+    pub config: Config,
+}
+
+/// Report the lag of the finalized block behind the best block of every node.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct FinalityConfig {
+    #[clap(flatten)]
+    pub config: Config,
+
+    /// Flag the nodes whose finalized block lagged more than N blocks behind their best block.
+    #[clap(long, default_value_t = 20)]
+    pub max_lag: u64,
+}
+
+/// Report the dispute and approval-voting lines, aggregated per validator index.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct DisputesConfig {
+    #[clap(flatten)]
+    pub config: Config,
+
+    /// The `field=value` pairs extracted from the lines, reported with their most frequent
+    /// values.
+    ///
+    /// The validator index is always extracted, from `validator_index` or `validator`.
+    #[clap(long, value_delimiter = ',', default_value = "candidate_hash,session")]
+    pub extract_field: Vec<String>,
+}
+
+/// Triage the lines selected by an arbitrary LogQL filter.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct QueryConfig {
+    #[clap(flatten)]
+    pub config: Config,
+
+    /// The LogQL filter appended to the stream selector of the chunked queries
+    /// (ie `|~ "litep2p"`).
+    #[clap(
+        value_name = "FILTER",
+        value_parser = query::parse_filter,
+        conflicts_with_all = ["file", "file_glob", "zombienet_dir"]
+    )]
+    pub filter: String,
+}
+
+/// Run the triage on a schedule, and serve the latest report over HTTP.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct ServeConfig {
+    #[clap(flatten)]
+    pub config: Config,
+
+    /// The address of the HTTP server.
+    #[clap(long, default_value = "127.0.0.1:9616")]
+    pub listen: String,
+}
+
+/// List the chains, and the nodes and other labels of the selected chains.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct LabelsConfig {
+    #[clap(flatten)]
+    pub config: Config,
+
+    /// Only list the values of these labels (ie `node`), every label by default.
+    ///
+    /// Can be repeated or provided as a comma-separated list.
+    #[clap(long, value_delimiter = ',')]
+    pub name: Vec<String>,
+}
+
+/// Walk through the clusters of the unknown lines, and append the accepted regexes to
+/// `--extra-regex-file`.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct TriageUnknownConfig {
+    #[clap(flatten)]
+    pub config: Config,
+}
+
+/// Report the log statements added, removed or changed between two refs of the regex repo.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct RegexDiffConfig {
+    #[clap(flatten)]
+    pub config: Config,
+
+    /// The previous branch, tag (ie `polkadot-v1.15.0`) or commit of `--regex-repo`.
+    #[clap(long)]
+    pub from: String,
+
+    /// The new branch, tag (ie `polkadot-v1.16.0`) or commit of `--regex-repo`.
+    #[clap(long)]
+    pub to: String,
+}
+
+/// Report the block import failures, grouped by error kind and block.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct ImportsConfig {
+    #[clap(flatten)]
+    pub config: Config,
+}
+
+/// Report the runtime failures (ie wasm traps), grouped by error kind and block.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct RuntimeConfig {
+    #[clap(flatten)]
+    pub config: Config,
+}
+
+/// Count the panics of the node logs.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct PanicsConfig {
+    #[clap(flatten)]
+    pub config: Config,
+
+    /// Consider any line containing `panic` as a panic.
+    ///
+    /// By default, only the `panicked at` messages and backtrace markers are queried,
+    /// which avoids benign messages (ie "panic handler installed").
+    #[clap(long)]
+    pub loose: bool,
+
+    /// Report whether the node restarted within this interval after every panic (ie `5m`).
+    ///
+    /// The restarts are detected from the banner printed by the nodes at startup.
+    #[clap(long, value_parser = histogram::parse_interval)]
+    pub restart_window: Option<chrono::Duration>,
+}
+
+/// Report the restarts of the nodes, detected from the banner printed at startup.
+#[derive(Debug, clap::Parser, Clone)]
+pub struct RestartsConfig {
+    #[clap(flatten)]
+    pub config: Config,
+}
+
+impl Config {
+    /// Open the query cache, unless disabled.
+    pub(crate) fn open_cache(
+        &self,
+    ) -> Result<Option<cache::QueryCache>, Box<dyn std::error::Error>> {
+        // The printed and captured queries are not fetched.
+        if self.no_cache || self.dry_run || self.from_query_output.is_some() {
+            return Ok(None);
+        }
+
+        let Some(dir) = self.cache_dir.clone().or_else(cache::default_dir) else {
+            log::warn!("No cache directory available, the query cache is disabled");
+            return Ok(None);
+        };
+
+        Ok(Some(
+            cache::QueryCache::open(dir)?
+                .reuse(self.use_cache)
+                .max_size(self.cache_max_size.saturating_mul(1024 * 1024)),
+        ))
+    }
+
+    /// The cache of the regex repository tarballs, unless disabled.
+    pub(crate) fn tarball_cache(&self) -> Option<tarball::TarballCache> {
+        if self.no_cache {
+            return None;
+        }
+
+        let Some(dir) = self.cache_dir.clone().or_else(cache::default_dir) else {
+            log::warn!("No cache directory available, the tarballs are not cached");
+            return None;
+        };
+
+        Some(tarball::TarballCache::new(dir.join("tarballs")))
+    }
+
+    /// The checkpoint file of the run of the command, with the provided query filter.
+    ///
+    /// Only the chunked Loki and Elasticsearch queries are checkpointed, the dry runs do not
+    /// process them. The stale checkpoints of the directory are removed.
+    pub(crate) fn checkpoint_file(&self, query_filter: &str) -> Option<checkpoint::CheckpointFile> {
+        let chunked = matches!(
+            self.source,
+            query::Source::Loki | query::Source::Elasticsearch
+        );
+        if self.dry_run || !self.is_query() || !chunked {
+            return None;
+        }
+
+        let Some(dir) = self.cache_dir.clone().or_else(cache::default_dir) else {
+            log::warn!("No cache directory available, the run is not checkpointed");
+            return None;
+        };
+        let dir = dir.join("checkpoints");
+        checkpoint::CheckpointFile::prune(&dir, checkpoint::MAX_AGE);
+
+        // The options selecting the queried lines, the times being resolved when the run starts.
+        let key = format!(
+            "{:?} {} {:?}\nnodes {:?} excluded {:?}\nlabels {:?}\ntime {:?} {:?} {}\n{query_filter}",
+            self.source,
+            self.address,
+            self.chain,
+            self.node,
+            self.exclude_node,
+            self.labels,
+            self.start_time,
+            self.end_time,
+            self.last_day,
+        );
+        Some(checkpoint::CheckpointFile::new(&dir, &key))
+    }
+
+    /// The regex extracting the node from the prefix of the lines, if configured.
+    ///
+    /// The lines read from `kubectl` are attributed to their pod by default.
+    pub(crate) fn node_prefix(&self) -> Result<Option<Regex>, Box<dyn std::error::Error>> {
+        let prefix = match &self.node_prefix {
+            Some(prefix) => prefix.as_str(),
+            None if self.source == query::Source::K8s && self.is_query() => k8s::NODE_PREFIX,
+            None => return Ok(None),
+        };
+
+        let prefix = Regex::new(prefix)?;
+        if !prefix.capture_names().any(|name| name == Some("node")) {
+            return Err("The node prefix regex must contain a `node` capture group".into());
+        }
+        Ok(Some(prefix))
+    }
+
+    /// The runner of the queries.
+    pub(crate) fn query_runner(&self) -> query::QueryRunner {
+        query::QueryRunner::new()
+            .retries(self.retries)
+            .dry_run(self.dry_run)
+            .output_dir(self.from_query_output.clone())
+            .auth(self.loki_auth())
+    }
+
+    /// The credentials of the Loki instance.
+    pub(crate) fn loki_auth(&self) -> query::LokiAuth {
+        query::LokiAuth {
+            token: self.auth_token.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+        }
+    }
+
+    /// The Grafana client pushing annotations, if configured.
+    ///
+    /// The dry runs do not annotate.
+    pub(crate) fn grafana(&self) -> Option<grafana::Grafana> {
+        if self.dry_run {
+            return None;
+        }
+
+        self.grafana_url.clone().map(|url| {
+            grafana::Grafana::new(
+                url,
+                self.grafana_token.clone(),
+                self.grafana_dashboard.clone(),
+            )
+        })
+    }
+
+    /// The builder of the Grafana Explore links of the patterns, if configured.
+    pub(crate) fn explore(&self) -> Result<Option<grafana::Explore>, Box<dyn std::error::Error>> {
+        let (Some(url), Some(datasource)) = (&self.explore_url, &self.explore_datasource) else {
+            return Ok(None);
+        };
+
+        let chain = match self.chain.as_slice() {
+            [chain] => format!("chain={chain:?}"),
+            chains => format!("chain=~{:?}", chains.join("|")),
+        };
+        let labels: String = query::node_labels(&self.node, &self.exclude_node)
+            .iter()
+            .chain(&self.labels)
+            .map(|label| format!(", {label}"))
+            .collect();
+        let selector = format!("{{{chain}{labels}}}");
+
+        let (start, end) = query::time_range(&self.start_time, &self.end_time, self.last_day)?;
+        let range = (
+            query::parse_time("start time", &start)?,
+            query::parse_time("end time", &end)?,
+        );
+
+        grafana::Explore::new(url, datasource.clone(), selector, range).map(Some)
+    }
+
+    /// The command reading every line of the source, `None` for Loki and Elasticsearch
+    /// whose queries are filtered and chunked.
+    pub(crate) fn command_query(&self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        match self.source {
+            query::Source::Loki | query::Source::Elasticsearch => Ok(None),
+            query::Source::K8s => {
+                if self.end_time.is_some() {
+                    return Err(
+                        "The k8s source reads the logs until now, it does not support --end-time"
+                            .into(),
+                    );
+                }
+
+                let pod = match self.node.as_slice() {
+                    _ if !self.exclude_node.is_empty() => {
+                        return Err("The k8s source does not support --exclude-node".into())
+                    }
+                    [] => None,
+                    [pod] => Some(pod.clone()),
+                    _ => return Err("The k8s source reads a single --node pod".into()),
+                };
+
+                k8s::KubectlQuery::new()
+                    .namespace(self.namespace.clone())
+                    .selector(self.selector.clone())
+                    .pod(pod)
+                    .container(self.container.clone())
+                    .set_time(self.start_time.clone(), self.last_day)
+                    .build()
+                    .map(Some)
+            }
+            query::Source::Journald => journald::JournalctlQuery::new()
+                .units(self.unit.clone())
+                .set_time(
+                    self.start_time.clone(),
+                    self.end_time.clone(),
+                    self.last_day,
+                )
+                .build()
+                .map(Some),
+        }
+    }
+
+    /// The source of the chunked queries of the chains.
+    ///
+    /// The command sources print the lines like logcli, their output is read as is.
+    pub(crate) fn log_source(
+        &self,
+    ) -> Result<Box<dyn query::LogSource>, Box<dyn std::error::Error>> {
+        Ok(match self.source {
+            query::Source::Elasticsearch => Box::new(
+                elasticsearch::ElasticsearchSource::new(self.es_url.clone(), self.es_index.clone())
+                    .set_time(
+                        self.start_time.clone(),
+                        self.end_time.clone(),
+                        self.last_day,
+                    )
+                    .limit(self.limit)
+                    .fields(
+                        self.es_message_field.clone(),
+                        self.es_level_field.clone(),
+                        self.es_timestamp_field.clone(),
+                    )
+                    .chain_field(self.es_chain_field.clone())
+                    .node(self.es_node_field.clone(), self.node.clone())
+                    .exclude_node(self.exclude_node.clone())
+                    .excluded_lines(self.excluded_lines()?),
+            ),
+            query::Source::Loki | query::Source::K8s | query::Source::Journald => {
+                Box::new(query::LokiSource::new(self.query_builder()?))
+            }
+        })
+    }
+
+    /// The substrings of the lines excluded from the queries: the common errors, the
+    /// `--exclude-line` substrings and the lines of `--exclude-line-file`.
+    pub(crate) fn excluded_lines(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut lines: Vec<String> = query::COMMON_ERRORS
+            .iter()
+            .map(ToString::to_string)
+            .chain(self.exclude_line.iter().cloned())
+            .collect();
+
+        if let Some(path) = &self.exclude_line_file {
+            let content = std::fs::read_to_string(path).map_err(|err| {
+                format!("Cannot read exclude line file {}: {err}", path.display())
+            })?;
+            for line in content.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                lines.push(query::parse_excluded_line(line)?);
+            }
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        lines.retain(|line| seen.insert(line.clone()));
+        Ok(lines)
+    }
+
+    /// The Loki query builder of the options shared by every query.
+    pub(crate) fn query_builder(&self) -> Result<query::QueryBuilder, Box<dyn std::error::Error>> {
+        Ok(query::QueryBuilder::new()
+            .address(self.address.clone())
+            .set_time(
+                self.start_time.clone(),
+                self.end_time.clone(),
+                self.last_day,
+            )
+            .org_id(self.org_id.clone())
+            .ca_cert(self.ca_cert.clone())
+            .insecure_skip_verify(self.insecure_skip_verify)
+            .node(self.node.clone())
+            .exclude_node(self.exclude_node.clone())
+            .labels(self.labels.clone())
+            .excluded_lines(self.excluded_lines()?)
+            .limit(self.limit))
+    }
+
+    /// Check if the lines are queried (ie from Loki), rather than read from local files.
+    pub(crate) fn is_query(&self) -> bool {
+        self.file_glob.is_none()
+            && self.zombienet_dir.is_none()
+            && Self::input_file(self.file.clone()).is_none()
+    }
+
+    /// The local files of `--file-glob` or `--zombienet-dir`, processed in parallel.
+    pub(crate) fn input_files(&self) -> Result<Option<Vec<PathBuf>>, Box<dyn std::error::Error>> {
+        if let Some(pattern) = &self.file_glob {
+            return Ok(Some(input::glob_files(pattern)?));
+        }
+        if let Some(dir) = &self.zombienet_dir {
+            return Ok(Some(input::zombienet_logs(dir)?));
+        }
+
+        Ok(None)
+    }
+
+    /// Check if the text report is colored.
+    ///
+    /// The reports written to files are never colored.
+    pub(crate) fn color(&self) -> bool {
+        !self.no_color
+            && self.format == output::OutputFormat::Text
+            && self.output.is_none()
+            && self.output_dir.is_none()
+            && self.template.is_none()
+            && output::is_color_terminal()
+    }
+
+    /// The interval of the repeated runs of `--watch` and `serve`.
+    pub(crate) fn run_interval(&self) -> chrono::Duration {
+        self.interval.unwrap_or(DEFAULT_RUN_INTERVAL)
+    }
+
+    /// The options of a repeated run ending now.
+    ///
+    /// Without `--start-time` and `--last-day`, the queries cover the last interval of logs.
+    pub(crate) fn repeated_run(&self) -> Config {
+        let mut config = self.clone();
+        if config.is_query() && config.start_time.is_none() && !config.last_day {
+            let now = chrono::Utc::now();
+            let format =
+                |time: chrono::DateTime<chrono::Utc>| time.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+            config.start_time = Some(format(now - self.run_interval()));
+            // The k8s source reads the logs until now.
+            if config.source != query::Source::K8s {
+                config.end_time = Some(format(now));
+            }
+        }
+
+        config
+    }
+
+    /// The destination of the report, with its template if any.
+    pub fn report_output(&self) -> Result<output::ReportOutput, Box<dyn std::error::Error>> {
+        Ok(output::ReportOutput {
+            format: self.format,
+            template: self
+                .template
+                .as_deref()
+                .map(template::Template::open)
+                .transpose()?,
+            path: self.output.clone(),
+            dir: self.output_dir.clone(),
+        })
+    }
+
+    /// Process the lines of the local files, or of the chunked queries selecting
+    /// the lines with the provided filter (ie ``|~ `banned` ``).
+    ///
+    /// The closure receives the name of the file of the lines, if any, and returns the
+    /// number of processed and matched lines.
+    pub(crate) fn process_sources(
+        &self,
+        query_filter: &str,
+        process: &mut impl FnMut(Option<&str>, &str) -> (usize, usize),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(files) = self.input_files()? {
+            for file in files {
+                let contents = input::open(&file)?;
+                let source = file.file_name().map(|name| name.to_string_lossy());
+                process(source.as_deref(), &self.file_lines(&contents));
+            }
+            return Ok(());
+        }
+
+        if let Some(file) = Self::input_file(self.file.clone()) {
+            let bytes = input::read(&file)?;
+            process(None, &self.file_lines(&bytes));
+            return Ok(());
+        }
+
+        let source = self.log_source()?;
+        let queries = match self.command_query()? {
+            // The command output is neither filtered nor chunked, every line is processed.
+            Some(query) => vec![query],
+            None => {
+                let selection = query::Selection {
+                    // The selected lines are not necessarily warnings or errors.
+                    levels: Vec::new(),
+                    // The filter already selects the lines of the subcommand, the common
+                    // errors are only excluded from the warnings and errors.
+                    exclude_common_errors: false,
+                    filter: query_filter.to_string(),
+                };
+                let mut queries = Vec::new();
+                for chain in &self.chain {
+                    queries.extend(source.queries(chain, &selection)?);
+                }
+                queries
+            }
+        };
+
+        query::run_chunked_queries(
+            queries,
+            self.query_runner(),
+            source.as_ref(),
+            self.progress.unwrap_or_else(progress::ProgressMode::detect),
+            &mut self.open_cache()?,
+            &mut |result| process(None, result),
+        )
+    }
+
+    /// The lines of a local file, without their ANSI escape sequences unless kept.
+    fn file_lines(&self, contents: &[u8]) -> String {
+        let lines = String::from_utf8_lossy(contents);
+        if self.keep_ansi {
+            return lines.into_owned();
+        }
+
+        parse::strip_ansi(&lines).into_owned()
+    }
+
+    /// The file to process, falling back to the standard input when it is piped.
+    pub(crate) fn input_file(file: Option<String>) -> Option<String> {
+        file.or_else(|| input::stdin_is_piped().then(|| input::STDIN.to_string()))
+    }
+}
+
+impl Default for Config {
+    /// The options of the command line without arguments.
+    fn default() -> Self {
+        <Config as clap::Parser>::parse_from(["warn-err"])
+    }
+}
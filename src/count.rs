@@ -0,0 +1,150 @@
+//! Fast count of the lines of a few patterns, with LogQL metric queries.
+//!
+//! Instead of fetching the lines, Loki counts them with
+//! ``sum(count_over_time({chain="versi"} |~ `pattern` [24h]))``, giving a cheap health check
+//! of the chains before committing to a full log download.
+
+use std::fmt::Write;
+
+use serde_json::Value;
+
+use crate::{
+    level,
+    output::{escape, Render},
+    Config,
+};
+
+/// The number of lines of a pattern on a chain.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PatternCount {
+    /// The queried chain.
+    pub chain: String,
+    /// The regex of the counted lines.
+    pub pattern: String,
+    /// The number of lines matching the regex.
+    pub count: usize,
+}
+
+/// The report of the counted patterns.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CountReport {
+    /// The counts of every pattern per chain, in the order of the options.
+    pub patterns: Vec<PatternCount>,
+}
+
+impl Render for CountReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "{0: <25} | {1: <10} | Pattern", "Chain", "Count")?;
+        for pattern in &self.patterns {
+            writeln!(
+                out,
+                "{0: <25} | {1: <10} | {2}",
+                pattern.chain, pattern.count, pattern.pattern
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "| Chain | Count | Pattern |")?;
+        writeln!(out, "|---|---|---|")?;
+        for pattern in &self.patterns {
+            writeln!(
+                out,
+                "| {} | {} | `{}` |",
+                pattern.chain,
+                pattern.count,
+                escape(&pattern.pattern)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse the output of `logcli instant-query`, summing the values of the vector.
+///
+/// The queries of the dry runs have no output, counted as 0 lines.
+fn parse_count(output: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
+    if output.iter().all(u8::is_ascii_whitespace) {
+        return Ok(0);
+    }
+
+    let samples: Value = serde_json::from_slice(output)?;
+    let samples = samples
+        .as_array()
+        .ok_or("Unexpected metric query output, expected a vector")?;
+
+    let mut count = 0.0;
+    for sample in samples {
+        count += sample["value"][1]
+            .as_str()
+            .and_then(|value| value.parse::<f64>().ok())
+            .ok_or_else(|| format!("Unexpected metric query sample: {sample}"))?;
+    }
+
+    Ok(count.round() as usize)
+}
+
+/// Count the lines of the `--count-pattern` regexes on every chain.
+pub fn run(opts: &Config) -> Result<CountReport, Box<dyn std::error::Error>> {
+    log::info!("Running count query");
+    if opts.source != crate::query::Source::Loki {
+        return Err("The count-only mode requires the Loki source".into());
+    }
+
+    let runner = opts.query_runner();
+    let levels = level::Levels::new(opts.levels.clone()).query_levels();
+    let mut report = CountReport::default();
+    for chain in &opts.chain {
+        let builder = opts
+            .query_builder()?
+            .chain(chain.clone())
+            .levels(levels.clone());
+
+        for pattern in &opts.count_pattern {
+            let output = runner.run(&builder.build_count(pattern)?)?;
+            report.patterns.push(PatternCount {
+                chain: chain.clone(),
+                pattern: pattern.clone(),
+                count: parse_count(&output)?,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let query = crate::query::QueryBuilder::new()
+            .chain("versi".to_string())
+            .levels(vec!["ERROR".to_string()])
+            .set_time(
+                Some("2024-03-29T16:00:00Z".to_string()),
+                Some("2024-03-30T16:00:00Z".to_string()),
+                false,
+            )
+            .build_count("panicked at 'oops'")
+            .unwrap();
+        assert!(query.starts_with(
+            r#"logcli instant-query --addr=127.0.0.1:10700 --now="2024-03-30T16:00:00Z" 'sum(count_over_time({chain="versi" , level=~"ERROR" }"#
+        ));
+        assert!(query.contains(r#"|~ `panicked at '\''oops'\''` [86400s]))'"#));
+        assert!(matches!(
+            crate::query::QueryBuilder::new().build_count("`quoted`"),
+            Err(crate::query::QueryError::InvalidCountPattern(_))
+        ));
+
+        let output = br#"[{"metric":{},"value":[1711814400,"1234"]},{"metric":{},"value":[1711814400,"6"]}]"#;
+        assert_eq!(parse_count(output).unwrap(), 1240);
+        assert_eq!(parse_count(b"[]").unwrap(), 0);
+        assert_eq!(parse_count(b"").unwrap(), 0);
+        assert!(parse_count(b"{}").is_err());
+    }
+}
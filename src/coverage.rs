@@ -0,0 +1,177 @@
+//! Coverage of the source log statements by the processed lines.
+//!
+//! The log statements whose regexes never matched are either dead log lines, or hint at
+//! extraction bugs, together with the statements skipped during extraction.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    fetch_git::{RegexDetails, SkippedLog},
+    matcher::Matches,
+};
+
+/// A log statement whose regex never matched.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UnmatchedLog {
+    /// The line of the log statement in the file.
+    pub line: usize,
+    /// The level of the log statement.
+    pub level: String,
+    /// The regex of the log statement.
+    pub pattern: String,
+    /// Link to the log statement in the source repository.
+    pub permalink: String,
+}
+
+/// The uncovered log statements of a single source file.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileCoverage {
+    /// The repository of the source file.
+    pub repo: String,
+    /// The source file.
+    pub file: String,
+    /// The log statements whose regexes never matched.
+    pub unmatched: Vec<UnmatchedLog>,
+    /// The log statements skipped during extraction.
+    pub skipped: Vec<SkippedLog>,
+}
+
+/// The coverage of the log statements, provided with `--coverage-report`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CoverageReport {
+    /// The number of regexes.
+    pub regexes: usize,
+    /// The number of regexes matching at least one line.
+    pub matched: usize,
+    /// The number of log statements skipped during extraction.
+    pub skipped: usize,
+    /// The uncovered log statements, grouped by source file.
+    pub files: Vec<FileCoverage>,
+}
+
+impl CoverageReport {
+    /// The percentage of regexes matching at least one line.
+    pub fn matched_percent(&self) -> f64 {
+        if self.regexes == 0 {
+            return 0.0;
+        }
+        self.matched as f64 / self.regexes as f64 * 100.0
+    }
+}
+
+/// The log statements of the run, for the coverage report.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    /// The regexes of the log statements.
+    regexes: Vec<(String, RegexDetails)>,
+    /// The log statements skipped during extraction.
+    skipped: Vec<SkippedLog>,
+}
+
+impl Coverage {
+    /// Construct the coverage of the provided regexes and skipped statements.
+    pub fn new(regexes: &[(regex::Regex, RegexDetails)], skipped: Vec<SkippedLog>) -> Self {
+        let regexes = regexes
+            .iter()
+            .map(|(regex, details)| (regex.as_str().to_string(), details.clone()))
+            .collect();
+
+        Coverage { regexes, skipped }
+    }
+
+    /// Report the log statements not covered by the matched lines, grouped by file.
+    pub fn report(&self, matches: &Matches) -> CoverageReport {
+        let matched: HashSet<&RegexDetails> = matches
+            .found_lines
+            .keys()
+            .map(|(_, details)| details)
+            .collect();
+
+        let mut files: BTreeMap<(&str, &str), FileCoverage> = BTreeMap::new();
+        let new = |repo: &str, file: &str| FileCoverage {
+            repo: repo.to_string(),
+            file: file.to_string(),
+            unmatched: Vec::new(),
+            skipped: Vec::new(),
+        };
+        for (pattern, details) in &self.regexes {
+            if matched.contains(details) {
+                continue;
+            }
+            files
+                .entry((&details.repo, &details.file))
+                .or_insert_with(|| new(&details.repo, &details.file))
+                .unmatched
+                .push(UnmatchedLog {
+                    line: details.line,
+                    level: details.ty.clone(),
+                    pattern: pattern.clone(),
+                    permalink: details.permalink.clone(),
+                });
+        }
+        for skipped in &self.skipped {
+            files
+                .entry((&skipped.repo, &skipped.file))
+                .or_insert_with(|| new(&skipped.repo, &skipped.file))
+                .skipped
+                .push(skipped.clone());
+        }
+
+        let unmatched: usize = files.values().map(|file| file.unmatched.len()).sum();
+        CoverageReport {
+            regexes: self.regexes.len(),
+            matched: self.regexes.len() - unmatched,
+            skipped: self.skipped.len(),
+            files: files.into_values().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::Matcher;
+
+    #[test]
+    fn test_coverage() {
+        let details = |file: &str, line| RegexDetails {
+            repo: "test".to_string(),
+            file: file.to_string(),
+            start: 0,
+            end: 0,
+            line,
+            column: 1,
+            permalink: String::new(),
+            description: None,
+            target: None,
+            owners: Vec::new(),
+            ty: "warn".to_string(),
+        };
+        let regexes = vec![
+            (regex::Regex::new("Slow").unwrap(), details("a.rs", 1)),
+            (regex::Regex::new("Dead").unwrap(), details("a.rs", 2)),
+            (regex::Regex::new("Failed").unwrap(), details("b.rs", 1)),
+        ];
+        let skipped = vec![SkippedLog {
+            repo: "test".to_string(),
+            file: "c.rs".to_string(),
+            line: 3,
+            permalink: String::new(),
+            reason: "empty message".to_string(),
+        }];
+        let coverage = Coverage::new(&regexes, skipped);
+        let mut matches = Matches::default();
+        Matcher::new(regexes, Vec::new())
+            .process_lines(["Slow", "Failed"].into_iter(), &mut matches);
+
+        let report = coverage.report(&matches);
+        assert_eq!((report.regexes, report.matched, report.skipped), (3, 2, 1));
+        let files: Vec<_> = report
+            .files
+            .iter()
+            .map(|file| (file.file.as_str(), file.unmatched.len(), file.skipped.len()))
+            .collect();
+        assert_eq!(files, vec![("a.rs", 1, 0), ("c.rs", 0, 1)]);
+        assert_eq!(report.files[0].unmatched[0].pattern, "Dead");
+    }
+}
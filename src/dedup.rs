@@ -0,0 +1,168 @@
+//! Automatic deduplication keys extracted from matched log lines, and detection
+//! of the lines returned twice by the queries.
+
+use std::{
+    collections::HashSet,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+/// Returns the identifier starting at the beginning of the provided string.
+///
+/// Identifiers may contain paths (ie `Error::Timeout`).
+fn leading_ident(s: &str) -> &str {
+    let end = s
+        .char_indices()
+        .find(|(_, c)| !(c.is_alphanumeric() || *c == '_' || *c == ':'))
+        .map(|(index, _)| index)
+        .unwrap_or(s.len());
+
+    s[..end].trim_end_matches(':')
+}
+
+/// Check if the identifier looks like an enum variant (ie `Timeout`, `Error::Timeout`).
+fn is_variant(ident: &str) -> bool {
+    ident
+        .rsplit("::")
+        .next()
+        .and_then(|last| last.chars().next())
+        .map(|c| c.is_uppercase())
+        .unwrap_or_default()
+}
+
+/// Extract the innermost enum variant of a debug-printed error.
+///
+/// For example, `Err(Network(Timeout))` produces `Timeout`, while
+/// `Error::Io(Kind(ConnectionReset))` produces `ConnectionReset`.
+///
+/// The extraction is best-effort: only chains starting with `Err(` or with an
+/// identifier containing `Error` are considered, to avoid picking up unrelated
+/// debug-printed values such as `Some(PeerId(..))`.
+pub fn extract_error_variant(line: &str) -> Option<String> {
+    let mut search = line;
+
+    while let Some(paren) = search.find('(') {
+        let before = &search[..paren];
+        let ident_start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        let outer = &before[ident_start..];
+
+        if outer == "Err" || (outer.contains("Error") && is_variant(outer)) {
+            let mut innermost = None;
+            let mut rest = &search[paren + 1..];
+
+            loop {
+                let ident = leading_ident(rest.trim_start());
+                if ident.is_empty() || !is_variant(ident) {
+                    break;
+                }
+                innermost = Some(ident);
+
+                rest = &rest.trim_start()[ident.len()..];
+                match rest.strip_prefix('(') {
+                    Some(next) => rest = next,
+                    None => break,
+                }
+            }
+
+            if let Some(innermost) = innermost {
+                return Some(innermost.to_string());
+            }
+        }
+
+        search = &search[paren + 1..];
+    }
+
+    None
+}
+
+/// Fingerprints of the processed lines.
+///
+/// Chunked queries can return the same line twice around their boundaries (and so
+/// can retried queries). Lines are identified by their timestamp, node and content,
+/// only a hash is kept per line.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct LineFingerprints {
+    seen: HashSet<u64>,
+    /// The fingerprints recorded since the last [`LineFingerprints::take_recent`].
+    #[serde(skip)]
+    recent: Vec<u64>,
+}
+
+impl LineFingerprints {
+    /// Record the line, returns `false` if the line was already recorded.
+    pub fn insert(
+        &mut self,
+        timestamp: chrono::NaiveDateTime,
+        node: Option<&str>,
+        content: &str,
+    ) -> bool {
+        let mut hasher = DefaultHasher::new();
+        (timestamp, node, content).hash(&mut hasher);
+        let fingerprint = hasher.finish();
+        let inserted = self.seen.insert(fingerprint);
+        if inserted {
+            self.recent.push(fingerprint);
+        }
+        inserted
+    }
+
+    /// The position of the fingerprints recorded so far, to roll back to.
+    pub fn mark(&self) -> usize {
+        self.recent.len()
+    }
+
+    /// Forget the fingerprints recorded since the mark, ie by a discarded attempt.
+    pub fn rollback(&mut self, mark: usize) {
+        for fingerprint in self.recent.drain(mark.min(self.recent.len())..) {
+            self.seen.remove(&fingerprint);
+        }
+    }
+
+    /// Take the fingerprints recorded since the last call, ie by the last chunk.
+    pub fn take_recent(&mut self) -> LineFingerprints {
+        LineFingerprints {
+            seen: std::mem::take(&mut self.recent).into_iter().collect(),
+            recent: Vec::new(),
+        }
+    }
+
+    /// Merge the fingerprints of another run into this one.
+    pub fn extend(&mut self, other: LineFingerprints) {
+        self.seen.extend(other.seen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_variants() {
+        let cases = [
+            ("Failed to dial: Err(Network(Timeout))", Some("Timeout")),
+            ("Error: Err(Timeout)", Some("Timeout")),
+            (
+                "Request failed: Error::Io(Kind(ConnectionReset))",
+                Some("ConnectionReset"),
+            ),
+            (
+                "Import failed: Err(ClientImport(\"Unknown block\"))",
+                Some("ClientImport"),
+            ),
+            ("NetworkError(Refused { peer: 1 })", Some("Refused")),
+            ("Peer Some(PeerId(\"12D3KooW\")) disconnected", None),
+            ("Banned, disconnecting. Reason: too many requests", None),
+            ("Err(\"string error\")", None),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(
+                extract_error_variant(line).as_deref(),
+                expected,
+                "line: {line}"
+            );
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! Analysis of the dispute and approval-voting lines, aggregated per validator index.
+//!
+//! The parachain subsystems print the structured fields of their events after the message,
+//! ie `parachain::dispute-coordinator: Dispute concluded against candidate
+//! candidate_hash=0x5e7d…a1f2 session=42 validator_index=ValidatorIndex(12)`. The fields are
+//! extracted from every line, such that misbehaving validators are identified from the logs.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    collector::{self, Collector},
+    output::{breakdown, escape, Render},
+    parse::LogLine,
+    DisputesConfig,
+};
+
+/// Loki filter selecting the lines of the dispute and approval-voting subsystems.
+const QUERY_FILTER: &str = "|~ `parachain::(dispute|approval)`";
+
+/// The targets of the dispute and approval-voting subsystems.
+const TARGETS: [&str; 2] = ["parachain::dispute", "parachain::approval"];
+
+/// The fields of the validator index, in order of precedence.
+const VALIDATOR_FIELDS: [&str; 2] = ["validator_index", "validator"];
+
+/// The field of the candidate hash.
+const CANDIDATE_FIELD: &str = "candidate_hash";
+
+/// The field of the session index.
+const SESSION_FIELD: &str = "session";
+
+/// The maximum number of values reported per extracted field.
+const MAX_VALUES: usize = 20;
+
+/// Extract the value of a `field=value` pair of the line.
+///
+/// The value ends at the first whitespace or comma, such that debug-printed values
+/// (ie `ValidatorIndex(12)`) are kept whole.
+pub fn extract_field<'a>(line: &'a str, field: &str) -> Option<&'a str> {
+    let pattern = format!("{field}=");
+    line.match_indices(&pattern)
+        .find(|(index, _)| {
+            line[..*index]
+                .chars()
+                .next_back()
+                .is_none_or(|ch| !ch.is_alphanumeric() && ch != '_')
+        })
+        .map(|(index, _)| {
+            let value = &line[index + pattern.len()..];
+            let end = value
+                .find(|ch: char| ch.is_whitespace() || ch == ',' || ch == '}')
+                .unwrap_or(value.len());
+            value[..end].trim_matches('"')
+        })
+        .filter(|value| !value.is_empty())
+}
+
+/// Parse the validator index of a field value (ie `12` or `ValidatorIndex(12)`).
+pub fn parse_validator_index(value: &str) -> Option<u32> {
+    let value = value
+        .strip_prefix("ValidatorIndex(")
+        .and_then(|value| value.strip_suffix(')'))
+        .unwrap_or(value);
+    value.parse().ok()
+}
+
+/// The lines reporting a single validator index.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ValidatorReport {
+    /// The index of the validator in its session.
+    pub index: u32,
+    /// The number of lines.
+    pub count: usize,
+    /// The number of distinct candidates of the lines.
+    pub candidates: usize,
+    /// The sessions of the lines.
+    pub sessions: BTreeSet<String>,
+    /// Number of lines per reporting node.
+    pub nodes: BTreeMap<String, usize>,
+}
+
+/// The most frequent values of an extracted field.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FieldReport {
+    /// The name of the field.
+    pub field: String,
+    /// The number of lines with the field.
+    pub count: usize,
+    /// The number of distinct values.
+    pub distinct: usize,
+    /// The most frequent values with their number of lines.
+    pub values: Vec<(String, usize)>,
+}
+
+/// The report of the dispute and approval-voting lines.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct DisputesReport {
+    /// The number of lines of the subsystems.
+    pub total: usize,
+    /// The lines per validator index, the most reported validators first.
+    pub validators: Vec<ValidatorReport>,
+    /// The values of the extracted fields.
+    pub fields: Vec<FieldReport>,
+}
+
+impl Render for DisputesReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Dispute and approval lines: {} reporting {} validators",
+            self.total,
+            self.validators.len()
+        )?;
+
+        if !self.validators.is_empty() {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{0: <10} | {1: <10} | {2: <10} | {3: <20} | Nodes",
+                "Validator", "Count", "Candidates", "Sessions"
+            )?;
+            for validator in &self.validators {
+                writeln!(
+                    out,
+                    "{0: <10} | {1: <10} | {2: <10} | {3: <20} | {4}",
+                    validator.index,
+                    validator.count,
+                    validator.candidates,
+                    sessions(validator),
+                    breakdown(&validator.nodes)
+                )?;
+            }
+        }
+
+        for field in &self.fields {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "Field {}: {} lines, {} distinct values",
+                field.field, field.count, field.distinct
+            )?;
+            for (value, count) in &field.values {
+                writeln!(out, "{0: <10} | {1}", count, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Dispute and approval lines: {} reporting {} validators",
+            self.total,
+            self.validators.len()
+        )?;
+
+        if !self.validators.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "| Validator | Count | Candidates | Sessions | Nodes |")?;
+            writeln!(out, "|---|---|---|---|---|")?;
+            for validator in &self.validators {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} |",
+                    validator.index,
+                    validator.count,
+                    validator.candidates,
+                    sessions(validator),
+                    breakdown(&validator.nodes)
+                )?;
+            }
+        }
+
+        for field in &self.fields {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "Field `{}`: {} lines, {} distinct values",
+                field.field, field.count, field.distinct
+            )?;
+            writeln!(out)?;
+            writeln!(out, "| Count | Value |")?;
+            writeln!(out, "|---|---|")?;
+            for (value, count) in &field.values {
+                writeln!(out, "| {} | `{}` |", count, escape(value))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The sessions of the validator (ie `41, 42`).
+fn sessions(validator: &ValidatorReport) -> String {
+    if validator.sessions.is_empty() {
+        return "-".to_string();
+    }
+
+    validator
+        .sessions
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The lines of a validator, with the distinct candidates.
+#[derive(Default)]
+struct Validator {
+    count: usize,
+    candidates: BTreeSet<String>,
+    sessions: BTreeSet<String>,
+    nodes: BTreeMap<String, usize>,
+}
+
+/// Collects the fields of the processed lines.
+struct Disputes {
+    /// The extracted fields, besides the validator index.
+    fields: Vec<String>,
+    total: usize,
+    validators: BTreeMap<u32, Validator>,
+    /// Field -> value -> lines.
+    values: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl Disputes {
+    fn new(fields: Vec<String>) -> Self {
+        Disputes {
+            fields,
+            total: 0,
+            validators: BTreeMap::new(),
+            values: BTreeMap::new(),
+        }
+    }
+}
+
+impl Collector for Disputes {
+    type Item<'a> = &'a str;
+    type Report = DisputesReport;
+
+    const QUERY_FILTER: &'static str = QUERY_FILTER;
+
+    fn select<'a>(&self, line: &'a LogLine) -> Option<&'a str> {
+        let target = line.target.as_deref().unwrap_or(&line.message);
+        TARGETS
+            .iter()
+            .any(|prefix| target.contains(prefix))
+            .then_some(&*line.message)
+    }
+
+    fn record(&mut self, node: Option<&str>, _: Option<chrono::NaiveDateTime>, message: &str) {
+        self.total += 1;
+
+        for field in &self.fields {
+            if let Some(value) = extract_field(message, field) {
+                *self
+                    .values
+                    .entry(field.clone())
+                    .or_default()
+                    .entry(value.to_string())
+                    .or_default() += 1;
+            }
+        }
+
+        let Some(index) = VALIDATOR_FIELDS
+            .iter()
+            .find_map(|field| extract_field(message, field).and_then(parse_validator_index))
+        else {
+            return;
+        };
+        let validator = self.validators.entry(index).or_default();
+        validator.count += 1;
+        if let Some(candidate) = extract_field(message, CANDIDATE_FIELD) {
+            validator.candidates.insert(candidate.to_string());
+        }
+        if let Some(session) = extract_field(message, SESSION_FIELD) {
+            validator.sessions.insert(session.to_string());
+        }
+        if let Some(node) = node {
+            *validator.nodes.entry(node.to_string()).or_default() += 1;
+        }
+    }
+
+    fn report(self) -> DisputesReport {
+        let mut validators: Vec<_> = self
+            .validators
+            .into_iter()
+            .map(|(index, validator)| ValidatorReport {
+                index,
+                count: validator.count,
+                candidates: validator.candidates.len(),
+                sessions: validator.sessions,
+                nodes: validator.nodes,
+            })
+            .collect();
+        validators.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.index.cmp(&b.index)));
+
+        // The fields are reported in the provided order.
+        let mut values = self.values;
+        let fields = self
+            .fields
+            .iter()
+            .filter_map(|field| {
+                let counts = values.remove(field)?;
+                let count = counts.values().sum();
+                let distinct = counts.len();
+                let mut values: Vec<_> = counts.into_iter().collect();
+                values.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                values.truncate(MAX_VALUES);
+
+                Some(FieldReport {
+                    field: field.clone(),
+                    count,
+                    distinct,
+                    values,
+                })
+            })
+            .collect();
+
+        DisputesReport {
+            total: self.total,
+            validators,
+            fields,
+        }
+    }
+}
+
+/// Report the dispute and approval-voting lines of the provided files or Loki query.
+pub fn run(opts: DisputesConfig) -> Result<DisputesReport, Box<dyn std::error::Error>> {
+    log::info!("Running disputes query");
+    collector::run(&opts.config, Disputes::new(opts.extract_field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::tests::collect;
+
+    #[test]
+    fn test_disputes() {
+        let line = "parachain::dispute-coordinator: Vote imported candidate_hash=0x5e7d…a1f2 session=42 validator_index=ValidatorIndex(12)";
+        assert_eq!(extract_field(line, "session"), Some("42"));
+        assert_eq!(
+            extract_field(line, "validator_index").and_then(parse_validator_index),
+            Some(12)
+        );
+        // The field names are not matched inside other names.
+        assert_eq!(extract_field("sub_session=3", "session"), None);
+
+        let lines = "\
+2024-03-29 16:00:01.000  WARN tokio-runtime-worker parachain::dispute-coordinator: Invalid vote candidate_hash=0xaaaa…1111 session=42 validator_index=ValidatorIndex(12)
+2024-03-29 16:00:02.000  WARN tokio-runtime-worker parachain::dispute-coordinator: Invalid vote candidate_hash=0xbbbb…2222 session=42 validator_index=ValidatorIndex(12)
+2024-03-29 16:00:03.000  WARN tokio-runtime-worker parachain::approval-voting: No assignment validator_index=3 candidate_hash=0xaaaa…1111 session=42
+2024-03-29 16:00:04.000  WARN tokio-runtime-worker sync: Block import failed";
+        let report = collect(
+            Disputes::new(vec![CANDIDATE_FIELD.to_string(), SESSION_FIELD.to_string()]),
+            &[("alice", lines)],
+        );
+        assert_eq!(report.total, 3);
+        let validators: Vec<_> = report
+            .validators
+            .iter()
+            .map(|validator| (validator.index, validator.count, validator.candidates))
+            .collect();
+        assert_eq!(validators, [(12, 2, 2), (3, 1, 1)]);
+        assert_eq!(report.fields[0].field, CANDIDATE_FIELD);
+        assert_eq!(report.fields[0].distinct, 2);
+        assert_eq!(report.fields[0].values[0], ("0xaaaa…1111".to_string(), 2));
+        assert_eq!(report.fields[1].values, [("42".to_string(), 3)]);
+    }
+}
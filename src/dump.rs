@@ -0,0 +1,213 @@
+//! Export of the matched lines to one file per pattern.
+//!
+//! The raw lines of every pattern printed by `--raw` drown the report of large runs. With
+//! `--dump-dir`, the lines of every pattern are written to their own file instead, with the
+//! unknown lines in `unknown.log` and an `index.json` listing the files, such that engineers
+//! download just the evidence of their pattern.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{cache::QueryCache, matcher::Matches};
+
+/// The maximum length of the pattern part of the file names.
+const MAX_NAME_LEN: usize = 60;
+
+/// The name of the file of the unknown lines.
+const UNKNOWN_NAME: &str = "unknown";
+
+/// The file listing the pattern files.
+const INDEX_FILE: &str = "index.json";
+
+/// A file of the lines of a pattern.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DumpedPattern {
+    /// The name of the file in the dump directory.
+    pub file: String,
+    /// The pattern.
+    pub pattern: String,
+    /// The level of the log statement (ie `warn`, `error`).
+    pub level: String,
+    /// The number of written lines.
+    pub lines: usize,
+    /// Link to the log statement in the source repository.
+    pub permalink: String,
+}
+
+/// Writes the matched lines to the files of a directory.
+#[derive(Debug, Clone)]
+pub struct Dump {
+    /// The directory of the files.
+    dir: PathBuf,
+    /// Compress the files with gzip.
+    compress: bool,
+}
+
+impl Dump {
+    /// Construct the dump of the provided directory.
+    pub fn new(dir: PathBuf, compress: bool) -> Self {
+        Dump { dir, compress }
+    }
+
+    /// The file name of the pattern (ie `warn-failed-to-dial-peer-1a2b3c4d.log`).
+    ///
+    /// The hash of the pattern keeps the names of similar patterns apart.
+    fn file_name(&self, level: &str, pattern: &str) -> String {
+        let mut slug = String::new();
+        for ch in pattern.chars().flat_map(char::to_lowercase) {
+            if ch.is_ascii_alphanumeric() {
+                slug.push(ch);
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+            if slug.len() >= MAX_NAME_LEN {
+                break;
+            }
+        }
+        let slug = slug.trim_end_matches('-');
+        let hash = QueryCache::key(pattern);
+
+        self.with_extension(format!("{level}-{slug}-{}", &hash[..8]))
+    }
+
+    fn with_extension(&self, name: String) -> String {
+        if self.compress {
+            format!("{name}.log.gz")
+        } else {
+            format!("{name}.log")
+        }
+    }
+
+    /// Write the lines to the file.
+    fn write_lines(&self, path: &Path, lines: &[String]) -> std::io::Result<()> {
+        fn write_all(writer: &mut impl Write, lines: &[String]) -> std::io::Result<()> {
+            for line in lines {
+                writeln!(writer, "{line}")?;
+            }
+            Ok(())
+        }
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        if self.compress {
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            write_all(&mut encoder, lines)?;
+            encoder.finish()?.flush()
+        } else {
+            write_all(&mut file, lines)?;
+            file.flush()
+        }
+    }
+
+    /// Write the lines of every pattern and the unknown lines, and return the written files.
+    ///
+    /// With `--samples`, only the samples of the patterns are kept and written.
+    pub fn write(
+        &self,
+        matches: &Matches,
+    ) -> Result<Vec<DumpedPattern>, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.dir).map_err(|err| {
+            format!(
+                "Cannot create the dump directory {}: {err}",
+                self.dir.display()
+            )
+        })?;
+
+        let mut patterns = Vec::new();
+        for ((pattern, details), entry) in &matches.found_lines {
+            let lines = entry.lines.lines();
+            if lines.is_empty() {
+                continue;
+            }
+
+            let file = self.file_name(&details.ty, pattern);
+            self.write_lines(&self.dir.join(&file), &lines)?;
+            patterns.push(DumpedPattern {
+                file,
+                pattern: pattern.clone(),
+                level: details.ty.clone(),
+                lines: lines.len(),
+                permalink: details.permalink.clone(),
+            });
+        }
+        patterns.sort_by(|a, b| b.lines.cmp(&a.lines).then_with(|| a.file.cmp(&b.file)));
+
+        let unknown = self.with_extension(UNKNOWN_NAME.to_string());
+        self.write_lines(&self.dir.join(unknown), &matches.unknown_lines)?;
+        std::fs::write(
+            self.dir.join(INDEX_FILE),
+            serde_json::to_vec_pretty(&patterns)?,
+        )?;
+        log::info!(
+            "Dumped the lines of {} patterns and {} unknown lines to {}",
+            patterns.len(),
+            matches.unknown_lines.len(),
+            self.dir.display()
+        );
+
+        Ok(patterns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fetch_git::RegexDetails, matcher::Matcher};
+
+    #[test]
+    fn test_dump() {
+        let details = RegexDetails {
+            repo: "test".to_string(),
+            file: "test.rs".to_string(),
+            start: 0,
+            end: 0,
+            line: 1,
+            column: 1,
+            permalink: "https://github.com/test/blob/master/test.rs#L1".to_string(),
+            description: None,
+            target: None,
+            owners: Vec::new(),
+            ty: "warn".to_string(),
+        };
+        let matcher = Matcher::new(
+            vec![(
+                regex::Regex::new("Failed to dial peer .*").unwrap(),
+                details,
+            )],
+            Vec::new(),
+        )
+        .with_lines(true, true);
+        let mut matches = Matches::default();
+        let lines = [
+            "2024-03-29 10:00:00.000  WARN main test: Failed to dial peer alice",
+            "2024-03-29 10:00:01.000  WARN main test: Something else",
+            "2024-03-29 10:00:02.000  WARN main test: Failed to dial peer bob",
+        ];
+        matcher.process_source(None, lines.into_iter(), &mut matches);
+
+        let dir = std::env::temp_dir().join(format!("sub-triage-logs-dump-{}", std::process::id()));
+        let patterns = Dump::new(dir.clone(), false).write(&matches).unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert!(patterns[0].file.starts_with("warn-failed-to-dial-peer-"));
+        assert_eq!(
+            std::fs::read_to_string(dir.join(&patterns[0].file)).unwrap(),
+            format!("{}\n{}\n", lines[0], lines[2])
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("unknown.log")).unwrap(),
+            format!("{}\n", lines[1])
+        );
+
+        let patterns = Dump::new(dir.clone(), true).write(&matches).unwrap();
+        let compressed = std::fs::read(dir.join(&patterns[0].file)).unwrap();
+        assert!(patterns[0].file.ends_with(".log.gz"));
+        assert_eq!(
+            crate::input::Compression::Gzip
+                .decompress(&compressed)
+                .unwrap(),
+            format!("{}\n{}\n", lines[0], lines[2]).into_bytes()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
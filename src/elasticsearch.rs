@@ -0,0 +1,641 @@
+//! Elasticsearch (or OpenSearch) logs, for the networks shipping their logs to an ES cluster.
+//!
+//! The indices are searched with `curl` against the `_search` API, the message field of
+//! every hit holding a line as written by the node (ie `2024-03-29 16:00:00.000  WARN ...`).
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::query::{self, shell_quote, LogSource, QueryError, QueryRunner, Selection};
+
+/// Default URL of the Elasticsearch cluster.
+pub const DEFAULT_URL: &str = "http://127.0.0.1:9200";
+/// Default index pattern of the logs.
+pub const DEFAULT_INDEX: &str = "logs-*";
+/// The environment variable of the API key, read by the shell running the query such
+/// that the key is neither logged nor part of the cache keys.
+const API_KEY_VAR: &str = "ES_API_KEY";
+/// The default `index.max_result_window` of the indices, the maximum size of a search.
+const MAX_RESULT_WINDOW: usize = 10_000;
+/// The time range of a search, as serialized in its body.
+const SEARCH_RANGE: &str = r#""gte":"([^"]+)","lt":"([^"]+)""#;
+/// The words of the line filters shorter than this are not searched, matching most lines.
+const MIN_WORD_LEN: usize = 3;
+
+/// Searches the logs of an Elasticsearch cluster.
+///
+/// The levels, the time range and the common errors are filtered by the cluster. The
+/// `|=` and `|~` LogQL line filters are translated to the words the lines must contain,
+/// such that the cluster returns a superset of the lines matched by the callers.
+///
+/// The searches returning [`MAX_RESULT_WINDOW`] hits are split in two halves of their
+/// time range until every line is returned, failing below a second.
+#[derive(Debug, Clone)]
+pub struct ElasticsearchSource {
+    url: String,
+    index: String,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    last_day: bool,
+    limit: usize,
+    message_field: String,
+    level_field: String,
+    timestamp_field: String,
+    chain_field: Option<String>,
+    node_field: Option<String>,
+    nodes: Vec<String>,
+    excluded_nodes: Vec<String>,
+    excluded_lines: Vec<String>,
+}
+
+impl ElasticsearchSource {
+    /// Create a new source searching the indices of the pattern (ie `logs-*`).
+    pub fn new(url: String, index: String) -> Self {
+        ElasticsearchSource {
+            url,
+            index,
+            start_time: None,
+            end_time: None,
+            last_day: false,
+            limit: query::DEFAULT_LIMIT,
+            message_field: "message".to_string(),
+            level_field: "level".to_string(),
+            timestamp_field: "@timestamp".to_string(),
+            chain_field: None,
+            node_field: None,
+            nodes: Vec::new(),
+            excluded_nodes: Vec::new(),
+            excluded_lines: query::COMMON_ERRORS
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+
+    /// Set the start and end times of the search.
+    ///
+    /// The format is "YYYY-MM-DDTHH:MM:SSZ".
+    ///
+    /// Default: 1 hour before the current time.
+    pub fn set_time(
+        mut self,
+        start_time: Option<String>,
+        end_time: Option<String>,
+        last_day: bool,
+    ) -> Self {
+        self.start_time = start_time;
+        self.end_time = end_time;
+        self.last_day = last_day;
+        self
+    }
+
+    /// Set the maximum number of hits of a search, capped by the default
+    /// `index.max_result_window` of 10000 hits.
+    ///
+    /// Default: 100000.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Set the fields of the line, of its level and of its timestamp.
+    ///
+    /// Nested fields are separated by dots (ie `log.level`).
+    ///
+    /// Default: "message", "level" and "@timestamp".
+    pub fn fields(mut self, message: String, level: String, timestamp: String) -> Self {
+        self.message_field = message;
+        self.level_field = level;
+        self.timestamp_field = timestamp;
+        self
+    }
+
+    /// Set the field of the chain, the chain is not filtered if not provided.
+    pub fn chain_field(mut self, chain_field: Option<String>) -> Self {
+        self.chain_field = chain_field;
+        self
+    }
+
+    /// Only search the lines of the nodes matching any of the regexes, in the provided field.
+    pub fn node(mut self, node_field: Option<String>, nodes: Vec<String>) -> Self {
+        self.node_field = node_field;
+        self.nodes = nodes;
+        self
+    }
+
+    /// Leave out the lines of the nodes matching any of the regexes, in the node field.
+    pub fn exclude_node(mut self, nodes: Vec<String>) -> Self {
+        self.excluded_nodes = nodes;
+        self
+    }
+
+    /// Set the substrings of the messages excluded with the common errors.
+    ///
+    /// Default: the [`query::COMMON_ERRORS`].
+    pub fn excluded_lines(mut self, excluded_lines: Vec<String>) -> Self {
+        self.excluded_lines = excluded_lines;
+        self
+    }
+
+    /// The maximum number of hits of a search.
+    fn size(&self) -> usize {
+        self.limit.min(MAX_RESULT_WINDOW)
+    }
+
+    /// The body of the search of the chunk.
+    fn body(&self, chain: &str, selection: &Selection, start_time: &str, end_time: &str) -> Value {
+        let mut filter = vec![json!({
+            "range": { &self.timestamp_field: { "gte": start_time, "lt": end_time } }
+        })];
+        if !selection.levels.is_empty() {
+            // The levels are indexed in either case depending on the shipper.
+            let levels: Vec<_> = selection
+                .levels
+                .iter()
+                .flat_map(|level| [level.to_uppercase(), level.to_lowercase()])
+                .collect();
+            filter.push(json!({ "terms": { &self.level_field: levels } }));
+        }
+        if let Some(field) = &self.chain_field {
+            filter.push(json!({ "term": { field: chain } }));
+        }
+        if let (Some(field), false) = (&self.node_field, self.nodes.is_empty()) {
+            filter.push(json!({ "regexp": { field: self.nodes.join("|") } }));
+        }
+
+        let mut must_not: Vec<_> = if selection.exclude_common_errors {
+            self.excluded_lines
+                .iter()
+                .map(|error| json!({ "match_phrase": { &self.message_field: error } }))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if let (Some(field), false) = (&self.node_field, self.excluded_nodes.is_empty()) {
+            must_not.push(json!({ "regexp": { field: self.excluded_nodes.join("|") } }));
+        }
+        for (operator, value) in line_filters(&selection.filter) {
+            let alternatives = match operator {
+                "|=" => vec![words(&value)],
+                "|~" => regex_words(&value),
+                // The lines not containing a string may still contain its words.
+                _ => continue,
+            };
+            if alternatives.iter().any(Vec::is_empty) {
+                continue;
+            }
+
+            let mut alternatives: Vec<_> = alternatives
+                .into_iter()
+                .map(|words| {
+                    let wildcards: Vec<_> = words
+                        .into_iter()
+                        .map(|word| {
+                            json!({ "wildcard": { &self.message_field: {
+                                "value": format!("*{word}*"),
+                                "case_insensitive": true,
+                            } } })
+                        })
+                        .collect();
+                    json!({ "bool": { "filter": wildcards } })
+                })
+                .collect();
+            if alternatives.len() == 1 {
+                filter.append(&mut alternatives);
+            } else {
+                filter.push(json!({
+                    "bool": { "should": alternatives, "minimum_should_match": 1 }
+                }));
+            }
+        }
+
+        json!({
+            "size": self.size(),
+            "sort": [{ &self.timestamp_field: "asc" }],
+            "_source": [&self.message_field],
+            "query": { "bool": { "filter": filter, "must_not": must_not } },
+        })
+    }
+
+    /// Build the `curl` command searching the chunk.
+    fn build(
+        &self,
+        chain: &str,
+        selection: &Selection,
+        start_time: &str,
+        end_time: &str,
+    ) -> String {
+        let auth = if std::env::var_os(API_KEY_VAR).is_some() {
+            format!(r#" --header "Authorization: ApiKey ${API_KEY_VAR}""#)
+        } else {
+            String::new()
+        };
+        let url = format!("{}/{}/_search", self.url.trim_end_matches('/'), self.index);
+        let body = self.body(chain, selection, start_time, end_time);
+
+        format!(
+            "curl --silent --show-error --fail --request POST --header 'Content-Type: application/json'{auth} --data {} {}",
+            shell_quote(&body.to_string()),
+            shell_quote(&url)
+        )
+    }
+}
+
+impl LogSource for ElasticsearchSource {
+    fn queries(&self, chain: &str, selection: &Selection) -> Result<Vec<String>, QueryError> {
+        let (start_time, end_time) =
+            query::time_range(&self.start_time, &self.end_time, self.last_day)?;
+
+        Ok(query::time_chunks(&start_time, &end_time)?
+            .into_iter()
+            .map(|(start_time, end_time)| self.build(chain, selection, &start_time, &end_time))
+            .collect())
+    }
+
+    fn lines(&self, output: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        // The queries of the dry runs have no output.
+        if output.is_empty() {
+            return Ok(output);
+        }
+
+        let response: Value = serde_json::from_slice(&output)?;
+        let hits = hits(&response)?;
+        if hits.len() >= self.size() {
+            return Err(format!(
+                "Search hit the limit of {} lines, lines are missing",
+                self.size()
+            )
+            .into());
+        }
+
+        Ok(self.messages(hits))
+    }
+
+    fn fetch(
+        &self,
+        runner: &QueryRunner,
+        query: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let output = runner.run(query)?;
+        if output.is_empty() {
+            return Ok(output);
+        }
+
+        let response: Value = serde_json::from_slice(&output)?;
+        let hits = hits(&response)?;
+        if hits.len() < self.size() {
+            return Ok(self.messages(hits));
+        }
+
+        let Some((first, second)) = split_search(query)? else {
+            return Err(format!(
+                "Search hit the limit of {} lines within a second, lines are missing: {query}",
+                self.size()
+            )
+            .into());
+        };
+        log::info!(
+            "Search hit the limit of {} lines, splitting its time range",
+            self.size()
+        );
+        let mut lines = self.fetch(runner, &first)?;
+        lines.extend(self.fetch(runner, &second)?);
+
+        Ok(lines)
+    }
+
+    fn streams(&self) -> bool {
+        // The hits are only parsed once the whole response is read.
+        false
+    }
+}
+
+impl ElasticsearchSource {
+    /// The lines of the messages of the hits.
+    fn messages(&self, hits: &[Value]) -> Vec<u8> {
+        let mut lines = Vec::new();
+        for hit in hits {
+            let Some(message) = field(&hit["_source"], &self.message_field).and_then(Value::as_str)
+            else {
+                log::debug!("Skipping hit without message: {}", hit);
+                continue;
+            };
+            lines.extend_from_slice(message.trim_end().as_bytes());
+            lines.push(b'\n');
+        }
+
+        lines
+    }
+}
+
+/// The hits of the search response.
+fn hits(response: &Value) -> Result<&[Value], Box<dyn std::error::Error>> {
+    Ok(response["hits"]["hits"]
+        .as_array()
+        .ok_or("Unexpected Elasticsearch response, the hits are missing")?)
+}
+
+/// Split the search in the two halves of its time range, `None` below a second.
+fn split_search(query: &str) -> Result<Option<(String, String)>, QueryError> {
+    static RANGE: OnceLock<Regex> = OnceLock::new();
+    let range = RANGE.get_or_init(|| Regex::new(SEARCH_RANGE).expect("Regex is valid; qed"));
+
+    let Some(captures) = range.captures(query) else {
+        return Ok(None);
+    };
+    let start = query::parse_time("start_time", &captures[1])?;
+    let end = query::parse_time("end_time", &captures[2])?;
+    let half = (end - start) / 2;
+    if half < chrono::Duration::seconds(1) {
+        return Ok(None);
+    }
+
+    let middle = (start + chrono::Duration::seconds(half.num_seconds()))
+        .format(query::TIME_FORMAT)
+        .to_string();
+    let search = |start: &str, end: &str| {
+        range
+            .replace(query, format!(r#""gte":"{start}","lt":"{end}""#).as_str())
+            .into_owned()
+    };
+
+    Ok(Some((
+        search(&captures[1], &middle),
+        search(&middle, &captures[2]),
+    )))
+}
+
+/// The line filters of a LogQL filter (ie ``|= `Slow` |~ `banned|offence` ``), as pairs of
+/// operator and string, up to the first other stage.
+fn line_filters(filter: &str) -> Vec<(&str, String)> {
+    let mut filters = Vec::new();
+    let mut rest = filter.trim_start();
+
+    while let Some(operator) = ["|=", "!=", "|~", "!~"]
+        .into_iter()
+        .find(|operator| rest.starts_with(operator))
+    {
+        rest = rest[operator.len()..].trim_start();
+        let mut chars = rest.char_indices();
+        let mut value = String::new();
+        let end = match chars.next() {
+            Some((_, '`')) => chars.find(|(_, c)| *c == '`').map(|(i, _)| {
+                value.push_str(&rest[1..i]);
+                i + 1
+            }),
+            Some((_, '"')) => loop {
+                match chars.next() {
+                    Some((i, '"')) => break Some(i + 1),
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, c)) => value.push(c),
+                        None => break None,
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => break None,
+                }
+            },
+            _ => None,
+        };
+        let Some(end) = end else {
+            log::debug!("Unsupported LogQL line filter: {filter}");
+            break;
+        };
+
+        filters.push((operator, value));
+        rest = rest[end..].trim_start();
+    }
+
+    filters
+}
+
+/// The words of the literal, searched within the words indexed by the cluster.
+///
+/// The literal is split on every non alphanumeric character, more than the analyzers of
+/// the cluster, such that every word is contained in an indexed word of the line.
+fn words(literal: &str) -> Vec<String> {
+    literal
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_WORD_LEN)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The words of the regex (ie `Slow block|(?i)banned`), for each of its alternatives.
+///
+/// Only the literals of the alternatives are kept, the groups, classes and repeated
+/// characters matching a variable content. An alternative without words matches any line.
+fn regex_words(regex: &str) -> Vec<Vec<String>> {
+    let mut alternatives = vec![Vec::new()];
+    let mut literal = String::new();
+    let (mut depth, mut class) = (0usize, false);
+    let mut chars = regex.chars();
+
+    let end_literal = |literal: &mut String, alternatives: &mut Vec<Vec<String>>| {
+        if let Some(alternative) = alternatives.last_mut() {
+            alternative.extend(words(literal));
+        }
+        literal.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        if class || depth > 0 {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                ']' if class => class = false,
+                '[' => class = true,
+                '(' if !class => depth += 1,
+                ')' if !class => depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            // The escaped symbols are literals, the escaped letters are classes.
+            '\\' => match chars.next() {
+                Some(c) if !c.is_alphanumeric() => literal.push(c),
+                _ => end_literal(&mut literal, &mut alternatives),
+            },
+            '[' | '(' => {
+                end_literal(&mut literal, &mut alternatives);
+                class = c == '[';
+                depth = usize::from(c == '(');
+            }
+            '|' => {
+                end_literal(&mut literal, &mut alternatives);
+                alternatives.push(Vec::new());
+            }
+            // The optional character is not part of the literal.
+            '*' | '?' | '{' => {
+                literal.pop();
+                end_literal(&mut literal, &mut alternatives);
+                if c == '{' {
+                    chars.by_ref().find(|c| *c == '}');
+                }
+            }
+            '.' | '+' | '^' | '$' => end_literal(&mut literal, &mut alternatives),
+            _ => literal.push(c),
+        }
+    }
+    end_literal(&mut literal, &mut alternatives);
+
+    alternatives
+}
+
+/// The field of the document, either flattened (ie `"log.level": ..`) or nested.
+fn field<'a>(document: &'a Value, name: &str) -> Option<&'a Value> {
+    document.get(name).or_else(|| {
+        name.split('.')
+            .try_fold(document, |value, key| value.get(key))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elasticsearch() {
+        let source = ElasticsearchSource::new(DEFAULT_URL.to_string(), DEFAULT_INDEX.to_string())
+            .set_time(
+                Some("2024-03-29T16:00:00Z".to_string()),
+                Some("2024-03-29T17:30:00Z".to_string()),
+                false,
+            )
+            .limit(4)
+            .chain_field(Some("chain".to_string()));
+        let selection = Selection {
+            levels: vec!["WARN".to_string()],
+            ..Default::default()
+        };
+        let queries = source.queries("versi", &selection).unwrap();
+        assert_eq!(queries.len(), 2);
+        assert!(queries[0].starts_with("curl --silent --show-error --fail --request POST"));
+        assert!(queries[0].ends_with("'http://127.0.0.1:9200/logs-*/_search'"));
+        assert!(queries[1].contains(
+            r#"{"range":{"@timestamp":{"gte":"2024-03-29T17:00:00Z","lt":"2024-03-29T17:30:00Z"}}}"#
+        ));
+        assert!(queries[0].contains(r#"{"terms":{"level":["WARN","warn"]}}"#));
+        assert!(queries[0].contains(r#"{"term":{"chain":"versi"}}"#));
+        assert!(queries[0].contains(r#"{"match_phrase":{"message":"Error while dialing"}}"#));
+
+        let output = br#"{"hits":{"hits":[
+            {"_source":{"message":"2024-03-29 16:00:00.000  WARN sync: Slow\n"}},
+            {"_source":{"log":{"message":"nested"}}},
+            {"_source":{}}
+        ]}}"#;
+        assert_eq!(
+            source.lines(output.to_vec()).unwrap(),
+            b"2024-03-29 16:00:00.000  WARN sync: Slow\n"
+        );
+        let nested = source.clone().fields(
+            "log.message".to_string(),
+            "level".to_string(),
+            "@timestamp".to_string(),
+        );
+        assert_eq!(nested.lines(output.to_vec()).unwrap(), b"nested\n");
+        assert!(source.lines(b"{}".to_vec()).is_err());
+        // The truncated searches fail.
+        let truncated = br#"{"hits":{"hits":[{},{},{},{}]}}"#;
+        assert!(source.lines(truncated.to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_elasticsearch_line_filters() {
+        assert_eq!(
+            line_filters(r#"|= `Slow block` != "not \"this\"" | json |~ `x`"#),
+            vec![
+                ("|=", "Slow block".to_string()),
+                ("!=", "not \"this\"".to_string())
+            ]
+        );
+        assert_eq!(
+            words("Error while: dialing"),
+            vec!["error", "while", "dialing"]
+        );
+        assert_eq!(
+            regex_words(r"(?i)Banned peer.*reason|Offence \(\w+\) reported|Slow+ ab?c{2}"),
+            vec![
+                vec!["banned", "peer", "reason"],
+                vec!["offence", "reported"],
+                vec!["slow"]
+            ]
+        );
+        assert_eq!(
+            regex_words(r"[A-Z]+ block|\d+"),
+            vec![vec!["block"], vec![]]
+        );
+
+        let source = ElasticsearchSource::new(DEFAULT_URL.to_string(), DEFAULT_INDEX.to_string());
+        let selection = |filter: &str| Selection {
+            filter: filter.to_string(),
+            ..Default::default()
+        };
+        let body = source
+            .body("versi", &selection("|~ `Banned|Slow block`"), "a", "b")
+            .to_string();
+        assert!(body.contains(
+            r#"{"bool":{"minimum_should_match":1,"should":[{"bool":{"filter":[{"wildcard":{"message":{"case_insensitive":true,"value":"*banned*"}}}]}},{"bool":{"filter":[{"wildcard":{"message":{"case_insensitive":true,"value":"*slow*"}}},{"wildcard":{"message":{"case_insensitive":true,"value":"*block*"}}}]}}]}}"#
+        ));
+        // The filters without words are not translated.
+        for filter in ["|~ `Banned|\\d+`", "!= `Slow`", "!~ `Slow`", "|= `a`"] {
+            assert!(!source
+                .body("versi", &selection(filter), "a", "b")
+                .to_string()
+                .contains("wildcard"));
+        }
+    }
+
+    #[test]
+    fn test_elasticsearch_split() {
+        let dir = std::env::temp_dir().join(format!("elasticsearch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = ElasticsearchSource::new(DEFAULT_URL.to_string(), DEFAULT_INDEX.to_string())
+            .set_time(
+                Some("2024-03-29T16:00:00Z".to_string()),
+                Some("2024-03-29T16:00:03Z".to_string()),
+                false,
+            )
+            .limit(2);
+        let query = source
+            .queries("versi", &Selection::default())
+            .unwrap()
+            .remove(0);
+        let (first, second) = split_search(&query).unwrap().unwrap();
+        assert!(first.contains(r#""gte":"2024-03-29T16:00:00Z","lt":"2024-03-29T16:00:01Z""#));
+        assert!(second.contains(r#""gte":"2024-03-29T16:00:01Z","lt":"2024-03-29T16:00:03Z""#));
+        let (third, fourth) = split_search(&second).unwrap().unwrap();
+
+        let hits = |messages: &[&str]| {
+            let hits: Vec<_> = messages
+                .iter()
+                .map(|message| json!({ "_source": { "message": message } }))
+                .collect();
+            json!({ "hits": { "hits": hits } }).to_string()
+        };
+        let write = |query: &str, messages: &[&str]| {
+            std::fs::write(dir.join(query::output_file(query)), hits(messages)).unwrap()
+        };
+        write(&query, &["a", "b"]);
+        write(&first, &["a"]);
+        write(&second, &["b", "c"]);
+        write(&third, &["b"]);
+        write(&fourth, &["c"]);
+
+        // The truncated searches are split in the order of their time range.
+        let runner = QueryRunner::new().output_dir(Some(dir.clone()));
+        assert_eq!(source.fetch(&runner, &query).unwrap(), b"a\nb\nc\n");
+        assert_eq!(source.fetch(&runner, &fourth).unwrap(), b"c\n");
+
+        // The searches truncated within a second fail.
+        write(&first, &["a", "b"]);
+        assert!(source.fetch(&runner, &query).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+//! Detection of the session changes, and of the patterns clustered around them.
+//!
+//! Many issues are triggered by the rotation of the validator set. The changes are detected
+//! from the lines printed when a new epoch, authority set or era begins, ie
+//! `2024-03-29 16:00:00 👶 New epoch 42 launching at block 0x1234… (block slot 123 >= start slot 123).`
+
+use chrono::NaiveDateTime;
+
+use crate::histogram::Timeline;
+
+/// Loki filter selecting the session change lines.
+pub const QUERY_FILTER: &str =
+    "|~ `New epoch|Applying authority set change|new validator set of size`";
+
+/// The messages printed when a session changes.
+const MARKERS: [&str; 3] = [
+    "New epoch",
+    "Applying authority set change",
+    "new validator set of size",
+];
+
+/// The lines of a session change printed by every node closer than this are a single change.
+const MERGE_WINDOW: chrono::Duration = chrono::Duration::seconds(60);
+
+/// The matched lines closer than this to a session change are around the change.
+const AROUND_WINDOW: chrono::Duration = chrono::Duration::seconds(60);
+
+/// The minimum number of lines of the patterns clustered around the changes.
+const MIN_COUNT: usize = 10;
+
+/// The minimum share of the lines around the changes.
+const MIN_SHARE: f64 = 0.5;
+
+/// The minimum share of the lines around the changes, over the share of the time range
+/// around the changes.
+const MIN_RATIO: f64 = 3.0;
+
+/// Check if the line reports a session change.
+pub fn is_session_change(line: &str) -> bool {
+    MARKERS.iter().any(|marker| line.contains(marker))
+}
+
+/// The session changes of a run, in chronological order.
+#[derive(Debug, Clone, Default)]
+pub struct SessionChanges {
+    times: Vec<NaiveDateTime>,
+}
+
+impl SessionChanges {
+    /// Construct the changes from the timestamps of the session change lines of every node.
+    pub fn new(times: impl IntoIterator<Item = NaiveDateTime>) -> Self {
+        let mut sorted: Vec<_> = times.into_iter().collect();
+        sorted.sort_unstable();
+
+        let mut times: Vec<NaiveDateTime> = Vec::new();
+        for time in sorted {
+            match times.last() {
+                Some(last) if time - *last <= MERGE_WINDOW => {}
+                _ => times.push(time),
+            }
+        }
+
+        SessionChanges { times }
+    }
+
+    /// The times of the changes.
+    pub fn times(&self) -> &[NaiveDateTime] {
+        &self.times
+    }
+
+    /// Check if the time is around a change.
+    fn is_around(&self, time: NaiveDateTime) -> bool {
+        let index = self.times.partition_point(|change| *change < time);
+        let before = index.checked_sub(1).map(|index| self.times[index]);
+        let after = self.times.get(index).copied();
+        before.is_some_and(|before| time - before <= AROUND_WINDOW)
+            || after.is_some_and(|after| after - time <= AROUND_WINDOW)
+    }
+
+    /// The share of the lines around the changes, if the lines cluster around them.
+    ///
+    /// The lines cluster around the changes when most of them are printed around a change,
+    /// and [`MIN_RATIO`] times more often than if they were spread over the time range.
+    pub fn clustered_share(
+        &self,
+        timeline: &Timeline,
+        (start, end): (NaiveDateTime, NaiveDateTime),
+    ) -> Option<f64> {
+        let count = timeline.len();
+        if count < MIN_COUNT || self.times.is_empty() {
+            return None;
+        }
+
+        let around: usize = timeline
+            .slots()
+            .filter(|(time, _)| self.is_around(*time))
+            .map(|(_, count)| count)
+            .sum();
+        let share = around as f64 / count as f64;
+
+        // The windows around the changes are merged, such that they are not counted twice.
+        let (mut covered, mut covered_end) = (0, start);
+        for change in &self.times {
+            let from = (*change - AROUND_WINDOW).max(covered_end);
+            let to = (*change + AROUND_WINDOW).min(end);
+            if to > from {
+                covered += (to - from).num_seconds();
+                covered_end = to;
+            }
+        }
+        let range = (end - start).num_seconds().max(1);
+        let expected = covered as f64 / range as f64;
+
+        (share >= MIN_SHARE && share >= MIN_RATIO * expected).then_some(share)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clustered_share() {
+        let time = |minutes: i64| {
+            "2024-03-29T10:00:00".parse::<NaiveDateTime>().unwrap()
+                + chrono::Duration::minutes(minutes)
+        };
+        assert!(is_session_change(
+            "2024-03-29 11:00:00 INFO tokio-runtime-worker babe: 👶 New epoch 42 launching at block 0x1234"
+        ));
+
+        // Every node prints the change, a few seconds apart.
+        let changes = SessionChanges::new([
+            time(60),
+            time(60) + chrono::Duration::seconds(5),
+            time(120),
+            time(180),
+        ]);
+        assert_eq!(changes.times(), [time(60), time(120), time(180)]);
+
+        let range = (time(0), time(240));
+        let timeline = |times: &mut dyn Iterator<Item = NaiveDateTime>| {
+            let mut timeline = Timeline::default();
+            times.for_each(|time| timeline.insert(time));
+            timeline
+        };
+        let around = |count: i64| {
+            timeline(
+                &mut (0..count)
+                    .map(|index| time(60 * (index % 3 + 1)) + chrono::Duration::seconds(index)),
+            )
+        };
+        let share = changes.clustered_share(&around(20), range).unwrap();
+        assert!((share - 1.0).abs() < f64::EPSILON);
+
+        let spread = timeline(&mut (0..24).map(|index| time(index * 10 + 5)));
+        assert_eq!(changes.clustered_share(&spread, range), None);
+        assert_eq!(changes.clustered_share(&around(5), range), None);
+    }
+}
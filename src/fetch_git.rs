@@ -1,49 +1,212 @@
 use flate2::read::GzDecoder;
-use std::io::Read;
+use rayon::prelude::*;
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+    io::Read,
+    path::Path,
+    sync::{Mutex, OnceLock},
+};
 use tar::Archive;
 
-/// Fetch the github repo from the provided url and branch.
+use crate::{
+    github::GitHub,
+    owners::{CodeOwners, CODEOWNERS_PATHS},
+    tarball::TarballCache,
+};
+
+/// The sources of a repository fetched from GitHub.
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    /// The path of the files relative to the repository root, with their content.
+    pub files: Vec<(String, String)>,
+    /// The commit of the fetched tarball, when recorded by GitHub in the archive.
+    pub commit: Option<String>,
+}
+
+/// Selects the source files of a repository from which the regexes are built.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    /// The prefixes of the included paths, every path if empty.
+    pub include: Vec<String>,
+    /// The prefixes of the excluded paths, taking precedence over the included ones.
+    pub exclude: Vec<String>,
+}
+
+impl PathFilter {
+    /// Check if the path, relative to the repository root, is selected.
+    pub fn matches(&self, path: &str) -> bool {
+        let starts_with = |prefix: &String| path.starts_with(prefix.as_str());
+        (self.include.is_empty() || self.include.iter().any(starts_with))
+            && !self.exclude.iter().any(starts_with)
+    }
+}
+
+/// Fetch the github repo from the provided url and branch, tag or commit.
+///
+/// The rust sources are fetched, along with the `CODEOWNERS` file of the repo.
+///
+/// The tarball is reused from the provided cache when the branch did not change.
+/// The private repositories are fetched with the token of the GitHub client, and only the
+/// rust sources selected by the filter are kept.
 pub async fn fetch(
     url: String,
     branch: String,
-) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    github: &GitHub,
+    filter: &PathFilter,
+    cache: Option<&TarballCache>,
+) -> Result<Sources, Box<dyn std::error::Error>> {
     // Fetch the request.
-    let url = format!("{}/archive/{}.tar.gz", url, branch);
-    log::info!("Fetching from URL {}", url);
+    let archive_url = github.archive_url(&url, &branch);
+    log::info!("Fetching from URL {}", archive_url);
     let now = std::time::Instant::now();
 
-    let body = reqwest::get(url).await?.bytes().await?;
+    let body = match cache {
+        Some(cache) => cache.download(github, &url, &branch, &archive_url).await,
+        None => download(github, &archive_url).await,
+    }
+    .map_err(|err| {
+        if github.has_token() {
+            err
+        } else {
+            format!("{err} (provide --github-token or GITHUB_TOKEN for private repositories)")
+                .into()
+        }
+    })?;
+    let sources = read_sources(&body, filter)?;
+
+    log::info!(
+        "Fetched num files {} at commit {} in {:?}",
+        sources.files.len(),
+        sources.commit.as_deref().unwrap_or("unknown"),
+        now.elapsed()
+    );
+
+    Ok(sources)
+}
 
+/// Download the tarball of the provided URL.
+async fn download(
+    github: &GitHub,
+    archive_url: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let response = github.get(archive_url).send().await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Read the sources of the gzipped tarball of a repository.
+///
+/// GitHub records the commit of the tarball as the comment of its pax global header,
+/// which resolves the branches and tags to their commit.
+fn read_sources(
+    tarball: &[u8],
+    filter: &PathFilter,
+) -> Result<Sources, Box<dyn std::error::Error>> {
     // Decode the request.
-    let decoder = GzDecoder::new(body.as_ref());
+    let decoder = GzDecoder::new(tarball);
     let mut archive = Archive::new(decoder);
 
-    let mut data = Vec::new();
+    let mut sources = Sources::default();
     // Interpret decoded entries.
     let entries = archive.entries()?;
     for entry in entries {
         let mut entry = entry?;
 
+        if entry.header().entry_type().is_pax_global_extensions() {
+            if let Some(extensions) = entry.pax_extensions()? {
+                sources.commit = extensions
+                    .filter_map(Result::ok)
+                    .find(|extension| extension.key() == Ok("comment"))
+                    .and_then(|extension| extension.value().ok().map(str::to_string));
+            }
+            continue;
+        }
+
         let path = entry.path()?;
-        if path.extension() == Some(std::ffi::OsStr::new("rs")) {
-            let owned_path = path.to_string_lossy().into_owned();
+        // Strip the archive root directory (ie `polkadot-sdk-master/`).
+        let owned_path = path
+            .components()
+            .skip(1)
+            .collect::<std::path::PathBuf>()
+            .to_string_lossy()
+            .into_owned();
+        if (path.extension() == Some(std::ffi::OsStr::new("rs")) && filter.matches(&owned_path))
+            || CODEOWNERS_PATHS.contains(&owned_path.as_str())
+        {
             let mut buffer = String::with_capacity(entry.header().size()? as usize);
             entry.read_to_string(&mut buffer)?;
-            data.push((owned_path, buffer));
+            sources.files.push((owned_path, buffer));
         }
     }
 
-    log::info!("Fetched num files {} in {:?}", data.len(), now.elapsed());
+    Ok(sources)
+}
 
-    Ok(data)
+/// A git repository from which the regexes are built.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Repo {
+    /// The name of the repository displayed in the report.
+    pub name: String,
+    /// The URL of the repository (ie "https://github.com/paritytech/polkadot-sdk/").
+    pub url: String,
+    /// The branch, tag (ie `polkadot-v1.16.0`) or commit of the repository.
+    pub branch: String,
+    /// The commit the branch or tag resolved to, when known.
+    pub commit: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+impl Repo {
+    /// Construct a new repository.
+    pub fn new(name: String, url: String, branch: String) -> Self {
+        Self {
+            name,
+            url,
+            branch,
+            commit: None,
+        }
+    }
+
+    /// Pin the repository to the resolved commit of its branch or tag.
+    ///
+    /// The permalinks point at the commit instead of the moving branch.
+    pub fn with_commit(mut self, commit: Option<String>) -> Self {
+        self.commit = commit;
+        self
+    }
+
+    /// Construct a permalink to the provided file and line of the repository.
+    pub fn permalink(&self, file: &str, line: usize) -> String {
+        format!(
+            "{}/blob/{}/{}#L{}",
+            self.url.trim_end_matches('/'),
+            self.commit.as_deref().unwrap_or(&self.branch),
+            file,
+            line
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct RegexDetails {
     pub repo: String,
     pub file: String,
+    /// The byte offset of the log statement in the file.
     pub start: usize,
+    /// The byte offset of the end of the log statement in the file.
     pub end: usize,
+    /// The line of the log statement in the file (1-based).
+    pub line: usize,
+    /// The column of the log macro in the line (1-based, in characters).
+    pub column: usize,
+    /// Link to the log statement in the source repository.
+    pub permalink: String,
+    /// The comment above the log statement, which usually explains the message.
+    pub description: Option<String>,
+    /// The target of the log statement (ie `sub-libp2p`), when it could be resolved.
+    pub target: Option<String>,
+    /// The owners of the source file, from the `CODEOWNERS` file of the repo.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub owners: Vec<String>,
     pub ty: String,
 }
 
@@ -51,18 +214,66 @@ fn trim_dummy_chars(ch: char) -> bool {
     ch == '"' || ch == ',' || ch == ' ' || ch == '\\'
 }
 
-fn extract_log_line<'a>(mut line: &'a str) -> Option<&'a str> {
+/// The raw string literal (ie `r#"..."#`) at the start of the text, with the text following it.
+fn raw_literal(text: &str) -> Option<(&str, &str)> {
+    let text = text.strip_prefix('r')?;
+    let hashes = text.len() - text.trim_start_matches('#').len();
+    let text = text[hashes..].strip_prefix('"')?;
+    let end = format!("\"{}", "#".repeat(hashes));
+    let index = text.find(&end)?;
+    Some((&text[..index], &text[index + end.len()..]))
+}
+
+/// The format string at the start of the text written as a raw string literal, or as string
+/// literals concatenated with `concat!`.
+///
+/// The `concat!` call may be cut at the end of the text, where the log statement is cut.
+fn format_literal(text: &str) -> Option<Cow<'_, str>> {
+    let Some(args) = text.strip_prefix("concat!") else {
+        return raw_literal(text).map(|(literal, _)| Cow::Borrowed(literal));
+    };
+
+    let mut rest = args.trim_start().strip_prefix('(')?;
+    let mut format = String::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() || rest.starts_with(')') {
+            return Some(Cow::Owned(format));
+        }
+        // Only the string literals are concatenated, not ie `stringify!`.
+        let (literal, after) = match rest.strip_prefix('"') {
+            Some(text) => {
+                let end = text.find('"')?;
+                (&text[..end], &text[end + 1..])
+            }
+            None => raw_literal(rest)?,
+        };
+        format.push_str(literal);
+
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+fn extract_log_line(mut line: &str) -> Option<Cow<'_, str>> {
     line = line.trim();
 
+    if let Some(format) = format_literal(line) {
+        return Some(trim_format(format));
+    }
+    if line.starts_with("concat!") {
+        return None;
+    }
+
     if line.starts_with('\"') {
         line = &line[1..];
 
         let end = line.find('\"').unwrap_or_default();
         let line = &line[..end];
-        return Some(
+        return Some(Cow::Borrowed(
             line.trim_end_matches(trim_dummy_chars)
                 .trim_start_matches(trim_dummy_chars),
-        );
+        ));
     }
 
     while let Some(in_start) = line.find(',') {
@@ -72,6 +283,13 @@ fn extract_log_line<'a>(mut line: &'a str) -> Option<&'a str> {
         // Advance for the next search.
         line = &line[in_start + 1..];
 
+        if let Some(format) = format_literal(in_str.trim_start()) {
+            return Some(trim_format(format));
+        }
+        if in_str.trim_start().starts_with("concat!") {
+            return None;
+        }
+
         let in_end = in_str.find("\"").unwrap_or_default();
 
         let to_find_str = &in_str[..in_end];
@@ -93,129 +311,408 @@ fn extract_log_line<'a>(mut line: &'a str) -> Option<&'a str> {
 
             log::debug!("Found str line {}", new_str);
 
-            return Some(&new_str);
+            return Some(Cow::Borrowed(new_str));
         }
     }
 
     // The multiline could not be extracted.
-    Some(
+    Some(Cow::Borrowed(
         line.trim_end_matches(trim_dummy_chars)
             .trim_start_matches(trim_dummy_chars),
-    )
+    ))
 }
 
-pub fn build_regexes(
-    data: Vec<(String, String)>,
-    repo: String,
-) -> Vec<(regex::Regex, RegexDetails)> {
-    let mut regexes = Vec::new();
+/// The first line of the format string, without the dummy chars around it.
+fn trim_format(format: Cow<'_, str>) -> Cow<'_, str> {
+    fn first_line(format: &str) -> &str {
+        format
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches(trim_dummy_chars)
+            .trim_start_matches(trim_dummy_chars)
+    }
 
-    let mut encountered_logs = 0;
+    match format {
+        Cow::Borrowed(format) => Cow::Borrowed(first_line(format)),
+        Cow::Owned(format) => Cow::Owned(first_line(&format).to_string()),
+    }
+}
 
-    for (file_path, content) in data {
-        // How the log lines look like.
-        let searched_for = ["error!(", "warn!(", "warn_if_frequent!("];
-
-        for searched in searched_for {
-            let mut str_content = &content[..];
-            let len_searched = searched.len();
-
-            while let Some(start) = str_content.find(searched) {
-                encountered_logs += 1;
-
-                let end = if let Some(end) = str_content[start..].find(");") {
-                    end
-                } else if let Some(end) = str_content[start..].find("),") {
-                    end
-                } else {
-                    // Note: The file must be malformed, don't assume the log ends at the eof.
-                    log::error!("File {file_path} is malformed {start}:..");
-                    break;
-                };
-
-                // str contains everything in between log!( [content] );
-                let str = &str_content[start + len_searched..start + end];
-                // Advance for the next search.
-                str_content = &str_content[start + end..];
-
-                // Handle multiline case.
-                let current_str = str;
-                let multiline_search = extract_log_line(current_str);
-                let Some(line_matched) = multiline_search else {
-                    log::debug!("Skipped parsing: {:?}", current_str);
-                    continue;
-                };
-                if line_matched.is_empty() {
-                    log::debug!("Skipped empty: {:?}", current_str);
-                    continue;
-                }
+/// Extract the comment block (doc comments or `// reason:` comments) right above
+/// the line containing the provided offset.
+fn extract_description(content: &str, offset: usize) -> Option<String> {
+    let line_start = content[..offset]
+        .rfind('\n')
+        .map(|pos| pos + 1)
+        .unwrap_or(0);
 
-                let mut counting_brackets = 0;
-                let mut num_braces = 0;
-                let mut line_matched = line_matched
-                    .chars()
-                    .filter(|c| {
-                        if *c == '{' {
-                            counting_brackets += 1;
-                            num_braces += 1;
-                            return true;
-                        } else if *c == '}' {
-                            counting_brackets -= 1;
-                            return true;
-                        }
-
-                        counting_brackets == 0
-                    })
-                    .collect::<String>();
-                if counting_brackets > 0 {
-                    line_matched.push('}');
-                }
+    let mut comments: Vec<_> = content[..line_start]
+        .lines()
+        .rev()
+        .map(|line| line.trim())
+        .take_while(|line| line.starts_with("//"))
+        .map(|line| line.trim_start_matches('/').trim_start_matches('!').trim())
+        .collect();
+    comments.reverse();
 
-                // Only `{}` like lines.
-                if line_matched.len() == num_braces * 2 {
-                    log::debug!("Skipped only brackets: {:?}", current_str);
-                    continue;
-                }
+    let description = comments.join(" ");
+    let description = description.trim();
+    (!description.is_empty()).then(|| description.to_string())
+}
 
-                let mut regexed_line = line_matched
-                    .replace("{}", ".*")
-                    .replace("(", "\\(")
-                    .replace(")", "\\)")
-                    .replace("[", "\\[")
-                    .replace("]", "\\]");
+/// The string constants of a file (ie `const LOG_TARGET: &str = "parachain::dispute";`).
+fn extract_constants(content: &str) -> HashMap<&str, &str> {
+    static CONSTANT: OnceLock<regex::Regex> = OnceLock::new();
+    let regex = CONSTANT.get_or_init(|| {
+        regex::Regex::new(
+            r#"const\s+([A-Za-z_][A-Za-z0-9_]*)\s*:\s*&(?:'static\s+)?str\s*=\s*"([^"]*)""#,
+        )
+        .expect("Regex is valid; qed")
+    });
 
-                let has_chars = regexed_line.chars().any(|c| c.is_alphabetic());
-                if !has_chars {
-                    log::debug!("Skipped not having chars: {:?}", current_str);
-                    continue;
-                }
+    regex
+        .captures_iter(content)
+        .filter_map(|captures| Some((captures.get(1)?.as_str(), captures.get(2)?.as_str())))
+        .collect()
+}
 
-                log::debug!("Regexed line {}", regexed_line);
-                if regexed_line.len() < 10 {
-                    log::debug!("Skipped len < 10: {:?}", current_str);
-                    continue;
+/// The module files of the crate sources, the root modules being `lib.rs` or `main.rs`.
+const MODULE_FILES: [&str; 3] = ["mod.rs", "lib.rs", "main.rs"];
+
+/// The string constants of the fetched files.
+///
+/// The log targets are usually constants defined at the top of the module, or in a parent
+/// module (ie `super::LOG_TARGET`, `use crate::LOG_TARGET;`). The constants are resolved
+/// by following the module tree of the crate, derived from the paths of the files.
+///
+/// The constants are shared by the files extracted in parallel.
+struct Constants<'a> {
+    /// The content of the files by path.
+    files: HashMap<&'a str, &'a str>,
+    /// The constants of the files, extracted on demand.
+    ///
+    /// Every file has its own cell, such that the files are extracted in parallel and
+    /// only once.
+    constants: HashMap<&'a str, OnceLock<HashMap<&'a str, &'a str>>>,
+    /// The regexes of the imports of the constants, by name.
+    imports: Mutex<HashMap<String, regex::Regex>>,
+}
+
+impl<'a> Constants<'a> {
+    fn new(data: &'a [(String, String)]) -> Self {
+        Constants {
+            files: data
+                .iter()
+                .map(|(path, content)| (path.as_str(), content.as_str()))
+                .collect(),
+            constants: data
+                .iter()
+                .map(|(path, _)| (path.as_str(), OnceLock::new()))
+                .collect(),
+            imports: Default::default(),
+        }
+    }
+
+    /// The value of the constant defined in the provided file.
+    fn get(&self, file: &str, name: &str) -> Option<&'a str> {
+        let content = self.files.get(file)?;
+        let constants = self.constants.get(file)?;
+        constants
+            .get_or_init(|| extract_constants(content))
+            .get(name)
+            .copied()
+    }
+
+    /// The directory of the children of the module of the file (ie `src/a` for `src/a.rs`
+    /// and `src/a/mod.rs`).
+    fn module_dir(file: &str) -> &str {
+        match file.rsplit_once('/') {
+            Some((dir, name)) if MODULE_FILES.contains(&name) => dir,
+            None if MODULE_FILES.contains(&file) => "",
+            _ => file.trim_end_matches(".rs"),
+        }
+    }
+
+    /// The files of the module of the provided directory.
+    fn module_files(&self, dir: &str) -> Vec<String> {
+        let mut files: Vec<_> = MODULE_FILES
+            .iter()
+            .map(|name| match dir {
+                "" => name.to_string(),
+                dir => format!("{dir}/{name}"),
+            })
+            .collect();
+        if !dir.is_empty() {
+            files.push(format!("{dir}.rs"));
+        }
+        files.retain(|file| self.files.contains_key(file.as_str()));
+        files
+    }
+
+    /// Check if the directory is the root of a crate.
+    fn is_crate_root(&self, dir: &str) -> bool {
+        ["lib.rs", "main.rs"].iter().any(|name| {
+            let file = match dir {
+                "" => name.to_string(),
+                dir => format!("{dir}/{name}"),
+            };
+            self.files.contains_key(file.as_str())
+        })
+    }
+
+    /// The parent of the module directory, `None` at the root of the crate.
+    fn parent_dir<'b>(&self, dir: &'b str) -> Option<&'b str> {
+        // The files outside of a crate stop at the root of the repository.
+        if dir.is_empty() || self.is_crate_root(dir) {
+            return None;
+        }
+        Some(dir.rsplit_once('/').map_or("", |(parent, _)| parent))
+    }
+
+    /// The directory of the module path (ie `super::super`, `crate::network`), relative
+    /// to the module directory.
+    fn resolve_module(&self, mut dir: &'a str, path: &str) -> Option<String> {
+        let mut children = Vec::new();
+        for segment in path.split("::") {
+            match segment {
+                "self" => {}
+                "super" => dir = self.parent_dir(dir)?,
+                "crate" => {
+                    while !self.is_crate_root(dir) {
+                        dir = self.parent_dir(dir)?;
+                    }
                 }
+                child => children.push(child),
+            }
+        }
+
+        Some(
+            std::iter::once(dir)
+                .filter(|dir| !dir.is_empty())
+                .chain(children)
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+
+    /// The module imported with the constant (ie `crate::network` for
+    /// `use crate::network::LOG_TARGET;`).
+    fn import(&self, file: &str, name: &str) -> Option<String> {
+        let content = self.files.get(file)?;
+        // The regex is cloned, such that the files are not matched under the lock.
+        let regex = match self
+            .imports
+            .lock()
+            .expect("Lock is not poisoned; qed")
+            .entry(name.to_string())
+        {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => entry
+                .insert(
+                    regex::Regex::new(&format!(
+                        r"\buse\s+((?:[A-Za-z_][A-Za-z0-9_]*::)*[A-Za-z_][A-Za-z0-9_]*)::{name}\s*;"
+                    ))
+                    .ok()?,
+                )
+                .clone(),
+        };
+
+        Some(regex.captures(content)?.get(1)?.as_str().to_string())
+    }
+
+    /// Resolve the constant (ie `LOG_TARGET`, `super::LOG_TARGET`) used by the file.
+    ///
+    /// The constants are looked up in the module of the path (or of the `use` import of
+    /// the constant), then by name in the file and in its parent modules up to the crate
+    /// root (ie `use super::*;`, paths to other crates).
+    fn resolve(&self, file: &'a str, path: &str) -> Option<&'a str> {
+        let (module, name) = match path.rsplit_once("::") {
+            Some((module, name)) => (Some(module.to_string()), name),
+            None => (None, path),
+        };
+        let module = module.or_else(|| {
+            self.get(file, name)
+                .is_none()
+                .then(|| self.import(file, name))
+                .flatten()
+        });
+
+        let dir = Self::module_dir(file);
+        let value = module
+            .and_then(|module| self.resolve_module(dir, &module))
+            .and_then(|module_dir| {
+                self.module_files(&module_dir)
+                    .iter()
+                    .find_map(|file| self.get(file, name))
+            });
+        if let Some(value) = value.or_else(|| self.get(file, name)) {
+            return Some(value);
+        }
+
+        let mut dir = dir;
+        while let Some(parent) = self.parent_dir(dir) {
+            dir = parent;
+            if let Some(value) = self
+                .module_files(dir)
+                .iter()
+                .find_map(|file| self.get(file, name))
+            {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// Extract the target of the provided log statement arguments.
+///
+/// String literals are used as is, while constants are resolved against the
+/// string constants of the file and of its parent modules.
+fn extract_target<'a>(arguments: &str, file: &'a str, constants: &Constants<'a>) -> Option<String> {
+    static TARGET: OnceLock<regex::Regex> = OnceLock::new();
+    let regex = TARGET.get_or_init(|| {
+        regex::Regex::new(r#"\btarget\s*:\s*(?:"([^"]*)"|([A-Za-z_][A-Za-z0-9_:]*))"#)
+            .expect("Regex is valid; qed")
+    });
+
+    let captures = regex.captures(arguments)?;
+    if let Some(literal) = captures.get(1) {
+        return Some(literal.as_str().to_string());
+    }
+
+    constants
+        .resolve(file, captures.get(2)?.as_str())
+        .map(|target| target.to_string())
+}
+
+/// The 1-based column (in characters) of the provided byte offset.
+fn column(content: &str, offset: usize) -> usize {
+    let line_start = content[..offset]
+        .rfind('\n')
+        .map(|pos| pos + 1)
+        .unwrap_or(0);
+    content[line_start..offset].chars().count() + 1
+}
+
+/// The wildcard replacing the placeholders of the format strings.
+const PLACEHOLDER_WILDCARD: &str = ".*?";
+
+/// Marks the placeholders of the format strings until the literal parts are escaped.
+const PLACEHOLDER_MARKER: char = '\0';
+
+/// Convert the format string of a log statement to a regex.
+///
+/// Every placeholder, positional or named and with or without format spec (ie `{}`,
+/// `{0}`, `{peer}`, `{err:?}`, `{:>8}`), is replaced by a non-greedy wildcard, while the
+/// escaped braces (`{{` and `}}`) are literal braces. The regex metacharacters of the
+/// literal parts are escaped.
+fn format_to_regex(format: &str) -> String {
+    let mut marked = String::with_capacity(format.len());
+    let mut chars = format.chars().peekable();
 
-                // Extra care around misinterpreted lines.
-                if regexed_line.starts_with("PoV size") {
-                    regexed_line = "PoV size .*".to_string()
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+                marked.push(c);
+            }
+            '{' => {
+                // An unterminated placeholder spans the rest of the line.
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
                 }
-                let regex = regex::Regex::new(&regexed_line).unwrap();
-
-                regexes.push((
-                    regex,
-                    RegexDetails {
-                        repo: repo.clone(),
-                        file: file_path.clone(),
-                        start,
-                        end,
-                        ty: searched[..searched.len() - 2].to_string(),
-                    },
-                ));
+                marked.push(PLACEHOLDER_MARKER);
             }
+            // A literal marker would be replaced by a wildcard.
+            PLACEHOLDER_MARKER => {}
+            c => marked.push(c),
         }
     }
 
+    regex::escape(&marked).replace(PLACEHOLDER_MARKER, PLACEHOLDER_WILDCARD)
+}
+
+/// The logging macros searched by default.
+pub const DEFAULT_LOG_MACROS: &str = "error,warn,warn_if_frequent";
+
+/// Check if the macro found at the provided offset is the searched one, and not
+/// a macro whose name ends with it (ie `debug_warn!(` when searching `warn!(`).
+///
+/// Path prefixes (ie `tracing::warn!(`, `gum::warn!(`) are accepted.
+fn is_macro_start(content: &str, offset: usize) -> bool {
+    content[..offset]
+        .chars()
+        .next_back()
+        .is_none_or(|c| !(c.is_alphanumeric() || c == '_'))
+}
+
+/// A log statement whose regex could not be built.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SkippedLog {
+    /// The repository of the log statement.
+    pub repo: String,
+    /// The source file of the log statement.
+    pub file: String,
+    /// The line of the log statement in the file (1-based).
+    pub line: usize,
+    /// Link to the log statement in the source repository.
+    pub permalink: String,
+    /// Why the regex could not be built (ie `invalid regex`).
+    pub reason: String,
+}
+
+/// The regexes extracted from the sources of a repository.
+#[derive(Debug, Clone, Default)]
+pub struct Extraction {
+    /// The repositories of the regexes.
+    pub repos: Vec<Repo>,
+    /// The regexes of the log statements.
+    pub regexes: Vec<(regex::Regex, RegexDetails)>,
+    /// The log statements skipped during extraction.
+    pub skipped: Vec<SkippedLog>,
+}
+
+/// Build the regexes of the log lines printed by the provided macros (ie `warn`).
+///
+/// The name of the macro is used as the level of the regex.
+pub fn build_regexes(
+    data: Vec<(String, String)>,
+    repo: &Repo,
+    macros: &[String],
+) -> Vec<(regex::Regex, RegexDetails)> {
+    extract_regexes(data, repo, macros).regexes
+}
+
+/// Build the regexes of the log lines printed by the provided macros, along with the
+/// log statements skipped during extraction.
+///
+/// The files are extracted and their regexes compiled in parallel.
+pub fn extract_regexes(data: Vec<(String, String)>, repo: &Repo, macros: &[String]) -> Extraction {
+    let constants = Constants::new(&data);
+    let owners = CodeOwners::find(&data);
+
+    let files: Vec<_> = data
+        .par_iter()
+        .filter(|(file, _)| file.ends_with(".rs"))
+        .map(|(path, content)| extract_file(path, content, repo, macros, &constants, &owners))
+        .collect();
+
+    let mut regexes = Vec::new();
+    let mut skipped = Vec::new();
+    let mut encountered_logs = 0;
+    for file in files {
+        encountered_logs += file.encountered;
+        regexes.extend(file.regexes);
+        skipped.extend(file.skipped);
+    }
+
+    // Stable ordering, such that repeated runs produce identical reports.
+    regexes.sort_by(|(_, a), (_, b)| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+    skipped.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+
     log::info!(
         "Encountered num logs {} and parsed {} regexes with {:.2}% coverage",
         encountered_logs,
@@ -223,7 +720,233 @@ pub fn build_regexes(
         (regexes.len() as f64 / encountered_logs as f64) * 100.0
     );
 
-    regexes
+    Extraction {
+        repos: vec![repo.clone()],
+        regexes,
+        skipped,
+    }
+}
+
+/// The log statements of a source file.
+#[derive(Debug, Default)]
+struct FileExtraction {
+    /// The number of log statements of the file.
+    encountered: usize,
+    /// The regexes of the log statements.
+    regexes: Vec<(regex::Regex, RegexDetails)>,
+    /// The log statements skipped during extraction.
+    skipped: Vec<SkippedLog>,
+}
+
+/// Build the regexes of the log lines of the source file printed by the provided macros.
+fn extract_file<'a>(
+    file_path: &'a str,
+    content: &'a str,
+    repo: &Repo,
+    macros: &[String],
+    constants: &Constants<'a>,
+    owners: &CodeOwners,
+) -> FileExtraction {
+    let mut file = FileExtraction::default();
+
+    for name in macros {
+        // How the log lines look like.
+        let searched = format!("{}!(", name.trim_end_matches(['!', '(']));
+        let searched = searched.as_str();
+
+        let mut str_content = content;
+        let len_searched = searched.len();
+
+        // Line numbers are counted incrementally while advancing through the file.
+        let mut line_number = 1;
+        let mut counted_offset = 0;
+
+        while let Some(start) = str_content.find(searched) {
+            let offset = content.len() - str_content.len();
+            if !is_macro_start(content, offset + start) {
+                str_content = &str_content[start + len_searched..];
+                continue;
+            }
+            file.encountered += 1;
+
+            line_number += content[counted_offset..offset + start]
+                .matches('\n')
+                .count();
+            counted_offset = offset + start;
+            let mut skip = |reason: String| {
+                file.skipped.push(SkippedLog {
+                    repo: repo.name.clone(),
+                    file: file_path.to_string(),
+                    line: line_number,
+                    permalink: repo.permalink(file_path, line_number),
+                    reason,
+                })
+            };
+
+            let end = if let Some(end) = str_content[start..].find(");") {
+                end
+            } else if let Some(end) = str_content[start..].find("),") {
+                end
+            } else {
+                // Note: The file must be malformed, don't assume the log ends at the eof.
+                log::error!("File {file_path} is malformed {start}:..");
+                skip("malformed statement".to_string());
+                break;
+            };
+
+            // str contains everything in between log!( [content] );
+            let str = &str_content[start + len_searched..start + end];
+            // Advance for the next search.
+            str_content = &str_content[start + end..];
+
+            // Handle multiline case.
+            let current_str = str;
+            let multiline_search = extract_log_line(current_str);
+            let Some(line_matched) = multiline_search else {
+                log::debug!("Skipped parsing: {:?}", current_str);
+                skip("message not parsed".to_string());
+                continue;
+            };
+            if line_matched.is_empty() {
+                log::debug!("Skipped empty: {:?}", current_str);
+                skip("empty message".to_string());
+                continue;
+            }
+
+            // Lines made of placeholders only (ie `{}`) are skipped as not having chars.
+            let mut regexed_line = format_to_regex(&line_matched);
+
+            let has_chars = regexed_line.chars().any(|c| c.is_alphabetic());
+            if !has_chars {
+                log::debug!("Skipped not having chars: {:?}", current_str);
+                skip("message without characters".to_string());
+                continue;
+            }
+
+            log::debug!("Regexed line {}", regexed_line);
+            if regexed_line.len() < 10 {
+                log::debug!("Skipped len < 10: {:?}", current_str);
+                skip("message shorter than 10 characters".to_string());
+                continue;
+            }
+
+            // Extra care around misinterpreted lines.
+            if regexed_line.starts_with("PoV size") {
+                regexed_line = "PoV size .*".to_string()
+            }
+            let regex = match regex::Regex::new(&regexed_line) {
+                Ok(regex) => regex,
+                Err(err) => {
+                    log::warn!("Skipped invalid regex {regexed_line:?}: {err}");
+                    skip(format!("invalid regex: {err}"));
+                    continue;
+                }
+            };
+
+            file.regexes.push((
+                regex,
+                RegexDetails {
+                    repo: repo.name.clone(),
+                    file: file_path.to_string(),
+                    start: offset + start,
+                    end: offset + start + end,
+                    line: line_number,
+                    column: column(content, offset + start),
+                    permalink: repo.permalink(file_path, line_number),
+                    description: extract_description(content, offset + start),
+                    target: extract_target(current_str, file_path, constants),
+                    owners: owners.owners(file_path).to_vec(),
+                    ty: searched[..searched.len() - 2].to_string(),
+                },
+            ));
+        }
+    }
+
+    file
+}
+
+/// The level of a user-supplied regex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtraLevel {
+    Warn,
+    Error,
+}
+
+/// A user-supplied regex of a log line, ie produced by a dependency that is not fetched.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ExtraRegex {
+    /// The regex matching the log message.
+    pub pattern: String,
+    /// The level of the log line.
+    pub level: ExtraLevel,
+    /// The origin of the log line, displayed as the repository in the report (ie `libp2p`).
+    pub label: String,
+    /// Explains the log line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The target of the log line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// Load the user-supplied regexes from the provided YAML file.
+///
+/// ```yaml
+/// - pattern: "Libp2p => Failed to dial .*"
+///   level: warn
+///   label: libp2p
+/// ```
+///
+/// The regexes are appended to the ones built from the source repositories.
+pub fn load_extra_regexes(
+    path: &Path,
+) -> Result<Vec<(regex::Regex, RegexDetails)>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+    parse_extra_regexes(&content, &path.display().to_string())
+}
+
+fn parse_extra_regexes(
+    content: &str,
+    file: &str,
+) -> Result<Vec<(regex::Regex, RegexDetails)>, Box<dyn std::error::Error>> {
+    // The file is empty until `triage-unknown` appends the first pattern.
+    if content.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let entries: Vec<ExtraRegex> = serde_yaml::from_str(content)
+        .map_err(|err| format!("Invalid extra regex file {file}: {err}"))?;
+
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let regex = regex::Regex::new(&entry.pattern)
+                .map_err(|err| format!("Invalid extra regex {:?}: {err}", entry.pattern))?;
+            let ty = match entry.level {
+                ExtraLevel::Warn => "warn",
+                ExtraLevel::Error => "error",
+            };
+
+            Ok((
+                regex,
+                RegexDetails {
+                    repo: entry.label,
+                    file: file.to_string(),
+                    start: 0,
+                    end: 0,
+                    // The position of the entry in the file.
+                    line: index + 1,
+                    column: 1,
+                    permalink: String::new(),
+                    description: entry.description,
+                    target: entry.target,
+                    owners: Vec::new(),
+                    ty: ty.to_string(),
+                },
+            ))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -231,6 +954,293 @@ mod tests {
     use super::*;
     use std::collections::HashSet;
 
+    #[test]
+    fn test_extra_regexes() {
+        let regexes = parse_extra_regexes(
+            r#"
+- pattern: "Libp2p => Failed to dial .*"
+  level: warn
+  label: libp2p
+- pattern: "Method call error: .*"
+  level: error
+  label: jsonrpsee
+  description: The RPC request failed.
+"#,
+            "extra.yaml",
+        )
+        .unwrap();
+
+        assert_eq!(regexes.len(), 2);
+        assert!(regexes[0].0.is_match("Libp2p => Failed to dial 12D3Koo"));
+        assert_eq!(regexes[0].1.repo, "libp2p");
+        assert_eq!(regexes[0].1.ty, "warn");
+        assert_eq!(regexes[1].1.ty, "error");
+        assert_eq!(regexes[1].1.line, 2);
+
+        assert!(parse_extra_regexes("- pattern: \"(\"\n  level: warn\n  label: x", "x").is_err());
+        assert!(parse_extra_regexes("- pattern: x\n  level: info\n  label: x", "x").is_err());
+        assert!(parse_extra_regexes("\n", "x").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            format_to_regex("Failed {err:?} for {peer}"),
+            "Failed .*? for .*?"
+        );
+        assert_eq!(
+            format_to_regex("Candidate {0} of {1:>8} (session {session:#?})"),
+            "Candidate .*? of .*? \\(session .*?\\)"
+        );
+        assert_eq!(
+            format_to_regex("Invalid response {{{}}} from {}"),
+            "Invalid response \\{.*?\\} from .*?"
+        );
+        assert_eq!(format_to_regex("Unterminated {peer"), "Unterminated .*?");
+        assert_eq!(format_to_regex("Stray } brace"), "Stray \\} brace");
+
+        // Metacharacter-heavy lines only match themselves.
+        let line = "Slot ^2 + 3.5$ | a? * [b] (c) {{d}} #e: {}";
+        let regex = regex::Regex::new(&format_to_regex(line)).unwrap();
+        assert!(regex.is_match("Slot ^2 + 3.5$ | a? * [b] (c) {d} #e: 42"));
+        assert!(!regex.is_match("Slot ^2 + 325$ | a? * [b] (c) {d} #e: 42"));
+        assert!(!regex.is_match("a"));
+        assert_eq!(
+            format_to_regex("Dropping messages. Queue full? {}"),
+            "Dropping messages\\. Queue full\\? .*?"
+        );
+
+        let regex = regex::Regex::new(&format_to_regex("Failed {err:?} for {peer}")).unwrap();
+        assert!(regex.is_match("Failed Timeout for 12D3KooW"));
+    }
+
+    #[test]
+    fn test_descriptions() {
+        let content = r#"
+    fn on_ban(&mut self) {
+        // reason: the peer sent an invalid request, nothing to do
+        // unless it happens for all peers.
+        log::warn!(target: LOG_TARGET, "Peer banned");
+
+        let x = 1;
+        warn!(target: LOG_TARGET, "No description");
+    }
+"#;
+
+        let first = content.find("log::warn!(").unwrap() + "log::".len();
+        assert_eq!(
+            extract_description(content, first).as_deref(),
+            Some("reason: the peer sent an invalid request, nothing to do unless it happens for all peers.")
+        );
+
+        let second = content.find("warn!(target: LOG_TARGET, \"No").unwrap();
+        assert_eq!(extract_description(content, second), None);
+    }
+
+    #[test]
+    fn test_read_sources() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::fast(),
+        ));
+        let mut append = |entry_type, path: &str, data: &str| {
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(entry_type);
+            header.set_size(data.len() as u64);
+            builder
+                .append_data(&mut header, path, data.as_bytes())
+                .unwrap();
+        };
+        // The commit recorded by GitHub, in the `<length> <key>=<value>` pax format.
+        append(
+            tar::EntryType::XGlobalHeader,
+            "pax_global_header",
+            "21 comment=5f3c9e1ab\n",
+        );
+        append(
+            tar::EntryType::Regular,
+            "test-polkadot-v1.16.0/src/lib.rs",
+            "fn main() {}",
+        );
+        append(
+            tar::EntryType::Regular,
+            "test-polkadot-v1.16.0/README.md",
+            "# Test",
+        );
+        append(
+            tar::EntryType::Regular,
+            "test-polkadot-v1.16.0/src/network/mod.rs",
+            "mod peer;",
+        );
+        append(
+            tar::EntryType::Regular,
+            "test-polkadot-v1.16.0/src/network/tests.rs",
+            "mod tests;",
+        );
+        let tarball = builder.into_inner().unwrap().finish().unwrap();
+
+        let sources = read_sources(&tarball, &PathFilter::default()).unwrap();
+        assert_eq!(sources.commit.as_deref(), Some("5f3c9e1ab"));
+        let files: Vec<_> = sources
+            .files
+            .iter()
+            .map(|(file, _)| file.as_str())
+            .collect();
+        assert_eq!(
+            files,
+            ["src/lib.rs", "src/network/mod.rs", "src/network/tests.rs"]
+        );
+
+        let filter = PathFilter {
+            include: vec!["src/network".to_string()],
+            exclude: vec!["src/network/tests".to_string()],
+        };
+        let sources = read_sources(&tarball, &filter).unwrap();
+        assert_eq!(
+            sources.files,
+            vec![("src/network/mod.rs".to_string(), "mod peer;".to_string())]
+        );
+
+        // The permalinks point at the commit of the tag.
+        let repo = Repo::new(
+            "test".to_string(),
+            "https://github.com/paritytech/test/".to_string(),
+            "polkadot-v1.16.0".to_string(),
+        );
+        assert_eq!(
+            repo.permalink("src/lib.rs", 1),
+            "https://github.com/paritytech/test/blob/polkadot-v1.16.0/src/lib.rs#L1"
+        );
+        assert_eq!(
+            repo.with_commit(sources.commit).permalink("src/lib.rs", 1),
+            "https://github.com/paritytech/test/blob/5f3c9e1ab/src/lib.rs#L1"
+        );
+    }
+
+    #[test]
+    fn test_targets() {
+        let files = [
+            (
+                "net/src/lib.rs",
+                "pub const LOG_TARGET: &str = \"net\";\nmod peerset;\nmod protocol;",
+            ),
+            (
+                "net/src/peerset.rs",
+                "use super::*;\nfn ban() { warn!(target: LOG_TARGET, \"Peer banned from the peerset\"); }",
+            ),
+            (
+                "net/src/protocol/mod.rs",
+                "pub const LOG_TARGET: &str = \"net::protocol\";\nmod notifications;",
+            ),
+            (
+                "net/src/protocol/notifications.rs",
+                "fn close() { warn!(target: super::LOG_TARGET, \"Notification substream closed\"); }",
+            ),
+            (
+                "net/src/discovery.rs",
+                "use crate::protocol::LOG_TARGET;\nfn query() { warn!(target: LOG_TARGET, \"Discovery query failed\"); }",
+            ),
+            (
+                "other/src/lib.rs",
+                "fn other() { warn!(target: LOG_TARGET, \"Target of another crate\"); }",
+            ),
+            (".github/CODEOWNERS", "/net/ @paritytech/networking"),
+        ];
+        let repo = Repo::new(
+            "test".to_string(),
+            "https://github.com/paritytech/test/".to_string(),
+            "master".to_string(),
+        );
+        let result = build_regexes(
+            files
+                .iter()
+                .map(|(file, content)| (file.to_string(), content.to_string()))
+                .collect(),
+            &repo,
+            &["warn".to_string()],
+        );
+
+        let target = |pattern: &str| {
+            result
+                .iter()
+                .find(|(regex, _)| regex.as_str() == pattern)
+                .and_then(|(_, details)| details.target.clone())
+        };
+        assert_eq!(
+            target("Peer banned from the peerset").as_deref(),
+            Some("net")
+        );
+        assert_eq!(
+            target("Notification substream closed").as_deref(),
+            Some("net::protocol")
+        );
+        assert_eq!(
+            target("Discovery query failed").as_deref(),
+            Some("net::protocol")
+        );
+        assert_eq!(target("Target of another crate"), None);
+
+        // The source files are owned from the `CODEOWNERS` file.
+        let owners = |pattern: &str| {
+            result
+                .iter()
+                .find(|(regex, _)| regex.as_str() == pattern)
+                .map(|(_, details)| details.owners.clone())
+                .unwrap_or_default()
+        };
+        assert_eq!(owners("Discovery query failed"), ["@paritytech/networking"]);
+        assert!(owners("Target of another crate").is_empty());
+    }
+
+    #[test]
+    fn test_format_literals() {
+        let string = r##"
+    warn!(target: LOG_TARGET, r#"Failed to decode "{}" message"#, name);
+
+    error!(concat!("Slow block import ", "of {} blocks"), count);
+
+    log::warn!(
+        target: "sync",
+        ?peer,
+        concat!(
+            "Unable to ",
+            r"fetch the state of {:?}",
+        ),
+        hash,
+    );
+
+    warn!(concat!("Stringified ", stringify!(Type)));
+    "##;
+
+        let repo = Repo::new(
+            "test".to_string(),
+            "https://github.com/paritytech/test/".to_string(),
+            "master".to_string(),
+        );
+        let macros: Vec<_> = DEFAULT_LOG_MACROS.split(',').map(String::from).collect();
+        let extraction = extract_regexes(
+            vec![("test.rs".to_string(), string.to_string())],
+            &repo,
+            &macros,
+        );
+
+        let regexes: Vec<_> = extraction
+            .regexes
+            .iter()
+            .map(|(regex, details)| (regex.as_str(), details.ty.as_str()))
+            .collect();
+        assert_eq!(
+            regexes,
+            [
+                ("Failed to decode \".*?\" message", "warn"),
+                ("Slow block import of .*? blocks", "error"),
+                ("Unable to fetch the state of .*?", "warn"),
+            ]
+        );
+        assert_eq!(extraction.skipped.len(), 1);
+        assert_eq!(extraction.skipped[0].reason, "message not parsed");
+    }
+
     #[tokio::test]
     async fn test_inputs() {
         let string = r#"        log::info!("Running panic query");
@@ -277,6 +1287,10 @@ mod tests {
 
     tracing::error!("Failed to initialize overseer: {}", e);
 
+    gum::warn!(target: LOG_TARGET, ?candidate_hash, "Gum dispute participation failed");
+
+    debug_warn!("Not a warning {}", reason);
+
     error!("Checking inherent with identifier `{:?}` failed", e.0);
 
     log::error!("XCMP queue for sibling {:?} is full; dropping messages.", sender);
@@ -286,25 +1300,37 @@ mod tests {
         "`clear_prefix` failed to remove all keys for {}. THIS SHOULD NEVER HAPPEN! 🚨",
         P::get()
     );
-    
+
+    const LOG_TARGET: &str = "parachain::test";
     "#;
 
-        let result = build_regexes(vec![("test.rs".to_string(), string.to_string())]);
+        let repo = Repo::new(
+            "test".to_string(),
+            "https://github.com/paritytech/test/".to_string(),
+            "master".to_string(),
+        );
+        let macros: Vec<_> = DEFAULT_LOG_MACROS.split(',').map(String::from).collect();
+        let result = build_regexes(
+            vec![("test.rs".to_string(), string.to_string())],
+            &repo,
+            &macros,
+        );
 
         let expected: HashSet<_> = [
             // Warns
-            "Failed to prove .* parachain",
+            "Failed to prove .*? parachain",
             "Missing `per_leaf` for known active",
             "Could not retrieve session info from RuntimeInfo",
-            "Validation code unavailable for code hash .* in the state of block .*",
-            ".* banned, disconnecting, reason: .*",
+            "Validation code unavailable for code hash .*? in the state of block .*?",
+            ".*? banned, disconnecting, reason: .*?",
+            "Gum dispute participation failed",
             // Errors
             "Running panic query11",
             "Missing public key for validator",
-            "Failed to initialize overseer: .*",
-            "Checking inherent with identifier `.*` failed",
-            "XCMP queue for sibling .* is full; dropping messages.",
-            "`clear_prefix` failed to remove all keys for .*. THIS SHOULD NEVER HAPPEN! 🚨",
+            "Failed to initialize overseer: .*?",
+            "Checking inherent with identifier `.*?` failed",
+            "XCMP queue for sibling .*? is full; dropping messages\\.",
+            "`clear_prefix` failed to remove all keys for .*?\\. THIS SHOULD NEVER HAPPEN! 🚨",
         ]
         .into_iter()
         .map(|s| s.to_string())
@@ -313,5 +1339,33 @@ mod tests {
         let regex_results: HashSet<_> = result.iter().map(|(regex, _)| regex.to_string()).collect();
         assert_eq!(regex_results.len(), expected.len());
         assert_eq!(regex_results, expected);
+
+        let details = result
+            .iter()
+            .find(|(regex, _)| regex.as_str() == "Running panic query11")
+            .map(|(_, details)| details)
+            .unwrap();
+        assert_eq!(details.line, 4);
+        assert_eq!(details.column, 14);
+        assert_eq!(details.target, None);
+
+        let target = |pattern: &str| {
+            result
+                .iter()
+                .find(|(regex, _)| regex.as_str() == pattern)
+                .and_then(|(_, details)| details.target.clone())
+        };
+        assert_eq!(
+            target("Failed to prove .*? parachain").as_deref(),
+            Some("bridge")
+        );
+        assert_eq!(
+            target("Missing public key for validator").as_deref(),
+            Some("parachain::test")
+        );
+        assert_eq!(
+            details.permalink,
+            "https://github.com/paritytech/test/blob/master/test.rs#L4"
+        );
     }
 }
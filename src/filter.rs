@@ -0,0 +1,98 @@
+//! Filtering of the matched patterns, ie to exclude noisy but accepted patterns.
+//!
+//! The ignore file contains one regex per line, empty lines and lines starting
+//! with `#` are skipped:
+//!
+//! ```text
+//! # Telemetry is not reachable from the test networks.
+//! Error while dialing .*telemetry
+//! ```
+
+use std::path::Path;
+
+use regex::Regex;
+
+/// Excludes the ignored patterns and restricts the run to the selected patterns.
+#[derive(Debug, Clone, Default)]
+pub struct PatternFilter {
+    /// The patterns matching any of these regexes are excluded.
+    ignore: Vec<Regex>,
+    /// When provided, only the patterns matching any of these regexes are kept.
+    only: Vec<Regex>,
+}
+
+impl PatternFilter {
+    /// Construct the filter from the provided regexes and the optional ignore file.
+    pub fn new(
+        ignore: &[String],
+        ignore_file: Option<&Path>,
+        only: &[String],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let compile = |regex: &str| {
+            Regex::new(regex).map_err(|err| format!("Invalid pattern regex {regex:?}: {err}"))
+        };
+
+        let mut filter = PatternFilter {
+            ignore: ignore
+                .iter()
+                .map(|regex| compile(regex))
+                .collect::<Result<_, _>>()?,
+            only: only
+                .iter()
+                .map(|regex| compile(regex))
+                .collect::<Result<_, _>>()?,
+        };
+
+        if let Some(path) = ignore_file {
+            let content = std::fs::read_to_string(path)
+                .map_err(|err| format!("Cannot read ignore file {}: {err}", path.display()))?;
+            for line in content.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                filter.ignore.push(compile(line)?);
+            }
+        }
+
+        Ok(filter)
+    }
+
+    /// Check if the filter keeps every pattern.
+    pub fn is_empty(&self) -> bool {
+        self.ignore.is_empty() && self.only.is_empty()
+    }
+
+    /// Check if the provided pattern is kept in the report.
+    pub fn keep(&self, pattern: &str) -> bool {
+        if self.ignore.iter().any(|regex| regex.is_match(pattern)) {
+            return false;
+        }
+
+        self.only.is_empty() || self.only.iter().any(|regex| regex.is_match(pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_filter() {
+        let filter = PatternFilter::new(&["telemetry".to_string()], None, &[]).unwrap();
+        assert!(!filter.keep("Error while dialing .*telemetry"));
+        assert!(filter.keep("Failed to prove .* parachain"));
+
+        let filter = PatternFilter::new(
+            &["telemetry".to_string()],
+            None,
+            &["dialing".to_string(), "banned".to_string()],
+        )
+        .unwrap();
+        assert!(!filter.keep("Error while dialing .*telemetry"));
+        assert!(filter.keep("Error while dialing .* peer"));
+        assert!(!filter.keep("Failed to prove .* parachain"));
+
+        assert!(PatternFilter::new(&["(".to_string()], None, &[]).is_err());
+        assert!(PatternFilter::default().is_empty());
+    }
+}
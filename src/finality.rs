@@ -0,0 +1,276 @@
+//! Detection of the nodes whose finality lags behind their best block.
+//!
+//! The informant of every node periodically prints its best and finalized blocks, ie
+//! `💤 Idle (8 peers), best: #1234 (0x5e7d…a1f2), finalized #1230 (0x1c2d…b3e4), ⬇ 1.2kiB/s ⬆ 3.4kiB/s`.
+
+use std::{collections::BTreeMap, fmt::Write, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::{
+    collector::{self, Collector},
+    output::Render,
+    parse::LogLine,
+    FinalityConfig,
+};
+
+/// Loki filter selecting the informant lines.
+const QUERY_FILTER: &str = "|~ `best: #[0-9]+.*finalized #[0-9]+`";
+
+/// The node of the lines without node information.
+const UNKNOWN_NODE: &str = "unknown";
+
+/// Extract the best and finalized block numbers of an informant line.
+pub fn parse_informant(line: &str) -> Option<(u64, u64)> {
+    static INFORMANT: OnceLock<Regex> = OnceLock::new();
+
+    // Cheap check before running the regex on every line.
+    if !line.contains("finalized #") {
+        return None;
+    }
+
+    let regex = INFORMANT.get_or_init(|| {
+        Regex::new(r"best: #(\d+).*finalized #(\d+)").expect("Regex is valid; qed")
+    });
+    let captures = regex.captures(line)?;
+
+    Some((captures[1].parse().ok()?, captures[2].parse().ok()?))
+}
+
+/// The finality lag of a single node.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NodeLag {
+    /// The name of the node.
+    pub node: String,
+    /// The number of informant lines.
+    pub samples: usize,
+    /// The maximum lag of the finalized block behind the best block.
+    pub max_lag: u64,
+    /// The average lag of the finalized block behind the best block.
+    pub avg_lag: f64,
+    /// The last reported best block.
+    pub best: u64,
+    /// The last reported finalized block.
+    pub finalized: u64,
+    /// The maximum lag exceeds the threshold.
+    pub lagging: bool,
+}
+
+/// The report of the finality lag of the nodes.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct FinalityReport {
+    /// The lag above which a node is flagged.
+    pub max_lag: u64,
+    /// The lag of every node, the most lagging nodes first.
+    pub nodes: Vec<NodeLag>,
+}
+
+impl FinalityReport {
+    /// The number of nodes whose lag exceeds the threshold.
+    pub fn lagging(&self) -> usize {
+        self.nodes.iter().filter(|node| node.lagging).count()
+    }
+}
+
+impl Render for FinalityReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Finality lag: {} of {} nodes lagging more than {} blocks",
+            self.lagging(),
+            self.nodes.len(),
+            self.max_lag
+        )?;
+        if self.nodes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{0: <40} | {1: <10} | {2: <10} | {3: <10} | {4: <12} | {5: <12} | Status",
+            "Node", "Samples", "Max lag", "Avg lag", "Best", "Finalized"
+        )?;
+        for node in &self.nodes {
+            writeln!(
+                out,
+                "{0: <40} | {1: <10} | {2: <10} | {3: <10.1} | {4: <12} | {5: <12} | {6}",
+                node.node,
+                node.samples,
+                node.max_lag,
+                node.avg_lag,
+                node.best,
+                node.finalized,
+                status(node)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Finality lag: {} of {} nodes lagging more than {} blocks",
+            self.lagging(),
+            self.nodes.len(),
+            self.max_lag
+        )?;
+        if self.nodes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "| Node | Samples | Max lag | Avg lag | Best | Finalized | Status |"
+        )?;
+        writeln!(out, "|---|---|---|---|---|---|---|")?;
+        for node in &self.nodes {
+            writeln!(
+                out,
+                "| {} | {} | {} | {:.1} | {} | {} | {} |",
+                node.node,
+                node.samples,
+                node.max_lag,
+                node.avg_lag,
+                node.best,
+                node.finalized,
+                status(node)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The status of the node, `LAGGING` when its lag exceeds the threshold.
+fn status(node: &NodeLag) -> &'static str {
+    if node.lagging {
+        "LAGGING"
+    } else {
+        "ok"
+    }
+}
+
+/// The informant lines of a single node.
+#[derive(Debug, Default)]
+struct Samples {
+    count: usize,
+    total_lag: u64,
+    max_lag: u64,
+    /// The timestamp, best and finalized blocks of the last line.
+    last: Option<(Option<chrono::NaiveDateTime>, u64, u64)>,
+}
+
+/// Collects the informant lines of the processed lines.
+struct Finality {
+    /// The finality lag above which a node is lagging.
+    max_lag: u64,
+    nodes: BTreeMap<String, Samples>,
+}
+
+impl Finality {
+    fn new(max_lag: u64) -> Self {
+        Finality {
+            max_lag,
+            nodes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Collector for Finality {
+    type Item<'a> = (u64, u64);
+    type Report = FinalityReport;
+
+    const QUERY_FILTER: &'static str = QUERY_FILTER;
+
+    fn select(&self, line: &LogLine) -> Option<(u64, u64)> {
+        parse_informant(&line.message)
+    }
+
+    fn record(
+        &mut self,
+        node: Option<&str>,
+        datetime: Option<chrono::NaiveDateTime>,
+        (best, finalized): (u64, u64),
+    ) {
+        let lag = best.saturating_sub(finalized);
+        let samples = self
+            .nodes
+            .entry(node.unwrap_or(UNKNOWN_NODE).to_string())
+            .or_default();
+        samples.count += 1;
+        samples.total_lag += lag;
+        samples.max_lag = samples.max_lag.max(lag);
+        // Query chunks are processed in order, but keep the latest line regardless.
+        if samples
+            .last
+            .is_none_or(|(last, _, _)| datetime.is_none() || last <= datetime)
+        {
+            samples.last = Some((datetime, best, finalized));
+        }
+    }
+
+    fn report(self) -> FinalityReport {
+        let mut nodes: Vec<_> = self
+            .nodes
+            .into_iter()
+            .map(|(node, samples)| {
+                let (_, best, finalized) = samples.last.unwrap_or_default();
+                NodeLag {
+                    node,
+                    samples: samples.count,
+                    max_lag: samples.max_lag,
+                    avg_lag: samples.total_lag as f64 / samples.count.max(1) as f64,
+                    best,
+                    finalized,
+                    lagging: samples.max_lag > self.max_lag,
+                }
+            })
+            .collect();
+        nodes.sort_by(|a, b| b.max_lag.cmp(&a.max_lag).then_with(|| a.node.cmp(&b.node)));
+
+        FinalityReport {
+            max_lag: self.max_lag,
+            nodes,
+        }
+    }
+}
+
+/// Report the finality lag of the nodes of the provided files or Loki query.
+pub fn run(opts: FinalityConfig) -> Result<FinalityReport, Box<dyn std::error::Error>> {
+    log::info!("Running finality query");
+    collector::run(&opts.config, Finality::new(opts.max_lag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::tests::collect;
+
+    #[test]
+    fn test_finality() {
+        assert_eq!(
+            parse_informant("⚙️  Syncing 12.3 bps, target=#5000 (8 peers), best: #1234 (0x5e7d…a1f2), finalized #1200 (0x1c2d…b3e4)"),
+            Some((1234, 1200))
+        );
+        assert_eq!(parse_informant("🏆 Imported #1234 (0x5e7d…a1f2)"), None);
+
+        let alice = "\
+2024-03-29 16:00:00.000  INFO tokio-runtime-worker substrate: 💤 Idle (8 peers), best: #100 (0x5e7d…a1f2), finalized #98 (0x1c2d…b3e4)
+2024-03-29 16:00:05.000  INFO tokio-runtime-worker substrate: 💤 Idle (8 peers), best: #101 (0x5e7d…a1f2), finalized #97 (0x1c2d…b3e4)";
+        let bob = "\
+2024-03-29 16:00:00.000  INFO tokio-runtime-worker substrate: 💤 Idle (8 peers), best: #100 (0x5e7d…a1f2), finalized #60 (0x1c2d…b3e4)
+2024-03-29 16:00:00.000  INFO tokio-runtime-worker substrate: ✨ Imported #101 (0x5e7d…a1f2)";
+        let report = collect(Finality::new(20), &[("alice", alice), ("bob", bob)]);
+        assert_eq!(report.lagging(), 1);
+        assert_eq!(report.nodes[0].node, "bob");
+        assert_eq!(report.nodes[0].max_lag, 40);
+        assert!(report.nodes[0].lagging);
+        assert_eq!(report.nodes[1].node, "alice");
+        assert_eq!(report.nodes[1].max_lag, 4);
+        assert_eq!(report.nodes[1].avg_lag, 3.0);
+        assert_eq!((report.nodes[1].best, report.nodes[1].finalized), (101, 97));
+    }
+}
@@ -0,0 +1,175 @@
+//! Time to the first error of every node.
+//!
+//! Soak tests often run clean for hours before the nodes start erroring. The first error of
+//! every node is measured from the start of the window, or from the last start of the node
+//! before the error, detected from the version printed in its startup banner.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+
+use crate::matcher::Matches;
+
+/// The first error line of a node.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FirstError {
+    /// The timestamp of the line.
+    pub time: NaiveDateTime,
+    /// The pattern matching the line.
+    pub pattern: String,
+    /// Link to the log statement in the source repository.
+    pub permalink: String,
+}
+
+impl FirstError {
+    /// Record the error of the node, unless an earlier error is already recorded.
+    pub fn record(first_errors: &mut BTreeMap<String, FirstError>, node: &str, error: FirstError) {
+        match first_errors.get(node) {
+            Some(first) if first.time <= error.time => {}
+            _ => {
+                first_errors.insert(node.to_string(), error);
+            }
+        }
+    }
+}
+
+/// The time to the first error of a node, provided with `--time-to-first-error`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FirstErrorReport {
+    /// The node.
+    pub node: String,
+    /// The time from which the first error is measured.
+    pub since: NaiveDateTime,
+    /// The node restarted at `since`, otherwise `since` is the start of the window.
+    pub restarted: bool,
+    /// The first error of the node.
+    pub error: FirstError,
+    /// The seconds from `since` to the first error.
+    pub elapsed_secs: i64,
+}
+
+impl FirstErrorReport {
+    /// The time to the first error (ie `6h 2m 3s`).
+    pub fn elapsed(&self) -> String {
+        let secs = self.elapsed_secs;
+        let (hours, minutes, secs) = (secs / 3600, secs % 3600 / 60, secs % 60);
+        match (hours, minutes) {
+            (0, 0) => format!("{secs}s"),
+            (0, _) => format!("{minutes}m {secs}s"),
+            _ => format!("{hours}h {minutes}m {secs}s"),
+        }
+    }
+
+    /// The origin of the measure (ie `restart 2024-03-29 16:00:00`).
+    pub fn origin(&self, format: &str) -> String {
+        let origin = if self.restarted {
+            "restart"
+        } else {
+            "window start"
+        };
+        format!("{origin} {}", self.since.format(format))
+    }
+}
+
+/// The time to the first error of every node, the earliest first.
+///
+/// The errors are measured from the provided start of the window, or from the first matched
+/// line when not provided.
+pub fn report(matches: &Matches, window_start: Option<NaiveDateTime>) -> Vec<FirstErrorReport> {
+    let window_start = window_start.or_else(|| {
+        matches
+            .found_lines
+            .values()
+            .filter_map(|entry| entry.timeline.first)
+            .min()
+    });
+
+    let mut reports: Vec<_> = matches
+        .first_errors
+        .iter()
+        .map(|(node, error)| {
+            let restart = matches
+                .starts
+                .get(node)
+                .and_then(|starts| starts.iter().filter(|start| **start <= error.time).max());
+            let since = restart
+                .copied()
+                .or(window_start)
+                .map_or(error.time, |since| since.min(error.time));
+
+            FirstErrorReport {
+                node: node.clone(),
+                since,
+                restarted: restart.is_some(),
+                error: error.clone(),
+                elapsed_secs: (error.time - since).num_seconds(),
+            }
+        })
+        .collect();
+    reports.sort_by(|a, b| {
+        a.elapsed_secs
+            .cmp(&b.elapsed_secs)
+            .then_with(|| a.node.cmp(&b.node))
+    });
+
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fetch_git::RegexDetails, matcher::Matcher};
+
+    #[test]
+    fn test_first_errors() {
+        let details = |ty: &str| RegexDetails {
+            repo: "test".to_string(),
+            file: "test.rs".to_string(),
+            start: 0,
+            end: 0,
+            line: 1,
+            column: 1,
+            permalink: format!("https://github.com/test/blob/master/test.rs#{ty}"),
+            description: None,
+            target: None,
+            owners: Vec::new(),
+            ty: ty.to_string(),
+        };
+        let matcher = Matcher::new(
+            vec![
+                (regex::Regex::new("Slow").unwrap(), details("warn")),
+                (regex::Regex::new("Failed").unwrap(), details("error")),
+            ],
+            Vec::new(),
+        )
+        .with_first_errors(true);
+
+        let mut matches = Matches::default();
+        let lines = [
+            "2024-03-29 10:00:00.000  WARN main test: Slow",
+            "2024-03-29 16:00:03.000 ERROR main test: Failed",
+            "2024-03-29 16:30:00.000 ERROR main test: Failed",
+        ];
+        matcher.process_source(Some("alice"), lines.into_iter(), &mut matches);
+        let bob = [
+            "2024-03-29 12:00:00.000  INFO main sc_cli::runner: ✌️  version 1.10.0-7049c3c9883",
+            "2024-03-29 12:05:00.000 ERROR main test: Failed",
+        ];
+        matcher.detect_versions(Some("bob"), bob.into_iter(), &mut matches);
+        matcher.process_source(Some("bob"), bob.into_iter(), &mut matches);
+
+        let window_start = "2024-03-29T10:00:00".parse().ok();
+        let reports = report(&matches, window_start);
+        let reports: Vec<_> = reports
+            .iter()
+            .map(|report| (report.node.as_str(), report.restarted, report.elapsed()))
+            .collect();
+        assert_eq!(
+            reports,
+            [
+                ("bob", true, "5m 0s".to_string()),
+                ("alice", false, "6h 0m 3s".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Access to the repositories hosted on GitHub or GitHub Enterprise.
+//!
+//! The private repositories are fetched with a token, through the tarball endpoint of the
+//! REST API: the archive links of the web interface do not accept tokens.
+
+use reqwest::{RequestBuilder, Url};
+
+/// The user agent of the GitHub API requests, which rejects the requests without one.
+const USER_AGENT: &str = "sub-triage-logs";
+
+/// The host of the public GitHub, whose API is served from a dedicated host.
+const GITHUB_HOST: &str = "github.com";
+
+/// The client of the GitHub repositories.
+#[derive(Clone)]
+pub struct GitHub {
+    /// The token authenticating the requests, for the private repositories.
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for GitHub {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitHub")
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Default for GitHub {
+    /// The anonymous client, for the public repositories.
+    fn default() -> Self {
+        GitHub::new(None)
+    }
+}
+
+impl GitHub {
+    /// Construct the client authenticating with the provided token.
+    pub fn new(token: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap_or_default();
+
+        GitHub { token, client }
+    }
+
+    /// Check if the requests are authenticated.
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// A GET request of the provided URL, authenticated when a token is provided.
+    ///
+    /// The token is dropped by the redirects to other hosts (ie `codeload.github.com`).
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        let request = self.client.get(url);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// The URL of the tarball of the repository branch, tag or commit.
+    ///
+    /// The authenticated requests use the REST API, the others the public archive links.
+    pub fn archive_url(&self, repo_url: &str, branch: &str) -> String {
+        match api_repo_url(repo_url).filter(|_| self.has_token()) {
+            Some(api) => format!("{api}/tarball/{branch}"),
+            None => format!(
+                "{}/archive/{}.tar.gz",
+                repo_url.trim_end_matches('/'),
+                branch
+            ),
+        }
+    }
+}
+
+/// The API URL of the latest commit of the branch.
+pub fn commit_url(repo_url: &str, branch: &str) -> Option<String> {
+    api_repo_url(repo_url).map(|api| format!("{api}/commits/{branch}"))
+}
+
+/// The API URL of the repository.
+///
+/// The API of GitHub Enterprise is served from the `/api/v3` path of the instance.
+fn api_repo_url(repo_url: &str) -> Option<String> {
+    let url = Url::parse(repo_url).ok()?;
+    let host = url.host_str()?;
+    let mut segments = url.path_segments()?.filter(|segment| !segment.is_empty());
+    let (owner, repo) = (segments.next()?, segments.next()?);
+    let repo = repo.trim_end_matches(".git");
+
+    let api = if host == GITHUB_HOST {
+        "https://api.github.com".to_string()
+    } else {
+        let port = url
+            .port()
+            .map(|port| format!(":{port}"))
+            .unwrap_or_default();
+        format!("{}://{host}{port}/api/v3", url.scheme())
+    };
+    Some(format!("{api}/repos/{owner}/{repo}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            commit_url("https://github.com/paritytech/polkadot-sdk/", "master").as_deref(),
+            Some("https://api.github.com/repos/paritytech/polkadot-sdk/commits/master")
+        );
+        assert_eq!(
+            commit_url("https://ghe.example.com/parity/node.git", "v1").as_deref(),
+            Some("https://ghe.example.com/api/v3/repos/parity/node/commits/v1")
+        );
+        assert_eq!(commit_url("https://github.com/paritytech", "master"), None);
+
+        let url = "https://github.com/paritytech/polkadot-sdk/";
+        assert_eq!(
+            GitHub::default().archive_url(url, "master"),
+            "https://github.com/paritytech/polkadot-sdk/archive/master.tar.gz"
+        );
+        assert_eq!(
+            GitHub::new(Some("token".to_string())).archive_url(url, "master"),
+            "https://api.github.com/repos/paritytech/polkadot-sdk/tarball/master"
+        );
+        assert!(
+            !format!("{:?}", GitHub::new(Some("ghp_secret".to_string()))).contains("ghp_secret")
+        );
+    }
+}
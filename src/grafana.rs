@@ -0,0 +1,329 @@
+//! Push annotations of the detected events to Grafana.
+//!
+//! The annotations are created via the Grafana HTTP API, such that panics and
+//! error spikes show up overlaid on the node dashboards.
+
+use chrono::NaiveDateTime;
+use reqwest::Url;
+
+use crate::{
+    histogram::{Buckets, Timeline},
+    normalize,
+};
+
+/// The default interval of the buckets used to detect error spikes.
+pub const DEFAULT_SPIKE_INTERVAL: chrono::Duration = chrono::Duration::minutes(5);
+
+/// The panics of a location within an interval are a single annotation.
+pub const PANIC_INTERVAL: chrono::Duration = chrono::Duration::minutes(5);
+
+/// The maximum length of the pattern in the annotation tags.
+const MAX_TAG_LEN: usize = 50;
+
+/// The maximum number of annotations created by a run, the first ones are kept.
+const MAX_ANNOTATIONS: usize = 500;
+
+/// The number of annotations created concurrently.
+const BATCH_SIZE: usize = 20;
+
+/// An annotation of the Grafana HTTP API.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    /// The start of the annotation in milliseconds since the unix epoch.
+    pub time: i64,
+    /// The end of region annotations in milliseconds since the unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_end: Option<i64>,
+    /// The dashboard of the annotation, organization wide if not provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dashboard_uid: Option<String>,
+    /// The tags of the annotation.
+    pub tags: Vec<String>,
+    /// The text of the annotation.
+    pub text: String,
+}
+
+impl Annotation {
+    /// Annotate the panics of a location, starting at the provided time with the line
+    /// of the first panic.
+    pub fn panic(time: NaiveDateTime, line: &str, count: usize) -> Self {
+        Annotation {
+            time: time.and_utc().timestamp_millis(),
+            time_end: None,
+            dashboard_uid: None,
+            tags: vec!["panic".to_string()],
+            text: match count {
+                1 => line.to_string(),
+                count => format!("{line} ({count} panics)"),
+            },
+        }
+    }
+
+    /// Annotate the spikes of a pattern, detected from the timeline of its lines
+    /// bucketed by the provided interval, exceeding `factor` times its median count.
+    pub fn spikes(
+        pattern: &str,
+        level: &str,
+        timeline: &Timeline,
+        interval: chrono::Duration,
+        factor: f64,
+    ) -> Vec<Self> {
+        let Some(buckets) = Buckets::new(interval, timeline.bounds()) else {
+            return Vec::new();
+        };
+
+        buckets
+            .spikes(&buckets.count_timeline(timeline), factor)
+            .into_iter()
+            .map(|spike| Annotation {
+                time: spike.start.and_utc().timestamp_millis(),
+                time_end: Some(spike.end.and_utc().timestamp_millis()),
+                dashboard_uid: None,
+                tags: vec![
+                    "error-spike".to_string(),
+                    level.to_string(),
+                    pattern.chars().take(MAX_TAG_LEN).collect(),
+                ],
+                text: format!("Spike of {} lines matching `{}`", spike.count, pattern),
+            })
+            .collect()
+    }
+
+    /// Add a tag to the annotation (ie the chain).
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+}
+
+/// Creates annotations via the Grafana HTTP API.
+#[derive(Clone)]
+pub struct Grafana {
+    /// The URL of the Grafana instance.
+    url: String,
+    /// The API token of the service account.
+    token: Option<String>,
+    /// The dashboard of the annotations.
+    dashboard_uid: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Grafana {
+    /// Construct a new client of the Grafana instance.
+    pub fn new(url: String, token: Option<String>, dashboard_uid: Option<String>) -> Self {
+        Grafana {
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            dashboard_uid,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Create the provided annotations, at most [`MAX_ANNOTATIONS`] in batches of
+    /// [`BATCH_SIZE`] concurrent requests.
+    ///
+    /// Failures are logged and do not abort the remaining annotations.
+    pub async fn annotate(&self, mut annotations: Vec<Annotation>) -> usize {
+        if annotations.len() > MAX_ANNOTATIONS {
+            log::warn!(
+                "Creating only the first {MAX_ANNOTATIONS} of {} Grafana annotations",
+                annotations.len()
+            );
+            annotations.truncate(MAX_ANNOTATIONS);
+        }
+
+        let mut created = 0;
+        let mut annotations = annotations.into_iter().peekable();
+        while annotations.peek().is_some() {
+            let mut batch = tokio::task::JoinSet::new();
+            for mut annotation in annotations.by_ref().take(BATCH_SIZE) {
+                annotation.dashboard_uid = self.dashboard_uid.clone();
+                let grafana = self.clone();
+                batch.spawn(async move {
+                    grafana
+                        .create(&annotation)
+                        .await
+                        .map_err(|err| err.to_string())
+                });
+            }
+
+            while let Some(result) = batch.join_next().await {
+                match result
+                    .map_err(|err| err.to_string())
+                    .and_then(|result| result)
+                {
+                    Ok(()) => created += 1,
+                    Err(err) => log::warn!("Failed to create Grafana annotation: {err}"),
+                }
+            }
+        }
+
+        log::info!("Created {created} Grafana annotations");
+        created
+    }
+
+    async fn create(&self, annotation: &Annotation) -> Result<(), Box<dyn std::error::Error>> {
+        let mut request = self
+            .client
+            .post(format!("{}/api/annotations", self.url))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(annotation)?);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "{}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds the links of the patterns to their lines in Grafana Explore.
+#[derive(Debug, Clone)]
+pub struct Explore {
+    /// The Explore page of the Grafana instance.
+    url: Url,
+    /// The UID of the Loki datasource.
+    datasource: String,
+    /// The stream selector of the queried lines (ie `{chain="versi"}`).
+    selector: String,
+    /// The queried time range, in milliseconds since the unix epoch.
+    range: (i64, i64),
+}
+
+impl Explore {
+    /// Construct the links of the Grafana instance, querying the selected streams of the
+    /// datasource over the time range.
+    pub fn new(
+        url: &str,
+        datasource: String,
+        selector: String,
+        (from, to): (NaiveDateTime, NaiveDateTime),
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let url = Url::parse(&format!("{}/explore", url.trim_end_matches('/')))
+            .map_err(|err| format!("Invalid Grafana URL {url}: {err}"))?;
+
+        Ok(Explore {
+            url,
+            datasource,
+            selector,
+            range: (
+                from.and_utc().timestamp_millis(),
+                to.and_utc().timestamp_millis(),
+            ),
+        })
+    }
+
+    /// The link to the lines matching the regex, and containing the deduplication key if any.
+    ///
+    /// The normalized keys are matched by their literal parts, between the placeholders.
+    pub fn link(&self, regex: &str, key: Option<&str>) -> String {
+        // The debug representation escapes the quotes and backslashes like LogQL strings.
+        let mut expr = format!("{} |~ {regex:?}", self.selector);
+        for literal in key.map(normalize::literals).unwrap_or_default() {
+            expr.push_str(&format!(" |= {literal:?}"));
+        }
+        let panes = serde_json::json!({
+            "a": {
+                "datasource": self.datasource,
+                "queries": [{
+                    "refId": "A",
+                    "expr": expr,
+                    "queryType": "range",
+                    "datasource": { "type": "loki", "uid": self.datasource },
+                }],
+                "range": {
+                    "from": self.range.0.to_string(),
+                    "to": self.range.1.to_string(),
+                },
+            }
+        });
+
+        let mut url = self.url.clone();
+        url.query_pairs_mut()
+            .append_pair("schemaVersion", "1")
+            .append_pair("panes", &panes.to_string());
+        url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spike_annotations() {
+        let start =
+            NaiveDateTime::parse_from_str("2024-03-29 16:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let mut timeline = Timeline::default();
+        (0..10)
+            .map(|index| start + chrono::Duration::minutes(index * 5))
+            .chain((0..20).map(|_| start + chrono::Duration::minutes(21)))
+            .for_each(|time| timeline.insert(time));
+
+        let annotations = Annotation::spikes(
+            "Failed to prove .* parachain",
+            "warn",
+            &timeline,
+            DEFAULT_SPIKE_INTERVAL,
+            crate::histogram::DEFAULT_SPIKE_FACTOR,
+        );
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(
+            annotations[0].time,
+            (start + chrono::Duration::minutes(20))
+                .and_utc()
+                .timestamp_millis()
+        );
+        assert_eq!(annotations[0].tags[0], "error-spike");
+    }
+
+    #[test]
+    fn test_explore_link() {
+        let range = (
+            NaiveDateTime::parse_from_str("2024-03-29 16:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            NaiveDateTime::parse_from_str("2024-03-29 17:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+        );
+        let explore = Explore::new(
+            "https://grafana.example.com/",
+            "loki-uid".to_string(),
+            r#"{chain="versi"}"#.to_string(),
+            range,
+        )
+        .unwrap();
+
+        let parse = |link: String| {
+            let url = Url::parse(&link).unwrap();
+            assert_eq!(url.path(), "/explore");
+            let panes = url
+                .query_pairs()
+                .find(|(name, _)| name == "panes")
+                .map(|(_, panes)| panes.into_owned())
+                .unwrap();
+            serde_json::from_str::<serde_json::Value>(&panes).unwrap()
+        };
+        let panes = parse(explore.link(r"Failed to dial .*?\.", Some("Timeout")));
+        assert_eq!(
+            panes["a"]["queries"][0]["expr"],
+            r#"{chain="versi"} |~ "Failed to dial .*?\\." |= "Timeout""#
+        );
+        assert_eq!(panes["a"]["datasource"], "loki-uid");
+        assert_eq!(panes["a"]["range"]["from"], "1711728000000");
+
+        // The normalized keys are matched by their literal parts.
+        let panes = parse(explore.link("Banned", Some(" <peer> at #<num>")));
+        assert_eq!(
+            panes["a"]["queries"][0]["expr"],
+            r#"{chain="versi"} |~ "Banned" |= " at #""#
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Time-bucketed occurrence histograms of the matched patterns.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+
+/// The maximum number of buckets of a histogram.
+///
+/// Larger intervals are used when the provided interval would exceed it.
+const MAX_BUCKETS: i64 = 500;
+
+/// The duration of the slots of the timelines, in seconds.
+const SLOT_SECS: i64 = 10;
+
+/// Characters of the sparkline, from the lowest to the highest count.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Parse an interval like `30s`, `10m`, `1h` or `1d`.
+pub fn parse_interval(value: &str) -> Result<chrono::Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("Invalid interval {value:?}, expected ie `10m`"))?;
+    if number == 0 {
+        return Err("The interval must be positive".into());
+    }
+
+    match unit {
+        "s" => Ok(chrono::Duration::seconds(number)),
+        "m" | "" => Ok(chrono::Duration::minutes(number)),
+        "h" => Ok(chrono::Duration::hours(number)),
+        "d" => Ok(chrono::Duration::days(number)),
+        _ => Err(format!(
+            "Invalid interval unit {unit:?}, expected s, m, h or d"
+        )),
+    }
+}
+
+/// The times of the lines of a pattern, counted per slot of [`SLOT_SECS`] seconds, such
+/// that its memory is bounded by the time range rather than by the number of lines.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Timeline {
+    /// The time of the first line.
+    pub first: Option<NaiveDateTime>,
+    /// The time of the last line.
+    pub last: Option<NaiveDateTime>,
+    /// The number of lines per slot, by the UNIX timestamp of the start of the slot.
+    slots: BTreeMap<i64, usize>,
+}
+
+impl Timeline {
+    /// Record the time of a line.
+    pub fn insert(&mut self, time: NaiveDateTime) {
+        self.first = Some(self.first.map_or(time, |first| first.min(time)));
+        self.last = Some(self.last.map_or(time, |last| last.max(time)));
+
+        let secs = time.and_utc().timestamp();
+        *self
+            .slots
+            .entry(secs - secs.rem_euclid(SLOT_SECS))
+            .or_default() += 1;
+    }
+
+    /// Merge the lines of another timeline into this one.
+    pub fn merge(&mut self, other: Timeline) {
+        self.first = self.first.into_iter().chain(other.first).min();
+        self.last = self.last.into_iter().chain(other.last).max();
+        for (slot, count) in other.slots {
+            *self.slots.entry(slot).or_default() += count;
+        }
+    }
+
+    /// The number of lines with a time.
+    pub fn len(&self) -> usize {
+        self.slots.values().sum()
+    }
+
+    /// Check if no line has a time.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The times of the first and last lines.
+    pub fn bounds(&self) -> impl Iterator<Item = &NaiveDateTime> {
+        self.first.iter().chain(self.last.iter())
+    }
+
+    /// The number of lines per slot, by the start of the slot (or the first time).
+    pub fn slots(&self) -> impl Iterator<Item = (NaiveDateTime, usize)> + '_ {
+        self.slots.iter().filter_map(|(slot, count)| {
+            let start = chrono::DateTime::from_timestamp(*slot, 0)?.naive_utc();
+            Some((self.first.map_or(start, |first| start.max(first)), *count))
+        })
+    }
+}
+
+/// A window of consecutive buckets whose counts spike over the median count.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Spike {
+    /// The start of the first bucket of the window.
+    pub start: NaiveDateTime,
+    /// The end of the last bucket of the window.
+    pub end: NaiveDateTime,
+    /// The number of lines of the window.
+    pub count: usize,
+    /// The highest count of a bucket of the window, over the median count.
+    pub factor: f64,
+}
+
+/// The time buckets shared by the histograms of all patterns.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Buckets {
+    /// The start of the first bucket.
+    pub start: NaiveDateTime,
+    /// The duration of a bucket in seconds.
+    pub interval_secs: i64,
+    /// The number of buckets.
+    pub len: usize,
+}
+
+impl Buckets {
+    /// Construct the buckets covering the provided timestamps.
+    ///
+    /// Buckets are aligned to multiples of the interval since the unix epoch.
+    pub fn new<'a>(
+        interval: chrono::Duration,
+        timestamps: impl Iterator<Item = &'a NaiveDateTime>,
+    ) -> Option<Self> {
+        let (min, max) = timestamps.fold(None, |range, timestamp| match range {
+            None => Some((*timestamp, *timestamp)),
+            Some((min, max)) => Some((min.min(*timestamp), max.max(*timestamp))),
+        })?;
+
+        let range = (max - min).num_seconds();
+        let mut interval_secs = interval.num_seconds().max(1);
+        if range / interval_secs >= MAX_BUCKETS {
+            interval_secs = range / (MAX_BUCKETS - 1) + 1;
+            log::warn!(
+                "Histogram interval too small for the time range, using {}s",
+                interval_secs
+            );
+        }
+
+        let start_secs = min.and_utc().timestamp();
+        let start_secs = start_secs - start_secs.rem_euclid(interval_secs);
+        let start = chrono::DateTime::from_timestamp(start_secs, 0)?.naive_utc();
+        let len = ((max - start).num_seconds() / interval_secs) as usize + 1;
+
+        Some(Buckets {
+            start,
+            interval_secs,
+            len,
+        })
+    }
+
+    /// The start of the bucket at the provided index.
+    pub fn bucket_start(&self, index: usize) -> NaiveDateTime {
+        self.start + chrono::Duration::seconds(self.interval_secs * index as i64)
+    }
+
+    /// The windows of consecutive buckets whose counts exceed `factor` times the median
+    /// count of the buckets.
+    ///
+    /// Buckets with less than [`SPIKE_MIN_COUNT`] lines are not spikes. A median of zero
+    /// counts as one line, such that the sparse patterns are compared against a single
+    /// line per bucket.
+    pub fn spikes(&self, counts: &[usize], factor: f64) -> Vec<Spike> {
+        let mut sorted = counts.to_vec();
+        sorted.sort_unstable();
+        let median = match sorted.len() {
+            0 => return Vec::new(),
+            len if len % 2 == 0 => (sorted[len / 2 - 1] + sorted[len / 2]) as f64 / 2.0,
+            len => sorted[len / 2] as f64,
+        };
+        let median = median.max(1.0);
+
+        let mut spikes: Vec<Spike> = Vec::new();
+        let mut previous = None;
+        for (index, &count) in counts.iter().enumerate() {
+            if count < SPIKE_MIN_COUNT || (count as f64) < factor * median {
+                continue;
+            }
+
+            let end = self.bucket_start(index + 1);
+            match spikes.last_mut() {
+                // Consecutive buckets are merged in a single window.
+                Some(spike) if previous == Some(index - 1) => {
+                    spike.end = end;
+                    spike.count += count;
+                    spike.factor = spike.factor.max(count as f64 / median);
+                }
+                _ => spikes.push(Spike {
+                    start: self.bucket_start(index),
+                    end,
+                    count,
+                    factor: count as f64 / median,
+                }),
+            }
+            previous = Some(index);
+        }
+
+        spikes
+    }
+
+    /// Render the buckets of the provided times as `|`, aligned with the sparklines.
+    pub fn markers<'a>(&self, times: impl Iterator<Item = &'a NaiveDateTime>) -> String {
+        self.count(times)
+            .into_iter()
+            .map(|count| if count > 0 { '|' } else { ' ' })
+            .collect()
+    }
+
+    /// Count the lines of the timeline per bucket.
+    pub fn count_timeline(&self, timeline: &Timeline) -> Vec<usize> {
+        let mut counts = vec![0; self.len];
+        for (time, count) in timeline.slots() {
+            let index = (time - self.start).num_seconds() / self.interval_secs;
+            if let Some(bucket) = counts.get_mut(index as usize) {
+                *bucket += count;
+            }
+        }
+        counts
+    }
+
+    /// Count the provided timestamps per bucket.
+    pub fn count<'a>(&self, timestamps: impl Iterator<Item = &'a NaiveDateTime>) -> Vec<usize> {
+        let mut counts = vec![0; self.len];
+        for timestamp in timestamps {
+            let index = (*timestamp - self.start).num_seconds() / self.interval_secs;
+            if let Some(count) = counts.get_mut(index as usize) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+}
+
+/// The minimum number of lines in a bucket to be considered a spike.
+const SPIKE_MIN_COUNT: usize = 10;
+
+/// The factor over the median count of the buckets of the spikes, unless provided
+/// (ie the spikes annotated in Grafana without `--spike-factor`).
+pub const DEFAULT_SPIKE_FACTOR: f64 = 5.0;
+
+/// Render the counts as a sparkline, scaled to the highest count.
+pub fn sparkline(counts: &[usize]) -> String {
+    let max = counts.iter().copied().max().unwrap_or_default();
+
+    counts
+        .iter()
+        .map(|&count| match count {
+            0 => ' ',
+            count => SPARKS[(count * SPARKS.len()).div_ceil(max) - 1],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        assert_eq!(
+            parse_interval("10m").unwrap(),
+            chrono::Duration::minutes(10)
+        );
+        assert_eq!(parse_interval("1h").unwrap(), chrono::Duration::hours(1));
+        assert!(parse_interval("10x").is_err());
+
+        let time = |value: &str| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap();
+        let timestamps = [
+            time("2024-03-29 16:01:00"),
+            time("2024-03-29 16:02:00"),
+            time("2024-03-29 16:35:00"),
+        ];
+
+        let buckets = Buckets::new(parse_interval("10m").unwrap(), timestamps.iter()).unwrap();
+        assert_eq!(buckets.start, time("2024-03-29 16:00:00"));
+        assert_eq!(buckets.count(timestamps.iter()), vec![2, 0, 0, 1]);
+
+        // The timelines count the lines per slot, merged in any order.
+        let (mut timeline, mut other) = (Timeline::default(), Timeline::default());
+        timeline.insert(timestamps[2]);
+        timeline.insert(time("2024-03-29 16:35:05"));
+        other.insert(timestamps[1]);
+        other.insert(timestamps[0]);
+        timeline.merge(other);
+        assert_eq!(timeline.len(), 4);
+        assert_eq!(timeline.first, Some(timestamps[0]));
+        assert_eq!(timeline.last, Some(time("2024-03-29 16:35:05")));
+        assert_eq!(buckets.count_timeline(&timeline), vec![2, 0, 0, 2]);
+        assert_eq!(sparkline(&[2, 0, 0, 1]), "█  ▄");
+
+        let spikes = buckets.spikes(&[2, 2, 40, 30, 2, 60, 2], 10.0);
+        assert_eq!(
+            spikes,
+            vec![
+                Spike {
+                    start: time("2024-03-29 16:20:00"),
+                    end: time("2024-03-29 16:40:00"),
+                    count: 70,
+                    factor: 20.0,
+                },
+                Spike {
+                    start: time("2024-03-29 16:50:00"),
+                    end: time("2024-03-29 17:00:00"),
+                    count: 60,
+                    factor: 30.0,
+                }
+            ]
+        );
+        assert!(buckets.spikes(&[0, 0, 9, 0], 5.0).is_empty());
+        assert!(buckets
+            .spikes(&[10, 12, 11, 10], DEFAULT_SPIKE_FACTOR)
+            .is_empty());
+        assert!(buckets.spikes(&[], 5.0).is_empty());
+    }
+}
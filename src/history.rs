@@ -0,0 +1,216 @@
+//! Persistent history of the triage runs, stored in SQLite.
+//!
+//! Every run records its time range, chains and the count of every pattern, such
+//! that the evolution of a pattern can be followed across runs (see `trend`).
+
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::report::TriageReport;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    recorded_at TEXT NOT NULL,
+    start_time TEXT,
+    end_time TEXT,
+    chain TEXT,
+    categories INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    warns INTEGER NOT NULL,
+    unknown INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS patterns (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    pattern TEXT NOT NULL,
+    level TEXT NOT NULL,
+    repo TEXT NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    count INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS patterns_run ON patterns(run_id);
+";
+
+/// The context of a recorded run.
+#[derive(Debug, Clone, Default)]
+pub struct RunInfo {
+    /// The start of the triaged time range.
+    pub start_time: Option<String>,
+    /// The end of the triaged time range.
+    pub end_time: Option<String>,
+    /// The triaged chains, comma separated.
+    pub chain: Option<String>,
+}
+
+/// The count of the matching patterns in a recorded run.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct TrendPoint {
+    /// The identifier of the run.
+    pub run: i64,
+    /// When the run was recorded.
+    pub recorded_at: String,
+    /// The start of the triaged time range.
+    pub start_time: Option<String>,
+    /// The end of the triaged time range.
+    pub end_time: Option<String>,
+    /// The triaged chains.
+    pub chain: Option<String>,
+    /// The number of lines of the matching patterns.
+    pub count: usize,
+    /// The number of matching patterns.
+    pub patterns: usize,
+}
+
+/// The history of the triage runs.
+pub struct HistoryStore {
+    connection: Connection,
+}
+
+impl HistoryStore {
+    /// Open the store from an URL like `sqlite://triage.db`, creating it if needed.
+    pub fn open(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = url.strip_prefix("sqlite://").unwrap_or(url);
+        if path.is_empty() {
+            return Err(format!("Invalid store {url:?}, expected ie `sqlite://triage.db`").into());
+        }
+
+        Self::new(Connection::open(Path::new(path))?)
+    }
+
+    fn new(connection: Connection) -> Result<Self, Box<dyn std::error::Error>> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(HistoryStore { connection })
+    }
+
+    /// Record the counts of every pattern of the report.
+    ///
+    /// Returns the identifier of the recorded run.
+    pub fn record(
+        &mut self,
+        run: &RunInfo,
+        report: &TriageReport,
+    ) -> Result<i64, Box<dyn std::error::Error>> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (recorded_at, start_time, end_time, chain, categories, errors, warns, unknown)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                run.start_time,
+                run.end_time,
+                run.chain,
+                report.summary.categories,
+                report.summary.errors,
+                report.summary.warns,
+                report.summary.unknown,
+            ],
+        )?;
+        let run_id = transaction.last_insert_rowid();
+
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO patterns (run_id, pattern, level, repo, file, line, count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for pattern in &report.patterns {
+                insert.execute(params![
+                    run_id,
+                    pattern.pattern,
+                    pattern.level,
+                    pattern.repo,
+                    pattern.file,
+                    pattern.line,
+                    pattern.count,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+
+        log::info!("Recorded run {run_id} in the history store");
+        Ok(run_id)
+    }
+
+    /// The count of the patterns containing the provided text in the last `runs` runs,
+    /// oldest first.
+    ///
+    /// Runs are optionally restricted to the provided chain.
+    pub fn trend(
+        &self,
+        pattern: &str,
+        chain: Option<&str>,
+        runs: usize,
+    ) -> Result<Vec<TrendPoint>, Box<dyn std::error::Error>> {
+        let mut statement = self.connection.prepare(
+            "SELECT runs.id, runs.recorded_at, runs.start_time, runs.end_time, runs.chain,
+                    COALESCE(SUM(patterns.count), 0), COUNT(patterns.pattern)
+             FROM runs
+             LEFT JOIN patterns ON patterns.run_id = runs.id AND instr(patterns.pattern, ?1) > 0
+             WHERE ?2 IS NULL OR runs.chain = ?2
+             GROUP BY runs.id
+             ORDER BY runs.id DESC
+             LIMIT ?3",
+        )?;
+
+        let mut points = statement
+            .query_map(params![pattern, chain, runs as i64], |row| {
+                Ok(TrendPoint {
+                    run: row.get(0)?,
+                    recorded_at: row.get(1)?,
+                    start_time: row.get(2)?,
+                    end_time: row.get(3)?,
+                    chain: row.get(4)?,
+                    count: row.get::<_, i64>(5)? as usize,
+                    patterns: row.get::<_, i64>(6)? as usize,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        points.reverse();
+
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::Matches;
+
+    #[test]
+    fn test_history() {
+        let mut store = HistoryStore::new(Connection::open_in_memory().unwrap()).unwrap();
+        let report = TriageReport::new(&Matches::default(), None, false, None);
+        let run = RunInfo {
+            chain: Some("versi".to_string()),
+            ..Default::default()
+        };
+
+        let report_with = |count| {
+            let mut report = report.clone();
+            for (pattern, count) in [("Failed to prove .* parachain", count), ("Other", 1)] {
+                report
+                    .patterns
+                    .push(crate::report::PatternReport::new(pattern, count));
+            }
+            report
+        };
+        store.record(&run, &report_with(10)).unwrap();
+        store.record(&run, &report).unwrap();
+        store.record(&run, &report_with(3)).unwrap();
+
+        let counts: Vec<_> = store
+            .trend("Failed to prove", Some("versi"), 10)
+            .unwrap()
+            .into_iter()
+            .map(|point| point.count)
+            .collect();
+        assert_eq!(counts, vec![10, 0, 3]);
+
+        assert_eq!(store.trend("Failed", None, 2).unwrap().len(), 2);
+        assert!(store
+            .trend("Failed", Some("westend"), 10)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,369 @@
+//! Analysis of the block import failures, grouped by error kind and block.
+//!
+//! The import failures are reported by the nodes as
+//! `💔 Error importing block 0x5e7d…a1f2: consensus error: Import failed: Unknown parent`,
+//! optionally with the block number (ie `Error importing block #42 (0x5e7d…a1f2): ..`).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    sync::OnceLock,
+};
+
+use regex::Regex;
+
+use crate::{
+    collector::{self, Collector},
+    dedup,
+    output::{breakdown, escape, Render},
+    parse::LogLine,
+    ImportsConfig,
+};
+
+/// Loki filter selecting the import failures.
+const QUERY_FILTER: &str = "|= `Error importing block`";
+
+/// The block of the failures whose block could not be extracted.
+const UNKNOWN_BLOCK: &str = "unknown";
+
+/// A block import failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportFailure<'a> {
+    /// The number of the block, when printed.
+    pub number: Option<u64>,
+    /// The hash of the block, possibly shortened (ie `0x5e7d…a1f2`).
+    pub hash: Option<&'a str>,
+    /// The error of the import.
+    pub error: &'a str,
+}
+
+impl ImportFailure<'_> {
+    /// The block of the failure, identified by its hash or number.
+    pub fn block(&self) -> String {
+        match (self.number, self.hash) {
+            (Some(number), Some(hash)) => format!("#{number} ({hash})"),
+            (None, Some(hash)) => hash.to_string(),
+            (Some(number), None) => format!("#{number}"),
+            (None, None) => UNKNOWN_BLOCK.to_string(),
+        }
+    }
+
+    /// The kind of the error, such that the failures of different blocks are grouped.
+    ///
+    /// The innermost variant of debug-printed errors is used when available, otherwise
+    /// the hashes and numbers of the error are masked.
+    pub fn kind(&self) -> String {
+        static MASKS: OnceLock<(Regex, Regex)> = OnceLock::new();
+
+        if let Some(variant) = dedup::extract_error_variant(self.error) {
+            return variant;
+        }
+
+        let (hashes, numbers) = MASKS.get_or_init(|| {
+            (
+                Regex::new(r"0x[0-9a-fA-F]+(?:…[0-9a-fA-F]+)?").expect("Regex is valid; qed"),
+                Regex::new(r"\b\d+\b").expect("Regex is valid; qed"),
+            )
+        });
+        let kind = hashes.replace_all(self.error.trim(), "0x…");
+        numbers.replace_all(&kind, "N").into_owned()
+    }
+}
+
+/// Parse a block import failure, `None` if the line does not report one.
+pub fn parse_import_failure(line: &str) -> Option<ImportFailure<'_>> {
+    static FAILURE: OnceLock<Regex> = OnceLock::new();
+
+    // Cheap check before running the regex on every line.
+    if !line.contains("Error importing block") {
+        return None;
+    }
+
+    let regex = FAILURE.get_or_init(|| {
+        Regex::new(
+            r"Error importing block\s*(?:#(?P<number>\d+)\s*)?\(?(?P<hash>0x[0-9a-fA-F]+(?:…[0-9a-fA-F]+)?)?\)?\s*:\s*(?P<error>.*)$",
+        )
+        .expect("Regex is valid; qed")
+    });
+    let captures = regex.captures(line)?;
+
+    Some(ImportFailure {
+        number: captures
+            .name("number")
+            .and_then(|number| number.as_str().parse().ok()),
+        hash: captures.name("hash").map(|hash| hash.as_str()),
+        error: captures.name("error").map_or("", |error| error.as_str()),
+    })
+}
+
+/// The import failures of a single error kind.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct KindReport {
+    /// The kind of the error.
+    pub kind: String,
+    /// The number of failures.
+    pub count: usize,
+    /// The number of distinct nodes that failed.
+    pub nodes: usize,
+    /// The number of distinct blocks that failed.
+    pub blocks: usize,
+}
+
+/// The import failures of a single block.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlockReport {
+    /// The block, identified by its hash or number.
+    pub block: String,
+    /// The timestamp of the first failure, when it could be parsed.
+    pub first_seen: Option<chrono::NaiveDateTime>,
+    /// The number of failures.
+    pub count: usize,
+    /// The kinds of the errors.
+    pub kinds: BTreeSet<String>,
+    /// Number of failures per node.
+    pub nodes: BTreeMap<String, usize>,
+}
+
+/// The report of the block import failures.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ImportsReport {
+    /// The number of failures.
+    pub total: usize,
+    /// The failures per error kind, the most frequent kinds first.
+    pub kinds: Vec<KindReport>,
+    /// The failures per block, in order of first failure.
+    pub blocks: Vec<BlockReport>,
+}
+
+impl Render for ImportsReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Block import failures: {} across {} blocks",
+            self.total,
+            self.blocks.len()
+        )?;
+        if self.total == 0 {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{0: <100} | {1: <10} | {2: <10} | Blocks",
+            "Kind", "Count", "Nodes"
+        )?;
+        for kind in &self.kinds {
+            writeln!(
+                out,
+                "{0: <100} | {1: <10} | {2: <10} | {3}",
+                kind.kind, kind.count, kind.nodes, kind.blocks
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{0: <23} | {1: <30} | {2: <10} | {3: <40} | Kinds",
+            "First seen", "Block", "Count", "Nodes"
+        )?;
+        for block in &self.blocks {
+            writeln!(
+                out,
+                "{0: <23} | {1: <30} | {2: <10} | {3: <40} | {4}",
+                first_seen(block),
+                block.block,
+                block.count,
+                breakdown(&block.nodes),
+                block.kinds.iter().cloned().collect::<Vec<_>>().join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Block import failures: {} across {} blocks",
+            self.total,
+            self.blocks.len()
+        )?;
+        if self.total == 0 {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(out, "| Kind | Count | Nodes | Blocks |")?;
+        writeln!(out, "|---|---|---|---|")?;
+        for kind in &self.kinds {
+            writeln!(
+                out,
+                "| `{}` | {} | {} | {} |",
+                escape(&kind.kind),
+                kind.count,
+                kind.nodes,
+                kind.blocks
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "| First seen | Block | Count | Nodes | Kinds |")?;
+        writeln!(out, "|---|---|---|---|---|")?;
+        for block in &self.blocks {
+            let kinds: Vec<_> = block
+                .kinds
+                .iter()
+                .map(|kind| format!("`{}`", escape(kind)))
+                .collect();
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} |",
+                first_seen(block),
+                block.block,
+                block.count,
+                breakdown(&block.nodes),
+                kinds.join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The timestamp of the first failure of the block, `-` if unknown.
+fn first_seen(block: &BlockReport) -> String {
+    block
+        .first_seen
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Collects the import failures of the processed lines.
+#[derive(Default)]
+struct Imports {
+    total: usize,
+    /// Kind -> (failures, nodes, blocks).
+    kinds: BTreeMap<String, (usize, BTreeSet<String>, BTreeSet<String>)>,
+    blocks: BTreeMap<String, BlockReport>,
+}
+
+impl Collector for Imports {
+    type Item<'a> = ImportFailure<'a>;
+    type Report = ImportsReport;
+
+    const QUERY_FILTER: &'static str = QUERY_FILTER;
+
+    fn select<'a>(&self, line: &'a LogLine) -> Option<ImportFailure<'a>> {
+        parse_import_failure(&line.message)
+    }
+
+    fn record(
+        &mut self,
+        node: Option<&str>,
+        datetime: Option<chrono::NaiveDateTime>,
+        failure: ImportFailure,
+    ) {
+        self.total += 1;
+
+        let (block, kind) = (failure.block(), failure.kind());
+        let counts = self.kinds.entry(kind.clone()).or_default();
+        counts.0 += 1;
+        counts.2.insert(block.clone());
+
+        let report = self
+            .blocks
+            .entry(block.clone())
+            .or_insert_with(|| BlockReport {
+                block,
+                first_seen: None,
+                count: 0,
+                kinds: BTreeSet::new(),
+                nodes: BTreeMap::new(),
+            });
+        report.count += 1;
+        report.kinds.insert(kind);
+        if let Some(node) = node {
+            counts.1.insert(node.to_string());
+            *report.nodes.entry(node.to_string()).or_default() += 1;
+        }
+        if let Some(datetime) = datetime {
+            report.first_seen = Some(report.first_seen.map_or(datetime, |t| t.min(datetime)));
+        }
+    }
+
+    fn report(self) -> ImportsReport {
+        let mut kinds: Vec<_> = self
+            .kinds
+            .into_iter()
+            .map(|(kind, (count, nodes, blocks))| KindReport {
+                kind,
+                count,
+                nodes: nodes.len(),
+                blocks: blocks.len(),
+            })
+            .collect();
+        kinds.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.kind.cmp(&b.kind)));
+
+        // The blocks without timestamp come last.
+        let mut blocks: Vec<_> = self.blocks.into_values().collect();
+        blocks.sort_by_key(|block| (block.first_seen.is_none(), block.first_seen));
+
+        ImportsReport {
+            total: self.total,
+            kinds,
+            blocks,
+        }
+    }
+}
+
+/// Report the block import failures of the provided files or Loki query.
+pub fn run(opts: ImportsConfig) -> Result<ImportsReport, Box<dyn std::error::Error>> {
+    log::info!("Running block import failures query");
+    collector::run(&opts.config, Imports::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::tests::collect;
+
+    #[test]
+    fn test_imports() {
+        assert_eq!(
+            parse_import_failure(
+                "💔 Error importing block #42 (0x5e7d…a1f2): Import failed: Unknown parent"
+            ),
+            Some(ImportFailure {
+                number: Some(42),
+                hash: Some("0x5e7d…a1f2"),
+                error: "Import failed: Unknown parent",
+            })
+        );
+        assert_eq!(parse_import_failure("Imported #42 (0x5e7d…a1f2)"), None);
+
+        let failure = parse_import_failure(
+            "💔 Error importing block 0x5e7d…a1f2: consensus error: Parent block of 0x5e7d…a1f2 has no associated weight",
+        )
+        .unwrap();
+        assert_eq!(failure.block(), "0x5e7d…a1f2");
+        assert_eq!(
+            failure.kind(),
+            "consensus error: Parent block of 0x… has no associated weight"
+        );
+
+        let lines = "\
+2024-03-29 16:00:02.000  WARN tokio-runtime-worker sync: 💔 Error importing block 0xbbbb…2222: Import failed: Unknown parent
+2024-03-29 16:00:01.000  WARN tokio-runtime-worker sync: 💔 Error importing block 0xaaaa…1111: Import failed: Unknown parent
+2024-03-29 16:00:03.000  WARN tokio-runtime-worker sync: 💔 Error importing block 0xaaaa…1111: Import failed: Unknown parent
+2024-03-29 16:00:03.000  INFO tokio-runtime-worker sync: 🏆 Imported #42 (0xaaaa…1111)";
+        let report = collect(Imports::default(), &[("alice", lines)]);
+        assert_eq!(report.total, 3);
+        assert_eq!(report.kinds.len(), 1);
+        assert_eq!(report.kinds[0].blocks, 2);
+        assert_eq!(report.kinds[0].nodes, 1);
+        assert_eq!(report.blocks[0].block, "0xaaaa…1111");
+        assert_eq!(report.blocks[0].count, 2);
+        assert_eq!(report.blocks[1].block, "0xbbbb…2222");
+    }
+}
@@ -0,0 +1,295 @@
+//! Reading log lines from local inputs.
+
+use std::{
+    io::{BufRead, BufReader, IsTerminal, Read},
+    path::{Path, PathBuf},
+};
+
+/// The file name that denotes the standard input.
+pub const STDIN: &str = "-";
+
+/// Check if the standard input is piped from another process or redirected from a file.
+///
+/// A closed or `/dev/null` standard input (ie cron jobs) is not considered piped.
+pub fn stdin_is_piped() -> bool {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return false;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        std::fs::metadata("/dev/stdin")
+            .map(|metadata| metadata.file_type().is_fifo() || metadata.is_file())
+            .unwrap_or_default()
+    }
+
+    #[cfg(not(unix))]
+    true
+}
+
+/// The compression of a log file, detected from its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Plain text.
+    None,
+    /// Gzip compressed (`.gz`).
+    Gzip,
+    /// Zstandard compressed (`.zst`).
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression of the provided path from its extension.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Decompress the provided bytes.
+    pub fn decompress(self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut decoded = Vec::new();
+        match self {
+            Compression::None => decoded.extend_from_slice(bytes),
+            Compression::Gzip => {
+                flate2::read::MultiGzDecoder::new(bytes).read_to_end(&mut decoded)?;
+            }
+            Compression::Zstd => {
+                zstd::stream::read::Decoder::new(bytes)?.read_to_end(&mut decoded)?;
+            }
+        }
+
+        Ok(decoded)
+    }
+}
+
+/// Read the provided file, or the standard input if the file is [`STDIN`].
+///
+/// Compressed files (`.gz`, `.zst`) are decompressed.
+pub fn read(file: &str) -> std::io::Result<Vec<u8>> {
+    if file == STDIN {
+        let mut bytes = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut bytes)?;
+        return Ok(bytes);
+    }
+
+    let path = Path::new(file);
+    match Compression::from_path(path) {
+        Compression::None => std::fs::read(path),
+        compression => compression.decompress(&map_file(path)?),
+    }
+}
+
+/// Stream the provided file, or the standard input if the file is [`STDIN`].
+///
+/// Compressed files (`.gz`, `.zst`) are decompressed on the fly, such that huge files
+/// are read with bounded memory.
+pub fn reader(file: &str) -> std::io::Result<Box<dyn BufRead + Send>> {
+    if file == STDIN {
+        return Ok(Box::new(BufReader::new(std::io::stdin())));
+    }
+
+    open_reader(Path::new(file))
+}
+
+/// Stream the provided file, decompressing it on the fly.
+pub fn open_reader(path: &Path) -> std::io::Result<Box<dyn BufRead + Send>> {
+    let file = std::fs::File::open(path)?;
+
+    Ok(match Compression::from_path(path) {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::new(file)?)),
+    })
+}
+
+/// List the log files of the provided directory, sorted by name.
+///
+/// Hidden files and subdirectories are ignored.
+pub fn list_dir(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        let hidden = path
+            .file_name()
+            .map(|name| name.to_string_lossy().starts_with('.'))
+            .unwrap_or_default();
+        if hidden || !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        files.push(path);
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// List the files matching the provided glob, sorted by name.
+///
+/// Directories are ignored. Fails if no file matches the glob.
+pub fn glob_files(pattern: &str) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for path in glob::glob(pattern)? {
+        let path = path?;
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+
+    if files.is_empty() {
+        return Err(format!("No files matching {pattern:?}").into());
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Discover the node log files of a zombienet output directory, sorted by name.
+///
+/// Both layouts are supported: `logs/<node>.log` of zombienet, and `<node>/<node>.log`
+/// of zombienet-sdk. Fails if no node log is found.
+pub fn zombienet_logs(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+
+    let logs = dir.join("logs");
+    if logs.is_dir() {
+        files.extend(
+            list_dir(&logs)?
+                .into_iter()
+                .filter(|path| path.extension().is_some_and(|extension| extension == "log")),
+        );
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let node = entry?.path();
+        let Some(name) = node
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+        let log = node.join(format!("{name}.log"));
+        if node.is_dir() && log.is_file() {
+            files.push(log);
+        }
+    }
+
+    if files.is_empty() {
+        return Err(format!("No zombienet node logs found in {}", dir.display()).into());
+    }
+
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// The contents of a log file.
+pub enum Contents {
+    /// The memory mapped plain text file.
+    Mapped(memmap2::Mmap),
+    /// The decompressed content of a compressed file.
+    Decompressed(Vec<u8>),
+}
+
+impl std::ops::Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Mapped(mmap) => mmap,
+            Contents::Decompressed(bytes) => bytes,
+        }
+    }
+}
+
+/// Open the provided file for processing.
+///
+/// Plain text files are memory mapped, while compressed files are decompressed in memory.
+pub fn open(path: &Path) -> std::io::Result<Contents> {
+    let mmap = map_file(path)?;
+
+    match Compression::from_path(path) {
+        Compression::None => Ok(Contents::Mapped(mmap)),
+        compression => Ok(Contents::Decompressed(compression.decompress(&mmap)?)),
+    }
+}
+
+/// Memory map the provided file.
+///
+/// Mapping avoids copying large log files into memory before processing them.
+pub fn map_file(path: &Path) -> std::io::Result<memmap2::Mmap> {
+    let file = std::fs::File::open(path)?;
+
+    // Safety: the log files are expected to be immutable while processing.
+    // Files that are still appended to are not affected, since the mapping
+    // only covers the length at the time of mapping.
+    unsafe { memmap2::Mmap::map(&file) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_decompress() {
+        let text = b"2024-03-29 16:00:00.000 WARN tokio-runtime-worker sync: Failed\n";
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(text).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(Compression::Gzip.decompress(&gzip).unwrap(), text);
+
+        let zstd = zstd::encode_all(&text[..], 0).unwrap();
+        assert_eq!(Compression::Zstd.decompress(&zstd).unwrap(), text);
+
+        assert_eq!(
+            Compression::from_path(Path::new("node.log.zst")),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::from_path(Path::new("node.log")),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn test_zombienet_logs() {
+        let dir =
+            std::env::temp_dir().join(format!("sub-triage-logs-zombienet-{}", std::process::id()));
+        for path in [
+            "logs/alice.log",
+            "logs/zombie.json",
+            "bob/bob.log",
+            "bob/cfg/genesis.json",
+            "charlie/charlie.err",
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let files = zombienet_logs(&dir).unwrap();
+        let files: Vec<_> = files
+            .iter()
+            .map(|file| {
+                file.strip_prefix(&dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(files, ["bob/bob.log", "logs/alice.log"]);
+        assert!(zombienet_logs(&dir.join("bob/cfg")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+//! Systemd journal logs, for the nodes running as systemd units on the host.
+//!
+//! The logs are read with `journalctl --output=cat`, which prints the lines as written
+//! by the node (ie `2024-03-29 16:00:00.000  WARN ...`).
+
+use crate::query::shell_quote;
+
+/// Builds the `journalctl` command reading the logs of the selected units.
+#[derive(Debug, Clone, Default)]
+pub struct JournalctlQuery {
+    units: Vec<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    last_day: bool,
+}
+
+impl JournalctlQuery {
+    /// Create a new query.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the units to read (ie `polkadot.service`).
+    pub fn units(mut self, units: Vec<String>) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Set the start and end times of the logs.
+    ///
+    /// The format is "YYYY-MM-DDTHH:MM:SSZ".
+    ///
+    /// Default: 1 hour before the current time.
+    pub fn set_time(
+        mut self,
+        start_time: Option<String>,
+        end_time: Option<String>,
+        last_day: bool,
+    ) -> Self {
+        self.start_time = start_time;
+        self.end_time = end_time;
+        self.last_day = last_day;
+        self
+    }
+
+    /// Build the command.
+    pub fn build(&self) -> Result<String, Box<dyn std::error::Error>> {
+        if self.units.is_empty() {
+            return Err("The journald source requires a --unit".into());
+        }
+
+        let units: String = self
+            .units
+            .iter()
+            .map(|unit| format!(" --unit={}", shell_quote(unit)))
+            .collect();
+        let since = match (&self.start_time, self.last_day) {
+            (Some(start_time), _) => journal_time(start_time),
+            (None, true) => "-24h".to_string(),
+            (None, false) => "-1h".to_string(),
+        };
+        let until = self
+            .end_time
+            .as_ref()
+            .map(|end_time| format!(" --until={}", shell_quote(&journal_time(end_time))))
+            .unwrap_or_default();
+
+        Ok(format!(
+            "journalctl{units} --output=cat --no-pager --quiet --since={}{until}",
+            shell_quote(&since)
+        ))
+    }
+}
+
+/// Convert the time to the format of journalctl (ie `2024-03-29 16:00:00 UTC`).
+///
+/// Times that are not RFC 3339 are provided as is.
+fn journal_time(time: &str) -> String {
+    match chrono::DateTime::parse_from_rfc3339(time) {
+        Ok(time) => time.to_utc().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        Err(_) => time.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journalctl_query() {
+        assert_eq!(
+            JournalctlQuery::new()
+                .units(vec!["polkadot.service".to_string()])
+                .set_time(
+                    Some("2024-03-29T16:00:00Z".to_string()),
+                    Some("2024-03-29T17:00:00Z".to_string()),
+                    false
+                )
+                .build()
+                .unwrap(),
+            "journalctl --unit='polkadot.service' --output=cat --no-pager --quiet --since='2024-03-29 16:00:00 UTC' --until='2024-03-29 17:00:00 UTC'"
+        );
+        assert_eq!(
+            JournalctlQuery::new()
+                .units(vec!["polkadot.service".to_string()])
+                .set_time(None, None, true)
+                .build()
+                .unwrap(),
+            "journalctl --unit='polkadot.service' --output=cat --no-pager --quiet --since='-24h'"
+        );
+        assert!(JournalctlQuery::new().build().is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! Kubernetes pod logs, for the networks without Loki.
+//!
+//! The logs are read with `kubectl logs`, prefixing every line with its pod and container
+//! (ie `[pod/alice-0/substrate] 2024-03-29 16:00:00.000  WARN ...`).
+
+use crate::query::shell_quote;
+
+/// Extracts the pod of the lines prefixed by `kubectl logs --prefix`.
+pub const NODE_PREFIX: &str = r"^\[pod/(?P<node>[^/\]]+)/[^\]]*\]\s*";
+
+/// The maximum number of pods whose logs are read concurrently.
+const MAX_LOG_REQUESTS: usize = 50;
+
+/// Builds the `kubectl logs` command reading the logs of the selected pods.
+#[derive(Debug, Clone, Default)]
+pub struct KubectlQuery {
+    namespace: Option<String>,
+    selector: Option<String>,
+    pod: Option<String>,
+    container: Option<String>,
+    start_time: Option<String>,
+    last_day: bool,
+}
+
+impl KubectlQuery {
+    /// Create a new query.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the namespace of the pods, the namespace of the current context if not provided.
+    pub fn namespace(mut self, namespace: Option<String>) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    /// Select the pods by label (ie `app.kubernetes.io/instance=versi`).
+    pub fn selector(mut self, selector: Option<String>) -> Self {
+        self.selector = selector;
+        self
+    }
+
+    /// Read the logs of a single pod, instead of the pods of the selector.
+    pub fn pod(mut self, pod: Option<String>) -> Self {
+        self.pod = pod;
+        self
+    }
+
+    /// Only read the logs of this container, all the containers if not provided.
+    pub fn container(mut self, container: Option<String>) -> Self {
+        self.container = container;
+        self
+    }
+
+    /// Set the start time of the logs.
+    ///
+    /// The format is "YYYY-MM-DDTHH:MM:SSZ".
+    ///
+    /// Default: 1 hour before the current time.
+    pub fn set_time(mut self, start_time: Option<String>, last_day: bool) -> Self {
+        self.start_time = start_time;
+        self.last_day = last_day;
+        self
+    }
+
+    /// Build the command.
+    pub fn build(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let pods = match (&self.pod, &self.selector) {
+            (Some(pod), _) => shell_quote(&format!("pod/{pod}")),
+            (None, Some(selector)) => format!(
+                "--selector={} --max-log-requests={MAX_LOG_REQUESTS}",
+                shell_quote(selector)
+            ),
+            (None, None) => return Err("The k8s source requires a --selector or a --node".into()),
+        };
+        let namespace = self
+            .namespace
+            .as_ref()
+            .map(|namespace| format!(" --namespace={}", shell_quote(namespace)))
+            .unwrap_or_default();
+        let containers = match &self.container {
+            Some(container) => format!("--container={}", shell_quote(container)),
+            None => "--all-containers".to_string(),
+        };
+        let since = match (&self.start_time, self.last_day) {
+            (Some(start_time), _) => format!("--since-time={}", shell_quote(start_time)),
+            (None, true) => "--since=24h".to_string(),
+            (None, false) => "--since=1h".to_string(),
+        };
+
+        Ok(format!(
+            "kubectl logs{namespace} {pods} {containers} --prefix --ignore-errors --tail=-1 {since}"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kubectl_query() {
+        assert_eq!(
+            KubectlQuery::new()
+                .namespace(Some("versi".to_string()))
+                .selector(Some("app=polkadot,role=validator".to_string()))
+                .set_time(Some("2024-03-29T16:00:00Z".to_string()), false)
+                .build()
+                .unwrap(),
+            "kubectl logs --namespace='versi' --selector='app=polkadot,role=validator' --max-log-requests=50 --all-containers --prefix --ignore-errors --tail=-1 --since-time='2024-03-29T16:00:00Z'"
+        );
+        assert_eq!(
+            KubectlQuery::new()
+                .pod(Some("alice-0".to_string()))
+                .container(Some("substrate".to_string()))
+                .build()
+                .unwrap(),
+            "kubectl logs 'pod/alice-0' --container='substrate' --prefix --ignore-errors --tail=-1 --since=1h"
+        );
+        assert!(KubectlQuery::new().build().is_err());
+
+        // The logs are read until now, the end time cannot be honored.
+        let opts = |end_time: &[&str]| {
+            <crate::Config as clap::Parser>::parse_from(
+                ["warn-err", "--source", "k8s", "--selector", "app=polkadot"]
+                    .iter()
+                    .chain(end_time),
+            )
+        };
+        assert!(opts(&[]).command_query().unwrap().is_some());
+        assert!(opts(&["--end-time", "2024-03-30T16:00:00Z"])
+            .command_query()
+            .is_err());
+
+        let prefix = regex::Regex::new(NODE_PREFIX).unwrap();
+        let captures = prefix
+            .captures("[pod/alice-0/substrate] 2024-03-29 16:00:00.000  WARN sync: Slow")
+            .unwrap();
+        assert_eq!(&captures["node"], "alice-0");
+    }
+}
@@ -0,0 +1,131 @@
+//! Annotations of known issues, loaded from a YAML file.
+//!
+//! ```yaml
+//! - pattern: "banned, disconnecting, reason"
+//!   status: known
+//!   owner: "@lexnv"
+//!   issue: https://github.com/paritytech/polkadot-sdk/issues/1234
+//! - pattern: "Failed to prove .*? parachain"
+//!   status: fixed
+//!   fixed_in: polkadot-v1.12.0
+//! ```
+
+use std::path::Path;
+
+/// The triage status of an issue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    /// The issue is known and expected, it does not need attention.
+    Known,
+    /// The issue is being investigated.
+    Triaged,
+    /// The issue is fixed, the pattern should disappear once deployed.
+    Fixed,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Known => write!(f, "known"),
+            Status::Triaged => write!(f, "triaged"),
+            Status::Fixed => write!(f, "fixed"),
+        }
+    }
+}
+
+/// The annotation of the patterns containing a substring.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Annotation {
+    /// The substring of the reported pattern.
+    pub pattern: String,
+    /// The triage status.
+    pub status: Status,
+    /// The version that fixed the issue.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_in: Option<String>,
+    /// The owner of the issue.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// The tracking issue URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issue: Option<String>,
+}
+
+impl std::fmt::Display for Annotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.status)?;
+        if let Some(version) = &self.fixed_in {
+            write!(f, " in {}", version)?;
+        }
+        if let Some(owner) = &self.owner {
+            write!(f, " {}", owner)?;
+        }
+        if let Some(issue) = &self.issue {
+            write!(f, " {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// The annotations of the known issues.
+#[derive(Debug, Clone, Default)]
+pub struct KnownIssues {
+    annotations: Vec<Annotation>,
+}
+
+impl KnownIssues {
+    /// Load the annotations from the provided YAML file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let annotations = serde_yaml::from_str(&content)
+            .map_err(|err| format!("Invalid known issues file {}: {err}", path.display()))?;
+
+        Ok(KnownIssues { annotations })
+    }
+
+    /// Find the annotation of the provided pattern.
+    ///
+    /// The first annotation whose substring is contained in the pattern is returned.
+    pub fn find(&self, pattern: &str) -> Option<&Annotation> {
+        self.annotations
+            .iter()
+            .find(|annotation| pattern.contains(&annotation.pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotations() {
+        let annotations = serde_yaml::from_str(
+            r#"
+- pattern: "banned, disconnecting, reason"
+  status: known
+  owner: "@lexnv"
+  issue: https://github.com/paritytech/polkadot-sdk/issues/1234
+- pattern: "Failed to prove"
+  status: fixed
+  fixed_in: polkadot-v1.12.0
+"#,
+        )
+        .unwrap();
+        let known = KnownIssues { annotations };
+
+        let annotation = known
+            .find(".* banned, disconnecting, reason: .* (Timeout)")
+            .unwrap();
+        assert_eq!(annotation.status, Status::Known);
+        assert_eq!(
+            annotation.to_string(),
+            "known @lexnv https://github.com/paritytech/polkadot-sdk/issues/1234"
+        );
+
+        let annotation = known.find("Failed to prove .* parachain").unwrap();
+        assert_eq!(annotation.to_string(), "fixed in polkadot-v1.12.0");
+
+        assert!(known.find("Missing public key for validator").is_none());
+    }
+}
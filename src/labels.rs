@@ -0,0 +1,268 @@
+//! The chains, nodes and other label values available in Loki over the time range.
+//!
+//! The valid `--chain`, `--node` and `--label` values are otherwise guessed. The chains are
+//! listed with `logcli labels chain`, and the labels of the streams of every selected chain
+//! with `logcli series`, with the number of streams of every value.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    sync::OnceLock,
+};
+
+use regex::Regex;
+
+use crate::{
+    output::{escape, Render},
+    LabelsConfig,
+};
+
+/// The label of the chains.
+const CHAIN_LABEL: &str = "chain";
+
+/// A value of a label.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct LabelValue {
+    /// The value.
+    pub value: String,
+    /// The number of streams with the value.
+    pub streams: usize,
+}
+
+/// The values of a label of the streams.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct LabelValues {
+    /// The name of the label (ie `node`).
+    pub name: String,
+    /// The values, the most frequent first.
+    pub values: Vec<LabelValue>,
+}
+
+/// The chains and the labels of the streams of the selected chains.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct LabelsReport {
+    /// Every chain with logs over the time range.
+    pub chains: Vec<String>,
+    /// The number of streams of the selected chains.
+    pub streams: usize,
+    /// The labels of the streams, by name.
+    pub labels: Vec<LabelValues>,
+}
+
+impl Render for LabelsReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Chains ({}): {}",
+            self.chains.len(),
+            self.chains.join(", ")
+        )?;
+        writeln!(out, "Streams of the selected chains: {}", self.streams)?;
+
+        for label in &self.labels {
+            writeln!(out)?;
+            writeln!(out, "{} ({} values)", label.name, label.values.len())?;
+            for value in &label.values {
+                writeln!(out, "  {0: <40} | {1} streams", value.value, value.streams)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Chains ({}): {}",
+            self.chains.len(),
+            self.chains
+                .iter()
+                .map(|chain| format!("`{chain}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+        writeln!(out)?;
+        writeln!(out, "Streams of the selected chains: {}", self.streams)?;
+
+        for label in &self.labels {
+            writeln!(out)?;
+            writeln!(out, "**{}** ({} values)", label.name, label.values.len())?;
+            writeln!(out)?;
+            writeln!(out, "| Value | Streams |")?;
+            writeln!(out, "|---|---|")?;
+            for value in &label.values {
+                writeln!(out, "| `{}` | {} |", escape(&value.value), value.streams)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse the labels of a stream printed by `logcli series` (ie `{chain="versi", node="alice"}`).
+pub fn parse_series(line: &str) -> BTreeMap<String, String> {
+    static LABEL: OnceLock<Regex> = OnceLock::new();
+
+    let regex = LABEL.get_or_init(|| {
+        Regex::new(r#"([a-zA-Z_][a-zA-Z0-9_]*)="((?:[^"\\]|\\.)*)""#).expect("Regex is valid; qed")
+    });
+    regex
+        .captures_iter(line)
+        .map(|captures| {
+            (
+                captures[1].to_string(),
+                captures[2].replace(r#"\""#, "\"").replace(r"\\", r"\"),
+            )
+        })
+        .collect()
+}
+
+/// Aggregates the labels of the streams.
+#[derive(Debug, Default)]
+struct Streams {
+    /// The number of streams.
+    count: usize,
+    /// The number of streams of every value of every label.
+    labels: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl Streams {
+    /// Count the streams printed by `logcli series`, keeping the provided labels (every
+    /// label if empty).
+    fn process(&mut self, output: &str, names: &BTreeSet<String>) {
+        for line in output
+            .lines()
+            .filter(|line| line.trim_start().starts_with('{'))
+        {
+            self.count += 1;
+            for (name, value) in parse_series(line) {
+                if names.is_empty() || names.contains(&name) {
+                    *self
+                        .labels
+                        .entry(name)
+                        .or_default()
+                        .entry(value)
+                        .or_default() += 1;
+                }
+            }
+        }
+    }
+
+    fn report(self, chains: Vec<String>) -> LabelsReport {
+        let labels = self
+            .labels
+            .into_iter()
+            .map(|(name, values)| {
+                let mut values: Vec<_> = values
+                    .into_iter()
+                    .map(|(value, streams)| LabelValue { value, streams })
+                    .collect();
+                values.sort_by(|a, b| {
+                    b.streams
+                        .cmp(&a.streams)
+                        .then_with(|| a.value.cmp(&b.value))
+                });
+                LabelValues { name, values }
+            })
+            .collect();
+
+        LabelsReport {
+            chains,
+            streams: self.count,
+            labels,
+        }
+    }
+}
+
+/// List the chains and the labels of the streams of the selected chains.
+pub fn run(opts: LabelsConfig) -> Result<LabelsReport, Box<dyn std::error::Error>> {
+    let names: BTreeSet<_> = opts.name.into_iter().collect();
+    let opts = opts.config;
+    if !opts.is_query() {
+        return Err("The labels are listed from Loki, local files are not supported".into());
+    }
+
+    let runner = opts.query_runner();
+    let builder = opts.query_builder()?;
+    let chains = runner.run(&builder.build_label_values(CHAIN_LABEL)?)?;
+    let chains = String::from_utf8_lossy(&chains)
+        .lines()
+        .map(str::trim)
+        .filter(|chain| !chain.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let mut streams = Streams::default();
+    for chain in &opts.chain {
+        log::info!("Listing the streams of {chain}");
+        let output = runner.run(&builder.clone().chain(chain.clone()).build_series()?)?;
+        streams.process(&String::from_utf8_lossy(&output), &names);
+    }
+
+    Ok(streams.report(chains))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{output::OutputFormat, query::QueryBuilder};
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            parse_series(r#"{chain="versi", node="alice", message="say \"hi\""}"#),
+            [
+                ("chain".to_string(), "versi".to_string()),
+                ("message".to_string(), r#"say "hi""#.to_string()),
+                ("node".to_string(), "alice".to_string()),
+            ]
+            .into()
+        );
+
+        let output = r#"
+{chain="versi", level="WARN", node="alice"}
+{chain="versi", level="ERROR", node="alice"}
+{chain="versi", level="WARN", node="bob"}
+"#;
+        let mut streams = Streams::default();
+        streams.process(output, &["node".to_string()].into());
+        let report = streams.report(vec!["kusama".to_string(), "versi".to_string()]);
+        assert_eq!(report.streams, 3);
+        assert_eq!(
+            report.labels,
+            [LabelValues {
+                name: "node".to_string(),
+                values: vec![
+                    LabelValue {
+                        value: "alice".to_string(),
+                        streams: 2
+                    },
+                    LabelValue {
+                        value: "bob".to_string(),
+                        streams: 1
+                    },
+                ],
+            }]
+        );
+        assert!(report
+            .render(OutputFormat::Text)
+            .unwrap()
+            .starts_with("Chains (2): kusama, versi\n"));
+
+        let builder = QueryBuilder::new().set_time(
+            Some("2024-03-29T16:00:00Z".to_string()),
+            Some("2024-03-29T17:00:00Z".to_string()),
+            false,
+        );
+        assert!(builder
+            .clone()
+            .chain("versi".to_string())
+            .build_series()
+            .unwrap()
+            .starts_with(r#"logcli series --addr=127.0.0.1:10700 --from="2024-03-29T16:00:00Z" --to="2024-03-29T17:00:00Z" '{chain="versi" }'"#));
+        assert!(builder
+            .build_label_values("chain")
+            .unwrap()
+            .starts_with("logcli labels 'chain' --addr="));
+    }
+}
@@ -0,0 +1,112 @@
+//! Selection of the triaged log levels, optionally restricted to targets.
+//!
+//! Levels are provided as `LEVEL` or `LEVEL:TARGET`, ie `ERROR` or `INFO:sync`.
+
+use crate::parse::LogLine;
+
+/// A selected level, optionally restricted to the lines of a target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelFilter {
+    /// The uppercase level (ie `WARN`).
+    pub level: String,
+    /// Only the lines of this target are selected.
+    pub target: Option<String>,
+}
+
+/// Parse a level like `ERROR` or `INFO:sync`.
+pub fn parse_level(value: &str) -> Result<LevelFilter, String> {
+    let (level, target) = match value.split_once(':') {
+        Some((level, target)) if !target.is_empty() => (level, Some(target.to_string())),
+        Some(_) => return Err(format!("Missing target of level {value:?}")),
+        None => (value, None),
+    };
+
+    let level = match level.to_ascii_uppercase().as_str() {
+        "WARNING" => "WARN".to_string(),
+        level @ ("TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR") => level.to_string(),
+        _ => {
+            return Err(format!(
+                "Invalid level {level:?}, expected TRACE, DEBUG, INFO, WARN or ERROR"
+            ))
+        }
+    };
+
+    Ok(LevelFilter { level, target })
+}
+
+/// The selected levels of a triage run.
+#[derive(Debug, Clone)]
+pub struct Levels {
+    filters: Vec<LevelFilter>,
+}
+
+impl Levels {
+    /// Select the provided levels.
+    pub fn new(filters: Vec<LevelFilter>) -> Self {
+        Levels { filters }
+    }
+
+    /// The distinct levels to query from Loki.
+    pub fn query_levels(&self) -> Vec<String> {
+        let mut levels: Vec<_> = self
+            .filters
+            .iter()
+            .map(|filter| filter.level.clone())
+            .collect();
+        levels.sort();
+        levels.dedup();
+        levels
+    }
+
+    /// Check if some levels are restricted to targets.
+    ///
+    /// Loki only filters the levels, the targets are filtered after querying.
+    pub fn has_targets(&self) -> bool {
+        self.filters.iter().any(|filter| filter.target.is_some())
+    }
+
+    /// Check if the provided line has one of the selected levels.
+    pub fn matches(&self, line: &str) -> bool {
+        let parsed = LogLine::parse(line);
+
+        self.filters.iter().any(|filter| {
+            parsed.has_level(line, &filter.level)
+                && filter
+                    .target
+                    .as_ref()
+                    .is_none_or(|target| parsed.has_target(line, target))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(
+            parse_level("info:sync").unwrap(),
+            LevelFilter {
+                level: "INFO".to_string(),
+                target: Some("sync".to_string())
+            }
+        );
+        assert_eq!(parse_level("warning").unwrap().level, "WARN");
+        assert!(parse_level("FATAL").is_err());
+        assert!(parse_level("INFO:").is_err());
+
+        let levels = Levels::new(vec![
+            parse_level("ERROR").unwrap(),
+            parse_level("INFO:sync").unwrap(),
+        ]);
+        assert_eq!(levels.query_levels(), vec!["ERROR", "INFO"]);
+        assert!(levels.has_targets());
+
+        assert!(levels.matches("2024-03-29 16:00:00.000 ERROR tokio-runtime-worker db: Failed"));
+        assert!(levels.matches("2024-03-29 16:00:00.000  INFO tokio-runtime-worker sync: Idle"));
+        assert!(!levels.matches("2024-03-29 16:00:00.000  INFO tokio-runtime-worker peerset: Idle"));
+        assert!(!levels.matches("2024-03-29 16:00:00.000  WARN tokio-runtime-worker sync: Slow"));
+        assert!(levels.matches(r#"{"level":"info","target":"sync","msg":"Idle"}"#));
+    }
+}
@@ -0,0 +1,75 @@
+//! Triage the logs of Substrate-based testing stacks, grouping them by common patterns.
+//!
+//! The patterns are built from the log statements of the source repositories
+//! (ie `polkadot-sdk`), and matched against the lines of local files or Loki queries.
+//! See [`TriageSession`] to embed the triage in other tools.
+
+pub mod bans;
+pub mod blame;
+pub mod cache;
+pub mod checkpoint;
+mod collector;
+pub mod command;
+pub mod compare;
+pub mod config;
+pub mod count;
+pub mod coverage;
+pub mod dedup;
+pub mod disputes;
+pub mod dump;
+pub mod elasticsearch;
+pub mod epoch;
+pub mod fetch_git;
+pub mod filter;
+pub mod finality;
+pub mod first_error;
+pub mod github;
+pub mod grafana;
+pub mod histogram;
+pub mod history;
+pub mod imports;
+pub mod input;
+pub mod journald;
+pub mod k8s;
+pub mod known;
+pub mod labels;
+pub mod level;
+pub mod matcher;
+pub mod metadata;
+pub mod normalize;
+pub mod output;
+pub mod owners;
+pub mod panics;
+pub mod parse;
+pub mod peer;
+pub mod progress;
+pub mod query;
+pub mod record;
+pub mod regex_diff;
+pub mod report;
+pub mod restarts;
+pub mod runtime;
+pub mod samples;
+pub mod serve;
+pub mod session;
+mod stats;
+pub mod subsystem;
+pub mod suggest;
+pub mod summary;
+pub mod suppress;
+pub mod tarball;
+pub mod template;
+pub mod threshold;
+pub mod trend;
+pub mod triage_unknown;
+pub mod version;
+pub mod warp_time;
+pub mod watch;
+
+pub use config::{
+    BansConfig, CompareConfig, Config, DisputesConfig, FinalityConfig, ImportsConfig, LabelsConfig,
+    PanicsConfig, QueryConfig, RegexDiffConfig, RestartsConfig, RuntimeConfig, ServeConfig,
+    TriageUnknownConfig,
+};
+pub use report::TriageReport;
+pub use session::TriageSession;
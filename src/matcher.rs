@@ -0,0 +1,575 @@
+//! Match log lines against the regexes built from the source code.
+
+use std::collections::{hash_map, BTreeMap, HashMap};
+
+use regex::Regex;
+
+use crate::{
+    dedup::{self, LineFingerprints},
+    epoch,
+    fetch_git::RegexDetails,
+    first_error::FirstError,
+    histogram::Timeline,
+    normalize,
+    panics::PanicDetector,
+    parse::LogLine,
+    peer,
+    samples::Samples,
+    version,
+};
+
+/// Group lines of a matched regex by the content after a specific marker.
+pub struct DeduplicationInfo {
+    pub log_line: String,
+    pub dedup_after: String,
+}
+
+/// The lines matched by a pattern.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    /// Number of matched lines.
+    pub total: usize,
+    /// The matched lines, bounded to samples with `--samples`.
+    pub lines: Samples,
+    /// Number of matched lines produced by a panic.
+    pub panics: usize,
+    /// Number of matched lines per node.
+    pub nodes: BTreeMap<String, usize>,
+    /// Number of matched lines per chain, for multi-chain runs.
+    pub chains: BTreeMap<String, usize>,
+    /// Number of matched lines per mentioned peer, when extracting the peers.
+    pub peers: BTreeMap<String, usize>,
+    /// The times of the matched lines, when they could be parsed.
+    pub timeline: Timeline,
+}
+
+impl Entry {
+    /// Construct an entry recording its lines in the provided samples.
+    pub fn new(lines: Samples) -> Self {
+        Entry {
+            lines,
+            ..Default::default()
+        }
+    }
+
+    /// Number of matched lines.
+    pub fn count(&self) -> usize {
+        self.total
+    }
+
+    /// Merge the lines of another entry into this one.
+    pub fn merge(&mut self, other: Entry) {
+        self.total += other.total;
+        self.panics += other.panics;
+        self.lines.merge(other.lines);
+        self.timeline.merge(other.timeline);
+        for (node, count) in other.nodes {
+            *self.nodes.entry(node).or_default() += count;
+        }
+        for (chain, count) in other.chains {
+            *self.chains.entry(chain).or_default() += count;
+        }
+        for (peer, count) in other.peers {
+            *self.peers.entry(peer).or_default() += count;
+        }
+    }
+}
+
+/// The results of matching log lines.
+///
+/// Results produced independently (ie for different files) can be merged together, or
+/// persisted in the checkpoints of interrupted runs.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Matches {
+    /// Total number of processed lines.
+    pub total: usize,
+    /// Number of empty lines.
+    pub empty_lines: usize,
+    /// Number of lines not matched by any pattern.
+    pub unknown: usize,
+    /// Number of unknown lines produced by a panic.
+    pub unknown_panics: usize,
+    /// The unknown lines, when kept by the matcher.
+    pub unknown_lines: Vec<String>,
+    /// The found lines from the regex.
+    #[serde(with = "found_lines")]
+    pub found_lines: HashMap<(String, RegexDetails), Entry>,
+    /// Number of unknown lines per node.
+    pub unknown_nodes: BTreeMap<String, usize>,
+    /// The version of every node, when detected.
+    pub versions: BTreeMap<String, String>,
+    /// Number of duplicate lines dropped (ie returned by overlapping chunks).
+    pub duplicates: usize,
+    /// The fingerprints of the processed lines, when dropping duplicates.
+    pub fingerprints: LineFingerprints,
+    /// The first error of every node, when detecting the first errors.
+    pub first_errors: BTreeMap<String, FirstError>,
+    /// The starts of every node, when detecting the first errors.
+    pub starts: BTreeMap<String, Vec<chrono::NaiveDateTime>>,
+    /// The timestamps of the session change lines of every node, when detecting them.
+    pub session_changes: Vec<chrono::NaiveDateTime>,
+}
+
+impl Matches {
+    /// Number of lines matched by a pattern.
+    pub fn matched(&self) -> usize {
+        self.total - self.empty_lines - self.duplicates - self.unknown
+    }
+
+    /// Attribute all the matched lines to the provided chain.
+    pub fn tag_chain(&mut self, chain: &str) {
+        for entry in self.found_lines.values_mut() {
+            entry.chains.insert(chain.to_string(), entry.count());
+        }
+    }
+
+    /// Merge the results of another matching into this one.
+    pub fn merge(&mut self, other: Matches) {
+        self.total += other.total;
+        self.empty_lines += other.empty_lines;
+        self.unknown += other.unknown;
+        self.unknown_panics += other.unknown_panics;
+        self.duplicates += other.duplicates;
+        self.fingerprints.extend(other.fingerprints);
+        self.unknown_lines.extend(other.unknown_lines);
+        for (node, count) in other.unknown_nodes {
+            *self.unknown_nodes.entry(node).or_default() += count;
+        }
+
+        self.versions.extend(other.versions);
+        for (node, error) in other.first_errors {
+            FirstError::record(&mut self.first_errors, &node, error);
+        }
+        for (node, starts) in other.starts {
+            self.starts.entry(node).or_default().extend(starts);
+        }
+        self.session_changes.extend(other.session_changes);
+
+        for (key, entry) in other.found_lines {
+            match self.found_lines.entry(key) {
+                hash_map::Entry::Occupied(mut occupied) => occupied.get_mut().merge(entry),
+                hash_map::Entry::Vacant(vacant) => {
+                    vacant.insert(entry);
+                }
+            }
+        }
+    }
+}
+
+/// The found lines are persisted as a list, their keys not being strings.
+mod found_lines {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{Entry, RegexDetails};
+
+    type FoundLines = HashMap<(String, RegexDetails), Entry>;
+
+    pub fn serialize<S: Serializer>(
+        found_lines: &FoundLines,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(found_lines.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FoundLines, D::Error> {
+        let found_lines: Vec<((String, RegexDetails), Entry)> = Vec::deserialize(deserializer)?;
+        Ok(found_lines.into_iter().collect())
+    }
+}
+
+/// Matches log lines against the regexes.
+///
+/// The matcher is immutable and can be shared between worker tasks.
+pub struct Matcher {
+    /// The deduplication information.
+    ///
+    /// This is used to provide a better triage report, grouping by specific error
+    /// that cannot be extracted by the regex.
+    dedup_info: Vec<DeduplicationInfo>,
+
+    /// The regexes to match against, downloaded and compiled from the git repository.
+    regexes: Vec<(Regex, RegexDetails)>,
+
+    /// Extracts the node from the prefix of lines that interleave multiple nodes.
+    ///
+    /// The regex must contain a `node` named capture group.
+    node_prefix: Option<Regex>,
+
+    /// Detect the version of the nodes from their startup lines.
+    versions: bool,
+
+    /// Record the first error and the starts of every node.
+    first_errors: bool,
+
+    /// Record the session changes.
+    session_changes: bool,
+
+    /// The number of sampled lines kept per pattern, all the lines if not provided.
+    samples: Option<usize>,
+
+    /// The maximum number of lines kept per pattern when not sampled.
+    max_lines: Option<usize>,
+
+    /// Keep the matched lines of every pattern.
+    lines: bool,
+
+    /// Keep the unknown lines.
+    unknown_lines: bool,
+
+    /// Drop the lines already processed, identified by their timestamp, node and content.
+    drop_duplicates: bool,
+
+    /// Count the matched lines per peer mentioned in their content.
+    peers: bool,
+}
+
+impl Matcher {
+    /// Construct a new matcher.
+    pub fn new(regexes: Vec<(Regex, RegexDetails)>, dedup_info: Vec<DeduplicationInfo>) -> Self {
+        Matcher {
+            dedup_info,
+            regexes,
+            node_prefix: None,
+            versions: false,
+            first_errors: false,
+            session_changes: false,
+            samples: None,
+            max_lines: None,
+            lines: true,
+            unknown_lines: true,
+            drop_duplicates: false,
+            peers: false,
+        }
+    }
+
+    /// Count the matched lines of every pattern per mentioned peer.
+    pub fn with_peers(mut self, peers: bool) -> Self {
+        self.peers = peers;
+        self
+    }
+
+    /// Drop the duplicate lines, ie returned by overlapping query chunks.
+    ///
+    /// Only the lines with a timestamp are deduplicated.
+    pub fn with_drop_duplicates(mut self, drop_duplicates: bool) -> Self {
+        self.drop_duplicates = drop_duplicates;
+        self
+    }
+
+    /// Only keep the first, last and N random lines of every pattern.
+    pub fn with_samples(mut self, samples: Option<usize>) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Only keep the first N lines of every pattern when not sampled, counting the others.
+    pub fn with_max_lines(mut self, max_lines: Option<usize>) -> Self {
+        self.max_lines = max_lines;
+        self
+    }
+
+    /// Keep the matched lines of every pattern, and the unknown lines.
+    ///
+    /// Only the number of lines is recorded otherwise, such that huge inputs are
+    /// processed with bounded memory. Default: true.
+    pub fn with_lines(mut self, lines: bool, unknown_lines: bool) -> Self {
+        self.lines = lines;
+        self.unknown_lines = unknown_lines;
+        self
+    }
+
+    /// Detect the version of the nodes from their startup lines.
+    pub fn with_versions(mut self, versions: bool) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Check if the versions of the nodes are detected.
+    pub fn detects_versions(&self) -> bool {
+        self.versions
+    }
+
+    /// Record the first error of every node, and their starts from the startup lines.
+    pub fn with_first_errors(mut self, first_errors: bool) -> Self {
+        self.first_errors = first_errors;
+        self
+    }
+
+    /// Record the session changes printed by the nodes.
+    pub fn with_session_changes(mut self, session_changes: bool) -> Self {
+        self.session_changes = session_changes;
+        self
+    }
+
+    /// Record the timestamps of the session changes printed in the provided lines.
+    pub fn detect_session_changes<'a>(
+        &self,
+        lines: impl Iterator<Item = &'a str>,
+        matches: &mut Matches,
+    ) {
+        if !self.session_changes {
+            return;
+        }
+
+        for line in lines {
+            let (_, content) = self.split_node(line);
+            if !epoch::is_session_change(content) {
+                continue;
+            }
+            if let Some(datetime) = LogLine::parse(content).datetime() {
+                matches.session_changes.push(datetime);
+            }
+        }
+    }
+
+    /// Record the versions printed by the nodes in the provided lines, and the starts
+    /// of the nodes when recording the first errors.
+    ///
+    /// Lines without node information are attributed to the source. The last
+    /// version printed by a node wins (ie after an upgrade).
+    pub fn detect_versions<'a>(
+        &self,
+        source: Option<&str>,
+        lines: impl Iterator<Item = &'a str>,
+        matches: &mut Matches,
+    ) {
+        if !self.versions && !self.first_errors {
+            return;
+        }
+
+        for line in lines {
+            let (node, content) = self.split_node(line);
+            let Some(node) = node.or(source) else {
+                continue;
+            };
+
+            let Some(version) = version::extract_version(content) else {
+                continue;
+            };
+            if self.versions {
+                matches
+                    .versions
+                    .insert(node.to_string(), version.to_string());
+            }
+            // The version is printed once per start.
+            if let (true, Some(datetime)) = (self.first_errors, LogLine::parse(content).datetime())
+            {
+                matches
+                    .starts
+                    .entry(node.to_string())
+                    .or_default()
+                    .push(datetime);
+            }
+        }
+    }
+
+    /// Extract the node of every line from the provided prefix regex.
+    pub fn with_node_prefix(mut self, node_prefix: Option<Regex>) -> Self {
+        self.node_prefix = node_prefix;
+        self
+    }
+
+    /// Split the line into the node that produced it and the log content.
+    ///
+    /// The node is extracted from the configured prefix regex, or from the
+    /// `node="..."` label printed by logcli.
+    pub(crate) fn split_node<'a>(&self, line: &'a str) -> (Option<&'a str>, &'a str) {
+        if let Some(prefix) = &self.node_prefix {
+            if let Some(captures) = prefix.captures(line) {
+                let node = captures.name("node").map(|node| node.as_str());
+                let end = captures.get(0).map(|all| all.end()).unwrap_or_default();
+                return (node, &line[end..]);
+            }
+
+            return (None, line);
+        }
+
+        let node = line.split_once("node=\"").and_then(|(_, rest)| {
+            let end = rest.find('"')?;
+            Some(&rest[..end])
+        });
+        (node, line)
+    }
+
+    /// Split a pattern of the matches into its regex and its deduplication key, if any.
+    ///
+    /// The patterns of the deduplicated lines are formatted as `regex (key)`.
+    pub fn split_pattern<'a>(&self, pattern: &'a str) -> (&'a str, Option<&'a str>) {
+        self.regexes
+            .iter()
+            .map(|(regex, _)| regex.as_str())
+            .filter(|regex| pattern.starts_with(regex))
+            .max_by_key(|regex| regex.len())
+            .and_then(|regex| {
+                let rest = &pattern[regex.len()..];
+                if rest.is_empty() {
+                    return Some((&pattern[..regex.len()], None));
+                }
+                let key = rest.strip_prefix(" (")?.strip_suffix(')')?;
+                Some((&pattern[..regex.len()], Some(key)))
+            })
+            .unwrap_or((pattern, None))
+    }
+
+    fn find_deduplication_key(&self, line: &str) -> Option<String> {
+        for dedup in &self.dedup_info {
+            if !line.contains(&dedup.log_line) {
+                continue;
+            }
+
+            // The keys of the lines embedding hashes or peers would be distinct for every line.
+            let substr = line.rsplit_once(&dedup.dedup_after);
+            if let Some((_, rest)) = substr {
+                return Some(normalize::normalize(rest).into_owned());
+            }
+        }
+
+        // Fallback to the innermost variant of debug-printed errors.
+        dedup::extract_error_variant(line)
+    }
+
+    /// Match the provided lines and record the results.
+    pub fn process_lines(
+        &self,
+        lines: impl Iterator<Item = impl AsRef<str>>,
+        matches: &mut Matches,
+    ) {
+        self.process_source(None, lines, matches)
+    }
+
+    /// Match the lines of the provided source (ie a log file) and record the results.
+    ///
+    /// Lines without node information are attributed to the source, such that
+    /// per-node logs produce a per-file breakdown.
+    pub fn process_source(
+        &self,
+        source: Option<&str>,
+        lines: impl Iterator<Item = impl AsRef<str>>,
+        matches: &mut Matches,
+    ) {
+        let now = std::time::Instant::now();
+        let panics = PanicDetector::default();
+
+        for line in lines {
+            let line = line.as_ref();
+            log::debug!("{}", line);
+
+            matches.total += 1;
+
+            if line.is_empty() {
+                matches.empty_lines += 1;
+                continue;
+            }
+
+            let (node, content) = self.split_node(line);
+            let node = node.or(source);
+            let parsed = LogLine::parse(content);
+            let content = parsed.message.as_ref();
+            let datetime = parsed.datetime();
+
+            if let (true, Some(datetime)) = (self.drop_duplicates, datetime) {
+                if !matches.fingerprints.insert(datetime, node, content) {
+                    log::debug!("Dropping duplicate line: {}", line);
+                    matches.duplicates += 1;
+                    continue;
+                }
+            }
+
+            let mut found = false;
+
+            for (reg, reg_details) in &self.regexes {
+                if reg.is_match(content) {
+                    let dedup_key = self.find_deduplication_key(content);
+
+                    let entry_key = if let Some(dedup_key) = dedup_key {
+                        format!("{} ({})", reg, dedup_key)
+                    } else {
+                        reg.to_string()
+                    };
+
+                    let entry = matches
+                        .found_lines
+                        .entry((entry_key, reg_details.clone()))
+                        .or_insert_with(|| {
+                            Entry::new(Samples::new(self.samples).max_lines(self.max_lines))
+                        });
+                    entry.total += 1;
+                    if panics.is_panic(line) {
+                        entry.panics += 1;
+                    }
+                    if self.lines {
+                        entry.lines.push(line.to_string());
+                    }
+                    if let Some(datetime) = datetime {
+                        entry.timeline.insert(datetime);
+                    }
+                    if let Some(node) = node {
+                        *entry.nodes.entry(node.to_string()).or_default() += 1;
+                    }
+                    if let (true, Some(node), Some(time)) = (
+                        self.first_errors && reg_details.ty == "error",
+                        node,
+                        datetime,
+                    ) {
+                        let error = FirstError {
+                            time,
+                            pattern: reg.to_string(),
+                            permalink: reg_details.permalink.clone(),
+                        };
+                        FirstError::record(&mut matches.first_errors, node, error);
+                    }
+                    if self.peers {
+                        for peer in peer::extract_peers(content) {
+                            *entry.peers.entry(peer.to_string()).or_default() += 1;
+                        }
+                    }
+
+                    found = true;
+                    break;
+                }
+            }
+
+            if !found {
+                matches.unknown += 1;
+                if panics.is_panic(line) {
+                    matches.unknown_panics += 1;
+                }
+                if self.unknown_lines {
+                    matches.unknown_lines.push(line.to_string());
+                }
+                if let Some(node) = node {
+                    *matches.unknown_nodes.entry(node.to_string()).or_default() += 1;
+                }
+            }
+        }
+
+        log::info!(" Processing line took {:?}", now.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_duplicates() {
+        let matcher = Matcher::new(Vec::new(), Vec::new()).with_drop_duplicates(true);
+        let mut matches = Matches::default();
+
+        let first = [
+            "2024-03-29 16:00:00.000  WARN tokio-runtime-worker sync: Slow",
+            "2024-03-29 16:00:01.000  WARN tokio-runtime-worker sync: Slow",
+        ];
+        let second = [
+            "2024-03-29 16:00:01.000  WARN tokio-runtime-worker sync: Slow",
+            "2024-03-29 16:00:02.000  WARN tokio-runtime-worker sync: Slow",
+        ];
+        matcher.process_lines(first.into_iter(), &mut matches);
+        matcher.process_lines(second.into_iter(), &mut matches);
+
+        assert_eq!(matches.duplicates, 1);
+        assert_eq!(matches.unknown_lines.len(), 3);
+    }
+}
@@ -0,0 +1,186 @@
+//! Provenance of the triage reports.
+//!
+//! The reports are shared around, detached from the command that produced them. Every
+//! report starts with the metadata of its run: the version of the tool, the triaged
+//! inputs and time range, the number of patterns and the runtime. The regex repositories
+//! and their resolved commits follow.
+
+use clap::ValueEnum;
+
+use crate::{
+    query::{self, QueryError},
+    Config,
+};
+
+/// The format of the start time of the runs.
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// The metadata of a triage run.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Metadata {
+    /// The version of the tool.
+    pub version: String,
+    /// When the run started.
+    pub started_at: String,
+    /// The source of the lines (ie `loki`, `k8s`, `files`).
+    pub source: String,
+    /// The address of the Loki instance, only provided for the Loki queries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// The queried chains, empty for the local files.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub chains: Vec<String>,
+    /// The node filters of the queries.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<String>,
+    /// The nodes left out of the queries.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub excluded_nodes: Vec<String>,
+    /// The local file, glob or zombienet directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    /// The start of the triaged time range, the first matched line for the local files.
+    pub start_time: Option<String>,
+    /// The end of the triaged time range, the last matched line for the local files.
+    pub end_time: Option<String>,
+    /// The substrings of the lines excluded from the queries, with `--show-exclusions`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclusions: Vec<String>,
+    /// The number of patterns the lines are matched against.
+    pub patterns: usize,
+    /// The runtime of the triage in seconds.
+    pub runtime: f64,
+}
+
+impl Metadata {
+    /// The metadata of the run of the provided options, before the regexes are built.
+    pub fn new(opts: &Config) -> Result<Self, QueryError> {
+        let input = Config::input_file(opts.file.clone())
+            .or_else(|| opts.file_glob.clone())
+            .or_else(|| {
+                opts.zombienet_dir
+                    .as_ref()
+                    .map(|dir| dir.display().to_string())
+            });
+        let source = if input.is_some() {
+            "files".to_string()
+        } else {
+            opts.source
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default()
+        };
+        let queried = input.is_none();
+        let loki = queried && opts.source == query::Source::Loki;
+        let (start_time, end_time) = if queried {
+            let (start_time, end_time) =
+                query::time_range(&opts.start_time, &opts.end_time, opts.last_day)?;
+            (Some(start_time), Some(end_time))
+        } else {
+            (opts.start_time.clone(), opts.end_time.clone())
+        };
+
+        Ok(Metadata {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: chrono::Utc::now().format(TIME_FORMAT).to_string(),
+            source,
+            address: loki.then(|| opts.address.clone()),
+            chains: if queried {
+                opts.chain.clone()
+            } else {
+                Vec::new()
+            },
+            nodes: opts.node.clone(),
+            excluded_nodes: opts.exclude_node.clone(),
+            input,
+            start_time,
+            end_time,
+            exclusions: Vec::new(),
+            patterns: 0,
+            runtime: 0.0,
+        })
+    }
+
+    /// The named values of the metadata, in the rendered order.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("Version", self.version.clone()),
+            ("Started at", self.started_at.clone()),
+            ("Source", self.source.clone()),
+        ];
+        if let Some(address) = &self.address {
+            fields.push(("Loki", address.clone()));
+        }
+        if !self.chains.is_empty() {
+            fields.push(("Chains", self.chains.join(", ")));
+        }
+        if !self.nodes.is_empty() {
+            fields.push(("Nodes", self.nodes.join(", ")));
+        }
+        if !self.excluded_nodes.is_empty() {
+            fields.push(("Excluded nodes", self.excluded_nodes.join(", ")));
+        }
+        if let Some(input) = &self.input {
+            fields.push(("Input", input.clone()));
+        }
+        if let (Some(start_time), Some(end_time)) = (&self.start_time, &self.end_time) {
+            fields.push(("Time range", format!("{start_time} - {end_time}")));
+        }
+        if !self.exclusions.is_empty() {
+            let exclusions: Vec<_> = self
+                .exclusions
+                .iter()
+                .map(|line| format!("{line:?}"))
+                .collect();
+            fields.push(("Excluded lines", exclusions.join(", ")));
+        }
+        fields.push(("Patterns", self.patterns.to_string()));
+        fields.push(("Runtime", format!("{:.1}s", self.runtime)));
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_metadata() {
+        let opts = Config::parse_from([
+            "warn-err",
+            "--file",
+            "node.log",
+            "--node",
+            "alice,bob",
+            "--exclude-node",
+            "canary-.*",
+        ]);
+        let mut metadata = Metadata::new(&opts).unwrap();
+        assert_eq!(metadata.source, "files");
+        assert_eq!(metadata.input.as_deref(), Some("node.log"));
+        assert!(metadata.address.is_none() && metadata.chains.is_empty());
+        assert!(metadata.start_time.is_none());
+
+        metadata.start_time = Some("2024-03-29T16:00:00Z".to_string());
+        metadata.end_time = Some("2024-03-29T17:00:00Z".to_string());
+        metadata.patterns = 42;
+        metadata.runtime = 1.5;
+        let fields = metadata.fields();
+        assert_eq!(fields[2], ("Source", "files".to_string()));
+        assert_eq!(
+            &fields[3..],
+            [
+                ("Nodes", "alice, bob".to_string()),
+                ("Excluded nodes", "canary-.*".to_string()),
+                ("Input", "node.log".to_string()),
+                (
+                    "Time range",
+                    "2024-03-29T16:00:00Z - 2024-03-29T17:00:00Z".to_string()
+                ),
+                ("Patterns", "42".to_string()),
+                ("Runtime", "1.5s".to_string()),
+            ]
+        );
+    }
+}
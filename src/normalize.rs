@@ -0,0 +1,99 @@
+//! Normalization of the variable values of the log lines.
+//!
+//! The lines embed hashes, block numbers, peer identities and durations, such that the
+//! same message rarely repeats verbatim, ie `Banned, disconnecting. Reason: PeerId("12D3KooW..")`.
+//! The values are replaced with typed placeholders before the unknown lines are clustered
+//! and the deduplication keys are computed, keeping their cardinality low.
+
+use std::{borrow::Cow, sync::OnceLock};
+
+use regex::{Captures, Regex};
+
+/// The placeholder of the libp2p peer identities.
+pub const PEER: &str = "<peer>";
+
+/// The placeholder of the hex hashes (ie `0x5e7d…a1f2`).
+pub const HASH: &str = "<hash>";
+
+/// The placeholder of the durations (ie `1.5s`, `300ms`).
+pub const DURATION: &str = "<duration>";
+
+/// The placeholder of the numbers (ie block numbers).
+pub const NUM: &str = "<num>";
+
+/// Every placeholder.
+pub const PLACEHOLDERS: [&str; 4] = [PEER, HASH, DURATION, NUM];
+
+/// Replace the variable values of the text with their typed placeholders.
+///
+/// The values are recognized in order: the peer identities, the hex hashes, the durations,
+/// then the remaining numbers. The text is only copied when it contains a value.
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    static VALUES: OnceLock<Regex> = OnceLock::new();
+
+    // Every value contains a digit.
+    if !text.bytes().any(|byte| byte.is_ascii_digit()) {
+        return Cow::Borrowed(text);
+    }
+
+    let regex = VALUES.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?P<peer>\b(?:12D3KooW|16Uiu2HA|Qm)[1-9A-HJ-NP-Za-km-z]{44,45}\b)",
+            r"|(?P<hash>\b0x[0-9a-fA-F]+(?:…[0-9a-fA-F]+)?|\b[0-9a-fA-F]{16,}\b)",
+            r"|(?P<duration>\b\d+(?:\.\d+)?(?:ns|µs|us|ms|s|m|h)\b)",
+            r"|(?P<num>\b\d+(?:\.\d+)?\b)",
+        ))
+        .expect("Regex is valid; qed")
+    });
+    regex.replace_all(text, |captures: &Captures| {
+        if captures.name("peer").is_some() {
+            PEER
+        } else if captures.name("hash").is_some() {
+            HASH
+        } else if captures.name("duration").is_some() {
+            DURATION
+        } else {
+            NUM
+        }
+    })
+}
+
+/// The literal parts of a normalized text, between its placeholders.
+pub fn literals(text: &str) -> Vec<&str> {
+    let mut parts = vec![text];
+    for placeholder in PLACEHOLDERS {
+        parts = parts
+            .into_iter()
+            .flat_map(|part| part.split(placeholder))
+            .collect();
+    }
+    parts.retain(|part| !part.trim().is_empty());
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let peer = "12D3KooWJmAHvNWrrHAhHBfkPb4zGoTvBcEq7nWxBHm4F4X3Hf9R";
+        assert_eq!(
+            normalize(&format!(
+                "banned, disconnecting, reason: peer {peer} misbehaved at #1234 after 1.5s"
+            )),
+            "banned, disconnecting, reason: peer <peer> misbehaved at #<num> after <duration>"
+        );
+        assert_eq!(
+            normalize("Error importing block 0x5e7d…a1f2: UnknownParent(0xdeadbeef) in 300ms"),
+            "Error importing block <hash>: UnknownParent(<hash>) in <duration>"
+        );
+        assert_eq!(normalize("parachain v2 protocol"), "parachain v2 protocol");
+        assert!(matches!(normalize("No values"), Cow::Borrowed(_)));
+
+        assert_eq!(
+            literals(" Reason: <peer> misbehaved at #<num>"),
+            [" Reason: ", " misbehaved at #"]
+        );
+    }
+}
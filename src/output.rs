@@ -0,0 +1,165 @@
+//! Output formats of the reports.
+
+use std::{collections::BTreeMap, fmt::Write, path::PathBuf};
+
+use crate::template::Template;
+
+/// The format of the printed report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Plain text tables, aligned for terminals.
+    #[default]
+    Text,
+    /// Markdown tables.
+    Markdown,
+    /// JSON object, suitable for scripts.
+    Json,
+}
+
+impl OutputFormat {
+    /// The formats of the reports written to a directory.
+    const ALL: [OutputFormat; 3] = [
+        OutputFormat::Text,
+        OutputFormat::Markdown,
+        OutputFormat::Json,
+    ];
+
+    /// The extension of the report files.
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+/// A report rendered in every [`OutputFormat`].
+pub trait Render: serde::Serialize {
+    /// Write the report as plain text tables.
+    fn render_text(&self, out: &mut String) -> std::fmt::Result;
+
+    /// Write the report as markdown tables.
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result;
+
+    /// Render the report in the provided format.
+    fn render(&self, format: OutputFormat) -> Result<String, Box<dyn std::error::Error>> {
+        let mut out = String::new();
+        match format {
+            OutputFormat::Text => self.render_text(&mut out)?,
+            OutputFormat::Markdown => self.render_markdown(&mut out)?,
+            OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(self)?)?,
+        }
+
+        Ok(out)
+    }
+}
+
+/// Escape the pipes of a markdown table cell, which would otherwise split the cell.
+pub fn escape(value: &str) -> String {
+    value.replace('|', "\\|")
+}
+
+/// The counts per name (ie `alice=2 bob=1`), `-` if there are none.
+pub fn breakdown(counts: &BTreeMap<String, usize>) -> String {
+    if counts.is_empty() {
+        return "-".to_string();
+    }
+
+    counts
+        .iter()
+        .map(|(name, count)| format!("{name}={count}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The destination of a report: the standard output, a file or a directory.
+#[derive(Debug, Clone)]
+pub struct ReportOutput {
+    /// The format of the report printed or written to the file.
+    pub format: OutputFormat,
+    /// The template rendering the report instead of the format.
+    pub template: Option<Template>,
+    /// The file of the report.
+    pub path: Option<PathBuf>,
+    /// The directory of the report, written in every format.
+    pub dir: Option<PathBuf>,
+}
+
+impl ReportOutput {
+    /// Write the report to its destination, rendered in its format or by the template.
+    pub fn write(&self, report: &impl Render) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = &self.dir {
+            std::fs::create_dir_all(dir)?;
+            for format in OutputFormat::ALL {
+                let path = dir.join(format!("report.{}", format.extension()));
+                std::fs::write(&path, report.render(format)?)?;
+                log::info!("Report written to {}", path.display());
+            }
+            return Ok(());
+        }
+
+        let report = match &self.template {
+            Some(template) => template.render(report)?,
+            None => report.render(self.format)?,
+        };
+        match &self.path {
+            Some(path) => {
+                std::fs::write(path, report)
+                    .map_err(|err| format!("Cannot write the report {}: {err}", path.display()))?;
+                log::info!("Report written to {}", path.display());
+            }
+            None => print!("{report}"),
+        }
+
+        Ok(())
+    }
+}
+
+/// The colors of the text reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// The errors.
+    Red,
+    /// The warnings.
+    Yellow,
+    /// The patterns reporting panics.
+    Magenta,
+    /// The headers of the tables.
+    Bold,
+}
+
+impl Color {
+    /// The SGR parameter of the color.
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Yellow => "33",
+            Color::Magenta => "35",
+            Color::Bold => "1",
+        }
+    }
+
+    /// Wrap the text with the escape sequences of the color, when enabled.
+    ///
+    /// The text is padded before being painted, since the escape sequences would be
+    /// counted by the width of the format specifiers.
+    pub fn paint(self, text: &str, enabled: bool) -> String {
+        if enabled {
+            format!("\u{1b}[{}m{text}\u{1b}[0m", self.code())
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Check if the reports printed to the standard output can be colored.
+///
+/// The colors are disabled when the output is redirected, or by the `NO_COLOR` environment
+/// variable (see <https://no-color.org>).
+pub fn is_color_terminal() -> bool {
+    use std::io::IsTerminal;
+
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && std::io::stdout().is_terminal()
+}
@@ -0,0 +1,147 @@
+//! Ownership of the source files, from the `CODEOWNERS` file of the repositories.
+//!
+//! ```text
+//! # Networking
+//! /substrate/client/network/ @paritytech/networking
+//! /polkadot/node/core/dispute-coordinator/ @paritytech/parachains-core
+//! *.md @paritytech/docs
+//! ```
+//!
+//! Like on GitHub, the last matching rule of the file takes precedence.
+
+use regex::Regex;
+
+/// The locations of the `CODEOWNERS` file, in the lookup order of GitHub.
+pub const CODEOWNERS_PATHS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// A rule of the `CODEOWNERS` file.
+#[derive(Debug, Clone)]
+struct Rule {
+    /// The paths matched by the pattern of the rule.
+    regex: Regex,
+    /// The owners of the matched paths, empty to remove the ownership.
+    owners: Vec<String>,
+}
+
+/// The owners of the source files of a repository.
+#[derive(Debug, Clone, Default)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+impl CodeOwners {
+    /// Parse the rules of the `CODEOWNERS` file.
+    ///
+    /// The rules with invalid patterns are skipped, like GitHub does.
+    pub fn parse(content: &str) -> Self {
+        let rules = content
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(rule, _)| rule))
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let pattern = parts.next()?;
+                let Ok(regex) = Regex::new(&pattern_to_regex(pattern)) else {
+                    log::debug!("Skipped invalid CODEOWNERS pattern {pattern:?}");
+                    return None;
+                };
+
+                Some(Rule {
+                    regex,
+                    owners: parts.map(str::to_string).collect(),
+                })
+            })
+            .collect();
+
+        CodeOwners { rules }
+    }
+
+    /// Find and parse the `CODEOWNERS` file among the files of a repository.
+    pub fn find(files: &[(String, String)]) -> Self {
+        CODEOWNERS_PATHS
+            .iter()
+            .find_map(|path| files.iter().find(|(file, _)| file == path))
+            .map(|(_, content)| CodeOwners::parse(content))
+            .unwrap_or_default()
+    }
+
+    /// The owners of the provided file, relative to the repository root.
+    pub fn owners(&self, file: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.regex.is_match(file))
+            .map(|rule| rule.owners.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// Convert a gitignore-like pattern of the `CODEOWNERS` file to a regex of the paths.
+///
+/// The patterns without a leading or inner slash match at any depth, and the patterns
+/// of directories match all the files below.
+fn pattern_to_regex(pattern: &str) -> String {
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    // `docs/*` only matches the files directly in `docs`.
+    let shallow = pattern.ends_with("/*") && !pattern.ends_with("**/*");
+    let pattern = pattern.trim_end_matches('/');
+
+    let mut regex = String::from(if anchored { "^" } else { "^(.*/)?" });
+    let mut chars = pattern.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            ch => regex.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    regex.push_str(if shallow { "$" } else { "(/.*)?$" });
+
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owners() {
+        let owners = CodeOwners::find(&[(
+            ".github/CODEOWNERS".to_string(),
+            r#"
+# Lists some code owners.
+* @paritytech/ci
+/substrate/client/network/ @paritytech/networking
+/polkadot/node/core/dispute-coordinator @paritytech/parachains-core @alice
+Cargo.toml @paritytech/release
+/docs/* @paritytech/docs
+/substrate/client/network/README.md
+"#
+            .to_string(),
+        )]);
+
+        assert_eq!(
+            owners.owners("substrate/client/network/src/service.rs"),
+            ["@paritytech/networking"]
+        );
+        assert_eq!(
+            owners.owners("polkadot/node/core/dispute-coordinator/src/lib.rs"),
+            ["@paritytech/parachains-core", "@alice"]
+        );
+        assert_eq!(
+            owners.owners("substrate/client/db/Cargo.toml"),
+            ["@paritytech/release"]
+        );
+        assert_eq!(owners.owners("docs/index.md"), ["@paritytech/docs"]);
+        assert_eq!(owners.owners("docs/guide/index.md"), ["@paritytech/ci"]);
+        // The last matching rule removes the ownership.
+        assert!(owners
+            .owners("substrate/client/network/README.md")
+            .is_empty());
+        assert!(CodeOwners::default().owners("src/lib.rs").is_empty());
+    }
+}
@@ -0,0 +1,703 @@
+//! Detection of panics in the node logs.
+//!
+//! The panics are listed in order of detection, and deduplicated by their location and
+//! normalized message, such that the same panic of many nodes is reported once.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+};
+
+use chrono::NaiveDateTime;
+
+use crate::{
+    collector::Collection,
+    dedup, grafana,
+    matcher::Matcher,
+    normalize,
+    output::{escape, Render},
+    parse, restarts,
+    stats::Stats,
+    summary::Summary,
+    PanicsConfig,
+};
+
+/// Loki filter selecting the lines produced by a panic.
+///
+/// - `panicked at`: the panic message (ie `Thread 'tokio-runtime-worker' panicked at ..`)
+/// - `stack backtrace:`: the backtrace printed by the default panic hook
+/// - `This is a bug. Please report it at`: the footer of the substrate panic handler
+/// - `^[^0-9{]`: the lines without leading timestamp, ie the message printed on the line
+///   following the location by the recent toolchains
+const STRICT_FILTER: &str =
+    r"|~ `panicked at|stack backtrace:|This is a bug\. Please report it at|^[^0-9{]`";
+
+/// Loki filter selecting any line mentioning a panic, and the lines without leading
+/// timestamp.
+const LOOSE_FILTER: &str = "|~ `panic|^[^0-9{]`";
+
+/// Markers of the lines produced by a panic, besides the panic message itself.
+const BACKTRACE_MARKERS: [&str; 2] = ["stack backtrace:", "This is a bug. Please report it at"];
+
+/// The prefix of the location and message of the panics.
+const PANICKED_AT: &str = "panicked at ";
+
+/// The format of the times of the panics.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// Detects panics in log lines.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PanicDetector {
+    /// Consider any line containing `panic` as a panic.
+    loose: bool,
+}
+
+impl PanicDetector {
+    /// Construct a new detector.
+    ///
+    /// The loose detector matches any line containing `panic`, which also matches
+    /// benign messages (ie "panic handler installed").
+    pub fn new(loose: bool) -> Self {
+        PanicDetector { loose }
+    }
+
+    /// The Loki line filter of the detector.
+    pub fn query_filter(&self) -> &'static str {
+        if self.loose {
+            LOOSE_FILTER
+        } else {
+            STRICT_FILTER
+        }
+    }
+
+    /// Check if the line reports a panic.
+    ///
+    /// Each panic is counted once, by its `panicked at` message.
+    pub fn is_panic(&self, line: &str) -> bool {
+        if self.loose {
+            line.contains("panic")
+        } else {
+            line.contains("panicked at")
+        }
+    }
+
+    /// Check if the line is part of the output of a panic (message or backtrace).
+    pub fn is_panic_output(&self, line: &str) -> bool {
+        self.is_panic(line) || BACKTRACE_MARKERS.iter().any(|marker| line.contains(marker))
+    }
+}
+
+/// Split the panic message of the line into its location and message.
+///
+/// Supports the `panicked at 'message', src/lib.rs:12` messages of the substrate panic
+/// handler and of the older toolchains, and the `panicked at src/lib.rs:12:5:` messages of
+/// the recent toolchains, whose message follows on the next line (empty if not provided).
+/// The lines without location are returned as the message.
+pub fn parse_panic(line: &str) -> (Option<String>, String) {
+    let Some((_, rest)) = line.split_once(PANICKED_AT) else {
+        return (None, line.trim().to_string());
+    };
+
+    if let Some((message, location)) = rest
+        .strip_prefix('\'')
+        .and_then(|quoted| quoted.rsplit_once("', "))
+    {
+        return (Some(location.trim().to_string()), message.to_string());
+    }
+
+    let (location, message) = rest.split_once('\n').unwrap_or((rest, ""));
+    let location = location.trim_end();
+    let location = location.strip_suffix(':').unwrap_or(location);
+    if location.is_empty() || location.contains(char::is_whitespace) || !location.contains(':') {
+        return (None, rest.trim().to_string());
+    }
+    (Some(location.to_string()), message.trim().to_string())
+}
+
+/// Strip the prefix of the lines printed by `logcli` (ie `2024-03-29T16:00:00Z {node="alice"} `),
+/// returning the time of the line and its content.
+fn strip_logcli_prefix(line: &str) -> Option<(NaiveDateTime, &str)> {
+    let (time, rest) = line.split_once(' ')?;
+    let time = chrono::DateTime::parse_from_rfc3339(time).ok()?.naive_utc();
+    let (_, content) = rest.strip_prefix('{')?.split_once("} ")?;
+    Some((time, content))
+}
+
+/// The index of the line logged next by the node of every line, if any.
+///
+/// The lines of the nodes are interleaved, and printed newest first by `logcli`.
+fn next_lines(lines: &[&str], matcher: &Matcher) -> Vec<Option<usize>> {
+    let mut next = vec![None; lines.len()];
+    let mut previous = HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        let (node, content) = matcher.split_node(line);
+        let Some(previous) = previous.insert(node, index) else {
+            continue;
+        };
+        if strip_logcli_prefix(content).is_some() {
+            next[index] = Some(previous);
+        } else {
+            next[previous] = Some(index);
+        }
+    }
+    next
+}
+
+/// The message of a panic printed on the line following its location by the recent
+/// toolchains (ie `panicked at src/lib.rs:12:5:`), from the next line of its node.
+fn next_message(line: Option<&str>, matcher: &Matcher, detector: &PanicDetector) -> Option<String> {
+    let (_, next) = matcher.split_node(line?);
+    let next = strip_logcli_prefix(next).map_or(next, |(_, content)| content);
+    (!next.trim().is_empty()
+        && parse::parse_timestamp(next).is_none()
+        && !detector.is_panic_output(next))
+    .then(|| next.trim().to_string())
+}
+
+/// A detected panic.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PanicEvent {
+    /// The timestamp of the panic, when it could be parsed.
+    pub time: Option<NaiveDateTime>,
+    /// The node that panicked, when the line carries node information.
+    pub node: Option<String>,
+    /// The start of the node following the panic, only provided with `--restart-window`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart: Option<NaiveDateTime>,
+    /// The source location of the panic (ie `src/lib.rs:12:5`), when it could be parsed.
+    pub location: Option<String>,
+    /// The message of the panic, empty when not printed along the location.
+    pub message: String,
+    /// The panic message.
+    pub line: String,
+}
+
+/// The panics of a single location and normalized message.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PanicGroup {
+    /// The source location of the panics.
+    pub location: Option<String>,
+    /// The message of the panics, normalized (see [`normalize::normalize`]).
+    pub message: String,
+    /// The nodes that panicked, sorted.
+    pub nodes: Vec<String>,
+    /// The number of panics.
+    pub count: usize,
+    /// The timestamp of the first panic.
+    pub first: Option<NaiveDateTime>,
+    /// The timestamp of the last panic.
+    pub last: Option<NaiveDateTime>,
+    /// The link to the panics in Grafana Explore, only provided with `--explore-url`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explore: Option<String>,
+}
+
+/// Deduplicate the panics by location and normalized message, the most frequent first.
+pub fn group(panics: &[PanicEvent]) -> Vec<PanicGroup> {
+    let mut groups: BTreeMap<(Option<&str>, String), PanicGroup> = BTreeMap::new();
+    for panic in panics {
+        let message = normalize::normalize(&panic.message).into_owned();
+        let group = groups
+            .entry((panic.location.as_deref(), message.clone()))
+            .or_insert_with(|| PanicGroup {
+                location: panic.location.clone(),
+                message,
+                nodes: Vec::new(),
+                count: 0,
+                first: None,
+                last: None,
+                explore: None,
+            });
+        group.count += 1;
+        if let Some(node) = &panic.node {
+            group.nodes.push(node.clone());
+        }
+        if let Some(time) = panic.time {
+            group.first = Some(group.first.map_or(time, |first| first.min(time)));
+            group.last = Some(group.last.map_or(time, |last| last.max(time)));
+        }
+    }
+
+    let mut groups: Vec<_> = groups
+        .into_values()
+        .map(|mut group| {
+            group.nodes = group
+                .nodes
+                .into_iter()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            group
+        })
+        .collect();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.count));
+    groups
+}
+
+/// The Grafana annotations of the timestamped panics, one per location (or normalized
+/// message) and [`grafana::PANIC_INTERVAL`], the most frequent first.
+///
+/// The annotations are tagged with the nodes that panicked and the provided chains.
+fn annotations(panics: &[PanicEvent], chains: &[String]) -> Vec<grafana::Annotation> {
+    let interval = grafana::PANIC_INTERVAL.num_seconds();
+    // (Location, message, bucket) -> (first panic, count, nodes).
+    let mut buckets: BTreeMap<_, (NaiveDateTime, &str, usize, BTreeSet<&str>)> = BTreeMap::new();
+    for panic in panics {
+        let Some(time) = panic.time else {
+            continue;
+        };
+        let message = match panic.location {
+            Some(_) => Default::default(),
+            None => normalize::normalize(&panic.message),
+        };
+        let bucket = time.and_utc().timestamp().div_euclid(interval);
+        let entry = buckets
+            .entry((panic.location.as_deref(), message, bucket))
+            .or_insert((time, &panic.line, 0, BTreeSet::new()));
+        if time < entry.0 {
+            (entry.0, entry.1) = (time, &panic.line);
+        }
+        entry.2 += 1;
+        entry.3.extend(panic.node.as_deref());
+    }
+
+    let mut buckets: Vec<_> = buckets.into_values().collect();
+    buckets.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    buckets
+        .into_iter()
+        .map(|(time, line, count, nodes)| {
+            nodes
+                .into_iter()
+                .chain(chains.iter().map(String::as_str))
+                .fold(
+                    grafana::Annotation::panic(time, line, count),
+                    |annotation, tag| annotation.tag(tag),
+                )
+        })
+        .collect()
+}
+
+/// The report of the detected panics.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct PanicsReport {
+    /// The panics deduplicated by location and normalized message, the most frequent first.
+    pub groups: Vec<PanicGroup>,
+    /// The detected panics, in order of detection.
+    pub panics: Vec<PanicEvent>,
+    /// The panics were correlated with the restarts of the nodes in this interval.
+    #[serde(skip)]
+    pub restart_window: Option<chrono::Duration>,
+    /// The summary of the run.
+    pub summary: Summary,
+}
+
+impl PanicsReport {
+    /// Whether the node restarted after the panic, ie `after 35s`.
+    fn restart(&self, panic: &PanicEvent) -> String {
+        match (panic.time, panic.restart) {
+            (Some(time), Some(restart)) => format!("after {}s", (restart - time).num_seconds()),
+            _ => "no".to_string(),
+        }
+    }
+}
+
+impl Render for PanicsReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        if self.panics.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(
+            out,
+            "{0: <60} | {1: <6} | {2: <23} | {3: <23} | {4: <40} | Message",
+            "Location", "Count", "First", "Last", "Nodes"
+        )?;
+        for group in &self.groups {
+            writeln!(
+                out,
+                "{0: <60} | {1: <6} | {2: <23} | {3: <23} | {4: <40} | {5}",
+                group.location.as_deref().unwrap_or("-"),
+                group.count,
+                format_time(group.first),
+                format_time(group.last),
+                format_nodes(&group.nodes),
+                format_message(&group.message)
+            )?;
+        }
+        writeln!(out)?;
+
+        writeln!(
+            out,
+            "{0: <23} | {1: <40}{2} | Panic",
+            "Time",
+            "Node",
+            if self.restart_window.is_some() {
+                format!(" | {: <12}", "Restart")
+            } else {
+                String::new()
+            }
+        )?;
+        for panic in &self.panics {
+            writeln!(
+                out,
+                "{0: <23} | {1: <40}{2} | {3}",
+                format_time(panic.time),
+                panic.node.as_deref().unwrap_or("-"),
+                if self.restart_window.is_some() {
+                    format!(" | {: <12}", self.restart(panic))
+                } else {
+                    String::new()
+                },
+                panic.line
+            )?;
+        }
+        writeln!(out)?;
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        if self.panics.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "| Location | Message | Nodes | Count | First | Last |")?;
+        writeln!(out, "|---|---|---|---|---|---|")?;
+        for group in &self.groups {
+            writeln!(
+                out,
+                "| {}{} | `{}` | {} | {} | {} | {} |",
+                group
+                    .location
+                    .as_ref()
+                    .map(|location| format!("`{location}`"))
+                    .unwrap_or_else(|| "-".to_string()),
+                group
+                    .explore
+                    .as_ref()
+                    .map(|explore| format!(" [logs]({explore})"))
+                    .unwrap_or_default(),
+                escape(format_message(&group.message)),
+                escape(&format_nodes(&group.nodes)),
+                group.count,
+                format_time(group.first),
+                format_time(group.last)
+            )?;
+        }
+        writeln!(out)?;
+
+        let restarts = self.restart_window.is_some();
+        writeln!(
+            out,
+            "| Time | Node |{} Panic |",
+            if restarts { " Restart |" } else { "" }
+        )?;
+        writeln!(out, "|---|---|{}---|", if restarts { "---|" } else { "" })?;
+        for panic in &self.panics {
+            writeln!(
+                out,
+                "| {} | {} |{} `{}` |",
+                format_time(panic.time),
+                panic.node.as_deref().unwrap_or("-"),
+                if restarts {
+                    format!(" {} |", self.restart(panic))
+                } else {
+                    String::new()
+                },
+                escape(&panic.line)
+            )?;
+        }
+        writeln!(out)?;
+
+        Ok(())
+    }
+}
+
+/// The time of a panic, `-` if unknown.
+fn format_time(time: Option<NaiveDateTime>) -> String {
+    time.map(|time| time.format(TIME_FORMAT).to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// The nodes of a panic, `-` if unknown.
+fn format_nodes(nodes: &[String]) -> String {
+    if nodes.is_empty() {
+        "-".to_string()
+    } else {
+        nodes.join(", ")
+    }
+}
+
+/// The message of a panic, `-` if not printed along the location.
+fn format_message(message: &str) -> &str {
+    if message.is_empty() {
+        "-"
+    } else {
+        message
+    }
+}
+
+/// Detect the panics of the provided files or Loki query.
+///
+/// The panics are annotated in Grafana when configured, correlated with the restarts of
+/// the nodes with `--restart-window`, and linked to Grafana Explore with `--explore-url`.
+pub async fn run(opts: PanicsConfig) -> Result<PanicsReport, Box<dyn std::error::Error>> {
+    log::info!("Running panic query");
+    let mut stats = Stats::new();
+    let detector = PanicDetector::new(opts.loose);
+    let restart_window = opts.restart_window;
+    let opts = opts.config;
+    let grafana = opts.grafana();
+    let explore = opts.explore()?;
+    let matcher = Matcher::new(Vec::new(), Vec::new()).with_node_prefix(opts.node_prefix()?);
+    // Overlapping query chunks can return the same panic twice.
+    let drop_duplicates = opts.is_query();
+    let mut fingerprints = dedup::LineFingerprints::default();
+    let mut panics = Vec::new();
+    // Local files are read once (ie the standard input), the banners are collected
+    // along the panics. The Loki banners are queried separately.
+    let mut banners = if restart_window.is_some() && !drop_duplicates {
+        let matcher = Matcher::new(Vec::new(), Vec::new()).with_node_prefix(opts.node_prefix()?);
+        let banners = Collection::new(restarts::Banners::default(), matcher, false);
+        Some((banners, Stats::new()))
+    } else {
+        None
+    };
+
+    // Returns the number of processed lines and panics.
+    let mut count_lines = |source: Option<&str>, result: &str| {
+        if let Some((banners, stats)) = &mut banners {
+            banners.process(source, result, stats);
+        }
+
+        let (mut lines, mut count) = (0, 0);
+        let result: Vec<_> = result.lines().collect();
+        let next = next_lines(&result, &matcher);
+        for (index, line) in result.iter().copied().enumerate() {
+            log::debug!("{}", line);
+            lines += 1;
+
+            if line.is_empty() {
+                stats.empty_lines += 1;
+                continue;
+            }
+
+            if !detector.is_panic(line) {
+                if detector.is_panic_output(line) {
+                    log::debug!("Skipping panic output line: {}", line);
+                }
+                continue;
+            }
+
+            let (node, content) = matcher.split_node(line);
+            let node = node.or(source);
+            let (time, content) = match strip_logcli_prefix(content) {
+                Some((time, content)) => (Some(time), content),
+                None => (None, content),
+            };
+            let parsed = parse::LogLine::parse(content);
+            let datetime = parsed.datetime().or(time);
+            if let (true, Some(datetime)) = (drop_duplicates, datetime) {
+                if !fingerprints.insert(datetime, node, content) {
+                    stats.duplicates += 1;
+                    continue;
+                }
+            }
+
+            stats.total += 1;
+            count += 1;
+
+            let (location, mut message) = parse_panic(&parsed.message);
+            if location.is_some() && message.is_empty() {
+                let next = next[index].map(|next| result[next]);
+                message = next_message(next, &matcher, &detector).unwrap_or_default();
+            }
+            panics.push(PanicEvent {
+                time: datetime,
+                node: node.map(str::to_string),
+                restart: None,
+                location,
+                message,
+                line: line.to_string(),
+            });
+        }
+        (lines, count)
+    };
+    opts.process_sources(detector.query_filter(), &mut count_lines)?;
+
+    if let Some(window) = restart_window {
+        let starts = match banners {
+            Some((banners, _)) => banners.report(),
+            None => restarts::collect(&opts)?,
+        };
+        for panic in &mut panics {
+            if let Some(time) = panic.time {
+                panic.restart = starts.restart_after(panic.node.as_deref(), time, window);
+            }
+        }
+    }
+
+    if let Some(grafana) = &grafana {
+        // The lines of the local files are not attributed to the chains.
+        let chains = if opts.is_query() {
+            opts.chain.as_slice()
+        } else {
+            &[]
+        };
+        grafana.annotate(annotations(&panics, chains)).await;
+    }
+
+    let mut groups = group(&panics);
+    if let Some(explore) = &explore {
+        for group in &mut groups {
+            group.explore = Some(match &group.location {
+                Some(location) => explore.link(&regex::escape(location), None),
+                None => explore.link("panic", Some(&group.message)),
+            });
+        }
+    }
+
+    Ok(PanicsReport {
+        summary: Summary {
+            panics: panics.len(),
+            ..Default::default()
+        },
+        groups,
+        panics,
+        restart_window,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OutputFormat;
+
+    #[test]
+    fn test_panic_groups() {
+        assert_eq!(
+            parse_panic("Thread 'tokio-runtime-worker' panicked at 'Storage root must match that calculated.', /builds/substrate/frame/executive/src/lib.rs:508"),
+            (
+                Some("/builds/substrate/frame/executive/src/lib.rs:508".to_string()),
+                "Storage root must match that calculated.".to_string()
+            )
+        );
+        assert_eq!(
+            parse_panic("thread 'main' panicked at src/main.rs:2:5:"),
+            (Some("src/main.rs:2:5".to_string()), String::new())
+        );
+        assert_eq!(
+            parse_panic("thread 'main' panicked at src/main.rs:2:5:\nindex out of bounds"),
+            (
+                Some("src/main.rs:2:5".to_string()),
+                "index out of bounds".to_string()
+            )
+        );
+        assert_eq!(
+            parse_panic("panic handler installed"),
+            (None, "panic handler installed".to_string())
+        );
+
+        let time = |time: &str| parse::parse_timestamp(time);
+        let panic = |at: &str, node: &str, message: &str| PanicEvent {
+            time: time(at),
+            node: Some(node.to_string()),
+            restart: None,
+            location: Some("src/lib.rs:12:5".to_string()),
+            message: message.to_string(),
+            line: String::new(),
+        };
+        let panics = [
+            panic("2024-03-29 16:00:00.000", "bob", "Block #12 not found"),
+            panic("2024-03-29 17:00:00.000", "alice", "Block #13 not found"),
+            panic("2024-03-29 15:00:00.000", "bob", "Block #14 not found"),
+            panic("2024-03-29 15:30:00.000", "alice", "Out of memory"),
+        ];
+        let report = PanicsReport {
+            groups: group(&panics),
+            panics: panics.to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(report.groups.len(), 2);
+        assert_eq!(report.groups[0].message, "Block #<num> not found");
+        assert_eq!(report.groups[0].nodes, ["alice", "bob"]);
+        assert_eq!(report.groups[0].count, 3);
+        assert_eq!(report.groups[0].first, time("2024-03-29 15:00:00.000"));
+        assert_eq!(report.groups[0].last, time("2024-03-29 17:00:00.000"));
+
+        let markdown = report.render(OutputFormat::Markdown).unwrap();
+        assert!(markdown.contains("| `src/lib.rs:12:5` | `Block #<num> not found` | alice, bob | 3 | 2024-03-29 15:00:00.000 | 2024-03-29 17:00:00.000 |"));
+
+        // The panics of a location within the interval are annotated once, the panics
+        // without timestamp are not annotated.
+        let mut burst = panics.to_vec();
+        burst.push(panic(
+            "2024-03-29 15:02:00.000",
+            "alice",
+            "Block #15 not found",
+        ));
+        burst.push(PanicEvent {
+            time: None,
+            ..panic("2024-03-29 15:00:00.000", "alice", "Block #16 not found")
+        });
+        let annotations = annotations(&burst, &["versi".to_string()]);
+        assert_eq!(annotations.len(), 4);
+        assert_eq!(
+            annotations[0].time,
+            time("2024-03-29 15:00:00.000")
+                .unwrap()
+                .and_utc()
+                .timestamp_millis()
+        );
+        assert_eq!(annotations[0].tags, ["panic", "alice", "bob", "versi"]);
+        assert_eq!(annotations[0].text, " (2 panics)");
+        assert_eq!(annotations[1].tags, ["panic", "alice", "versi"]);
+    }
+
+    #[test]
+    fn test_panic_messages() {
+        // The lines of the nodes are interleaved and newest first, the messages
+        // preceding their locations.
+        let lines = [
+            r#"2024-03-29T16:00:03Z {chain="versi", node="alice"} invalid block"#,
+            r#"2024-03-29T16:00:02Z {chain="versi", node="bob"} invalid state"#,
+            r#"2024-03-29T16:00:02Z {chain="versi", node="alice"} thread 'tokio-runtime-worker' panicked at src/block.rs:7:9:"#,
+            r#"2024-03-29T16:00:01Z {chain="versi", node="bob"} thread 'main' panicked at src/lib.rs:12:5:"#,
+            r#"2024-03-29T16:00:00Z {chain="versi", node="bob"} 2024-03-29 16:00:00.000  WARN sync: Slow"#,
+        ];
+        let (matcher, detector) = (
+            Matcher::new(Vec::new(), Vec::new()),
+            PanicDetector::new(false),
+        );
+        let next = next_lines(&lines, &matcher);
+        assert_eq!(next, [None, None, Some(0), Some(1), Some(3)]);
+
+        let (time, content) = strip_logcli_prefix(lines[3]).unwrap();
+        assert_eq!(
+            Some(time),
+            parse::parse_timestamp("2024-03-29 16:00:01.000")
+        );
+        assert_eq!(
+            parse_panic(content),
+            (Some("src/lib.rs:12:5".to_string()), String::new())
+        );
+        let message =
+            |index: usize| next_message(next[index].map(|next| lines[next]), &matcher, &detector);
+        assert_eq!(message(2).as_deref(), Some("invalid block"));
+        assert_eq!(message(3).as_deref(), Some("invalid state"));
+        // The panic lines are not messages.
+        assert_eq!(message(4), None);
+
+        // The local files are in the order of the lines.
+        let lines = [
+            "thread 'main' panicked at src/lib.rs:12:5:",
+            "invalid state",
+        ];
+        let next = next_lines(&lines, &matcher);
+        assert_eq!(next, [Some(1), None]);
+        assert_eq!(
+            next_message(Some(lines[1]), &matcher, &detector).as_deref(),
+            Some("invalid state")
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Parse log lines, detecting structured JSON records.
+//!
+//! Deployments emitting JSON logs produce records like
+//! `{"timestamp":"..","level":"WARN","target":"sync","fields":{"message":".."}}`.
+//! Only the message of such records is matched against the regexes.
+
+use std::borrow::Cow;
+
+/// A parsed log line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine<'a> {
+    /// The timestamp of the line, if provided by a structured record.
+    pub timestamp: Option<String>,
+    /// The level of the line, if provided by a structured record.
+    pub level: Option<String>,
+    /// The target of the line, if provided by a structured record.
+    pub target: Option<String>,
+    /// The message to match against, or the full line for plain text logs.
+    pub message: Cow<'a, str>,
+}
+
+impl<'a> LogLine<'a> {
+    /// Parse the provided line.
+    ///
+    /// Lines that are not JSON records are returned as plain text messages.
+    pub fn parse(line: &'a str) -> Self {
+        Self::parse_json(line).unwrap_or(LogLine {
+            timestamp: None,
+            level: None,
+            target: None,
+            message: Cow::Borrowed(line),
+        })
+    }
+
+    fn parse_json(line: &str) -> Option<Self> {
+        let trimmed = line.trim();
+        if !trimmed.starts_with('{') {
+            return None;
+        }
+
+        let value: serde_json::Value = serde_json::from_str(trimmed).ok()?;
+        let record = value.as_object()?;
+        let fields = record.get("fields").and_then(|fields| fields.as_object());
+
+        let field = |keys: &[&str]| {
+            keys.iter().find_map(|key| {
+                record
+                    .get(*key)
+                    .or_else(|| fields.and_then(|fields| fields.get(*key)))
+                    .map(|value| match value {
+                        serde_json::Value::String(value) => value.clone(),
+                        value => value.to_string(),
+                    })
+            })
+        };
+
+        let message = field(&["message", "msg"])?;
+
+        Some(LogLine {
+            timestamp: field(&["timestamp", "ts", "time"]),
+            level: field(&["level", "lvl"]),
+            target: field(&["target"]),
+            message: Cow::Owned(message),
+        })
+    }
+
+    /// The timestamp of the line.
+    ///
+    /// Structured records provide the timestamp as a field, while plain text lines
+    /// are expected to start with it (ie `2024-03-29 16:00:00.000` or the RFC 3339
+    /// timestamp printed by logcli).
+    pub fn datetime(&self) -> Option<chrono::NaiveDateTime> {
+        match &self.timestamp {
+            Some(timestamp) => parse_timestamp(timestamp),
+            None => parse_timestamp(&self.message),
+        }
+    }
+
+    /// Check if the line is a warning or an error.
+    ///
+    /// The level of structured records is compared case-insensitively, while
+    /// plain text lines are expected to contain the `WARN` or `ERROR` level.
+    pub fn is_warn_or_error(&self, line: &str) -> bool {
+        self.has_level(line, "WARN") || self.has_level(line, "ERROR")
+    }
+
+    /// Check if the line has the provided uppercase level (ie `INFO`).
+    ///
+    /// The level of structured records is compared case-insensitively, while
+    /// plain text lines are expected to contain the level.
+    pub fn has_level(&self, line: &str, level: &str) -> bool {
+        match &self.level {
+            Some(record) if level == "WARN" && record.eq_ignore_ascii_case("warning") => true,
+            Some(record) => record.eq_ignore_ascii_case(level),
+            None => line.contains(level),
+        }
+    }
+
+    /// Check if the line was produced by the provided target.
+    ///
+    /// Plain text lines are expected to contain the target followed by a colon,
+    /// ie `INFO tokio-runtime-worker sync: Idle`.
+    pub fn has_target(&self, line: &str, target: &str) -> bool {
+        match &self.target {
+            Some(record) => record == target,
+            None => line.contains(&format!(" {target}: ")),
+        }
+    }
+}
+
+/// The escape character starting the ANSI sequences.
+const ESCAPE: char = '\u{1b}';
+
+/// Strip the ANSI escape sequences (ie colors) of the logs captured from terminals.
+///
+/// The control sequences (`ESC [ .. final`), the operating system commands (`ESC ] ..`
+/// terminated by `BEL` or `ESC \`) and the two-character sequences are removed.
+pub fn strip_ansi(text: &str) -> Cow<'_, str> {
+    if !text.contains(ESCAPE) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESCAPE {
+            stripped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // The parameters end with a final byte in the `@` to `~` range.
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' || (c == ESCAPE && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Cow::Owned(stripped)
+}
+
+/// Parse the timestamp at the start of the provided text.
+pub fn parse_timestamp(text: &str) -> Option<chrono::NaiveDateTime> {
+    let mut tokens = text.split_whitespace();
+    let first = tokens.next()?;
+
+    if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(first) {
+        return Some(datetime.naive_utc());
+    }
+
+    let current = format!("{} {}", first, tokens.next()?);
+    chrono::NaiveDateTime::parse_from_str(&current, "%Y-%m-%d %H:%M:%S%.f").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let line = r#"{"timestamp":"2024-03-29T16:00:00.000Z","level":"WARN","target":"peerset","fields":{"message":"12D3KooW banned, disconnecting, reason: Timeout"}}"#;
+        let parsed = LogLine::parse(line);
+        assert_eq!(parsed.level.as_deref(), Some("WARN"));
+        assert_eq!(parsed.target.as_deref(), Some("peerset"));
+        assert_eq!(
+            parsed.message,
+            "12D3KooW banned, disconnecting, reason: Timeout"
+        );
+        assert!(parsed.is_warn_or_error(line));
+        assert_eq!(
+            parsed.datetime().unwrap().to_string(),
+            "2024-03-29 16:00:00"
+        );
+
+        let line = r#"{"ts":"2024-03-29T16:00:00Z","level":"info","msg":"Imported #10"}"#;
+        let parsed = LogLine::parse(line);
+        assert_eq!(parsed.message, "Imported #10");
+        assert!(!parsed.is_warn_or_error(line));
+
+        assert_eq!(
+            strip_ansi(
+                "\u{1b}[2m2024-03-29 16:00:00.000\u{1b}[0m \u{1b}[1;33mWARN\u{1b}[0m sync: Slow"
+            ),
+            "2024-03-29 16:00:00.000 WARN sync: Slow"
+        );
+        assert_eq!(
+            strip_ansi("\u{1b}]8;;https://parity.io\u{7}link\u{1b}]8;;\u{1b}\\ done\u{1b}("),
+            "link done"
+        );
+        assert!(matches!(strip_ansi("plain"), Cow::Borrowed("plain")));
+
+        let line = "2024-03-29 16:00:00.000 WARN tokio-runtime-worker sync: {\"a\": 1}";
+        let parsed = LogLine::parse(line);
+        assert_eq!(parsed.level, None);
+        assert_eq!(parsed.message, line);
+        assert!(parsed.is_warn_or_error(line));
+        assert_eq!(
+            parsed.datetime().unwrap().to_string(),
+            "2024-03-29 16:00:00"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Extraction of the libp2p peer identities mentioned by the log lines.
+//!
+//! Networking errors usually embed the remote peer, ie
+//! `Banned, disconnecting. PeerId("12D3KooW...")`.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// Extract the distinct peer identities of the provided line, in order of appearance.
+///
+/// Recognizes the base58 encoding of ed25519 (`12D3KooW..`), secp256k1 (`16Uiu2HA..`)
+/// and legacy RSA (`Qm..`) identities.
+pub fn extract_peers(line: &str) -> Vec<&str> {
+    static PEER_ID: OnceLock<Regex> = OnceLock::new();
+
+    // Cheap check before running the regex on every line.
+    if !line.contains("12D3KooW") && !line.contains("16Uiu2HA") && !line.contains("Qm") {
+        return Vec::new();
+    }
+
+    let regex = PEER_ID.get_or_init(|| {
+        Regex::new(r"\b(?:12D3KooW|16Uiu2HA|Qm)[1-9A-HJ-NP-Za-km-z]{44,45}\b")
+            .expect("Regex is valid; qed")
+    });
+
+    let mut peers: Vec<&str> = Vec::new();
+    for peer in regex.find_iter(line) {
+        if !peers.contains(&peer.as_str()) {
+            peers.push(peer.as_str());
+        }
+    }
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_peers() {
+        let peer = "12D3KooWJmAHvNWrrHAhHBfkPb4zGoTvBcEq7nWxBHm4F4X3Hf9R";
+        assert_eq!(
+            extract_peers(&format!(
+                "Banned, disconnecting. PeerId(\"{peer}\") Reason: BadProtocol, peer={peer}"
+            )),
+            vec![peer]
+        );
+        assert_eq!(
+            extract_peers("Peer QmWrNb8WyHvFEdwYEFBT1Dg4SFfMeEKe6ovXh8A7VbLBTk disconnected"),
+            vec!["QmWrNb8WyHvFEdwYEFBT1Dg4SFfMeEKe6ovXh8A7VbLBTk"]
+        );
+        assert!(extract_peers("Failed to dial peer 12D3KooW").is_empty());
+    }
+}
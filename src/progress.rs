@@ -0,0 +1,124 @@
+//! Progress reporting of long triage runs.
+//!
+//! The progress is reported on stderr, such that the report printed on stdout
+//! can still be redirected.
+
+use std::io::IsTerminal;
+
+/// How the progress is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressMode {
+    /// Interactive progress bar.
+    Bar,
+    /// One JSON event per line, suitable for wrapper scripts.
+    Json,
+    /// No progress reporting.
+    None,
+}
+
+impl ProgressMode {
+    /// The progress bar for terminals, nothing otherwise.
+    pub fn detect() -> Self {
+        if std::io::stderr().is_terminal() {
+            ProgressMode::Bar
+        } else {
+            ProgressMode::None
+        }
+    }
+}
+
+/// A machine-readable progress event.
+#[derive(Debug, serde::Serialize)]
+struct Event<'a> {
+    unit: &'a str,
+    completed: usize,
+    total: usize,
+    lines: usize,
+    matches: usize,
+}
+
+/// Reports the processed units (ie chunks, files), lines and matches.
+pub struct Progress {
+    mode: ProgressMode,
+    bar: Option<indicatif::ProgressBar>,
+    unit: &'static str,
+    total: usize,
+    completed: usize,
+    lines: usize,
+    matches: usize,
+}
+
+impl Progress {
+    /// Report the progress of processing `total` units.
+    pub fn new(mode: ProgressMode, unit: &'static str, total: usize) -> Self {
+        let bar = (mode == ProgressMode::Bar).then(|| {
+            let bar = indicatif::ProgressBar::new(total as u64);
+            bar.set_style(
+                indicatif::ProgressStyle::with_template(
+                    "{elapsed_precise} [{bar:40}] {pos}/{len} {msg} (eta {eta})",
+                )
+                .expect("Template is valid; qed")
+                .progress_chars("=> "),
+            );
+            bar.set_message(unit);
+            bar
+        });
+
+        Progress {
+            mode,
+            bar,
+            unit,
+            total,
+            completed: 0,
+            lines: 0,
+            matches: 0,
+        }
+    }
+
+    /// Record a completed unit with the number of processed lines and matches.
+    pub fn inc(&mut self, lines: usize, matches: usize) {
+        self.completed += 1;
+        self.lines += lines;
+        self.matches += matches;
+
+        match self.mode {
+            ProgressMode::Bar => {
+                if let Some(bar) = &self.bar {
+                    bar.set_message(format!(
+                        "{} | {} lines | {} matches",
+                        self.unit, self.lines, self.matches
+                    ));
+                    bar.inc(1);
+                }
+            }
+            ProgressMode::Json => {
+                let event = Event {
+                    unit: self.unit,
+                    completed: self.completed,
+                    total: self.total,
+                    lines: self.lines,
+                    matches: self.matches,
+                };
+                if let Ok(event) = serde_json::to_string(&event) {
+                    eprintln!("{event}");
+                }
+            }
+            ProgressMode::None => {}
+        }
+    }
+
+    /// Print the message on stderr, above the progress bar.
+    pub fn println(&self, message: &str) {
+        match &self.bar {
+            Some(bar) => bar.suspend(|| eprintln!("{message}")),
+            None => eprintln!("{message}"),
+        }
+    }
+
+    /// Finish the progress reporting, clearing the progress bar.
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
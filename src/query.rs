@@ -1,12 +1,373 @@
 //! Query builder and running for fetching the grafana logs.
 
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::PathBuf,
+    process::{Child, ChildStdout, Stdio},
+    sync::OnceLock,
+};
+
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+use crate::{cache, command, progress};
+
 /// Default URL of the Loki instance.
 const DEFAULT_URL: &str = "127.0.0.1:10700";
 /// Default chain to query.
 const DEFAULT_CHAIN: &str = "versi-networking";
-/// Exclude common errors from the query.
-const EXCLUDE_KNOWN_ERRORS: &str = " != `Error while dialing` != `Some security issues have been detected` != `The hardware does not meet`";
+/// Default maximum number of lines returned by a query.
+pub const DEFAULT_LIMIT: usize = 100000;
+
+/// The source of the log lines, when no local file is provided.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    /// Query the Loki instance with `logcli`.
+    #[default]
+    Loki,
+    /// Read the logs of the pods with `kubectl logs`.
+    K8s,
+    /// Read the logs of the systemd units with `journalctl`.
+    Journald,
+    /// Search the Elasticsearch (or OpenSearch) indices with `curl`.
+    Elasticsearch,
+}
+
+/// The lines selected by a query, translated by every [`LogSource`] to its query language.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    /// Only select the lines of these levels, every level if empty.
+    pub levels: Vec<String>,
+    /// Exclude the [`COMMON_ERRORS`] and the other excluded lines of the source.
+    pub exclude_common_errors: bool,
+    /// A LogQL line filter (ie ``|~ `banned` ``).
+    pub filter: String,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Selection {
+            levels: Vec::new(),
+            exclude_common_errors: true,
+            filter: String::new(),
+        }
+    }
+}
+
+/// A backend storing the logs of the chains, queried in chunks of the time range.
+///
+/// The sources unable to apply a part of the selection (ie the LogQL line filter) return
+/// more lines, the callers match the content of the lines regardless.
+pub trait LogSource: Send + Sync {
+    /// The queries of the selected lines of the chain, one per chunk of the time range.
+    fn queries(&self, chain: &str, selection: &Selection) -> Result<Vec<String>, QueryError>;
+
+    /// Extract the log lines from the output of a query, one per line.
+    fn lines(&self, output: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(output)
+    }
+
+    /// Run the query and extract its log lines.
+    ///
+    /// The sources whose searches are truncated (ie by a maximum result size) split the
+    /// query instead of returning partial results.
+    fn fetch(
+        &self,
+        runner: &QueryRunner,
+        query: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.lines(runner.run(query)?)
+    }
+
+    /// Check if the output of the queries is made of lines, such that it can be
+    /// processed while streamed. The other outputs are converted by [`Self::lines`].
+    fn streams(&self) -> bool {
+        true
+    }
+}
+
+/// Queries Loki with `logcli`, the builder providing the options shared by every query
+/// (ie address, time range, labels).
+#[derive(Debug, Clone)]
+pub struct LokiSource {
+    builder: QueryBuilder,
+}
+
+impl LokiSource {
+    pub fn new(builder: QueryBuilder) -> Self {
+        LokiSource { builder }
+    }
+}
+
+impl LogSource for LokiSource {
+    fn queries(&self, chain: &str, selection: &Selection) -> Result<Vec<String>, QueryError> {
+        self.builder
+            .clone()
+            .chain(chain.to_string())
+            .levels(selection.levels.clone())
+            .exclude_common_errors(selection.exclude_common_errors)
+            .append_query(selection.filter.clone())
+            .build_chunks()
+    }
+}
+
+/// The format of the start and end times of the queries.
+pub(crate) const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// Parse the start or end time of a query, in the [`TIME_FORMAT`].
+pub(crate) fn parse_time(field: &'static str, value: &str) -> Result<NaiveDateTime, QueryError> {
+    NaiveDateTime::parse_from_str(value, TIME_FORMAT).map_err(|source| QueryError::InvalidTime {
+        field,
+        value: value.to_string(),
+        source,
+    })
+}
+
+/// The UNIX timestamps from this value are in milliseconds (ie in the Grafana URLs), the
+/// timestamps in seconds only reach it in the year 5138.
+const MILLIS_TIMESTAMP: i64 = 100_000_000_000;
+
+/// Parse the `--start-time` or `--end-time` value, normalized to UTC in the [`TIME_FORMAT`],
+/// such that the invalid times are reported before the run.
+///
+/// Besides the [`TIME_FORMAT`], the times copied from Grafana are accepted: RFC 3339 with an
+/// offset (ie `2024-05-01T10:00:00+02:00`), `2024-05-01 10:00:00` in UTC, a date for its
+/// midnight in UTC (ie `2024-05-01`), and UNIX timestamps in seconds or milliseconds.
+pub fn parse_time_arg(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    let time = if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        time.naive_utc()
+    } else if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        time
+    } else if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        date.and_time(chrono::NaiveTime::MIN)
+    } else if let Ok(timestamp) = value.parse::<i64>() {
+        let seconds = if timestamp >= MILLIS_TIMESTAMP {
+            timestamp / 1000
+        } else {
+            timestamp
+        };
+        chrono::DateTime::from_timestamp(seconds, 0)
+            .ok_or_else(|| format!("Invalid UNIX timestamp {value}"))?
+            .naive_utc()
+    } else {
+        return Err(format!(
+            "Invalid time {value:?}, expected YYYY-MM-DDTHH:MM:SSZ, RFC 3339 with an offset \
+             (ie 2024-05-01T10:00:00+02:00), a date (ie 2024-05-01) or a UNIX timestamp"
+        ));
+    };
+
+    Ok(time.format(TIME_FORMAT).to_string())
+}
+
+/// The start and end times of the query, in the "YYYY-MM-DDTHH:MM:SSZ" format.
+///
+/// Default: the last hour, or the last 24 hours with `last_day`.
+pub(crate) fn time_range(
+    start_time: &Option<String>,
+    end_time: &Option<String>,
+    last_day: bool,
+) -> Result<(String, String), QueryError> {
+    let hours = match (start_time, end_time, last_day) {
+        (None, None, true) => 24,
+        (None, None, false) => 1,
+        (Some(start_time), Some(end_time), false) => {
+            log::debug!("Using provided time {start_time} {end_time}");
+            let start = parse_time("start time", start_time)?;
+            let end = parse_time("end time", end_time)?;
+            if start >= end {
+                return Err(QueryError::EmptyTimeRange {
+                    start_time: start_time.clone(),
+                    end_time: end_time.clone(),
+                });
+            }
+            return Ok((start_time.clone(), end_time.clone()));
+        }
+        (Some(_), Some(_), true) => return Err(QueryError::ConflictingTimeRange),
+        (Some(start_time), None, _) => {
+            return Err(QueryError::PartialTimeRange {
+                provided: "--start-time",
+                value: start_time.clone(),
+                missing: "--end-time",
+            })
+        }
+        (None, Some(end_time), _) => {
+            return Err(QueryError::PartialTimeRange {
+                provided: "--end-time",
+                value: end_time.clone(),
+                missing: "--start-time",
+            })
+        }
+    };
+
+    // Compute endtime as now.
+    let date_time = chrono::Utc::now();
+    let end_time = format!("{}", date_time.format(TIME_FORMAT));
+    let date_time = date_time - chrono::Duration::hours(hours);
+    let start_time = format!("{}", date_time.format(TIME_FORMAT));
 
+    log::debug!("Generating time {} {}", start_time, end_time);
+    Ok((start_time, end_time))
+}
+
+/// The [`time_range`] parsed, ie to measure the time from the start of the window.
+pub(crate) fn time_window(
+    start_time: &Option<String>,
+    end_time: &Option<String>,
+    last_day: bool,
+) -> Result<(NaiveDateTime, NaiveDateTime), QueryError> {
+    let (start, end) = time_range(start_time, end_time, last_day)?;
+    Ok((
+        parse_time("start time", &start)?,
+        parse_time("end time", &end)?,
+    ))
+}
+
+/// Split the time range in chunks of an hour, the last chunk ending at the end time.
+pub(crate) fn time_chunks(
+    start_time: &str,
+    end_time: &str,
+) -> Result<Vec<(String, String)>, QueryError> {
+    let format = |time: NaiveDateTime| format!("{}", time.format(TIME_FORMAT));
+
+    let end = parse_time("end time", end_time)?;
+    let mut start = parse_time("start time", start_time)?;
+    let mut chunks = Vec::new();
+    while start + chrono::Duration::hours(1) < end {
+        let next = start + chrono::Duration::hours(1);
+        chunks.push((format(start), format(next)));
+        start = next;
+    }
+    if start < end {
+        chunks.push((format(start), end_time.to_string()));
+    }
+
+    Ok(chunks)
+}
+
+/// The common errors excluded from the warnings and errors, see
+/// [`QueryBuilder::exclude_common_errors`].
+pub(crate) const COMMON_ERRORS: &[&str] = &[
+    "Error while dialing",
+    "Some security issues have been detected",
+    "The hardware does not meet",
+];
+
+/// Parse a `--exclude-line` substring, excluding the lines containing it from the queries.
+pub fn parse_excluded_line(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() {
+        return Err("The excluded line is empty".to_string());
+    }
+    // The substrings are provided to logcli in backticks, within single quotes.
+    if value.contains(['`', '\'']) {
+        return Err(format!(
+            "Invalid excluded line {value:?}, backticks and single quotes are not supported"
+        ));
+    }
+
+    Ok(value.to_string())
+}
+
+/// The LogQL line filters excluding the lines containing any of the substrings.
+fn line_exclusions(lines: &[String]) -> String {
+    lines.iter().map(|line| format!(" != `{line}`")).collect()
+}
+
+/// Quote the value for the shell running the query.
+pub(crate) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// An extra label matcher of the stream selector (ie `namespace=~"versi-.*"`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    /// The name of the label.
+    pub name: String,
+    /// The match operator: `=`, `!=`, `=~` or `!~`.
+    pub operator: &'static str,
+    /// The value, or the regex for the regex operators.
+    pub value: String,
+}
+
+impl std::fmt::Display for Label {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The debug representation escapes the quotes and backslashes like LogQL strings.
+        write!(f, "{}{}{:?}", self.name, self.operator, self.value)
+    }
+}
+
+/// The matchers of the selected and excluded nodes, ie `node=~"alice|bob"` and
+/// `node!~"canary-.*"`.
+pub(crate) fn node_labels(nodes: &[String], excluded: &[String]) -> Vec<Label> {
+    [("=~", nodes), ("!~", excluded)]
+        .into_iter()
+        .filter(|(_, nodes)| !nodes.is_empty())
+        .map(|(operator, nodes)| Label {
+            name: "node".to_string(),
+            operator,
+            value: nodes.join("|"),
+        })
+        .collect()
+}
+
+/// Parse a label matcher like `namespace=versi`, `pod=~"validator-.*"` or `host!=ci`.
+pub fn parse_label(value: &str) -> Result<Label, String> {
+    // The longest operators first, such that `=~` is not parsed as `=`.
+    let (index, operator) = ["=~", "!~", "!=", "="]
+        .into_iter()
+        .filter_map(|operator| Some((value.find(operator)?, operator)))
+        .min_by_key(|(index, operator)| (*index, std::cmp::Reverse(operator.len())))
+        .ok_or_else(|| format!("Invalid label {value:?}, expected ie `namespace=versi`"))?;
+
+    let name = value[..index].trim();
+    let valid_name = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Err(format!("Invalid label name {name:?}"));
+    }
+
+    let value = value[index + operator.len()..].trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    // The query is provided to logcli in single quotes.
+    if value.contains('\'') {
+        return Err(format!(
+            "Invalid label value {value:?}, single quotes are not supported"
+        ));
+    }
+
+    Ok(Label {
+        name: name.to_string(),
+        operator,
+        value: value.to_string(),
+    })
+}
+
+/// Parse a LogQL filter expression appended to the stream selector, like ``|~ "litep2p"``
+/// or `|= "Failed" != "telemetry"`.
+pub fn parse_filter(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if !value.starts_with(['|', '!']) {
+        return Err(format!(
+            "Invalid filter {value:?}, expected a LogQL filter starting with `|` or `!` (ie `|~ \"litep2p\"`)"
+        ));
+    }
+    // The query is provided to logcli in single quotes.
+    if value.contains('\'') {
+        return Err(format!(
+            "Invalid filter {value:?}, single quotes are not supported"
+        ));
+    }
+
+    Ok(value.to_string())
+}
+
+#[derive(Debug, Clone)]
 pub struct QueryBuilder {
     address: Option<String>,
     chain: Option<String>,
@@ -17,9 +378,16 @@ pub struct QueryBuilder {
     batch: usize,
     limit: usize,
     exclude_common_errors: bool,
+    excluded_lines: Vec<String>,
     appended_query: String,
     org_id: Option<String>,
-    node: Option<String>,
+    nodes: Vec<String>,
+    excluded_nodes: Vec<String>,
+    labels: Vec<Label>,
+    raw_output: bool,
+    forward: bool,
+    ca_cert: Option<PathBuf>,
+    insecure_skip_verify: bool,
 }
 
 impl Default for QueryBuilder {
@@ -39,11 +407,18 @@ impl QueryBuilder {
             last_day: false,
             levels: Vec::new(),
             batch: 5000,
-            limit: 100000,
+            limit: DEFAULT_LIMIT,
             exclude_common_errors: true,
+            excluded_lines: COMMON_ERRORS.iter().map(ToString::to_string).collect(),
             appended_query: String::new(),
             org_id: None,
-            node: None,
+            nodes: Vec::new(),
+            excluded_nodes: Vec::new(),
+            labels: Vec::new(),
+            raw_output: false,
+            forward: false,
+            ca_cert: None,
+            insecure_skip_verify: false,
         }
     }
 
@@ -87,12 +462,22 @@ impl QueryBuilder {
     /// - "Some security issues have been detected" - PVF hardware error requiring different kernel settings
     /// - "The hardware does not meet" - Requires a better hardware to be a validator
     ///
+    /// The common errors are configured with [`Self::excluded_lines`].
+    ///
     /// Default: true.
     pub fn exclude_common_errors(mut self, exclude_common_errors: bool) -> Self {
         self.exclude_common_errors = exclude_common_errors;
         self
     }
 
+    /// Set the substrings of the lines excluded with [`Self::exclude_common_errors`].
+    ///
+    /// Default: the [`COMMON_ERRORS`].
+    pub fn excluded_lines(mut self, excluded_lines: Vec<String>) -> Self {
+        self.excluded_lines = excluded_lines;
+        self
+    }
+
     /// Set the batch size of the query.
     ///
     /// Default: 5000.
@@ -131,216 +516,1146 @@ impl QueryBuilder {
         self
     }
 
-    /// Set the node name.
+    /// Only query the nodes matching any of the regexes (ie `alice`, `validator-.*`).
+    ///
+    /// Default: every node.
+    pub fn node(mut self, nodes: Vec<String>) -> Self {
+        self.nodes = nodes;
+        self
+    }
+
+    /// Exclude the nodes matching any of the regexes (ie the canary nodes).
+    ///
+    /// Default: empty.
+    pub fn exclude_node(mut self, nodes: Vec<String>) -> Self {
+        self.excluded_nodes = nodes;
+        self
+    }
+
+    /// Add extra label matchers to the stream selector (ie namespace, pod, host).
+    ///
+    /// Default: empty.
+    pub fn labels(mut self, labels: Vec<Label>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// The node and extra label matchers of the stream selector.
+    fn label_matchers(&self) -> String {
+        node_labels(&self.nodes, &self.excluded_nodes)
+            .iter()
+            .chain(&self.labels)
+            .map(|label| format!(", {label} "))
+            .collect()
+    }
+
+    /// Print only the log lines, without the timestamp and labels added by logcli.
+    ///
+    /// Default: false.
+    pub fn raw_output(mut self, raw_output: bool) -> Self {
+        self.raw_output = raw_output;
+        self
+    }
+
+    /// Return the lines in chronological order instead of the newest first.
+    ///
+    /// Default: false.
+    pub fn forward(mut self, forward: bool) -> Self {
+        self.forward = forward;
+        self
+    }
+
+    /// Verify the certificate of the Loki instance with the provided CA certificate.
     ///
     /// Default: None.
-    pub fn node(mut self, node: Option<String>) -> Self {
-        self.node = node;
+    pub fn ca_cert(mut self, ca_cert: Option<PathBuf>) -> Self {
+        self.ca_cert = ca_cert;
         self
     }
 
-    /// Build the query.
-    pub fn build(&self) -> String {
-        let exclude_common_errors = self
-            .exclude_common_errors
-            .then_some(EXCLUDE_KNOWN_ERRORS)
-            .unwrap_or_default();
+    /// Skip the verification of the certificate of the Loki instance.
+    ///
+    /// Default: false.
+    pub fn insecure_skip_verify(mut self, insecure_skip_verify: bool) -> Self {
+        self.insecure_skip_verify = insecure_skip_verify;
+        self
+    }
 
-        let (start_time, end_time) = match (&self.start_time, &self.end_time, self.last_day) {
-            (None, None, true) => {
-                // Compute endtime as now.
-                let date_time = chrono::Utc::now();
-                let end_time = format!("{}", date_time.format("%Y-%m-%dT%H:%M:%SZ"));
-                // Subtract 24 hour from date time
-                let date_time = date_time - chrono::Duration::hours(24);
-                let start_time = format!("{}", date_time.format("%Y-%m-%dT%H:%M:%SZ"));
-
-                log::debug!("Generating time {} {}", start_time, end_time);
-                (start_time, end_time)
-            }
-            (None, None, false) => {
-                // Compute endtime as now.
-                let date_time = chrono::Utc::now();
-                let end_time = format!("{}", date_time.format("%Y-%m-%dT%H:%M:%SZ"));
-                // Subtract 1 hour from date time
-                let date_time = date_time - chrono::Duration::hours(1);
-                let start_time = format!("{}", date_time.format("%Y-%m-%dT%H:%M:%SZ"));
-
-                log::debug!("Generating time {} {}", start_time, end_time);
-                (start_time, end_time)
-            }
-            (Some(start_time), Some(end_time), false) => {
-                log::debug!("Using provided time {start_time} {end_time}");
-                (start_time.clone(), end_time.clone())
-            }
-            _ => {
-                panic!("Either both start and end time should be provided or none")
-            }
-        };
+    /// Extra logcli flags derived from the TLS options.
+    fn tls_flags(&self) -> String {
+        let mut flags = String::new();
+        if let Some(ca_cert) = &self.ca_cert {
+            flags.push_str(&format!(
+                " --ca-cert={}",
+                shell_quote(&ca_cert.to_string_lossy())
+            ));
+        }
+        if self.insecure_skip_verify {
+            flags.push_str(" --tls-skip-verify");
+        }
+        flags
+    }
+
+    /// Extra logcli flags derived from the output options.
+    fn output_flags(&self) -> String {
+        let mut flags = String::new();
+        if self.raw_output {
+            flags.push_str(" --output=raw");
+        }
+        if self.forward {
+            flags.push_str(" --forward");
+        }
+        flags
+    }
 
+    /// The arguments shared by the logcli commands, over the whole time range.
+    fn logcli(&self) -> Result<Logcli, QueryError> {
+        let (start_time, end_time) = time_range(&self.start_time, &self.end_time, self.last_day)?;
+        let exclusions = if self.exclude_common_errors {
+            line_exclusions(&self.excluded_lines)
+        } else {
+            String::new()
+        };
         let levels = (!self.levels.is_empty())
             .then_some(format!(", level=~\"{}\"", self.levels.join("|")))
             .unwrap_or_default();
-
-        let addr = self
-            .address
-            .as_ref()
-            .cloned()
-            .unwrap_or(DEFAULT_URL.to_string());
-        let chain = self
-            .chain
-            .as_ref()
-            .cloned()
-            .unwrap_or(DEFAULT_CHAIN.to_string());
-
         let org_id = self
             .org_id
             .as_ref()
             .map(|org_id| format!(r#" --org-id='{org_id}' "#))
             .unwrap_or_default();
 
-        let node = self
-            .node
-            .as_ref()
-            .map(|node| format!(r#", node=~"{node}" "#))
-            .unwrap_or_default();
+        Ok(Logcli {
+            addr: self.address.clone().unwrap_or(DEFAULT_URL.to_string()),
+            chain: self.chain.clone().unwrap_or(DEFAULT_CHAIN.to_string()),
+            start_time,
+            end_time,
+            levels,
+            labels: self.label_matchers(),
+            exclusions,
+            org_id,
+            tls_flags: self.tls_flags(),
+        })
+    }
 
+    /// Build the query.
+    pub fn build(&self) -> Result<String, QueryError> {
+        let Logcli {
+            addr,
+            chain,
+            start_time,
+            end_time,
+            levels,
+            labels,
+            exclusions,
+            org_id,
+            tls_flags,
+        } = self.logcli()?;
         let batch = self.batch;
         let limit = self.limit;
+        let output_flags = self.output_flags();
 
-        format!(
-            r#"logcli query --addr={addr} --timezone=UTC --from="{start_time}" --to="{end_time}" '{{chain="{chain}" {levels} {node}}} {exclude_common_errors}' --batch {batch} --limit {limit} {org_id}"#,
-        )
+        Ok(format!(
+            r#"logcli query --addr={addr} --timezone=UTC --from="{start_time}" --to="{end_time}" '{{chain="{chain}" {levels} {labels}}} {exclusions}' --batch {batch} --limit {limit} {org_id}{output_flags}{tls_flags}"#,
+        ))
     }
 
-    /// Build the query.
-    pub fn build_chunks(&self) -> Vec<String> {
-        let exclude_common_errors = self
-            .exclude_common_errors
-            .then_some(EXCLUDE_KNOWN_ERRORS)
-            .unwrap_or_default();
+    /// Build the metric query counting the lines matching the regex over the whole time
+    /// range, instead of fetching them.
+    pub fn build_count(&self, pattern: &str) -> Result<String, QueryError> {
+        if pattern.contains('`') {
+            return Err(QueryError::InvalidCountPattern(pattern.to_string()));
+        }
 
-        let (start_time, end_time) = match (&self.start_time, &self.end_time, self.last_day) {
-            (None, None, true) => {
-                // Compute endtime as now.
-                let date_time = chrono::Utc::now();
-                let end_time = format!("{}", date_time.format("%Y-%m-%dT%H:%M:%SZ"));
-                // Subtract 24 hour from date time
-                let date_time = date_time - chrono::Duration::hours(24);
-                let start_time = format!("{}", date_time.format("%Y-%m-%dT%H:%M:%SZ"));
-
-                log::debug!("Generating time {} {}", start_time, end_time);
-                (start_time, end_time)
-            }
-            (None, None, false) => {
-                // Compute endtime as now.
-                let date_time = chrono::Utc::now();
-                let end_time = format!("{}", date_time.format("%Y-%m-%dT%H:%M:%SZ"));
-                // Subtract 1 hour from date time
-                let date_time = date_time - chrono::Duration::hours(1);
-                let start_time = format!("{}", date_time.format("%Y-%m-%dT%H:%M:%SZ"));
-
-                log::debug!("Generating time {} {}", start_time, end_time);
-                (start_time, end_time)
-            }
-            (Some(start_time), Some(end_time), false) => {
-                log::debug!("Using provided time {start_time} {end_time}");
-                (start_time.clone(), end_time.clone())
-            }
-            _ => {
-                panic!("Either both start and end time should be provided or none")
-            }
-        };
+        let Logcli {
+            addr,
+            chain,
+            start_time,
+            end_time,
+            levels,
+            labels,
+            exclusions,
+            org_id,
+            tls_flags,
+        } = self.logcli()?;
+        let window = (parse_time("end time", &end_time)? - parse_time("start time", &start_time)?)
+            .num_seconds();
 
-        let levels = (!self.levels.is_empty())
-            .then_some(format!(", level=~\"{}\"", self.levels.join("|")))
-            .unwrap_or_default();
+        let query = format!(
+            r#"sum(count_over_time({{chain="{chain}" {levels} {labels}}} {exclusions} |~ `{pattern}` [{window}s]))"#
+        );
+        Ok(format!(
+            r#"logcli instant-query --addr={addr} --now="{end_time}" {} {org_id}{tls_flags}"#,
+            shell_quote(&query),
+        ))
+    }
 
-        let addr = self
-            .address
-            .as_ref()
-            .cloned()
-            .unwrap_or(DEFAULT_URL.to_string());
-        let chain = self
-            .chain
-            .as_ref()
-            .cloned()
-            .unwrap_or(DEFAULT_CHAIN.to_string());
-
-        let advance_time = |current: &str| {
-            log::debug!("Current time: {}", current);
-            let current =
-                chrono::NaiveDateTime::parse_from_str(current, "%Y-%m-%dT%H:%M:%SZ").unwrap();
-            let current = current + chrono::Duration::hours(1);
-            (format!("{}", current.format("%Y-%m-%dT%H:%M:%SZ")), current)
-        };
+    /// Build the query listing the streams of the chain over the whole time range, one per
+    /// line with their labels (ie `{chain="versi", node="alice"}`).
+    pub fn build_series(&self) -> Result<String, QueryError> {
+        let Logcli {
+            addr,
+            chain,
+            start_time,
+            end_time,
+            labels,
+            org_id,
+            tls_flags,
+            ..
+        } = self.logcli()?;
 
-        let value_end_time =
-            chrono::NaiveDateTime::parse_from_str(&end_time, "%Y-%m-%dT%H:%M:%SZ").unwrap();
-        let (mut end_time_str, mut end_time_date) = advance_time(&start_time);
-        let mut start_time_str = start_time;
-        let mut start_time_date =
-            chrono::NaiveDateTime::parse_from_str(&start_time_str, "%Y-%m-%dT%H:%M:%SZ").unwrap();
+        Ok(format!(
+            r#"logcli series --addr={addr} --from="{start_time}" --to="{end_time}" '{{chain="{chain}" {labels}}}' {org_id}{tls_flags}"#,
+        ))
+    }
 
-        let mut queries = Vec::new();
+    /// Build the query listing the values of the label over the whole time range, one per
+    /// line.
+    pub fn build_label_values(&self, name: &str) -> Result<String, QueryError> {
+        let Logcli {
+            addr,
+            start_time,
+            end_time,
+            org_id,
+            tls_flags,
+            ..
+        } = self.logcli()?;
 
-        let org_id = self
-            .org_id
-            .as_ref()
-            .map(|org_id| format!(r#" --org-id='{}' "#, org_id))
-            .unwrap_or_default();
+        Ok(format!(
+            r#"logcli labels {} --addr={addr} --from="{start_time}" --to="{end_time}" {org_id}{tls_flags}"#,
+            shell_quote(name),
+        ))
+    }
 
+    /// Build the query.
+    pub fn build_chunks(&self) -> Result<Vec<String>, QueryError> {
+        let Logcli {
+            addr,
+            chain,
+            start_time,
+            end_time,
+            levels,
+            labels,
+            exclusions,
+            org_id,
+            tls_flags,
+        } = self.logcli()?;
         let batch = self.batch;
         let limit = self.limit;
         let appended_query = &self.appended_query;
+        let output_flags = self.output_flags();
 
-        let node = self
-            .node
-            .as_ref()
-            .map(|node| format!(r#", node=~"{node}" "#))
-            .unwrap_or_default();
-
+        let mut queries = Vec::new();
         let build_query = |start_time_str: &str, end_time_str: &str| {
             format!(
-                r#"logcli query --addr={addr} --timezone=UTC --from="{start_time_str}" --to="{end_time_str}" '{{chain="{chain}" {levels} {node}}} {exclude_common_errors} {appended_query}' --batch {batch} --limit {limit} {org_id}"#,
+                r#"logcli query --addr={addr} --timezone=UTC --from="{start_time_str}" --to="{end_time_str}" '{{chain="{chain}" {levels} {labels}}} {exclusions} {appended_query}' --batch {batch} --limit {limit} {org_id}{output_flags}{tls_flags}"#,
+            )
+        };
+
+        for (start_time, end_time) in time_chunks(&start_time, &end_time)? {
+            queries.push(build_query(&start_time, &end_time));
+        }
+
+        log::debug!("Queries: {:?}", queries);
+
+        Ok(queries)
+    }
+}
+
+/// The arguments shared by the logcli commands of a [`QueryBuilder`].
+struct Logcli {
+    /// The address of the Loki instance.
+    addr: String,
+    chain: String,
+    /// The resolved time range.
+    start_time: String,
+    end_time: String,
+    /// The level and label matchers of the stream selector.
+    levels: String,
+    labels: String,
+    /// The line filters excluding the common errors, if enabled.
+    exclusions: String,
+    /// The `--org-id` and TLS flags.
+    org_id: String,
+    tls_flags: String,
+}
+
+/// Default number of retries of a failed query.
+pub const DEFAULT_RETRIES: u32 = 3;
+/// The delay before the first retry, doubled for every subsequent retry.
+const BASE_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+/// The maximum delay between retries.
+const MAX_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Markers of transient failures in the logcli output (timeouts, connection errors).
+const RETRYABLE_MARKERS: &[&str] = &[
+    "timeout",
+    "deadline exceeded",
+    "connection refused",
+    "connection reset",
+    "too many outstanding requests",
+];
+
+/// The connections closed by the server (ie `Get "...": EOF` or `unexpected EOF`).
+///
+/// Only matched as a whole uppercase word, not within the words of the messages.
+const RETRYABLE_EOF: &str = r"\bEOF\b";
+
+/// The transient status codes of the responses (5xx, rate limits).
+///
+/// The status is only matched after `status` or `response`, as a whole number.
+const RETRYABLE_STATUS: &str = r"(?i)\b(?:status|response)[^0-9\n]*\b(?:5\d\d|429)\b";
+
+/// The URLs of the stderr, ignored when classifying the failures: logcli echoes the request
+/// URL, whose query, limit and nanosecond timestamps contain any marker or number.
+const URL: &str = r"https?://\S+";
+
+/// The error of building or running a query.
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+    /// Transient failure, the query may succeed if retried.
+    #[error("Query failed (retryable): {0}")]
+    Retryable(String),
+    /// The query cannot succeed (ie invalid query, unauthorized).
+    #[error("Query failed: {0}")]
+    Fatal(String),
+    /// The start or end time is not in the "YYYY-MM-DDTHH:MM:SSZ" format.
+    #[error("Invalid {field} {value:?}, expected the YYYY-MM-DDTHH:MM:SSZ format (ie 2024-03-29T16:00:00Z): {source}")]
+    InvalidTime {
+        field: &'static str,
+        value: String,
+        source: chrono::ParseError,
+    },
+    /// Only one of the start and end times is provided.
+    #[error("{provided} {value:?} is provided without {missing}, provide both or neither")]
+    PartialTimeRange {
+        provided: &'static str,
+        value: String,
+        missing: &'static str,
+    },
+    /// Both the start and end times and the last day are provided.
+    #[error("--last-day cannot be combined with --start-time and --end-time")]
+    ConflictingTimeRange,
+    /// The regex of a count query contains a backtick, which quotes it in the query.
+    #[error("Count patterns cannot contain backticks: {0}")]
+    InvalidCountPattern(String),
+    /// The start time is not before the end time.
+    #[error(
+        "Empty time range {start_time} - {end_time}, the start time must be before the end time"
+    )]
+    EmptyTimeRange {
+        start_time: String,
+        end_time: String,
+    },
+}
+
+impl QueryError {
+    /// Classify the failure from the stderr of the query.
+    fn from_stderr(stderr: &str) -> Self {
+        static REGEXES: OnceLock<(Regex, Regex, Regex)> = OnceLock::new();
+
+        let (status, eof, url) = REGEXES.get_or_init(|| {
+            (
+                Regex::new(RETRYABLE_STATUS).expect("Regex is valid; qed"),
+                Regex::new(RETRYABLE_EOF).expect("Regex is valid; qed"),
+                Regex::new(URL).expect("Regex is valid; qed"),
             )
+        });
+        let message = url.replace_all(stderr, "");
+        let lowercase = message.to_lowercase();
+        let retryable = RETRYABLE_MARKERS
+            .iter()
+            .any(|marker| lowercase.contains(&marker.to_lowercase()))
+            || status.is_match(&message)
+            || eof.is_match(&message);
+
+        if retryable {
+            QueryError::Retryable(stderr.trim().to_string())
+        } else {
+            QueryError::Fatal(stderr.trim().to_string())
+        }
+    }
+}
+
+/// The credentials of the Loki instance, passed to `logcli` through its environment.
+///
+/// The credentials are not part of the queries, such that they are never printed by the
+/// dry runs, logged, or used as cache keys.
+#[derive(Clone, Default)]
+pub struct LokiAuth {
+    /// The bearer token (`LOKI_BEARER_TOKEN`).
+    pub token: Option<String>,
+    /// The username of the basic authentication (`LOKI_USERNAME`).
+    pub username: Option<String>,
+    /// The password of the basic authentication (`LOKI_PASSWORD`).
+    pub password: Option<String>,
+}
+
+impl LokiAuth {
+    /// The environment variables of the credentials read by `logcli`.
+    fn env(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("LOKI_BEARER_TOKEN", &self.token),
+            ("LOKI_USERNAME", &self.username),
+            ("LOKI_PASSWORD", &self.password),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+    }
+}
+
+impl std::fmt::Debug for LokiAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |value: &Option<String>| value.as_ref().map(|_| "<redacted>");
+        f.debug_struct("LokiAuth")
+            .field("token", &redacted(&self.token))
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .finish()
+    }
+}
+
+/// A running query, with its stdout and the stderr drained in the background.
+struct RunningQuery {
+    query: String,
+    attempt: u32,
+    child: Child,
+    stdout: ChildStdout,
+    stderr: Option<std::thread::JoinHandle<String>>,
+    /// The number of lines read from the query.
+    lines: usize,
+    started: std::time::Instant,
+}
+
+impl RunningQuery {
+    fn spawn(query: String, attempt: u32, auth: &LokiAuth) -> std::io::Result<Self> {
+        log::info!("Streaming query: {}", query);
+
+        let mut child = command::command(&query, auth.env())
+            .map_err(std::io::Error::other)?
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| std::io::Error::other("Missing stdout of the query"))?;
+        // The stderr is drained concurrently, such that a verbose query cannot block.
+        let stderr = child.stderr.take().map(|mut stderr| {
+            std::thread::spawn(move || {
+                let mut output = String::new();
+                let _ = stderr.read_to_string(&mut output);
+                output
+            })
+        });
+
+        Ok(RunningQuery {
+            query,
+            attempt,
+            child,
+            stdout,
+            stderr,
+            lines: 0,
+            started: std::time::Instant::now(),
+        })
+    }
+
+    /// Wait for the end of the query, classifying its failure.
+    fn finish(&mut self) -> Result<(), QueryError> {
+        let status = self
+            .child
+            .wait()
+            .map_err(|err| QueryError::Fatal(err.to_string()))?;
+        let stderr = self
+            .stderr
+            .take()
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+
+        if !status.success() {
+            log::error!("Query failed: {}", stderr);
+            return Err(QueryError::from_stderr(&stderr));
+        }
+
+        log::info!("Query completed in {:?}", self.started.elapsed());
+        Ok(())
+    }
+}
+
+impl Read for RunningQuery {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.stdout.read(buf)?;
+        self.lines += buf[..read].iter().filter(|byte| **byte == b'\n').count();
+        Ok(read)
+    }
+}
+
+impl Drop for RunningQuery {
+    fn drop(&mut self) {
+        // The query is interrupted when its output is not read to the end.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The streamed output of a query, one attempt at a time.
+///
+/// Transient failures are retried and the queries hitting their `--limit` are split, also for
+/// [`QueryRunner::run`] collecting the attempts. Every attempt is read to the end and ended
+/// by [`QueryStream::end`], which tells whether its output is complete: the output of the
+/// failed and split attempts is partial, and must be discarded by the callers.
+pub struct QueryStream {
+    retries: u32,
+    auth: LokiAuth,
+    /// The queries to run in order, with their attempt.
+    pending: std::collections::VecDeque<(String, u32)>,
+    /// The output of the printed or captured query, instead of running it.
+    output: Option<(String, Box<dyn BufRead + Send>)>,
+}
+
+impl QueryStream {
+    fn new(query: &str, retries: u32, auth: LokiAuth) -> Self {
+        QueryStream {
+            retries,
+            auth,
+            pending: [(query.to_string(), 0)].into(),
+            output: None,
+        }
+    }
+
+    /// The stream of the output of the query, instead of running it.
+    fn output(query: &str, output: Box<dyn BufRead + Send>) -> Self {
+        QueryStream {
+            retries: 0,
+            auth: LokiAuth::default(),
+            pending: Default::default(),
+            output: Some((query.to_string(), output)),
+        }
+    }
+
+    /// Start the next attempt, if any.
+    pub fn next_attempt(&mut self) -> std::io::Result<Option<Attempt>> {
+        if let Some((query, output)) = self.output.take() {
+            return Ok(Some(Attempt {
+                query,
+                reader: AttemptReader::Output(output),
+            }));
+        }
+
+        let Some((query, attempt)) = self.pending.pop_front() else {
+            return Ok(None);
         };
+        Ok(Some(Attempt {
+            query: query.clone(),
+            reader: AttemptReader::Running(BufReader::new(RunningQuery::spawn(
+                query, attempt, &self.auth,
+            )?)),
+        }))
+    }
 
-        while end_time_date < value_end_time {
-            queries.push(build_query(&start_time_str, &end_time_str));
+    /// End the attempt read to the end, returning whether its output is complete.
+    ///
+    /// The attempts failing transiently are retried, and the attempts hitting the limit
+    /// are split into the next attempts.
+    pub fn end(&mut self, attempt: Attempt) -> Result<bool, QueryError> {
+        let AttemptReader::Running(reader) = attempt.reader else {
+            return Ok(true);
+        };
+        let mut running = reader.into_inner();
+        match running.finish() {
+            Ok(()) => {}
+            Err(QueryError::Retryable(err)) if running.attempt < self.retries => {
+                let delay = QueryRunner::backoff(running.attempt);
+                log::warn!(
+                    "Query failed: {} (retry {}/{} in {:?})",
+                    err,
+                    running.attempt + 1,
+                    self.retries,
+                    delay
+                );
+                std::thread::sleep(delay);
+                let query = std::mem::take(&mut running.query);
+                self.pending.push_front((query, running.attempt + 1));
+                return Ok(false);
+            }
+            Err(err) => return Err(err),
+        }
 
-            (start_time_str, start_time_date) = (end_time_str.clone(), end_time_date);
-            (end_time_str, end_time_date) = advance_time(&end_time_str);
+        let query = &running.query;
+        let Some(limit) = query_limit(query) else {
+            return Ok(true);
+        };
+        if running.lines < limit {
+            return Ok(true);
         }
+        let Some((first, second)) = split_query(query) else {
+            log::warn!("Query hit the limit of {limit} lines, lines are missing: {query}");
+            return Ok(true);
+        };
+        log::warn!("Query hit the limit of {limit} lines, splitting its time range");
 
-        if start_time_date < value_end_time {
-            queries.push(build_query(&start_time_str, &end_time));
+        // Keep the order of the lines, newest first unless queried forward.
+        let ranges = if query.contains("--forward") {
+            [first, second]
+        } else {
+            [second, first]
+        };
+        for range in ranges.into_iter().rev() {
+            self.pending.push_front((range, 0));
         }
 
-        log::debug!("Queries: {:?}", queries);
+        Ok(false)
+    }
+}
+
+/// The output of an attempt of a streamed query.
+pub struct Attempt {
+    query: String,
+    reader: AttemptReader,
+}
+
+enum AttemptReader {
+    Running(BufReader<RunningQuery>),
+    Output(Box<dyn BufRead + Send>),
+}
 
-        queries
+impl Attempt {
+    /// The query of the attempt, ie a split query.
+    pub fn query(&self) -> &str {
+        &self.query
     }
 }
 
-pub struct QueryRunner;
+impl Read for Attempt {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.reader {
+            AttemptReader::Running(reader) => reader.read(buf),
+            AttemptReader::Output(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl BufRead for Attempt {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match &mut self.reader {
+            AttemptReader::Running(reader) => reader.fill_buf(),
+            AttemptReader::Output(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match &mut self.reader {
+            AttemptReader::Running(reader) => reader.consume(amount),
+            AttemptReader::Output(reader) => reader.consume(amount),
+        }
+    }
+}
+
+/// The file capturing the output of the query, printed by the dry runs.
+pub fn output_file(query: &str) -> String {
+    format!("{}.out", cache::QueryCache::key(query))
+}
+
+/// Runs the queries, retrying transient failures with exponential backoff.
+#[derive(Debug, Clone)]
+pub struct QueryRunner {
+    retries: u32,
+    dry_run: bool,
+    output_dir: Option<PathBuf>,
+    auth: LokiAuth,
+}
+
+impl Default for QueryRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl QueryRunner {
-    pub fn run(query: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        log::info!("Running query: {}", query);
-
-        let now = std::time::Instant::now();
-        let result = std::process::Command::new("sh")
-            .arg("-c")
-            .arg(query)
-            .output()?;
-
-        if !result.status.success() {
-            log::error!("Query failed: {}", String::from_utf8_lossy(&result.stderr));
-            return Err("Query failed".into());
+    pub fn new() -> Self {
+        QueryRunner {
+            retries: DEFAULT_RETRIES,
+            dry_run: false,
+            output_dir: None,
+            auth: LokiAuth::default(),
+        }
+    }
+
+    /// The maximum number of retries of a failed query.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Print the queries instead of running them, redirecting their output to the
+    /// [`output_file`] of the query.
+    ///
+    /// The printed queries have an empty output.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Read the outputs captured by running the printed queries of a dry run from the
+    /// directory, instead of running the queries.
+    pub fn output_dir(mut self, output_dir: Option<PathBuf>) -> Self {
+        self.output_dir = output_dir;
+        self
+    }
+
+    /// The credentials of the Loki instance.
+    pub fn auth(mut self, auth: LokiAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Run the query, streaming the output of its attempts instead of buffering it.
+    ///
+    /// Transient failures are retried and the queries hitting their `--limit` are split,
+    /// see [`QueryStream`].
+    pub fn stream(&self, query: &str) -> Result<QueryStream, Box<dyn std::error::Error>> {
+        if self.dry_run {
+            println!("{query} > {}", shell_quote(&output_file(query)));
+            return Ok(QueryStream::output(query, Box::new(std::io::empty())));
+        }
+        if let Some(dir) = &self.output_dir {
+            let path = dir.join(output_file(query));
+            log::info!("Reading the output of the query from {}", path.display());
+            let file = std::fs::File::open(&path).map_err(|err| {
+                format!(
+                    "Failed to read the output {} of the query {query}: {err}",
+                    path.display()
+                )
+            })?;
+            return Ok(QueryStream::output(query, Box::new(BufReader::new(file))));
+        }
+
+        Ok(QueryStream::new(query, self.retries, self.auth.clone()))
+    }
+
+    /// Run the query, retrying transient failures.
+    ///
+    /// Queries returning as many lines as their `--limit` are missing lines, their time
+    /// range is split in halves and queried again, recursively. The output of the
+    /// [`QueryRunner::stream`] attempts is collected, without their partial outputs.
+    pub fn run(&self, query: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut stream = self.stream(query)?;
+        let mut output = Vec::new();
+        while let Some(mut attempt) = stream.next_attempt()? {
+            let start = output.len();
+            attempt.read_to_end(&mut output)?;
+            if !stream.end(attempt)? {
+                output.truncate(start);
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// The delay before the provided retry, with up to 50% of random jitter.
+    fn backoff(attempt: u32) -> std::time::Duration {
+        let delay = BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_DELAY);
+
+        // The sub-second clock is random enough to spread concurrent retries.
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.subsec_nanos())
+            .unwrap_or_default();
+        let jitter = delay.mul_f64(f64::from(nanos % 1000) / 2000.0);
+
+        delay + jitter
+    }
+}
+
+/// The `--limit` of the query.
+fn query_limit(query: &str) -> Option<usize> {
+    let (_, rest) = query.split_once(" --limit ")?;
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+/// The value of a quoted flag of the query (ie `--from="2024-03-29T16:00:00Z"`).
+fn query_time(query: &str, flag: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    let (_, rest) = query.split_once(&format!("{flag}=\""))?;
+    let (time, _) = rest.split_once('"')?;
+    chrono::DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|time| time.to_utc())
+}
+
+/// Split the time range of the query in halves.
+///
+/// Returns `None` when the range cannot be split further (ie a single second).
+fn split_query(query: &str) -> Option<(String, String)> {
+    let from = query_time(query, "--from")?;
+    let to = query_time(query, "--to")?;
+
+    let half = (to - from) / 2;
+    if half < chrono::Duration::seconds(1) {
+        return None;
+    }
+    let middle = from + chrono::Duration::seconds(half.num_seconds());
+
+    let format = |time: chrono::DateTime<chrono::Utc>| time.format("%Y-%m-%dT%H:%M:%SZ");
+    let to_flag = format!("--to=\"{}\"", format(to));
+    let from_flag = format!("--from=\"{}\"", format(from));
+    let middle = format(middle).to_string();
+
+    Some((
+        query.replace(&to_flag, &format!("--to=\"{middle}\"")),
+        query.replace(&from_flag, &format!("--from=\"{middle}\"")),
+    ))
+}
+
+/// Run the chunked queries, reusing the cached chunks when allowed.
+///
+/// The outputs are converted to lines by the source before being cached.
+///
+/// The results are processed by the provided closure, returning the number of
+/// processed and matched lines.
+pub(crate) fn run_chunked_queries(
+    queries: Vec<String>,
+    runner: QueryRunner,
+    source: &dyn LogSource,
+    progress: progress::ProgressMode,
+    cache: &mut Option<cache::QueryCache>,
+    process: &mut impl FnMut(&str) -> (usize, usize),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut progress = progress::Progress::new(progress, "chunks", queries.len());
+
+    for query in queries {
+        if let Some(result) = cache.as_ref().and_then(|cache| cache.cached(&query)) {
+            let (lines, matches) = process(&String::from_utf8_lossy(&result));
+            progress.inc(lines, matches);
+            continue;
         }
 
-        log::info!("Query completed in {:?}", now.elapsed());
-        Ok(result.stdout)
+        // Run the query.
+        let result = source.fetch(&runner, &query)?;
+        if let Some(cache) = cache {
+            cache.record(&query, &result)?;
+        }
+        let (lines, matches) = process(&String::from_utf8_lossy(&result));
+        progress.inc(lines, matches);
+
+        log::info!("Finished partial query");
+    }
+    progress.finish();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_errors() {
+        let err = QueryError::from_stderr(
+            "Query failed: Error response from server: 504 Gateway Timeout",
+        );
+        assert!(matches!(err, QueryError::Retryable(_)));
+
+        let err = QueryError::from_stderr(
+            "Error response from server: parse error at line 1, col 12: syntax error (400)",
+        );
+        assert!(matches!(err, QueryError::Fatal(_)));
+
+        // The echoed URL contains `5000`, nanosecond timestamps and the `Timeout` filter.
+        let url = "http://127.0.0.1:10700/loki/api/v1/query_range?direction=BACKWARD&end=1711728000000000000&limit=5000&query=%7Bchain%3D%22versi%22%7D+%7C%3D+%60Timeout%60&start=1711724429504000000";
+        let err = QueryError::from_stderr(&format!(
+            "2024/03/29 16:00:00 {url}\n2024/03/29 16:00:00 Query failed: Error response from server: parse error at line 1, col 17: syntax error: unexpected IDENTIFIER (<nil>)"
+        ));
+        assert!(matches!(err, QueryError::Fatal(_)), "{err}");
+
+        let err = QueryError::from_stderr(&format!(
+            "2024/03/29 16:00:00 {url}\n2024/03/29 16:00:00 Query failed: Error response from server: 429 Too Many Requests (<nil>)"
+        ));
+        assert!(matches!(err, QueryError::Retryable(_)));
+        let err = QueryError::from_stderr(&format!(
+            "2024/03/29 16:00:00 {url}\n2024/03/29 16:00:00 Query failed: Run out of attempts while querying the server; status code: 502"
+        ));
+        assert!(matches!(err, QueryError::Retryable(_)));
+
+        // The closed connections are retried, not the words containing `eof`.
+        let err = QueryError::from_stderr(
+            r#"Query failed: Get "http://127.0.0.1:10700/loki/api/v1/query_range": EOF"#,
+        );
+        assert!(matches!(err, QueryError::Retryable(_)));
+        let err = QueryError::from_stderr("Query failed: unexpected EOF");
+        assert!(matches!(err, QueryError::Retryable(_)));
+        let err = QueryError::from_stderr(
+            "Error response from server: the labels thereof are invalid (400)",
+        );
+        assert!(matches!(err, QueryError::Fatal(_)), "{err}");
+    }
+
+    #[test]
+    fn test_split_query() {
+        let query = QueryBuilder::new()
+            .set_time(
+                Some("2024-03-29T16:00:00Z".to_string()),
+                Some("2024-03-29T17:00:00Z".to_string()),
+                false,
+            )
+            .limit(10)
+            .build_chunks()
+            .unwrap()
+            .remove(0);
+        assert_eq!(query_limit(&query), Some(10));
+
+        let (first, second) = split_query(&query).unwrap();
+        assert!(first.contains(r#"--from="2024-03-29T16:00:00Z" --to="2024-03-29T16:30:00Z""#));
+        assert!(second.contains(r#"--from="2024-03-29T16:30:00Z" --to="2024-03-29T17:00:00Z""#));
+
+        let query = query.replace("17:00:00Z", "16:00:01Z");
+        assert!(split_query(&query).is_none());
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let query = "logcli query --from=\"2024-03-29T16:00:00Z\" --to=\"2024-03-29T17:00:00Z\"";
+        let runner = QueryRunner::new().dry_run(true);
+        assert!(runner.run(query).unwrap().is_empty());
+
+        let dir =
+            std::env::temp_dir().join(format!("sub-triage-logs-outputs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(output_file(query)), "line\n").unwrap();
+        let runner = QueryRunner::new().output_dir(Some(dir.clone()));
+        assert_eq!(runner.run(query).unwrap(), b"line\n");
+        assert!(runner.run("logcli query").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_stream() {
+        // The output of every attempt, and whether it is complete.
+        let read = |query: &str, retries: u32| {
+            let mut stream = QueryRunner::new().retries(retries).stream(query).unwrap();
+            let mut attempts = Vec::new();
+            while let Some(mut attempt) = stream.next_attempt().unwrap() {
+                let mut output = String::new();
+                attempt.read_to_string(&mut output).unwrap();
+                attempts.push((output, stream.end(attempt)?));
+            }
+            Ok::<_, QueryError>(attempts)
+        };
+
+        assert_eq!(
+            read("printf 'a\\nb'", 0).unwrap(),
+            [("a\nb".to_string(), true)]
+        );
+
+        // The queries are not run in a shell, the failing queries are run by a script.
+        let dir = std::env::temp_dir().join(format!("stream-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("query.sh");
+        std::fs::write(
+            &script,
+            r#"
+case "$1" in
+    fail) printf 'a\n'; echo 'invalid query' >&2; exit 1 ;;
+    retry)
+        if [ -f "$2" ]; then echo complete; exit 0; fi
+        touch "$2"; echo partial; echo 'response status 503' >&2; exit 1 ;;
+esac
+"#,
+        )
+        .unwrap();
+        let script = shell_quote(&script.to_string_lossy());
+
+        let err = read(&format!("sh {script} fail"), 0).unwrap_err();
+        assert!(err.to_string().contains("invalid query"));
+
+        // The partial output of the failed first attempt is discarded.
+        let marker = shell_quote(&dir.join("marker").to_string_lossy());
+        let query = format!("sh {script} retry {marker}");
+        assert_eq!(
+            read(&query, 1).unwrap(),
+            [
+                ("partial\n".to_string(), false),
+                ("complete\n".to_string(), true)
+            ]
+        );
+        let runner = QueryRunner::new().retries(1);
+        let marker = shell_quote(&dir.join("run").to_string_lossy());
+        let output = runner.run(&format!("sh {script} retry {marker}")).unwrap();
+        assert_eq!(output, b"complete\n");
+        assert!(runner.run(&format!("sh {script} fail")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            parse_label("namespace=versi").unwrap().to_string(),
+            r#"namespace="versi""#
+        );
+        assert_eq!(
+            parse_label(r#"pod=~"validator-.*""#).unwrap().to_string(),
+            r#"pod=~"validator-.*""#
+        );
+        assert_eq!(parse_label("host!~ci-.*").unwrap().operator, "!~");
+        assert!(parse_label("namespace").is_err());
+        assert!(parse_label("1pod=x").is_err());
+
+        let query = QueryBuilder::new()
+            .set_time(
+                Some("2024-03-29T16:00:00Z".to_string()),
+                Some("2024-03-29T16:30:00Z".to_string()),
+                false,
+            )
+            .labels(vec![parse_label("namespace=~versi-.*").unwrap()])
+            .build_chunks()
+            .unwrap();
+        assert_eq!(query.len(), 1);
+        assert!(query[0].contains(r#"{chain="versi-networking"  , namespace=~"versi-.*" }"#));
+
+        let query = QueryBuilder::new()
+            .set_time(
+                Some("2024-03-29T16:00:00Z".to_string()),
+                Some("2024-03-29T16:30:00Z".to_string()),
+                false,
+            )
+            .node(vec!["alice".to_string(), "bob".to_string()])
+            .exclude_node(vec!["canary-.*".to_string()])
+            .build_chunks()
+            .unwrap();
+        assert!(query[0]
+            .contains(r#"{chain="versi-networking"  , node=~"alice|bob" , node!~"canary-.*" }"#));
+
+        assert_eq!(
+            parse_filter(r#" |~ "litep2p" "#).as_deref(),
+            Ok(r#"|~ "litep2p""#)
+        );
+        assert!(parse_filter(r#"!= "telemetry""#).is_ok());
+        assert!(parse_filter("litep2p").is_err());
+        assert!(parse_filter("|= 'litep2p'").is_err());
+
+        assert!(parse_excluded_line("Error while dialing").is_ok());
+        assert!(parse_excluded_line("telemetry `submit`").is_err());
+        let query = QueryBuilder::new()
+            .set_time(
+                Some("2024-03-29T16:00:00Z".to_string()),
+                Some("2024-03-29T16:30:00Z".to_string()),
+                false,
+            )
+            .excluded_lines(vec!["telemetry".to_string(), "Slow block".to_string()])
+            .build_chunks()
+            .unwrap();
+        assert!(query[0].contains("}  != `telemetry` != `Slow block`"));
+        assert!(!query[0].contains("Error while dialing"));
+    }
+
+    #[test]
+    fn test_time_range() {
+        let time = |value: &str| Some(value.to_string());
+        assert_eq!(
+            time_range(
+                &time("2024-03-29T16:00:00Z"),
+                &time("2024-03-29T17:00:00Z"),
+                false
+            )
+            .unwrap(),
+            (
+                "2024-03-29T16:00:00Z".to_string(),
+                "2024-03-29T17:00:00Z".to_string()
+            )
+        );
+
+        let err = time_range(&time("2024-03-29T16:00:00Z"), &None, false).unwrap_err();
+        assert!(matches!(err, QueryError::PartialTimeRange { .. }));
+        assert_eq!(
+            err.to_string(),
+            r#"--start-time "2024-03-29T16:00:00Z" is provided without --end-time, provide both or neither"#
+        );
+        let err = time_range(
+            &time("2024-03-29 16:00"),
+            &time("2024-03-29T17:00:00Z"),
+            false,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(r#"Invalid start time "2024-03-29 16:00""#));
+        assert!(matches!(
+            time_range(
+                &time("2024-03-29T17:00:00Z"),
+                &time("2024-03-29T16:00:00Z"),
+                false
+            ),
+            Err(QueryError::EmptyTimeRange { .. })
+        ));
+        assert!(matches!(
+            time_range(
+                &time("2024-03-29T16:00:00Z"),
+                &time("2024-03-29T17:00:00Z"),
+                true
+            ),
+            Err(QueryError::ConflictingTimeRange)
+        ));
+    }
+
+    #[test]
+    fn test_time_window() {
+        // The window of `--last-day` does not depend on the checkpoint of the run.
+        let opts = <crate::config::Config as clap::Parser>::parse_from(["warn-err", "--last-day"]);
+        assert!(opts.cache_dir.is_none());
+        let (start, end) = time_window(&opts.start_time, &opts.end_time, opts.last_day).unwrap();
+        assert_eq!(end - start, chrono::Duration::hours(24));
+        assert!(chrono::Utc::now().naive_utc() - end < chrono::Duration::minutes(1));
+    }
+
+    #[test]
+    fn test_parse_time_arg() {
+        for value in [
+            "2024-05-01T08:00:00Z",
+            "2024-05-01T10:00:00+02:00",
+            "2024-05-01T08:00:00.250Z",
+            "2024-05-01 08:00:00",
+            "1714550400",
+            "1714550400000",
+        ] {
+            assert_eq!(
+                parse_time_arg(value).as_deref(),
+                Ok("2024-05-01T08:00:00Z"),
+                "{value}"
+            );
+        }
+        assert_eq!(
+            parse_time_arg("2024-05-01").as_deref(),
+            Ok("2024-05-01T00:00:00Z")
+        );
+        assert!(parse_time_arg("yesterday").is_err());
+        assert!(parse_time_arg("2024-05-01T10:00").is_err());
+    }
+
+    #[test]
+    fn test_auth() {
+        let query = QueryBuilder::new()
+            .set_time(
+                Some("2024-03-29T16:00:00Z".to_string()),
+                Some("2024-03-29T16:30:00Z".to_string()),
+                false,
+            )
+            .ca_cert(Some(PathBuf::from("/etc/loki/ca.pem")))
+            .insecure_skip_verify(true)
+            .build_chunks()
+            .unwrap();
+        assert!(query[0].ends_with(" --ca-cert='/etc/loki/ca.pem' --tls-skip-verify"));
+
+        let auth = LokiAuth {
+            token: Some("secret-token".to_string()),
+            username: None,
+            password: None,
+        };
+        assert!(!format!("{auth:?}").contains("secret-token"));
+        let output = QueryRunner::new()
+            .auth(auth)
+            .run(r#"printf "$LOKI_BEARER_TOKEN""#)
+            .unwrap();
+        assert_eq!(output, b"secret-token");
     }
 }
@@ -0,0 +1,160 @@
+//! Assembly of the log records spanning multiple lines.
+//!
+//! Panics, backtraces and wasm traps are printed over multiple lines, only the first
+//! of them starting with a timestamp:
+//!
+//! ```text
+//! 2024-03-29 16:00:00.000 ERROR tokio-runtime-worker runtime: Wasm trap
+//! WASM backtrace:
+//!     0: 0x1b2c - <unknown>!rust_begin_unwind
+//! ```
+//!
+//! The continuation lines are joined to their parent record, such that the record is
+//! classified once.
+//!
+//! Only the records of the local files are stitched. The lines of the Loki queries are
+//! returned newest first by `logcli`, interleaving the nodes, and the continuation lines
+//! have no level to be selected by, such that they are read one by one.
+
+use std::io::BufRead;
+
+use crate::parse;
+
+/// Check if the line starts a new record, with a leading timestamp or as a JSON record.
+fn is_record_start(line: &str) -> bool {
+    line.trim_start().starts_with('{') || parse::parse_timestamp(line).is_some()
+}
+
+/// Reads the records of a stream, one line ahead of the returned record.
+///
+/// A record is a line starting with a timestamp, followed by its continuation lines
+/// (the non-empty lines without leading timestamp). The other lines (ie preceding the
+/// first timestamp) are records on their own, such that the logs without timestamps
+/// are processed line by line.
+///
+/// Only the current record is kept in memory, such that huge inputs (ie multi-GB files,
+/// command outputs) are read with bounded memory. Invalid UTF-8 is replaced.
+pub struct RecordReader<R> {
+    reader: R,
+    /// Join the continuation lines to their record.
+    stitch: bool,
+    /// The line following the current record.
+    next: Option<String>,
+    /// The line buffer of the reader.
+    buffer: Vec<u8>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    /// Read the records of the provided stream.
+    pub fn new(reader: R) -> Self {
+        RecordReader {
+            reader,
+            stitch: true,
+            next: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Join the continuation lines to their record, or read the lines one by one.
+    ///
+    /// Default: true.
+    pub fn stitch(mut self, stitch: bool) -> Self {
+        self.stitch = stitch;
+        self
+    }
+
+    /// Read the next line, without its line ending.
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        self.buffer.clear();
+        if self.reader.read_until(b'\n', &mut self.buffer)? == 0 {
+            return Ok(None);
+        }
+
+        let line = self.buffer.strip_suffix(b"\n").unwrap_or(&self.buffer);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        Ok(Some(String::from_utf8_lossy(line).into_owned()))
+    }
+
+    fn next_record(&mut self) -> std::io::Result<Option<String>> {
+        let mut record = match self.next.take() {
+            Some(line) => line,
+            None => match self.read_line()? {
+                Some(line) => line,
+                None => return Ok(None),
+            },
+        };
+        if !self.stitch || !is_record_start(&record) {
+            return Ok(Some(record));
+        }
+
+        while let Some(line) = self.read_line()? {
+            if line.trim().is_empty() || is_record_start(&line) {
+                self.next = Some(line);
+                break;
+            }
+            record.push('\n');
+            record.push_str(&line);
+        }
+
+        Ok(Some(record))
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records() {
+        let text = "\
+Starting the node
+2024-03-29 16:00:00.000 ERROR tokio-runtime-worker runtime: Wasm trap
+WASM backtrace:
+    0: 0x1b2c - <unknown>!rust_begin_unwind
+
+2024-03-29 16:00:01.000  WARN tokio-runtime-worker sync: Slow\r
+2024-03-29T16:00:02Z {node=\"alice\"} Thread 'main' panicked at 'oops', lib.rs:1:1\r
+   0: std::panicking::begin_panic\r
+{\"level\":\"WARN\",\"msg\":\"Structured\"}
+";
+        let records: Vec<_> = RecordReader::new(text.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            vec![
+                "Starting the node",
+                "2024-03-29 16:00:00.000 ERROR tokio-runtime-worker runtime: Wasm trap\nWASM backtrace:\n    0: 0x1b2c - <unknown>!rust_begin_unwind",
+                "",
+                "2024-03-29 16:00:01.000  WARN tokio-runtime-worker sync: Slow",
+                "2024-03-29T16:00:02Z {node=\"alice\"} Thread 'main' panicked at 'oops', lib.rs:1:1\n   0: std::panicking::begin_panic",
+                "{\"level\":\"WARN\",\"msg\":\"Structured\"}",
+            ]
+        );
+        let records = |text: &str, stitch: bool| {
+            RecordReader::new(text.as_bytes())
+                .stitch(stitch)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(records("no\ntimestamps", true), vec!["no", "timestamps"]);
+        assert!(records("", true).is_empty());
+
+        // The lines of the Loki queries are newest first, the continuation lines of a
+        // record preceding it, and are read one by one.
+        let logcli = "\
+2024-03-29T16:00:03Z {node=\"bob\"} 2024-03-29 16:00:03.000  WARN sync: Slow
+   0: std::panicking::begin_panic
+2024-03-29T16:00:02Z {node=\"alice\"} 2024-03-29 16:00:02.000 ERROR runtime: Thread 'main' panicked at 'oops', lib.rs:1:1
+";
+        assert_eq!(records(logcli, false), logcli.lines().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,429 @@
+//! Diff of the log statements of two refs of the regex repository.
+//!
+//! Before an upgrade, release managers review the errors and warnings the new release could
+//! print. The log statements of both refs (ie the previous and the new release tags) are
+//! extracted, and matched by file and pattern. The unmatched statements of a file are paired
+//! when their patterns share most of their words, such that a reworded message is reported
+//! as changed rather than as removed and added.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    fetch_git::{self, RegexDetails, Repo},
+    github::GitHub,
+    output::{escape, Render},
+    suggest, RegexDiffConfig,
+};
+
+/// The minimum share of common words of the patterns of a changed statement.
+const MIN_SIMILARITY: f64 = 0.5;
+
+/// A log statement of one of the refs.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Statement {
+    /// The source file of the statement.
+    pub file: String,
+    /// The line of the statement in the file (1-based).
+    pub line: usize,
+    /// The level of the statement (ie `warn`).
+    pub level: String,
+    /// The target of the statement, when it could be resolved.
+    pub target: Option<String>,
+    /// The regex of the printed lines.
+    pub pattern: String,
+    /// Link to the statement in the source repository.
+    pub permalink: String,
+}
+
+impl Statement {
+    fn new(regex: &regex::Regex, details: &RegexDetails) -> Self {
+        Statement {
+            file: details.file.clone(),
+            line: details.line,
+            level: details.ty.clone(),
+            target: details.target.clone(),
+            pattern: regex.as_str().to_string(),
+            permalink: details.permalink.clone(),
+        }
+    }
+
+    /// The location of the statement (ie `substrate/client/network/src/service.rs:42`).
+    pub fn location(&self) -> String {
+        format!("{}:{}", self.file, self.line)
+    }
+
+    /// The errors are reviewed first.
+    fn sort_key(&self) -> (bool, &str, usize) {
+        (self.level != "error", &self.file, self.line)
+    }
+}
+
+/// A log statement whose pattern, level or target changed between the refs.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ChangedStatement {
+    /// The statement of the previous ref.
+    pub from: Statement,
+    /// The statement of the new ref.
+    pub to: Statement,
+}
+
+impl ChangedStatement {
+    /// The level, or its change (ie `warn -> error`).
+    pub fn level(&self) -> String {
+        if self.from.level == self.to.level {
+            self.to.level.clone()
+        } else {
+            format!("{} -> {}", self.from.level, self.to.level)
+        }
+    }
+}
+
+/// The log statements added, removed or changed between two refs of the regex repository.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RegexDiffReport {
+    /// The previous ref of the repository.
+    pub from: Repo,
+    /// The new ref of the repository.
+    pub to: Repo,
+    /// The number of statements identical in both refs.
+    pub unchanged: usize,
+    /// The statements of the new ref only, the errors first.
+    pub added: Vec<Statement>,
+    /// The statements whose pattern, level or target changed, the errors first.
+    pub changed: Vec<ChangedStatement>,
+    /// The statements of the previous ref only, the errors first.
+    pub removed: Vec<Statement>,
+}
+
+impl RegexDiffReport {
+    fn summary(&self) -> String {
+        format!(
+            "Log statements of {} from {} to {}: {} added, {} changed, {} removed, {} unchanged",
+            self.to.url,
+            describe(&self.from),
+            describe(&self.to),
+            self.added.len(),
+            self.changed.len(),
+            self.removed.len(),
+            self.unchanged
+        )
+    }
+}
+
+impl Render for RegexDiffReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "{}", self.summary())?;
+
+        let line = |out: &mut String, sign: char, level: &str, statement: &Statement| {
+            writeln!(
+                out,
+                "{sign} {0: <14} | {1: <60} | {2}",
+                level,
+                statement.location(),
+                statement.pattern
+            )
+        };
+        if !self.added.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "Added:")?;
+            for statement in &self.added {
+                line(out, '+', &statement.level, statement)?;
+            }
+        }
+        if !self.changed.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "Changed:")?;
+            for changed in &self.changed {
+                line(out, '-', &changed.from.level, &changed.from)?;
+                line(out, '+', &changed.to.level, &changed.to)?;
+            }
+        }
+        if !self.removed.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "Removed:")?;
+            for statement in &self.removed {
+                line(out, '-', &statement.level, statement)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "{}", self.summary())?;
+
+        let link =
+            |statement: &Statement| format!("[{}]({})", statement.location(), statement.permalink);
+        let pattern = |statement: &Statement| format!("`{}`", escape(&statement.pattern));
+        for (title, statements) in [("Added", &self.added), ("Removed", &self.removed)] {
+            if statements.is_empty() {
+                continue;
+            }
+            writeln!(out)?;
+            writeln!(out, "#### {title}")?;
+            writeln!(out)?;
+            writeln!(out, "| Level | Statement | Pattern |")?;
+            writeln!(out, "|---|---|---|")?;
+            for statement in statements {
+                writeln!(
+                    out,
+                    "| {} | {} | {} |",
+                    statement.level,
+                    link(statement),
+                    pattern(statement)
+                )?;
+            }
+        }
+
+        if !self.changed.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "#### Changed")?;
+            writeln!(out)?;
+            writeln!(out, "| Level | Statement | Before | After |")?;
+            writeln!(out, "|---|---|---|---|")?;
+            for changed in &self.changed {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    changed.level(),
+                    link(&changed.to),
+                    pattern(&changed.from),
+                    pattern(&changed.to)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The ref of the repository, with its short commit when known (ie `polkadot-v1.16.0 (1234abc)`).
+fn describe(repo: &Repo) -> String {
+    match &repo.commit {
+        Some(commit) => format!("{} ({})", repo.branch, &commit[..commit.len().min(7)]),
+        None => repo.branch.clone(),
+    }
+}
+
+/// Diff the log statements extracted from the previous and the new ref.
+pub fn diff(
+    from: Repo,
+    from_regexes: &[(regex::Regex, RegexDetails)],
+    to: Repo,
+    to_regexes: &[(regex::Regex, RegexDetails)],
+) -> RegexDiffReport {
+    // The statements are identified by file and pattern, the lines move between the refs.
+    let group = |regexes: &[(regex::Regex, RegexDetails)]| {
+        let mut statements: BTreeMap<(String, String), Vec<Statement>> = BTreeMap::new();
+        for (regex, details) in regexes {
+            let statement = Statement::new(regex, details);
+            statements
+                .entry((statement.file.clone(), statement.pattern.clone()))
+                .or_default()
+                .push(statement);
+        }
+        statements
+    };
+    let mut previous = group(from_regexes);
+    let current = group(to_regexes);
+
+    let mut unchanged = 0;
+    let mut changed = Vec::new();
+    let mut added: BTreeMap<String, Vec<Statement>> = BTreeMap::new();
+    for (key, statements) in current {
+        let mut matched = previous.remove(&key).unwrap_or_default().into_iter();
+        for statement in statements {
+            match matched.next() {
+                Some(from) if from.level == statement.level && from.target == statement.target => {
+                    unchanged += 1
+                }
+                Some(from) => changed.push(ChangedStatement {
+                    from,
+                    to: statement,
+                }),
+                None => added
+                    .entry(statement.file.clone())
+                    .or_default()
+                    .push(statement),
+            }
+        }
+        let remaining: Vec<_> = matched.collect();
+        if !remaining.is_empty() {
+            previous.insert(key, remaining);
+        }
+    }
+
+    // The reworded statements of a file are paired with the most similar removed statement.
+    let mut removed = Vec::new();
+    let mut previous_files: BTreeMap<String, Vec<Statement>> = BTreeMap::new();
+    for ((file, _), statements) in previous {
+        previous_files.entry(file).or_default().extend(statements);
+    }
+    let mut added_statements = Vec::new();
+    for (file, statements) in added {
+        let mut candidates = previous_files.remove(&file).unwrap_or_default();
+        for statement in statements {
+            let words = suggest::words(&statement.pattern);
+            let best = candidates
+                .iter()
+                .enumerate()
+                .map(|(index, from)| {
+                    (
+                        index,
+                        suggest::jaccard(&words, &suggest::words(&from.pattern)),
+                    )
+                })
+                .filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            match best {
+                Some((index, _)) => changed.push(ChangedStatement {
+                    from: candidates.remove(index),
+                    to: statement,
+                }),
+                None => added_statements.push(statement),
+            }
+        }
+        removed.extend(candidates);
+    }
+    removed.extend(previous_files.into_values().flatten());
+
+    added_statements.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    removed.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    changed.sort_by(|a, b| a.to.sort_key().cmp(&b.to.sort_key()));
+
+    RegexDiffReport {
+        from,
+        to,
+        unchanged,
+        added: added_statements,
+        changed,
+        removed,
+    }
+}
+
+/// Fetch the repository at the provided ref, and extract its log statements.
+async fn extract(
+    opts: &RegexDiffConfig,
+    github: &GitHub,
+    branch: &str,
+) -> Result<(Repo, Vec<(regex::Regex, RegexDetails)>), Box<dyn std::error::Error>> {
+    let config = &opts.config;
+    let cache = config.tarball_cache();
+    let filter = fetch_git::PathFilter {
+        include: config.regex_include_path.clone(),
+        exclude: config.regex_exclude_path.clone(),
+    };
+    let repo = Repo::new(
+        "polkadot-sdk".into(),
+        config.regex_repo.clone().ok_or("Missing regex repo")?,
+        branch.to_string(),
+    );
+    let sources = fetch_git::fetch(
+        repo.url.clone(),
+        repo.branch.clone(),
+        github,
+        &filter,
+        cache.as_ref(),
+    )
+    .await?;
+    let repo = repo.with_commit(sources.commit);
+    let extraction = fetch_git::extract_regexes(sources.files, &repo, &config.log_macros);
+
+    Ok((repo, extraction.regexes))
+}
+
+/// Report the log statements added, removed or changed between the `--from` and `--to` refs.
+pub async fn run(opts: RegexDiffConfig) -> Result<RegexDiffReport, Box<dyn std::error::Error>> {
+    log::info!("Running regex diff from {} to {}", opts.from, opts.to);
+    let github = GitHub::new(opts.config.github_token.clone());
+
+    let (from, from_regexes) = extract(&opts, &github, &opts.from).await?;
+    let (to, to_regexes) = extract(&opts, &github, &opts.to).await?;
+
+    Ok(diff(from, &from_regexes, to, &to_regexes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OutputFormat;
+
+    #[test]
+    fn test_diff() {
+        let extract = |branch: &str, source: &str| {
+            let repo = Repo::new(
+                "test".to_string(),
+                "https://github.com/paritytech/test/".to_string(),
+                branch.to_string(),
+            );
+            let macros: Vec<_> = fetch_git::DEFAULT_LOG_MACROS
+                .split(',')
+                .map(String::from)
+                .collect();
+            let regexes = fetch_git::extract_regexes(
+                vec![("test.rs".to_string(), source.to_string())],
+                &repo,
+                &macros,
+            )
+            .regexes;
+            (repo, regexes)
+        };
+
+        let (from, from_regexes) = extract(
+            "v1",
+            r#"
+    warn!("Failed to dial peer {}", peer);
+    warn!("Block import is slow");
+    warn!("Missing public key for validator");
+    error!("Database column is corrupted");
+"#,
+        );
+        let (to, to_regexes) = extract(
+            "v2",
+            r#"
+    warn!("Failed to dial peer {}", peer);
+
+    error!("Block import is slow");
+    warn!("Missing public key for validator {}", index);
+    error!("Runtime upgrade failed");
+"#,
+        );
+
+        let report = diff(from, &from_regexes, to, &to_regexes);
+        assert_eq!(report.unchanged, 1);
+        let patterns = |statements: &[Statement]| {
+            statements
+                .iter()
+                .map(|statement| statement.pattern.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(patterns(&report.added), ["Runtime upgrade failed"]);
+        assert_eq!(patterns(&report.removed), ["Database column is corrupted"]);
+
+        let changed: Vec<_> = report
+            .changed
+            .iter()
+            .map(|changed| {
+                (
+                    changed.level(),
+                    changed.to.pattern.as_str(),
+                    changed.to.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            changed,
+            [
+                ("warn -> error".to_string(), "Block import is slow", 4),
+                (
+                    "warn".to_string(),
+                    "Missing public key for validator .*?",
+                    5
+                ),
+            ]
+        );
+
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("1 added, 2 changed, 1 removed, 1 unchanged"));
+    }
+}
@@ -0,0 +1,2272 @@
+//! The triage report and its rendering.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use chrono::NaiveDate;
+
+use crate::{
+    blame::Blame,
+    coverage::CoverageReport,
+    epoch::SessionChanges,
+    fetch_git::{RegexDetails, Repo},
+    first_error::FirstErrorReport,
+    histogram::{self, Buckets, Spike},
+    known::{Annotation, KnownIssues, Status},
+    matcher::{Entry, Matches},
+    metadata::Metadata,
+    output::{breakdown, escape, Color, Render},
+    subsystem::Subsystems,
+    suggest::UnknownCluster,
+    summary::Summary,
+    suppress::{Suppression, Suppressions},
+    threshold::Thresholds,
+    version,
+};
+
+/// A column grouping the counts of the patterns, with the selector of the counts.
+type Group = (&'static str, fn(&PatternReport) -> &BTreeMap<String, usize>);
+
+/// The order of the patterns in the report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortOrder {
+    /// The most frequent patterns first.
+    #[default]
+    Count,
+    /// Errors before warnings, then by count.
+    Level,
+    /// The patterns that occurred first come first.
+    FirstSeen,
+    /// The highest rates per hour first, like the counts over a single time range.
+    Rate,
+}
+
+/// How the pattern counts are aggregated in an extra table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    /// The target of the log statements (ie `sub-libp2p`).
+    Target,
+    /// The peers mentioned by the matched lines (ie banned or disconnected peers).
+    Peer,
+    /// The subsystem of the source files of the log statements (ie `disputes`).
+    Subsystem,
+    /// The source files of the log statements (ie `src/protocol/libp2p/kademlia/mod.rs`).
+    File,
+}
+
+/// The target of the patterns whose target could not be resolved.
+const UNKNOWN_TARGET: &str = "unknown";
+
+/// The subsystem of the patterns whose source file is not mapped to a subsystem.
+const UNKNOWN_SUBSYSTEM: &str = "unknown";
+
+/// The maximum number of peers reported with `--group-by peer`.
+const MAX_PEERS: usize = 50;
+
+/// The flag of the patterns exceeding their threshold.
+const ATTENTION_FLAG: &str = "⚠";
+
+/// The format of the first-seen and last-seen timestamps of the patterns.
+const SEEN_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The width of the first-seen and last-seen columns.
+const SEEN_WIDTH: usize = 19;
+
+/// The lines matched by a single pattern.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PatternReport {
+    /// The repository of the log statement.
+    pub repo: String,
+    /// The level of the log statement (ie `warn`, `error`).
+    pub level: String,
+    /// The target of the log statement, when it could be resolved.
+    pub target: Option<String>,
+    /// The subsystem of the source file, when it is mapped to a subsystem.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subsystem: Option<String>,
+    /// The owners of the source file, from the `CODEOWNERS` file of the repository.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub owners: Vec<String>,
+    /// The number of matched lines.
+    pub count: usize,
+    /// The pattern, including the deduplication key if any.
+    pub pattern: String,
+    /// The source file of the log statement.
+    pub file: String,
+    /// The line of the log statement in the source file.
+    pub line: usize,
+    /// The column of the log statement in the source file.
+    pub column: usize,
+    /// Link to the log statement in the source repository.
+    pub permalink: String,
+    /// The comment above the log statement.
+    pub description: Option<String>,
+    /// The commit that last touched the log statement.
+    pub blame: Option<String>,
+    /// The annotation from the known issues file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<Annotation>,
+    /// The threshold exceeded by the count, the pattern needs attention.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exceeded: Option<usize>,
+    /// The number of matched lines reporting a panic.
+    pub panics: usize,
+    /// Number of matched lines per node.
+    pub nodes: BTreeMap<String, usize>,
+    /// Number of matched lines per chain, only provided for multi-chain runs.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub chains: BTreeMap<String, usize>,
+    /// Number of matched lines per node version, only provided when grouping by version.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, usize>,
+    /// Number of matched lines per mentioned peer, only provided when grouping by peer.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub peers: BTreeMap<String, usize>,
+    /// The timestamp of the first matched line, when it could be parsed.
+    pub first_seen: Option<chrono::NaiveDateTime>,
+    /// The timestamp of the last matched line, when it could be parsed.
+    pub last_seen: Option<chrono::NaiveDateTime>,
+    /// The number of matched lines per hour over the time range of the run, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f64>,
+    /// Number of matched lines per time bucket, only provided for histograms.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub histogram: Vec<usize>,
+    /// The windows spiking over the median count, only provided with `--spike-factor`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spikes: Vec<Spike>,
+    /// The share of the lines around the session changes, only provided for the patterns
+    /// clustered around them with `--session-changes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_share: Option<f64>,
+    /// The link to the lines of the pattern in Grafana Explore, only provided with
+    /// `--explore-url`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explore: Option<String>,
+    /// The matched lines, only provided for raw reports.
+    ///
+    /// Only a sample of the lines is provided with `--samples`, and the first `--max-lines`
+    /// lines otherwise.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<String>,
+}
+
+#[cfg(test)]
+impl PatternReport {
+    /// A `warn` pattern of `polkadot-sdk` matched `count` times.
+    pub(crate) fn new(pattern: &str, count: usize) -> Self {
+        PatternReport {
+            repo: "polkadot-sdk".to_string(),
+            level: "warn".to_string(),
+            target: None,
+            count,
+            pattern: pattern.to_string(),
+            file: "lib.rs".to_string(),
+            line: 1,
+            column: 1,
+            permalink: String::new(),
+            description: None,
+            blame: None,
+            annotation: None,
+            exceeded: None,
+            subsystem: None,
+            owners: Vec::new(),
+            panics: 0,
+            nodes: Default::default(),
+            chains: Default::default(),
+            versions: Default::default(),
+            peers: Default::default(),
+            first_seen: None,
+            last_seen: None,
+            rate: None,
+            histogram: Vec::new(),
+            spikes: Vec::new(),
+            session_share: None,
+            explore: None,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Set the lines matched on the chain, counted by the pattern.
+    pub(crate) fn chain(mut self, chain: &str, count: usize) -> Self {
+        self.chains.insert(chain.to_string(), count);
+        self.count += count;
+        self
+    }
+}
+
+/// The matched lines of a single node.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NodeReport {
+    /// The name of the node.
+    pub node: String,
+    /// The number of matched lines.
+    pub count: usize,
+    /// The number of distinct patterns matched.
+    pub categories: usize,
+    /// The number of unknown lines.
+    pub unknown: usize,
+    /// The version of the node, when detected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// The matched lines of the patterns of a single target.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TargetReport {
+    /// The target of the log statements.
+    pub target: String,
+    /// The number of matched lines.
+    pub count: usize,
+    /// The number of distinct patterns matched.
+    pub categories: usize,
+}
+
+/// The matched lines of the patterns of a single subsystem.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SubsystemReport {
+    /// The subsystem of the source files.
+    pub subsystem: String,
+    /// The number of matched lines.
+    pub count: usize,
+    /// The number of distinct patterns matched.
+    pub categories: usize,
+}
+
+/// The matched lines of the patterns of a single source file.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileReport {
+    /// The repository of the source file.
+    pub repo: String,
+    /// The path of the source file in the repository.
+    pub file: String,
+    /// The number of matched lines.
+    pub count: usize,
+    /// The number of distinct patterns matched.
+    pub categories: usize,
+}
+
+/// The matched lines mentioning a single peer.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PeerReport {
+    /// The identity of the peer.
+    pub peer: String,
+    /// The number of matched lines mentioning the peer.
+    pub count: usize,
+    /// The number of distinct patterns mentioning the peer.
+    pub categories: usize,
+    /// The pattern mentioning the peer the most.
+    pub top_pattern: String,
+}
+
+/// A pattern whose count exceeds its threshold.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AttentionReport {
+    /// The pattern.
+    pub pattern: String,
+    /// The level of the log statement.
+    pub level: String,
+    /// The number of matched lines.
+    pub count: usize,
+    /// The exceeded threshold.
+    pub threshold: usize,
+}
+
+/// A pattern hidden by its suppression.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SuppressedReport {
+    /// The pattern.
+    pub pattern: String,
+    /// The level of the log statement.
+    pub level: String,
+    /// The number of matched lines.
+    pub count: usize,
+    /// The suppression of the pattern.
+    pub suppression: Suppression,
+}
+
+/// A suppression past its expiry date, its patterns being reported again.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExpiredReport {
+    /// The number of lines matched by the patterns of the suppression.
+    pub count: usize,
+    /// The expired suppression.
+    pub suppression: Suppression,
+}
+
+/// The report of a triage run.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TriageReport {
+    /// The provenance of the report, only provided by the triage sessions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    /// The repositories of the regexes, with the commit of their branch or tag.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<Repo>,
+    /// The suppressions past their expiry date, only provided with suppressions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub expired: Vec<ExpiredReport>,
+    /// The patterns exceeding their threshold, only provided with thresholds.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attention: Vec<AttentionReport>,
+    /// The matched patterns, sorted by count.
+    pub patterns: Vec<PatternReport>,
+    /// The patterns hidden by their suppression, only provided with suppressions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suppressed: Vec<SuppressedReport>,
+    /// The matched lines per node, when the lines carry node information.
+    pub nodes: Vec<NodeReport>,
+    /// The matched lines per target, only provided with `--group-by target`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetReport>,
+    /// The matched lines per subsystem, only provided with `--group-by subsystem`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subsystems: Vec<SubsystemReport>,
+    /// The matched lines per source file, only provided with `--group-by file`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileReport>,
+    /// The most mentioned peers, only provided with `--group-by peer`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub peers: Vec<PeerReport>,
+    /// The time to the first error of every node, only provided with `--time-to-first-error`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub first_errors: Vec<FirstErrorReport>,
+    /// The session changes, only provided with `--session-changes`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub session_changes: Vec<chrono::NaiveDateTime>,
+    /// The uncovered log statements, only provided with `--coverage-report`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<CoverageReport>,
+    /// The clusters of unknown lines with their closest patterns, only provided with
+    /// `--suggest-patterns`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_clusters: Vec<UnknownCluster>,
+    /// The lines that could not be matched, only provided with `--show-unknown`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_lines: Vec<String>,
+    /// The time buckets of the pattern histograms.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<Buckets>,
+    /// The summary of the run.
+    pub summary: Summary,
+    /// Blame attribution was requested for the run.
+    #[serde(skip)]
+    pub blame: bool,
+    /// The matched lines are included in the report.
+    #[serde(skip)]
+    pub raw: bool,
+    /// The patterns were annotated with the known issues.
+    #[serde(skip)]
+    pub known: bool,
+    /// The text report is colored.
+    #[serde(skip)]
+    pub color: bool,
+}
+
+impl TriageReport {
+    /// Build the report from the matched lines.
+    ///
+    /// The patterns are sorted by count, then by source location, such that
+    /// repeated runs over the same inputs produce identical reports.
+    ///
+    /// When an interval is provided, the occurrences of every pattern are
+    /// counted per time bucket.
+    pub fn new(
+        matches: &Matches,
+        mut blame: Option<&mut Blame>,
+        raw: bool,
+        interval: Option<chrono::Duration>,
+    ) -> Self {
+        let buckets = interval.and_then(|interval| {
+            Buckets::new(
+                interval,
+                matches
+                    .found_lines
+                    .values()
+                    .flat_map(|entry| entry.timeline.bounds()),
+            )
+        });
+
+        let mut patterns: Vec<_> = matches
+            .found_lines
+            .iter()
+            .filter(|(_, entry)| entry.count() > 0)
+            .map(|((pattern, details), entry)| PatternReport {
+                repo: details.repo.clone(),
+                level: details.ty.clone(),
+                target: details.target.clone(),
+                count: entry.count(),
+                pattern: pattern.clone(),
+                file: details.file.clone(),
+                line: details.line,
+                column: details.column,
+                permalink: details.permalink.clone(),
+                description: details.description.clone(),
+                blame: blame
+                    .as_mut()
+                    .and_then(|blame| blame.attribute(details))
+                    .map(|attribution| attribution.to_string()),
+                subsystem: None,
+                owners: details.owners.clone(),
+                annotation: None,
+                exceeded: None,
+                panics: entry.panics,
+                nodes: entry.nodes.clone(),
+                chains: entry.chains.clone(),
+                versions: versions(matches, entry),
+                peers: entry.peers.clone(),
+                first_seen: entry.timeline.first,
+                last_seen: entry.timeline.last,
+                rate: None,
+                histogram: buckets
+                    .as_ref()
+                    .map(|buckets| buckets.count_timeline(&entry.timeline))
+                    .unwrap_or_default(),
+                spikes: Vec::new(),
+                session_share: None,
+                explore: None,
+                lines: if raw { entry.lines.lines() } else { Vec::new() },
+            })
+            .collect();
+        patterns.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| {
+                    (&a.repo, &a.file, a.line, a.column).cmp(&(&b.repo, &b.file, b.line, b.column))
+                })
+                .then_with(|| a.pattern.cmp(&b.pattern))
+        });
+
+        // Node -> (matched lines, categories).
+        let mut nodes: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for pattern in &patterns {
+            for (node, count) in &pattern.nodes {
+                let stats = nodes.entry(node).or_default();
+                stats.0 += count;
+                stats.1 += 1;
+            }
+        }
+        for node in matches.unknown_nodes.keys() {
+            nodes.entry(node).or_default();
+        }
+        let nodes = nodes
+            .into_iter()
+            .map(|(node, (count, categories))| NodeReport {
+                node: node.to_string(),
+                count,
+                categories,
+                unknown: matches.unknown_nodes.get(node).copied().unwrap_or_default(),
+                version: matches.versions.get(node).cloned(),
+            })
+            .collect();
+
+        let mut summary = Summary::default();
+        for pattern in &patterns {
+            summary.categories += 1;
+            match pattern.level.as_str() {
+                "error" => summary.errors += pattern.count,
+                "warn" => summary.warns += pattern.count,
+                _ => {}
+            }
+            *summary.levels.entry(pattern.level.clone()).or_default() += pattern.count;
+        }
+
+        // Every category is new until annotated with the known issues.
+        summary.new = summary.categories;
+        summary.unknown = matches.unknown;
+        summary.panics = matches
+            .found_lines
+            .values()
+            .map(|entry| entry.panics)
+            .sum::<usize>()
+            + matches.unknown_panics;
+
+        TriageReport {
+            metadata: None,
+            expired: Vec::new(),
+            attention: Vec::new(),
+            patterns,
+            suppressed: Vec::new(),
+            nodes,
+            targets: Vec::new(),
+            subsystems: Vec::new(),
+            files: Vec::new(),
+            peers: Vec::new(),
+            repos: Vec::new(),
+            first_errors: Vec::new(),
+            session_changes: Vec::new(),
+            coverage: None,
+            unknown_clusters: Vec::new(),
+            unknown_lines: matches.unknown_lines.clone(),
+            histogram: buckets,
+            summary,
+            blame: blame.is_some(),
+            raw,
+            known: false,
+            color: false,
+        }
+    }
+
+    /// Annotate the patterns with the known issues.
+    ///
+    /// Only the patterns without annotation are counted as new.
+    pub fn annotate(&mut self, known: &KnownIssues) {
+        for pattern in &mut self.patterns {
+            pattern.annotation = known.find(&pattern.pattern).cloned();
+        }
+
+        self.known = true;
+        self.summary.new = self
+            .patterns
+            .iter()
+            .filter(|pattern| pattern.annotation.is_none())
+            .count();
+    }
+
+    /// Hide the suppressed patterns, and report the suppressions expired before today.
+    ///
+    /// The patterns of the expired suppressions stay in the report. The suppressed
+    /// patterns are not counted as new.
+    pub fn suppress(&mut self, suppressions: &Suppressions, today: NaiveDate) {
+        let patterns = std::mem::take(&mut self.patterns);
+        for pattern in patterns {
+            match suppressions.find(&pattern.pattern, today) {
+                Some(suppression) if !suppression.is_expired(today) => {
+                    self.suppressed.push(SuppressedReport {
+                        pattern: pattern.pattern,
+                        level: pattern.level,
+                        count: pattern.count,
+                        suppression: suppression.clone(),
+                    })
+                }
+                _ => self.patterns.push(pattern),
+            }
+        }
+        self.summary.new = self.summary.new.saturating_sub(self.suppressed.len());
+
+        self.expired = suppressions
+            .expired(today)
+            .map(|suppression| ExpiredReport {
+                count: self
+                    .patterns
+                    .iter()
+                    .filter(|pattern| {
+                        suppressions.find(&pattern.pattern, today) == Some(suppression)
+                    })
+                    .map(|pattern| pattern.count)
+                    .sum(),
+                suppression: suppression.clone(),
+            })
+            .collect();
+        for expired in &self.expired {
+            log::warn!(
+                "Suppression of {:?} expired on {}, revisit {}",
+                expired.suppression.pattern,
+                expired.suppression.expires,
+                expired.suppression.link
+            );
+        }
+    }
+
+    /// Aggregate the pattern counts per target, the most frequent targets first.
+    pub fn group_by_target(&mut self) {
+        // Target -> (matched lines, categories).
+        let mut targets: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for pattern in &self.patterns {
+            let target = pattern.target.as_deref().unwrap_or(UNKNOWN_TARGET);
+            let stats = targets.entry(target).or_default();
+            stats.0 += pattern.count;
+            stats.1 += 1;
+        }
+
+        let mut targets: Vec<_> = targets
+            .into_iter()
+            .map(|(target, (count, categories))| TargetReport {
+                target: target.to_string(),
+                count,
+                categories,
+            })
+            .collect();
+        targets.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.target.cmp(&b.target)));
+        self.targets = targets;
+    }
+
+    /// Classify the patterns in the subsystems of their source files.
+    pub fn classify(&mut self, subsystems: &Subsystems) {
+        for pattern in &mut self.patterns {
+            pattern.subsystem = subsystems
+                .classify(&pattern.repo, &pattern.file)
+                .map(str::to_string);
+        }
+    }
+
+    /// Aggregate the pattern counts per subsystem, the most frequent subsystems first.
+    pub fn group_by_subsystem(&mut self) {
+        // Subsystem -> (matched lines, categories).
+        let mut subsystems: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for pattern in &self.patterns {
+            let subsystem = pattern.subsystem.as_deref().unwrap_or(UNKNOWN_SUBSYSTEM);
+            let stats = subsystems.entry(subsystem).or_default();
+            stats.0 += pattern.count;
+            stats.1 += 1;
+        }
+
+        let mut subsystems: Vec<_> = subsystems
+            .into_iter()
+            .map(|(subsystem, (count, categories))| SubsystemReport {
+                subsystem: subsystem.to_string(),
+                count,
+                categories,
+            })
+            .collect();
+        subsystems.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.subsystem.cmp(&b.subsystem))
+        });
+        self.subsystems = subsystems;
+    }
+
+    /// Aggregate the pattern counts per source file, the most frequent files first.
+    pub fn group_by_file(&mut self) {
+        // (Repo, file) -> (matched lines, categories).
+        let mut files: BTreeMap<(&str, &str), (usize, usize)> = BTreeMap::new();
+        for pattern in &self.patterns {
+            let stats = files.entry((&pattern.repo, &pattern.file)).or_default();
+            stats.0 += pattern.count;
+            stats.1 += 1;
+        }
+
+        let mut files: Vec<_> = files
+            .into_iter()
+            .map(|((repo, file), (count, categories))| FileReport {
+                repo: repo.to_string(),
+                file: file.to_string(),
+                count,
+                categories,
+            })
+            .collect();
+        files.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| (&a.repo, &a.file).cmp(&(&b.repo, &b.file)))
+        });
+        self.files = files;
+    }
+
+    /// Aggregate the pattern counts per mentioned peer, the most mentioned peers first.
+    ///
+    /// Only the `MAX_PEERS` most mentioned peers are kept.
+    pub fn group_by_peer(&mut self) {
+        // Peer -> (matched lines, categories, (top pattern count, top pattern)).
+        let mut peers: BTreeMap<&str, (usize, usize, (usize, &str))> = BTreeMap::new();
+        for pattern in &self.patterns {
+            for (peer, count) in &pattern.peers {
+                let stats = peers.entry(peer).or_default();
+                stats.0 += count;
+                stats.1 += 1;
+                if *count > stats.2 .0 {
+                    stats.2 = (*count, &pattern.pattern);
+                }
+            }
+        }
+
+        let mut peers: Vec<_> = peers
+            .into_iter()
+            .map(|(peer, (count, categories, (_, top_pattern)))| PeerReport {
+                peer: peer.to_string(),
+                count,
+                categories,
+                top_pattern: top_pattern.to_string(),
+            })
+            .collect();
+        peers.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.peer.cmp(&b.peer)));
+        peers.truncate(MAX_PEERS);
+        self.peers = peers;
+    }
+
+    /// Flag the patterns whose count exceeds their threshold.
+    ///
+    /// The flagged patterns are listed in the needs attention section, regardless of the
+    /// displayed patterns.
+    pub fn highlight(&mut self, thresholds: &Thresholds) {
+        for pattern in &mut self.patterns {
+            pattern.exceeded = thresholds
+                .threshold(&pattern.pattern)
+                .filter(|threshold| pattern.count > *threshold);
+        }
+
+        self.attention = self
+            .patterns
+            .iter()
+            .filter_map(|pattern| {
+                Some(AttentionReport {
+                    pattern: pattern.pattern.clone(),
+                    level: pattern.level.clone(),
+                    count: pattern.count,
+                    threshold: pattern.exceeded?,
+                })
+            })
+            .collect();
+    }
+
+    /// Omit the patterns marked as known.
+    pub fn hide_known(&mut self) {
+        self.patterns.retain(|pattern| {
+            pattern
+                .annotation
+                .as_ref()
+                .map(|annotation| annotation.status != Status::Known)
+                .unwrap_or(true)
+        });
+    }
+
+    /// Detect the windows where the patterns spike over `factor` times their median count
+    /// per bucket of the provided interval.
+    ///
+    /// The buckets are shared by every pattern, like the histograms.
+    pub fn detect_spikes(&mut self, matches: &Matches, interval: chrono::Duration, factor: f64) {
+        let Some(buckets) = Buckets::new(
+            interval,
+            matches
+                .found_lines
+                .values()
+                .flat_map(|entry| entry.timeline.bounds()),
+        ) else {
+            return;
+        };
+
+        for ((pattern, details), entry) in &matches.found_lines {
+            let spikes = buckets.spikes(&buckets.count_timeline(&entry.timeline), factor);
+            if spikes.is_empty() {
+                continue;
+            }
+
+            if let Some(report) = self.pattern_mut(pattern, details) {
+                report.spikes = spikes;
+            }
+        }
+    }
+
+    /// Flag the patterns whose lines cluster around the session changes of the matches.
+    ///
+    /// Nothing is flagged when no session change was detected.
+    pub fn correlate_sessions(&mut self, matches: &Matches) {
+        let changes = SessionChanges::new(matches.session_changes.iter().copied());
+        let timelines = || matches.found_lines.values().map(|entry| &entry.timeline);
+        let first = timelines().filter_map(|timeline| timeline.first).min();
+        let last = timelines().filter_map(|timeline| timeline.last).max();
+        let Some(range) = first.zip(last) else {
+            return;
+        };
+
+        for ((pattern, details), entry) in &matches.found_lines {
+            let Some(share) = changes.clustered_share(&entry.timeline, range) else {
+                continue;
+            };
+            if let Some(report) = self.pattern_mut(pattern, details) {
+                report.session_share = Some(share);
+            }
+        }
+        self.session_changes = changes.times().to_vec();
+    }
+
+    /// The report of the matched pattern, unless it is not displayed.
+    fn pattern_mut(&mut self, pattern: &str, details: &RegexDetails) -> Option<&mut PatternReport> {
+        self.patterns.iter_mut().find(|report| {
+            report.pattern == pattern
+                && (&report.repo, &report.file, report.line, report.column)
+                    == (&details.repo, &details.file, details.line, details.column)
+        })
+    }
+
+    /// Compute the rates per hour of the patterns, over the time range of the run.
+    ///
+    /// The rates compare the patterns of runs over different time ranges.
+    pub fn rates(&mut self, range: chrono::Duration) {
+        let hours = range.num_seconds() as f64 / 3600.0;
+        if hours <= 0.0 {
+            return;
+        }
+
+        for pattern in &mut self.patterns {
+            pattern.rate = Some(pattern.count as f64 / hours);
+        }
+    }
+
+    /// Sort the patterns and drop the ones that are not displayed.
+    ///
+    /// The summary and the node breakdown still cover every pattern.
+    pub fn select(&mut self, sort: SortOrder, min_count: usize, top: Option<usize>) {
+        match sort {
+            // The patterns are constructed in count order.
+            SortOrder::Count => {}
+            SortOrder::Level => self
+                .patterns
+                .sort_by_key(|pattern| pattern.level != "error"),
+            SortOrder::FirstSeen => self
+                .patterns
+                .sort_by_key(|pattern| (pattern.first_seen.is_none(), pattern.first_seen)),
+            SortOrder::Rate => self.patterns.sort_by(|a, b| {
+                let rate = |pattern: &PatternReport| pattern.rate.unwrap_or(-1.0);
+                rate(b).total_cmp(&rate(a))
+            }),
+        }
+
+        self.patterns.retain(|pattern| pattern.count >= min_count);
+        if let Some(top) = top {
+            self.patterns.truncate(top);
+        }
+    }
+
+    /// Omit the unknown lines from the report, keeping only their number.
+    pub fn hide_unknown_lines(&mut self) {
+        self.unknown_lines.clear();
+    }
+
+    /// The number of lines of the suppressed patterns.
+    fn suppressed_lines(&self) -> usize {
+        self.suppressed
+            .iter()
+            .map(|suppressed| suppressed.count)
+            .sum()
+    }
+
+    /// Paint the cell with the color, when the report is colored.
+    fn paint(&self, color: Option<Color>, cell: String) -> String {
+        match color {
+            Some(color) => color.paint(&cell, self.color),
+            None => cell,
+        }
+    }
+
+    /// The columns grouping the counts of the patterns (ie per chain, per version).
+    fn groups(&self) -> Vec<Group> {
+        let groups: [Group; 2] = [
+            ("Chains", |pattern| &pattern.chains),
+            ("Versions", |pattern| &pattern.versions),
+        ];
+
+        groups
+            .into_iter()
+            .filter(|(_, group)| {
+                self.patterns
+                    .iter()
+                    .any(|pattern| !group(pattern).is_empty())
+            })
+            .collect()
+    }
+
+    /// Some nodes have a detected version.
+    fn has_versions(&self) -> bool {
+        self.nodes.iter().any(|node| node.version.is_some())
+    }
+
+    /// The patterns clustered around the session changes, the highest shares first.
+    fn clustered(&self) -> impl Iterator<Item = &PatternReport> {
+        let mut clustered: Vec<_> = self
+            .patterns
+            .iter()
+            .filter(|pattern| pattern.session_share.is_some())
+            .collect();
+        let share = |pattern: &PatternReport| pattern.session_share.unwrap_or_default();
+        clustered.sort_by(|a, b| share(b).total_cmp(&share(a)));
+        clustered.into_iter()
+    }
+
+    /// The patterns with spikes.
+    fn spiking(&self) -> impl Iterator<Item = &PatternReport> {
+        self.patterns
+            .iter()
+            .filter(|pattern| !pattern.spikes.is_empty())
+    }
+
+    /// Some patterns spiked over their median rate.
+    fn has_spikes(&self) -> bool {
+        self.spiking().next().is_some()
+    }
+
+    /// The spikes of the patterns, the highest factors first.
+    fn spikes(&self) -> Vec<(&PatternReport, &Spike)> {
+        let mut spikes: Vec<_> = self
+            .spiking()
+            .flat_map(|pattern| pattern.spikes.iter().map(move |spike| (pattern, spike)))
+            .collect();
+        spikes.sort_by(|(_, a), (_, b)| b.factor.total_cmp(&a.factor));
+        spikes
+    }
+
+    /// The rates of the patterns are computed.
+    fn has_rates(&self) -> bool {
+        self.patterns.iter().any(|pattern| pattern.rate.is_some())
+    }
+
+    /// Some patterns are mapped to a subsystem.
+    fn has_subsystems(&self) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.subsystem.is_some())
+    }
+
+    /// Some patterns have owners.
+    fn has_owners(&self) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| !pattern.owners.is_empty())
+    }
+
+    /// Some patterns have timestamped lines.
+    fn has_timestamps(&self) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.first_seen.is_some())
+    }
+}
+
+impl Render for TriageReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        let groups = self.groups();
+        let group_names: String = groups
+            .iter()
+            .map(|(name, _)| format!(" | {name}"))
+            .collect();
+        writeln!(out)?;
+        writeln!(out)?;
+        if let Some(metadata) = &self.metadata {
+            for (name, value) in metadata.fields() {
+                writeln!(out, "{name}: {value}")?;
+            }
+        }
+        for repo in &self.repos {
+            writeln!(out, "Regexes from {}", source(repo))?;
+        }
+        if self.metadata.is_some() || !self.repos.is_empty() {
+            writeln!(out)?;
+        }
+        if !self.expired.is_empty() {
+            let header = format!(
+                "EXPIRED SUPPRESSIONS: {} suppressions expired, their patterns are reported again",
+                self.expired.len()
+            );
+            writeln!(out, "{}", self.paint(Some(Color::Red), header))?;
+            writeln!(
+                out,
+                "{0: <10} | {1: <10} | {2: <40} | {3: <40} | Link",
+                "Expired", "Count", "Pattern", "Reason"
+            )?;
+            for expired in &self.expired {
+                let suppression = &expired.suppression;
+                writeln!(
+                    out,
+                    "{0: <10} | {1: <10} | {2: <40} | {3: <40} | {4}",
+                    suppression.expires,
+                    expired.count,
+                    suppression.pattern,
+                    suppression.reason,
+                    suppression.link
+                )?;
+            }
+            writeln!(out)?;
+        }
+        if !self.attention.is_empty() {
+            writeln!(
+                out,
+                "Needs attention: {} patterns above their threshold",
+                self.attention.len()
+            )?;
+            writeln!(
+                out,
+                "{0: <10} | {1: <10} | {2: <10} | Pattern",
+                "Count", "Threshold", "Level"
+            )?;
+            for attention in &self.attention {
+                writeln!(
+                    out,
+                    "{0: <10} | {1: <10} | {2: <10} | {3}",
+                    attention.count, attention.threshold, attention.level, attention.pattern
+                )?;
+            }
+            writeln!(out)?;
+        }
+        if self.has_spikes() {
+            writeln!(
+                out,
+                "Spikes: {} patterns spiked over their median rate",
+                self.spiking().count()
+            )?;
+            writeln!(
+                out,
+                "{0: <10} | {1: <35} | {2: <10} | {3: <10} | Pattern",
+                "Level", "Window", "Lines", "Factor"
+            )?;
+            for (pattern, spike) in self.spikes() {
+                writeln!(
+                    out,
+                    "{0: <10} | {1: <35} | {2: <10} | {3: <10} | {4}",
+                    pattern.level,
+                    window(spike),
+                    spike.count,
+                    format!("{:.1}x", spike.factor),
+                    pattern.pattern
+                )?;
+            }
+            writeln!(out)?;
+        }
+        if !self.session_changes.is_empty() {
+            writeln!(
+                out,
+                "Session changes: {} detected, {} patterns clustered around them",
+                self.session_changes.len(),
+                self.clustered().count()
+            )?;
+            if self.clustered().next().is_some() {
+                writeln!(
+                    out,
+                    "{0: <10} | {1: <10} | {2: <10} | Pattern",
+                    "Level", "Around", "Lines"
+                )?;
+            }
+            for pattern in self.clustered() {
+                writeln!(
+                    out,
+                    "{0: <10} | {1: <10} | {2: <10} | {3}",
+                    pattern.level,
+                    share(pattern),
+                    pattern.count,
+                    pattern.pattern
+                )?;
+            }
+            writeln!(out)?;
+        }
+        if let Some(buckets) = &self.histogram {
+            writeln!(
+                out,
+                "Histogram from {} with {}s buckets",
+                buckets.start, buckets.interval_secs
+            )?;
+            if !self.session_changes.is_empty() {
+                writeln!(
+                    out,
+                    "Session changes [{}]",
+                    buckets.markers(self.session_changes.iter())
+                )?;
+            }
+        }
+        // The columns are as wide as their content, instead of a fixed width fitting the
+        // longest patterns.
+        let patterns = || self.patterns.iter();
+        let repo = width("Repo", patterns().map(|pattern| &pattern.repo));
+        let count_width = width("Count", patterns().map(count));
+        let level = width("Level", patterns().map(|pattern| &pattern.level));
+        let target_width = width("Target", patterns().map(target));
+        let has_subsystems = self.has_subsystems();
+        let subsystem_width = width("Subsystem", patterns().map(subsystem));
+        let has_owners = self.has_owners();
+        let owners_width = width("Owners", patterns().map(owners));
+        let nodes = width(
+            "Nodes",
+            patterns().map(|pattern| pattern.nodes.len().to_string()),
+        );
+        let has_timestamps = self.has_timestamps();
+        let has_rates = self.has_rates();
+        let rate_width = width("Rate/h", patterns().map(rate));
+        let pattern_width = width("Triage report", patterns().map(|pattern| &pattern.pattern));
+        let header = format!(
+            "{0: <repo$} | {1: <count_width$}{rates}{2} | {3: <level$} | {8: <target_width$}{subsystems}{owners} | {4: <nodes$}{chains}{seen} | {5: <pattern_width$} | Source{6}{7} | Description",
+            "Repo",
+            "Count",
+            self.histogram
+                .as_ref()
+                .map(|buckets| format!(" | {: <1$}", "Histogram", buckets.len))
+                .unwrap_or_default(),
+            "Level",
+            "Nodes",
+            "Triage report",
+            if self.blame { " | Blame" } else { "" },
+            if self.known { " | Status" } else { "" },
+            "Target",
+            chains = group_names,
+            rates = if has_rates {
+                format!(" | {: <rate_width$}", "Rate/h")
+            } else {
+                String::new()
+            },
+            subsystems = if has_subsystems {
+                format!(" | {: <subsystem_width$}", "Subsystem")
+            } else {
+                String::new()
+            },
+            owners = if has_owners {
+                format!(" | {: <owners_width$}", "Owners")
+            } else {
+                String::new()
+            },
+            seen = if has_timestamps {
+                format!(" | {: <SEEN_WIDTH$} | {: <SEEN_WIDTH$}", "First seen", "Last seen")
+            } else {
+                String::new()
+            },
+        );
+        writeln!(out, "{}", self.paint(Some(Color::Bold), header))?;
+
+        for pattern in &self.patterns {
+            let mut attribution = if self.blame {
+                format!(" | {}", pattern.blame.as_deref().unwrap_or("-"))
+            } else {
+                String::new()
+            };
+            if self.known {
+                attribution.push_str(&format!(" | {}", annotation(pattern)));
+            }
+
+            let histogram = if self.histogram.is_some() {
+                format!(" | {}", histogram::sparkline(&pattern.histogram))
+            } else {
+                String::new()
+            };
+
+            let chains: String = groups
+                .iter()
+                .map(|(_, group)| format!(" | {}", breakdown(group(pattern))))
+                .collect();
+
+            let seen = if has_timestamps {
+                format!(
+                    " | {: <SEEN_WIDTH$} | {: <SEEN_WIDTH$}",
+                    seen(pattern.first_seen),
+                    seen(pattern.last_seen)
+                )
+            } else {
+                String::new()
+            };
+
+            let rates = if has_rates {
+                format!(" | {: <rate_width$}", rate(pattern))
+            } else {
+                String::new()
+            };
+
+            let subsystems = if has_subsystems {
+                format!(" | {: <subsystem_width$}", subsystem(pattern))
+            } else {
+                String::new()
+            };
+
+            let owners = if has_owners {
+                format!(" | {: <owners_width$}", owners(pattern))
+            } else {
+                String::new()
+            };
+
+            let color = level_color(pattern);
+            writeln!(
+                out,
+                "{0: <repo$} | {1: <count_width$}{rates}{2} | {3} | {9: <target_width$}{subsystems}{owners} | {4: <nodes$}{chains}{seen} | {5} | {6}{7} | {8}",
+                pattern.repo,
+                count(pattern),
+                histogram,
+                self.paint(color, format!("{: <level$}", pattern.level)),
+                pattern.nodes.len(),
+                self.paint(color, format!("{: <pattern_width$}", pattern.pattern)),
+                pattern.permalink,
+                attribution,
+                pattern.description.as_deref().unwrap_or("-"),
+                target(pattern),
+            )?;
+        }
+
+        if !self.suppressed.is_empty() {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "Suppressed: {} patterns, {} lines",
+                self.suppressed.len(),
+                self.suppressed_lines()
+            )?;
+            writeln!(
+                out,
+                "{0: <10} | {1: <10} | {2: <10} | {3: <40} | {4: <40} | Link",
+                "Count", "Level", "Expires", "Pattern", "Reason"
+            )?;
+            for suppressed in &self.suppressed {
+                let suppression = &suppressed.suppression;
+                writeln!(
+                    out,
+                    "{0: <10} | {1: <10} | {2: <10} | {3: <40} | {4: <40} | {5}",
+                    suppressed.count,
+                    suppressed.level,
+                    suppression.expires,
+                    suppressed.pattern,
+                    suppression.reason,
+                    suppression.link
+                )?;
+            }
+        }
+
+        if !self.targets.is_empty() {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{0: <40} | {1: <10} | {2: <10}",
+                "Target", "Count", "Categories"
+            )?;
+            for target in &self.targets {
+                writeln!(
+                    out,
+                    "{0: <40} | {1: <10} | {2: <10}",
+                    target.target, target.count, target.categories
+                )?;
+            }
+        }
+
+        if !self.subsystems.is_empty() {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{0: <40} | {1: <10} | {2: <10}",
+                "Subsystem", "Count", "Categories"
+            )?;
+            for subsystem in &self.subsystems {
+                writeln!(
+                    out,
+                    "{0: <40} | {1: <10} | {2: <10}",
+                    subsystem.subsystem, subsystem.count, subsystem.categories
+                )?;
+            }
+        }
+
+        if !self.files.is_empty() {
+            let repo = self
+                .files
+                .iter()
+                .map(|file| file.repo.len())
+                .max()
+                .unwrap_or_default()
+                .max("Repo".len());
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{0: <repo$} | {1: <80} | {2: <10} | {3: <10}",
+                "Repo", "File", "Count", "Categories"
+            )?;
+            for file in &self.files {
+                writeln!(
+                    out,
+                    "{0: <repo$} | {1: <80} | {2: <10} | {3: <10}",
+                    file.repo, file.file, file.count, file.categories
+                )?;
+            }
+        }
+
+        if !self.peers.is_empty() {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{0: <55} | {1: <10} | {2: <10} | Top pattern",
+                "Peer", "Count", "Categories"
+            )?;
+            for peer in &self.peers {
+                writeln!(
+                    out,
+                    "{0: <55} | {1: <10} | {2: <10} | {3}",
+                    peer.peer, peer.count, peer.categories, peer.top_pattern
+                )?;
+            }
+        }
+
+        if !self.nodes.is_empty() {
+            writeln!(out)?;
+            let has_versions = self.has_versions();
+            writeln!(
+                out,
+                "{0: <40} | {1: <10} | {2: <10} | {3: <10}{4}",
+                "Node",
+                "Count",
+                "Categories",
+                "Unknown",
+                if has_versions { " | Version" } else { "" }
+            )?;
+            for node in &self.nodes {
+                let version = if has_versions {
+                    format!(" | {}", node.version.as_deref().unwrap_or(version::UNKNOWN))
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    out,
+                    "{0: <40} | {1: <10} | {2: <10} | {3: <10}{4}",
+                    node.node, node.count, node.categories, node.unknown, version
+                )?;
+            }
+        }
+
+        if !self.first_errors.is_empty() {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{0: <40} | {1: <19} | {2: <12} | {3: <32} | Pattern",
+                "Node", "First error", "After", "Since"
+            )?;
+            for first in &self.first_errors {
+                writeln!(
+                    out,
+                    "{0: <40} | {1: <19} | {2: <12} | {3: <32} | {4}",
+                    first.node,
+                    seen(Some(first.error.time)),
+                    first.elapsed(),
+                    first.origin(SEEN_FORMAT),
+                    first.error.pattern
+                )?;
+            }
+        }
+
+        if let Some(coverage) = &self.coverage {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "Coverage: {} of {} regexes matched ({:.2}%), {} log statements skipped",
+                coverage.matched,
+                coverage.regexes,
+                coverage.matched_percent(),
+                coverage.skipped
+            )?;
+            for file in &coverage.files {
+                writeln!(out, "{} ({})", file.file, file.repo)?;
+                for log in &file.unmatched {
+                    writeln!(
+                        out,
+                        "  {0: <6} | unmatched | {1: <10} | {2} | {3}",
+                        log.line, log.level, log.pattern, log.permalink
+                    )?;
+                }
+                for log in &file.skipped {
+                    writeln!(
+                        out,
+                        "  {0: <6} | skipped   | {1} | {2}",
+                        log.line, log.reason, log.permalink
+                    )?;
+                }
+            }
+        }
+
+        writeln!(out, "\nTotals: {}", self.summary.level_totals())?;
+        if self.unknown_lines.is_empty() {
+            writeln!(out, "\nUnknown lines [num {}]", self.summary.unknown)?;
+        } else {
+            writeln!(
+                out,
+                "\nUnknown lines [num {}]: {:#?}",
+                self.summary.unknown, self.unknown_lines
+            )?;
+        }
+
+        if !self.unknown_clusters.is_empty() {
+            writeln!(out, "\nClosest patterns of the unknown lines:")?;
+            for cluster in &self.unknown_clusters {
+                writeln!(out, "{0: <10} | {1}", cluster.count, cluster.template)?;
+                if cluster.candidates.is_empty() {
+                    writeln!(out, "  no close pattern")?;
+                }
+                for candidate in &cluster.candidates {
+                    writeln!(
+                        out,
+                        "  {0:.2} | {1} | {2}",
+                        candidate.similarity, candidate.pattern, candidate.permalink
+                    )?;
+                }
+            }
+        }
+
+        if self.raw {
+            for pattern in &self.patterns {
+                let color = level_color(pattern);
+                writeln!(
+                    out,
+                    "{0:<10} | {1} | {2} | {3}",
+                    pattern.count,
+                    self.paint(color, format!("{: <10}", pattern.level)),
+                    self.paint(color, format!("{: <pattern_width$}", pattern.pattern)),
+                    pattern.permalink
+                )?;
+                for line in &pattern.lines {
+                    writeln!(out, "  - {}", line)?;
+                }
+                if pattern.lines.len() < pattern.count {
+                    writeln!(
+                        out,
+                        "  ({} of {} lines, {} dropped)",
+                        pattern.lines.len(),
+                        pattern.count,
+                        pattern.count - pattern.lines.len()
+                    )?;
+                }
+                writeln!(out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        let groups = self.groups();
+        let group_names: String = groups
+            .iter()
+            .map(|(name, _)| format!(" {name} |"))
+            .collect();
+        let has_rates = self.has_rates();
+        let has_timestamps = self.has_timestamps();
+        let has_subsystems = self.has_subsystems();
+        let has_owners = self.has_owners();
+        let group_separators = "---|".repeat(groups.len());
+
+        if let Some(metadata) = &self.metadata {
+            for (name, value) in metadata.fields() {
+                writeln!(out, "- **{name}**: {}", escape(&value))?;
+            }
+            writeln!(out)?;
+        }
+        for repo in &self.repos {
+            let commit = match &repo.commit {
+                Some(commit) => format!(
+                    " at [`{commit}`]({}/commit/{commit})",
+                    repo.url.trim_end_matches('/')
+                ),
+                None => String::new(),
+            };
+            writeln!(
+                out,
+                "Regexes from `{}` `{}`{commit}.",
+                repo.name, repo.branch
+            )?;
+            writeln!(out)?;
+        }
+        if !self.expired.is_empty() {
+            writeln!(
+                out,
+                "**Expired suppressions**: {} suppressions expired, their patterns are reported again.",
+                self.expired.len()
+            )?;
+            writeln!(out)?;
+            writeln!(out, "| Expired | Count | Pattern | Reason | Link |")?;
+            writeln!(out, "|---|---|---|---|---|")?;
+            for expired in &self.expired {
+                let suppression = &expired.suppression;
+                writeln!(
+                    out,
+                    "| {} | {} | `{}` | {} | {} |",
+                    suppression.expires,
+                    expired.count,
+                    escape(&suppression.pattern),
+                    escape(&suppression.reason),
+                    suppression.link
+                )?;
+            }
+            writeln!(out)?;
+        }
+        if !self.attention.is_empty() {
+            writeln!(
+                out,
+                "**Needs attention**: {} patterns above their threshold.",
+                self.attention.len()
+            )?;
+            writeln!(out)?;
+            writeln!(out, "| Count | Threshold | Level | Pattern |")?;
+            writeln!(out, "|---|---|---|---|")?;
+            for attention in &self.attention {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | `{}` |",
+                    attention.count,
+                    attention.threshold,
+                    attention.level,
+                    escape(&attention.pattern)
+                )?;
+            }
+            writeln!(out)?;
+        }
+        if self.has_spikes() {
+            writeln!(
+                out,
+                "**Spikes**: {} patterns spiked over their median rate.",
+                self.spiking().count()
+            )?;
+            writeln!(out)?;
+            writeln!(out, "| Level | Window | Lines | Factor | Pattern |")?;
+            writeln!(out, "|---|---|---|---|---|")?;
+            for (pattern, spike) in self.spikes() {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {:.1}x | `{}` |",
+                    pattern.level,
+                    window(spike),
+                    spike.count,
+                    spike.factor,
+                    escape(&pattern.pattern)
+                )?;
+            }
+            writeln!(out)?;
+        }
+        if !self.session_changes.is_empty() {
+            writeln!(
+                out,
+                "**Session changes**: {} detected, {} patterns clustered around them.",
+                self.session_changes.len(),
+                self.clustered().count()
+            )?;
+            writeln!(out)?;
+            if self.clustered().next().is_some() {
+                writeln!(out, "| Level | Around | Lines | Pattern |")?;
+                writeln!(out, "|---|---|---|---|")?;
+                for pattern in self.clustered() {
+                    writeln!(
+                        out,
+                        "| {} | {} | {} | `{}` |",
+                        pattern.level,
+                        share(pattern),
+                        pattern.count,
+                        escape(&pattern.pattern)
+                    )?;
+                }
+                writeln!(out)?;
+            }
+        }
+        if let Some(buckets) = &self.histogram {
+            writeln!(
+                out,
+                "Histogram from {} with {}s buckets.",
+                buckets.start, buckets.interval_secs
+            )?;
+            if !self.session_changes.is_empty() {
+                writeln!(out)?;
+                writeln!(
+                    out,
+                    "Session changes `{}`",
+                    buckets.markers(self.session_changes.iter())
+                )?;
+            }
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "| Repo | Count |{}{} Level | Target |{}{} Nodes |{chains}{} Pattern | Source |{}{} Description |",
+            if has_rates { " Rate/h |" } else { "" },
+            if self.histogram.is_some() {
+                " Histogram |"
+            } else {
+                ""
+            },
+            if has_subsystems { " Subsystem |" } else { "" },
+            if has_owners { " Owners |" } else { "" },
+            if has_timestamps {
+                " First seen | Last seen |"
+            } else {
+                ""
+            },
+            if self.blame { " Blame |" } else { "" },
+            if self.known { " Status |" } else { "" },
+            chains = group_names,
+        )?;
+        writeln!(
+            out,
+            "|---|---|{}{}---|---|{}{}---|{chains}{}---|---|{}{}---|",
+            if has_rates { "---|" } else { "" },
+            if self.histogram.is_some() { "---|" } else { "" },
+            if has_subsystems { "---|" } else { "" },
+            if has_owners { "---|" } else { "" },
+            if has_timestamps { "---|---|" } else { "" },
+            if self.blame { "---|" } else { "" },
+            if self.known { "---|" } else { "" },
+            chains = group_separators,
+        )?;
+        for pattern in &self.patterns {
+            let mut attribution = if self.blame {
+                format!(" {} |", escape(pattern.blame.as_deref().unwrap_or("-")))
+            } else {
+                String::new()
+            };
+            if self.known {
+                attribution.push_str(&format!(" {} |", escape(&annotation(pattern))));
+            }
+
+            let histogram = if self.histogram.is_some() {
+                format!(" `{}` |", histogram::sparkline(&pattern.histogram))
+            } else {
+                String::new()
+            };
+
+            let chains: String = groups
+                .iter()
+                .map(|(_, group)| format!(" {} |", breakdown(group(pattern))))
+                .collect();
+
+            let seen = if has_timestamps {
+                format!(
+                    " {} | {} |",
+                    seen(pattern.first_seen),
+                    seen(pattern.last_seen)
+                )
+            } else {
+                String::new()
+            };
+
+            let rates = if has_rates {
+                format!(" {} |", rate(pattern))
+            } else {
+                String::new()
+            };
+
+            let subsystems = if has_subsystems {
+                format!(" {} |", subsystem(pattern))
+            } else {
+                String::new()
+            };
+
+            let owners = if has_owners {
+                format!(" {} |", owners(pattern))
+            } else {
+                String::new()
+            };
+
+            writeln!(
+                out,
+                "| {} | {} |{rates}{} {} | {} |{subsystems}{owners} {} |{chains}{seen} `{}` | [{}:{}:{}]({}){} |{} {} |",
+                pattern.repo,
+                count(pattern),
+                histogram,
+                pattern.level,
+                pattern.target.as_deref().unwrap_or("-"),
+                pattern.nodes.len(),
+                escape(&pattern.pattern),
+                pattern.file,
+                pattern.line,
+                pattern.column,
+                pattern.permalink,
+                pattern
+                    .explore
+                    .as_ref()
+                    .map(|explore| format!(" [logs]({explore})"))
+                    .unwrap_or_default(),
+                attribution,
+                escape(pattern.description.as_deref().unwrap_or("-"))
+            )?;
+        }
+
+        if !self.nodes.is_empty() {
+            writeln!(out)?;
+            let has_versions = self.has_versions();
+            writeln!(
+                out,
+                "| Node | Count | Categories | Unknown |{}",
+                if has_versions { " Version |" } else { "" }
+            )?;
+            writeln!(
+                out,
+                "|---|---|---|---|{}",
+                if has_versions { "---|" } else { "" }
+            )?;
+            for node in &self.nodes {
+                let version = if has_versions {
+                    format!(" {} |", node.version.as_deref().unwrap_or(version::UNKNOWN))
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} |{}",
+                    node.node, node.count, node.categories, node.unknown, version
+                )?;
+            }
+        }
+
+        if !self.suppressed.is_empty() {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "**Suppressed**: {} patterns, {} lines.",
+                self.suppressed.len(),
+                self.suppressed_lines()
+            )?;
+            writeln!(out)?;
+            writeln!(out, "| Count | Level | Expires | Pattern | Reason | Link |")?;
+            writeln!(out, "|---|---|---|---|---|---|")?;
+            for suppressed in &self.suppressed {
+                let suppression = &suppressed.suppression;
+                writeln!(
+                    out,
+                    "| {} | {} | {} | `{}` | {} | {} |",
+                    suppressed.count,
+                    suppressed.level,
+                    suppression.expires,
+                    escape(&suppressed.pattern),
+                    escape(&suppression.reason),
+                    suppression.link
+                )?;
+            }
+        }
+
+        if !self.targets.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "| Target | Count | Categories |")?;
+            writeln!(out, "|---|---|---|")?;
+            for target in &self.targets {
+                writeln!(
+                    out,
+                    "| {} | {} | {} |",
+                    target.target, target.count, target.categories
+                )?;
+            }
+        }
+
+        if !self.subsystems.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "| Subsystem | Count | Categories |")?;
+            writeln!(out, "|---|---|---|")?;
+            for subsystem in &self.subsystems {
+                writeln!(
+                    out,
+                    "| {} | {} | {} |",
+                    subsystem.subsystem, subsystem.count, subsystem.categories
+                )?;
+            }
+        }
+
+        if !self.files.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "| Repo | File | Count | Categories |")?;
+            writeln!(out, "|---|---|---|---|")?;
+            for file in &self.files {
+                writeln!(
+                    out,
+                    "| {} | `{}` | {} | {} |",
+                    file.repo, file.file, file.count, file.categories
+                )?;
+            }
+        }
+
+        if !self.peers.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "| Peer | Count | Categories | Top pattern |")?;
+            writeln!(out, "|---|---|---|---|")?;
+            for peer in &self.peers {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | `{}` |",
+                    peer.peer,
+                    peer.count,
+                    peer.categories,
+                    escape(&peer.top_pattern)
+                )?;
+            }
+        }
+
+        if !self.first_errors.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "| Node | First error | After | Since | Pattern |")?;
+            writeln!(out, "|---|---|---|---|---|")?;
+            for first in &self.first_errors {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | [`{}`]({}) |",
+                    first.node,
+                    seen(Some(first.error.time)),
+                    first.elapsed(),
+                    first.origin(SEEN_FORMAT),
+                    escape(&first.error.pattern),
+                    first.error.permalink
+                )?;
+            }
+        }
+
+        if let Some(coverage) = &self.coverage {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "**Coverage**: {} of {} regexes matched ({:.2}%), {} log statements skipped.",
+                coverage.matched,
+                coverage.regexes,
+                coverage.matched_percent(),
+                coverage.skipped
+            )?;
+            writeln!(out)?;
+            for file in &coverage.files {
+                writeln!(out, "- `{}` ({})", file.file, file.repo)?;
+                for log in &file.unmatched {
+                    writeln!(
+                        out,
+                        "  - [L{}]({}) unmatched {}: `{}`",
+                        log.line,
+                        log.permalink,
+                        log.level,
+                        escape(&log.pattern)
+                    )?;
+                }
+                for log in &file.skipped {
+                    writeln!(
+                        out,
+                        "  - [L{}]({}) skipped: {}",
+                        log.line,
+                        log.permalink,
+                        escape(&log.reason)
+                    )?;
+                }
+            }
+        }
+
+        writeln!(out)?;
+        writeln!(out, "Totals: {}", self.summary.level_totals())?;
+        writeln!(out)?;
+        writeln!(out, "Unknown lines: {}", self.summary.unknown)?;
+        if !self.unknown_lines.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "```")?;
+            for line in &self.unknown_lines {
+                writeln!(out, "{}", line)?;
+            }
+            writeln!(out, "```")?;
+        }
+
+        if !self.unknown_clusters.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "Closest patterns of the unknown lines:")?;
+            writeln!(out)?;
+            for cluster in &self.unknown_clusters {
+                writeln!(out, "- {} x `{}`", cluster.count, escape(&cluster.template))?;
+                if cluster.candidates.is_empty() {
+                    writeln!(out, "  - no close pattern")?;
+                }
+                for candidate in &cluster.candidates {
+                    writeln!(
+                        out,
+                        "  - {:.2} [`{}`]({})",
+                        candidate.similarity,
+                        escape(&candidate.pattern),
+                        candidate.permalink
+                    )?;
+                }
+            }
+        }
+
+        if self.raw {
+            for pattern in &self.patterns {
+                writeln!(out)?;
+                writeln!(out, "### `{}` ({})", pattern.pattern, pattern.count)?;
+                writeln!(out)?;
+                writeln!(out, "```")?;
+                for line in &pattern.lines {
+                    writeln!(out, "{}", line)?;
+                }
+                writeln!(out, "```")?;
+                if pattern.lines.len() < pattern.count {
+                    writeln!(out)?;
+                    writeln!(
+                        out,
+                        "_{} of {} lines kept, {} dropped._",
+                        pattern.lines.len(),
+                        pattern.count,
+                        pattern.count - pattern.lines.len()
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of patterns listed by the intermediate reports.
+const PARTIAL_TOP: usize = 5;
+
+/// The intermediate report of a partial run, with the top patterns and the number of
+/// unknown lines of the provided matches.
+pub fn partial(matches: &[&Matches]) -> String {
+    let mut patterns: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    for ((pattern, details), entry) in matches.iter().flat_map(|matches| &matches.found_lines) {
+        *patterns
+            .entry((pattern.as_str(), details.ty.as_str()))
+            .or_default() += entry.count();
+    }
+    let mut patterns: Vec<_> = patterns.into_iter().collect();
+    patterns.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+
+    let total: usize = matches.iter().map(|matches| matches.total).sum();
+    let unknown: usize = matches.iter().map(|matches| matches.unknown).sum();
+    let mut out = format!(
+        "{total} lines, {} patterns, {unknown} unknown lines",
+        patterns.len()
+    );
+    for ((pattern, level), count) in patterns.into_iter().take(PARTIAL_TOP) {
+        let _ = write!(out, "\n  {count: <10} | {level: <10} | {pattern}");
+    }
+
+    out
+}
+
+/// The count of the pattern, flagged when it exceeds its threshold.
+fn count(pattern: &PatternReport) -> String {
+    match pattern.exceeded {
+        Some(_) => format!("{} {ATTENTION_FLAG}", pattern.count),
+        None => pattern.count.to_string(),
+    }
+}
+
+/// The time window of the spike (ie `2024-03-29 02:00 - 2024-03-29 02:20`).
+fn window(spike: &Spike) -> String {
+    format!(
+        "{} - {}",
+        spike.start.format("%Y-%m-%d %H:%M"),
+        spike.end.format("%Y-%m-%d %H:%M")
+    )
+}
+
+/// The share of the lines of the pattern around the session changes (ie `85%`).
+fn share(pattern: &PatternReport) -> String {
+    format!("{:.0}%", pattern.session_share.unwrap_or_default() * 100.0)
+}
+
+/// The rate per hour of the pattern, `-` if unknown.
+fn rate(pattern: &PatternReport) -> String {
+    pattern
+        .rate
+        .map(|rate| format!("{rate:.1}"))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// The timestamp of a matched line, `-` if unknown.
+fn seen(time: Option<chrono::NaiveDateTime>) -> String {
+    time.map(|time| time.format(SEEN_FORMAT).to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// The repository, branch and commit of the regexes (ie `polkadot-sdk master at 5f3c..`).
+fn source(repo: &Repo) -> String {
+    match &repo.commit {
+        Some(commit) => format!("{} {} at {commit}", repo.name, repo.branch),
+        None => format!("{} {}", repo.name, repo.branch),
+    }
+}
+
+/// The target of the pattern, `-` when it could not be resolved.
+fn target(pattern: &PatternReport) -> &str {
+    pattern.target.as_deref().unwrap_or("-")
+}
+
+/// The subsystem of the pattern, `-` when its source file is not mapped.
+fn subsystem(pattern: &PatternReport) -> &str {
+    pattern.subsystem.as_deref().unwrap_or("-")
+}
+
+/// The owners of the pattern, `-` when its source file has no owners.
+fn owners(pattern: &PatternReport) -> String {
+    if pattern.owners.is_empty() {
+        return "-".to_string();
+    }
+    pattern.owners.join(" ")
+}
+
+/// The color of the rows of the pattern, from its level.
+///
+/// The patterns reporting panics stand out from the other errors.
+fn level_color(pattern: &PatternReport) -> Option<Color> {
+    if pattern.panics > 0 {
+        return Some(Color::Magenta);
+    }
+
+    match pattern.level.as_str() {
+        "error" => Some(Color::Red),
+        "warn" => Some(Color::Yellow),
+        _ => None,
+    }
+}
+
+/// The width of a text column, fitting its header and its cells.
+fn width(header: &str, cells: impl Iterator<Item = impl AsRef<str>>) -> usize {
+    cells
+        .map(|cell| cell.as_ref().chars().count())
+        .fold(header.chars().count(), usize::max)
+}
+
+/// The annotation of the pattern, `new` when the pattern is not a known issue.
+fn annotation(pattern: &PatternReport) -> String {
+    pattern
+        .annotation
+        .as_ref()
+        .map(|annotation| annotation.to_string())
+        .unwrap_or_else(|| "new".to_string())
+}
+
+/// The number of matched lines per node version.
+///
+/// Lines of nodes without a detected version are counted as unknown.
+fn versions(matches: &Matches, entry: &Entry) -> BTreeMap<String, usize> {
+    let mut versions = BTreeMap::new();
+    if matches.versions.is_empty() {
+        return versions;
+    }
+
+    let mut attributed = 0;
+    for (node, count) in &entry.nodes {
+        let version = matches
+            .versions
+            .get(node)
+            .map(String::as_str)
+            .unwrap_or(version::UNKNOWN);
+        *versions.entry(version.to_string()).or_default() += count;
+        attributed += count;
+    }
+    if entry.count() > attributed {
+        *versions.entry(version::UNKNOWN.to_string()).or_default() += entry.count() - attributed;
+    }
+
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fetch_git::RegexDetails, matcher::Matcher, output::OutputFormat};
+
+    fn details(line: usize) -> RegexDetails {
+        RegexDetails {
+            repo: "extra".to_string(),
+            file: "extra.yaml".to_string(),
+            start: 0,
+            end: 0,
+            line,
+            column: 1,
+            permalink: String::new(),
+            description: None,
+            target: None,
+            owners: Vec::new(),
+            ty: "warn".to_string(),
+        }
+    }
+
+    /// Match the lines against the `Slow` and `Failed to dial` patterns.
+    fn matches(lines: &[&str]) -> Matches {
+        let matcher = Matcher::new(
+            vec![
+                (regex::Regex::new("Slow").unwrap(), details(1)),
+                (regex::Regex::new("Failed to dial").unwrap(), details(2)),
+            ],
+            Vec::new(),
+        );
+        let mut matches = Matches::default();
+        matcher.process_lines(lines.iter(), &mut matches);
+        matches
+    }
+
+    /// The timestamped line of the message, the provided seconds after 16:00.
+    fn line(seconds: i64, message: &str) -> String {
+        let time = "2024-03-29T16:00:00"
+            .parse::<chrono::NaiveDateTime>()
+            .unwrap()
+            + chrono::Duration::seconds(seconds);
+        format!(
+            "{}  WARN sync: {message}",
+            time.format("%Y-%m-%d %H:%M:%S%.3f")
+        )
+    }
+
+    fn report(matches: &Matches) -> TriageReport {
+        TriageReport::new(matches, None, false, None)
+    }
+
+    #[test]
+    fn test_partial() {
+        let first = matches(&["Slow", "Failed to dial", "Unknown"]);
+        let second = matches(&["Slow"]);
+
+        assert_eq!(
+            partial(&[&first, &second]),
+            "4 lines, 2 patterns, 1 unknown lines\n  2          | warn       | Slow\n  1          | warn       | Failed to dial"
+        );
+    }
+
+    #[test]
+    fn test_columns() {
+        // The columns of the text report fit their content.
+        let mut report = report(&matches(&["Slow", "Failed to dial"]));
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("\nRepo  | Count | Level | Target | Nodes | Triage report  |"));
+        assert!(text.contains("\nextra | 1     | warn  | -      | 0     | Failed to dial |"));
+        assert!(!text.contains('\u{1b}'));
+
+        report.color = true;
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("| \u{1b}[33mwarn \u{1b}[0m |"));
+    }
+
+    #[test]
+    fn test_rates() {
+        let mut report = report(&matches(&["Slow", "Failed to dial"]));
+        report.rates(chrono::Duration::minutes(30));
+        assert_eq!(report.patterns[0].rate, Some(2.0));
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("\nextra | 1     | 2.0    | warn  |"));
+    }
+
+    #[test]
+    fn test_group_by_subsystem() {
+        // The subsystem column is only provided for the mapped source files.
+        let mut report = report(&matches(&["Slow", "Failed to dial"]));
+        report.classify(&Subsystems::default());
+        assert!(!report.has_subsystems());
+        report.patterns[0].repo = "polkadot-sdk".to_string();
+        report.patterns[0].file = "polkadot/node/core/dispute-coordinator/src/lib.rs".to_string();
+        report.classify(&Subsystems::default());
+        report.group_by_subsystem();
+        let subsystems: Vec<_> = report
+            .subsystems
+            .iter()
+            .map(|subsystem| (subsystem.subsystem.as_str(), subsystem.count))
+            .collect();
+        assert_eq!(subsystems, vec![("disputes", 1), ("unknown", 1)]);
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("| Target | Subsystem | Nodes |"));
+        assert!(text.contains("| -      | disputes  | 0     |"));
+    }
+
+    #[test]
+    fn test_group_by_file() {
+        let mut report = report(&matches(&["Slow", "Failed to dial"]));
+        report.patterns[0].repo = "polkadot-sdk".to_string();
+        report.patterns[0].file = "polkadot/node/core/dispute-coordinator/src/lib.rs".to_string();
+        report.group_by_file();
+        let files: Vec<_> = report
+            .files
+            .iter()
+            .map(|file| (file.repo.as_str(), file.file.as_str(), file.count))
+            .collect();
+        assert_eq!(files.len(), 2);
+        assert!(files.contains(&(
+            "polkadot-sdk",
+            "polkadot/node/core/dispute-coordinator/src/lib.rs",
+            1
+        )));
+        let markdown = report.render(OutputFormat::Markdown).unwrap();
+        assert!(markdown.contains(
+            "| polkadot-sdk | `polkadot/node/core/dispute-coordinator/src/lib.rs` | 1 | 1 |"
+        ));
+    }
+
+    #[test]
+    fn test_seen() {
+        // The first-seen and last-seen columns are only provided for timestamped lines.
+        let text = report(&matches(&[
+            "2024-03-29 16:00:02.000  WARN sync: Slow",
+            "2024-03-29 14:32:00.000  WARN sync: Slow",
+        ]))
+        .render(OutputFormat::Text)
+        .unwrap();
+        assert!(text.contains("| 2024-03-29 14:32:00 | 2024-03-29 16:00:02 | Slow "));
+    }
+
+    #[test]
+    fn test_highlight() {
+        let mut report = report(&matches(&["Slow", "Slow", "Failed to dial"]));
+        report.highlight(&Thresholds::new(Some(1)));
+        assert_eq!(report.patterns[0].exceeded, Some(1));
+        assert_eq!(report.patterns[1].exceeded, None);
+        assert_eq!(report.attention.len(), 1);
+        assert_eq!(report.attention[0].pattern, "Slow");
+
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("Needs attention: 1 patterns above their threshold"));
+        assert!(text.contains(&format!("| 2 {ATTENTION_FLAG} ")));
+    }
+
+    #[test]
+    fn test_suppress() {
+        let dir = std::env::temp_dir().join(format!("report-suppress-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("suppressions.yaml");
+        std::fs::write(
+            &path,
+            r#"
+- pattern: "Failed to dial"
+  reason: "Rotated bootnodes"
+  link: https://github.com/paritytech/polkadot-sdk/issues/1234
+  expires: 2024-06-30
+- pattern: "Slow"
+  reason: "Known slow disks"
+  link: https://github.com/paritytech/polkadot-sdk/issues/5678
+  expires: 2024-03-01
+"#,
+        )
+        .unwrap();
+        let suppressions = Suppressions::load(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        // The patterns of the expired suppressions stay in the report.
+        let mut report = report(&matches(&[
+            "Slow",
+            "Slow",
+            "Failed to dial",
+            "Failed to dial",
+        ]));
+        report.suppress(&suppressions, NaiveDate::from_ymd_opt(2024, 3, 30).unwrap());
+        let patterns: Vec<_> = report.patterns.iter().map(|p| p.pattern.as_str()).collect();
+        assert_eq!(patterns, vec!["Slow"]);
+        assert_eq!(report.suppressed.len(), 1);
+        assert_eq!(report.suppressed[0].pattern, "Failed to dial");
+        assert_eq!(report.expired.len(), 1);
+        assert_eq!(report.expired[0].suppression.pattern, "Slow");
+        assert_eq!(report.expired[0].count, 2);
+
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("EXPIRED SUPPRESSIONS: 1 suppressions expired"));
+        assert!(text.contains("Suppressed: 1 patterns, 2 lines"));
+    }
+
+    #[test]
+    fn test_detect_spikes() {
+        // A line every 5 minutes, and a burst of 20 lines at 16:21.
+        let lines: Vec<_> = (0..10)
+            .map(|index| line(index * 300, "Slow"))
+            .chain((0..20).map(|_| line(21 * 60, "Slow")))
+            .chain([line(0, "Failed to dial")])
+            .collect();
+        let lines: Vec<_> = lines.iter().map(String::as_str).collect();
+        let matches = matches(&lines);
+
+        let mut report = report(&matches);
+        report.detect_spikes(&matches, chrono::Duration::minutes(5), 5.0);
+        let spikes = &report.patterns[0].spikes;
+        assert_eq!(spikes.len(), 1);
+        assert_eq!(spikes[0].count, 21);
+        assert_eq!(window(&spikes[0]), "2024-03-29 16:20 - 2024-03-29 16:25");
+        assert!(report.patterns[1].spikes.is_empty());
+
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("Spikes: 1 patterns spiked over their median rate"));
+        assert!(text.contains("| 2024-03-29 16:20 - 2024-03-29 16:25 "));
+    }
+
+    #[test]
+    fn test_correlate_sessions() {
+        // A burst around the session change at 17:00, and a line every 10 minutes.
+        let lines: Vec<_> = (0..10)
+            .map(|index| line(3600 + index, "Slow"))
+            .chain((0..12).map(|index| line(index * 600, "Failed to dial")))
+            .collect();
+        let lines: Vec<_> = lines.iter().map(String::as_str).collect();
+        let mut matches = matches(&lines);
+
+        // Nothing is flagged without session changes.
+        let mut report = report(&matches);
+        report.correlate_sessions(&matches);
+        assert!(report.session_changes.is_empty());
+        assert!(report.clustered().next().is_none());
+
+        matches.session_changes = vec!["2024-03-29T17:00:00".parse().unwrap()];
+        report.correlate_sessions(&matches);
+        assert_eq!(report.session_changes.len(), 1);
+        let clustered: Vec<_> = report
+            .clustered()
+            .map(|pattern| (pattern.pattern.as_str(), share(pattern)))
+            .collect();
+        assert_eq!(clustered, vec![("Slow", "100%".to_string())]);
+
+        let text = report.render(OutputFormat::Text).unwrap();
+        assert!(text.contains("Session changes: 1 detected, 1 patterns clustered around them"));
+    }
+}
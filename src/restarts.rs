@@ -0,0 +1,280 @@
+//! Detection of the node restarts from the banners printed at startup.
+//!
+//! Substrate nodes print a banner when starting, ie:
+//!
+//! ```text
+//! Parity Polkadot
+//! ✌️  version 1.10.0-7049c3c9883
+//! 📋 Chain specification: Versi
+//! ```
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use chrono::NaiveDateTime;
+
+use crate::{
+    collector::{self, Collector},
+    output::Render,
+    parse::LogLine,
+    version, Config, RestartsConfig,
+};
+
+/// Loki filter selecting the banner lines.
+const QUERY_FILTER: &str =
+    "|~ `Parity Polkadot|version [0-9]+\\.[0-9]+\\.[0-9]+|Chain specification:`";
+
+/// The banner lines of a node closer than this belong to the same start.
+const BANNER_WINDOW: chrono::Duration = chrono::Duration::seconds(60);
+
+/// The node of the lines without node information.
+const UNKNOWN_NODE: &str = "unknown";
+
+/// Check if the line is part of the banner printed by a starting node.
+pub fn is_start_banner(line: &str) -> bool {
+    line.contains("Parity Polkadot")
+        || line.contains("Chain specification:")
+        || version::extract_version(line).is_some()
+}
+
+/// The starts of the nodes, in chronological order per node.
+#[derive(Debug, Clone, Default)]
+pub struct Starts {
+    nodes: BTreeMap<String, Vec<NaiveDateTime>>,
+}
+
+impl Starts {
+    /// The first start of the node in the provided interval after the time, if any.
+    ///
+    /// The lines without node information are attributed to the same unknown node.
+    pub fn restart_after(
+        &self,
+        node: Option<&str>,
+        time: NaiveDateTime,
+        window: chrono::Duration,
+    ) -> Option<NaiveDateTime> {
+        self.nodes
+            .get(node.unwrap_or(UNKNOWN_NODE))?
+            .iter()
+            .copied()
+            .find(|start| *start >= time && *start - time <= window)
+    }
+}
+
+/// Collects the banner lines of the processed lines.
+#[derive(Default)]
+pub(crate) struct Banners {
+    /// The timestamps of the banner lines per node.
+    nodes: BTreeMap<String, Vec<NaiveDateTime>>,
+}
+
+impl Collector for Banners {
+    type Item<'a> = NaiveDateTime;
+    type Report = Starts;
+
+    const QUERY_FILTER: &'static str = QUERY_FILTER;
+
+    /// The banner lines without timestamp are ignored.
+    fn select(&self, line: &LogLine) -> Option<NaiveDateTime> {
+        if !is_start_banner(&line.message) {
+            return None;
+        }
+        let datetime = line.datetime();
+        if datetime.is_none() {
+            log::debug!("Skipping banner line without timestamp: {}", line.message);
+        }
+        datetime
+    }
+
+    fn record(&mut self, node: Option<&str>, _: Option<NaiveDateTime>, banner: NaiveDateTime) {
+        self.nodes
+            .entry(node.unwrap_or(UNKNOWN_NODE).to_string())
+            .or_default()
+            .push(banner);
+    }
+
+    /// Group the banner lines of every node into starts.
+    ///
+    /// Duplicate lines (ie from overlapping query chunks) fall in the same start.
+    fn report(self) -> Starts {
+        let nodes = self
+            .nodes
+            .into_iter()
+            .map(|(node, mut banners)| {
+                banners.sort();
+                let mut starts: Vec<NaiveDateTime> = Vec::new();
+                let mut last = None;
+                for banner in banners {
+                    if last.is_none_or(|last| banner - last > BANNER_WINDOW) {
+                        starts.push(banner);
+                    }
+                    last = Some(banner);
+                }
+                (node, starts)
+            })
+            .collect();
+
+        Starts { nodes }
+    }
+}
+
+/// Collect the starts of the nodes of the provided files or Loki query.
+pub(crate) fn collect(opts: &Config) -> Result<Starts, Box<dyn std::error::Error>> {
+    collector::run(opts, Banners::default())
+}
+
+/// The starts of a single node.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NodeRestarts {
+    /// The name of the node.
+    pub node: String,
+    /// The number of starts in the window.
+    pub restarts: usize,
+    /// The time of every start.
+    pub times: Vec<NaiveDateTime>,
+}
+
+/// The report of the node restarts.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct RestartsReport {
+    /// The number of starts of every node.
+    pub total: usize,
+    /// The starts of every node, the most restarted nodes first.
+    pub nodes: Vec<NodeRestarts>,
+}
+
+impl RestartsReport {
+    /// Build the report of the provided starts.
+    pub fn new(starts: Starts) -> Self {
+        let mut nodes: Vec<_> = starts
+            .nodes
+            .into_iter()
+            .map(|(node, times)| NodeRestarts {
+                node,
+                restarts: times.len(),
+                times,
+            })
+            .collect();
+        nodes.sort_by(|a, b| {
+            b.restarts
+                .cmp(&a.restarts)
+                .then_with(|| a.node.cmp(&b.node))
+        });
+
+        RestartsReport {
+            total: nodes.iter().map(|node| node.restarts).sum(),
+            nodes,
+        }
+    }
+}
+
+impl Render for RestartsReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Restarts: {} of {} nodes",
+            self.total,
+            self.nodes.len()
+        )?;
+        if self.nodes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(out, "{0: <40} | {1: <10} | Times", "Node", "Restarts")?;
+        for node in &self.nodes {
+            writeln!(
+                out,
+                "{0: <40} | {1: <10} | {2}",
+                node.node,
+                node.restarts,
+                times(&node.times)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Restarts: {} of {} nodes",
+            self.total,
+            self.nodes.len()
+        )?;
+        if self.nodes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(out, "| Node | Restarts | Times |")?;
+        writeln!(out, "|---|---|---|")?;
+        for node in &self.nodes {
+            writeln!(
+                out,
+                "| {} | {} | {} |",
+                node.node,
+                node.restarts,
+                times(&node.times)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The times of the starts (ie `2024-03-29 16:00:00, 2024-03-29 17:30:00`).
+fn times(times: &[NaiveDateTime]) -> String {
+    times
+        .iter()
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Report the restarts of the nodes of the provided files or Loki query.
+pub fn run(opts: RestartsConfig) -> Result<RestartsReport, Box<dyn std::error::Error>> {
+    log::info!("Running restarts query");
+    Ok(RestartsReport::new(collect(&opts.config)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::tests::collect;
+
+    #[test]
+    fn test_restarts() {
+        assert!(is_start_banner("✌️  version 1.10.0-7049c3c9883"));
+        assert!(!is_start_banner("Unsupported protocol version 2"));
+
+        let lines = "\
+2024-03-29 16:00:00.000  INFO main sc_cli::runner: Parity Polkadot
+2024-03-29 16:00:00.001  INFO main sc_cli::runner: ✌️  version 1.10.0-7049c3c9883
+2024-03-29 16:00:00.002  INFO main sc_cli::runner: 📋 Chain specification: Versi
+2024-03-29 16:10:00.000  INFO main sc_cli::runner: Parity Polkadot
+2024-03-29 16:10:00.002  INFO main sc_cli::runner: 📋 Chain specification: Versi
+2024-03-29 16:10:05.000  INFO tokio-runtime-worker substrate: 💤 Idle (8 peers)";
+        let starts = collect(Banners::default(), &[("alice", lines)]);
+        let time = |time| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(
+            starts.restart_after(
+                Some("alice"),
+                time("2024-03-29 16:09:30"),
+                chrono::Duration::minutes(1)
+            ),
+            Some(time("2024-03-29 16:10:00"))
+        );
+        assert_eq!(
+            starts.restart_after(
+                Some("alice"),
+                time("2024-03-29 16:05:00"),
+                chrono::Duration::minutes(1)
+            ),
+            None
+        );
+
+        let report = RestartsReport::new(starts);
+        assert_eq!(report.total, 2);
+        assert_eq!(report.nodes[0].node, "alice");
+    }
+}
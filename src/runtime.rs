@@ -0,0 +1,435 @@
+//! Analysis of the runtime failures, grouped by error kind and block.
+//!
+//! The runtime failures share none of the log statements of the regex repositories, such
+//! that they otherwise land in the unknown lines:
+//!
+//! - ``Execution aborted due to trap: wasm trap: wasm `unreachable` instruction executed``
+//! - `Execution failed: Runtime panicked: Storage root must match that calculated.`
+//! - `Bad input data provided to validate_transaction: Could not decode`
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    sync::OnceLock,
+};
+
+use regex::Regex;
+
+use crate::{
+    collector::{self, Collector},
+    normalize,
+    output::{breakdown, escape, Render},
+    parse::LogLine,
+    RuntimeConfig,
+};
+
+/// Loki filter selecting the runtime failures.
+const QUERY_FILTER: &str = "|~ `(?i)wasm trap|execution failed|bad input data provided to|storage root must match|storage root mismatch`";
+
+/// The markers of the runtime failures, lowercase, with the kind of their failures.
+///
+/// The first marker found in the line wins, such that the failures wrapped in a generic
+/// `Execution failed` error are reported by their cause.
+const MARKERS: [(&str, &str); 5] = [
+    ("storage root must match", "storage root mismatch"),
+    ("storage root mismatch", "storage root mismatch"),
+    ("bad input data provided to", "bad input data"),
+    ("wasm trap", "wasm trap"),
+    ("execution failed", "execution failed"),
+];
+
+/// The block of the failures whose block could not be extracted.
+const UNKNOWN_BLOCK: &str = "unknown";
+
+/// A runtime failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeFailure<'a> {
+    /// The kind of the failure (ie `wasm trap`).
+    pub kind: &'static str,
+    /// The number of the block, when printed (ie `#42`).
+    pub number: Option<u64>,
+    /// The hash of the block, possibly shortened (ie `0x5e7d…a1f2`).
+    pub hash: Option<&'a str>,
+    /// The error, from the marker of its kind to the end of the line.
+    pub error: &'a str,
+}
+
+impl RuntimeFailure<'_> {
+    /// The block of the failure, identified by its hash or number.
+    pub fn block(&self) -> String {
+        match (self.number, self.hash) {
+            (Some(number), Some(hash)) => format!("#{number} ({hash})"),
+            (None, Some(hash)) => hash.to_string(),
+            (Some(number), None) => format!("#{number}"),
+            (None, None) => UNKNOWN_BLOCK.to_string(),
+        }
+    }
+}
+
+/// Parse a runtime failure, `None` if the line does not report one.
+pub fn parse_runtime_failure(line: &str) -> Option<RuntimeFailure<'_>> {
+    static BLOCK: OnceLock<(Regex, Regex)> = OnceLock::new();
+
+    // The ASCII lowercase keeps the byte offsets of the line.
+    let lowercase = line.to_ascii_lowercase();
+    let (start, kind) = MARKERS
+        .iter()
+        .find_map(|(marker, kind)| lowercase.find(marker).map(|start| (start, *kind)))?;
+
+    let (numbers, hashes) = BLOCK.get_or_init(|| {
+        (
+            Regex::new(r"#(\d+)\b").expect("Regex is valid; qed"),
+            Regex::new(r"\b0x(?:[0-9a-fA-F]{4,}…[0-9a-fA-F]{4,}|[0-9a-fA-F]{16,})")
+                .expect("Regex is valid; qed"),
+        )
+    });
+
+    Some(RuntimeFailure {
+        kind,
+        number: numbers
+            .captures(line)
+            .and_then(|captures| captures[1].parse().ok()),
+        hash: hashes.find(line).map(|hash| hash.as_str()),
+        error: line[start..].trim(),
+    })
+}
+
+/// The runtime failures of a single kind.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct KindReport {
+    /// The kind of the failures.
+    pub kind: String,
+    /// The number of failures.
+    pub count: usize,
+    /// The number of distinct nodes that failed.
+    pub nodes: usize,
+    /// The number of distinct blocks that failed.
+    pub blocks: usize,
+}
+
+/// The runtime failures of a single normalized error.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ErrorReport {
+    /// The kind of the failures.
+    pub kind: String,
+    /// The error, normalized (see [`normalize::normalize`]).
+    pub error: String,
+    /// The number of failures.
+    pub count: usize,
+    /// The number of distinct nodes that failed.
+    pub nodes: usize,
+}
+
+/// The runtime failures of a single block.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlockReport {
+    /// The block, identified by its hash or number.
+    pub block: String,
+    /// The timestamp of the first failure, when it could be parsed.
+    pub first_seen: Option<chrono::NaiveDateTime>,
+    /// The number of failures.
+    pub count: usize,
+    /// The kinds of the failures.
+    pub kinds: BTreeSet<String>,
+    /// Number of failures per node.
+    pub nodes: BTreeMap<String, usize>,
+}
+
+/// The report of the runtime failures.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct RuntimeReport {
+    /// The number of failures.
+    pub total: usize,
+    /// The failures per kind, the most frequent kinds first.
+    pub kinds: Vec<KindReport>,
+    /// The failures per normalized error, the most frequent errors first.
+    pub errors: Vec<ErrorReport>,
+    /// The failures per block, in order of first failure.
+    pub blocks: Vec<BlockReport>,
+}
+
+impl Render for RuntimeReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Runtime failures: {} across {} blocks",
+            self.total,
+            self.blocks.len()
+        )?;
+        if self.total == 0 {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{0: <30} | {1: <10} | {2: <10} | Blocks",
+            "Kind", "Count", "Nodes"
+        )?;
+        for kind in &self.kinds {
+            writeln!(
+                out,
+                "{0: <30} | {1: <10} | {2: <10} | {3}",
+                kind.kind, kind.count, kind.nodes, kind.blocks
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{0: <30} | {1: <10} | {2: <10} | Error",
+            "Kind", "Count", "Nodes"
+        )?;
+        for error in &self.errors {
+            writeln!(
+                out,
+                "{0: <30} | {1: <10} | {2: <10} | {3}",
+                error.kind, error.count, error.nodes, error.error
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{0: <23} | {1: <30} | {2: <10} | {3: <40} | Kinds",
+            "First seen", "Block", "Count", "Nodes"
+        )?;
+        for block in &self.blocks {
+            writeln!(
+                out,
+                "{0: <23} | {1: <30} | {2: <10} | {3: <40} | {4}",
+                first_seen(block),
+                block.block,
+                block.count,
+                breakdown(&block.nodes),
+                block.kinds.iter().cloned().collect::<Vec<_>>().join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "Runtime failures: {} across {} blocks",
+            self.total,
+            self.blocks.len()
+        )?;
+        if self.total == 0 {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(out, "| Kind | Count | Nodes | Blocks |")?;
+        writeln!(out, "|---|---|---|---|")?;
+        for kind in &self.kinds {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                kind.kind, kind.count, kind.nodes, kind.blocks
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "| Kind | Count | Nodes | Error |")?;
+        writeln!(out, "|---|---|---|---|")?;
+        for error in &self.errors {
+            writeln!(
+                out,
+                "| {} | {} | {} | `{}` |",
+                error.kind,
+                error.count,
+                error.nodes,
+                escape(&error.error)
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "| First seen | Block | Count | Nodes | Kinds |")?;
+        writeln!(out, "|---|---|---|---|---|")?;
+        for block in &self.blocks {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} |",
+                first_seen(block),
+                block.block,
+                block.count,
+                breakdown(&block.nodes),
+                block.kinds.iter().cloned().collect::<Vec<_>>().join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The timestamp of the first failure of the block, `-` if unknown.
+fn first_seen(block: &BlockReport) -> String {
+    block
+        .first_seen
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Collects the runtime failures of the processed lines.
+#[derive(Default)]
+struct Failures {
+    total: usize,
+    /// Kind -> (failures, nodes, blocks).
+    kinds: BTreeMap<&'static str, (usize, BTreeSet<String>, BTreeSet<String>)>,
+    /// (Kind, normalized error) -> (failures, nodes).
+    errors: BTreeMap<(&'static str, String), (usize, BTreeSet<String>)>,
+    blocks: BTreeMap<String, BlockReport>,
+}
+
+impl Collector for Failures {
+    type Item<'a> = RuntimeFailure<'a>;
+    type Report = RuntimeReport;
+
+    const QUERY_FILTER: &'static str = QUERY_FILTER;
+
+    fn select<'a>(&self, line: &'a LogLine) -> Option<RuntimeFailure<'a>> {
+        parse_runtime_failure(&line.message)
+    }
+
+    fn record(
+        &mut self,
+        node: Option<&str>,
+        datetime: Option<chrono::NaiveDateTime>,
+        failure: RuntimeFailure,
+    ) {
+        self.total += 1;
+
+        let block = failure.block();
+        let counts = self.kinds.entry(failure.kind).or_default();
+        counts.0 += 1;
+        counts.2.insert(block.clone());
+        let error = self
+            .errors
+            .entry((
+                failure.kind,
+                normalize::normalize(failure.error).into_owned(),
+            ))
+            .or_default();
+        error.0 += 1;
+
+        let report = self
+            .blocks
+            .entry(block.clone())
+            .or_insert_with(|| BlockReport {
+                block,
+                first_seen: None,
+                count: 0,
+                kinds: BTreeSet::new(),
+                nodes: BTreeMap::new(),
+            });
+        report.count += 1;
+        report.kinds.insert(failure.kind.to_string());
+        if let Some(node) = node {
+            counts.1.insert(node.to_string());
+            error.1.insert(node.to_string());
+            *report.nodes.entry(node.to_string()).or_default() += 1;
+        }
+        if let Some(datetime) = datetime {
+            report.first_seen = Some(report.first_seen.map_or(datetime, |t| t.min(datetime)));
+        }
+    }
+
+    fn report(self) -> RuntimeReport {
+        let mut kinds: Vec<_> = self
+            .kinds
+            .into_iter()
+            .map(|(kind, (count, nodes, blocks))| KindReport {
+                kind: kind.to_string(),
+                count,
+                nodes: nodes.len(),
+                blocks: blocks.len(),
+            })
+            .collect();
+        kinds.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.kind.cmp(&b.kind)));
+
+        let mut errors: Vec<_> = self
+            .errors
+            .into_iter()
+            .map(|((kind, error), (count, nodes))| ErrorReport {
+                kind: kind.to_string(),
+                error,
+                count,
+                nodes: nodes.len(),
+            })
+            .collect();
+        errors.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| (&a.kind, &a.error).cmp(&(&b.kind, &b.error)))
+        });
+
+        // The blocks without timestamp come last.
+        let mut blocks: Vec<_> = self.blocks.into_values().collect();
+        blocks.sort_by_key(|block| (block.first_seen.is_none(), block.first_seen));
+
+        RuntimeReport {
+            total: self.total,
+            kinds,
+            errors,
+            blocks,
+        }
+    }
+}
+
+/// Report the runtime failures of the provided files or Loki query.
+pub fn run(opts: RuntimeConfig) -> Result<RuntimeReport, Box<dyn std::error::Error>> {
+    log::info!("Running runtime failures query");
+    collector::run(&opts.config, Failures::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collector::tests::collect, output::OutputFormat};
+
+    #[test]
+    fn test_runtime_failures() {
+        assert_eq!(
+            parse_runtime_failure(
+                "Block prepare storage changes error: Error at calling runtime api: Execution failed: Execution aborted due to trap: wasm trap: wasm `unreachable` instruction executed"
+            ),
+            Some(RuntimeFailure {
+                kind: "wasm trap",
+                number: None,
+                hash: None,
+                error: "wasm trap: wasm `unreachable` instruction executed",
+            })
+        );
+        assert_eq!(
+            parse_runtime_failure(
+                "💔 Error importing block #42 (0x5e7d…a1f2): Execution failed: Runtime panicked: Storage root must match that calculated."
+            )
+            .map(|failure| (failure.kind, failure.block())),
+            Some(("storage root mismatch", "#42 (0x5e7d…a1f2)".to_string()))
+        );
+        assert_eq!(parse_runtime_failure("Imported #42 (0x5e7d…a1f2)"), None);
+
+        let lines = "\
+2024-03-29 16:00:02.000  WARN tokio-runtime-worker txpool: Bad input data provided to validate_transaction: Could not decode call at 12
+2024-03-29 16:00:01.000  WARN tokio-runtime-worker txpool: Bad input data provided to validate_transaction: Could not decode call at 34
+2024-03-29 16:00:03.000 ERROR tokio-runtime-worker sync: Execution failed: Other: Block #43 (0xaaaa…1111) rejected
+2024-03-29 16:00:03.000  INFO tokio-runtime-worker sync: 🏆 Imported #42 (0xaaaa…1111)";
+        let report = collect(Failures::default(), &[("alice", lines)]);
+        assert_eq!(report.total, 3);
+        assert_eq!(report.kinds[0].kind, "bad input data");
+        assert_eq!(report.kinds[0].count, 2);
+        assert_eq!(report.kinds[0].blocks, 1);
+        assert_eq!(
+            report.errors[0].error,
+            "Bad input data provided to validate_transaction: Could not decode call at <num>"
+        );
+        assert_eq!(report.errors[0].count, 2);
+        assert_eq!(report.blocks[0].block, UNKNOWN_BLOCK);
+        assert_eq!(report.blocks[1].block, "#43 (0xaaaa…1111)");
+
+        let markdown = report.render(OutputFormat::Markdown).unwrap();
+        assert!(markdown.contains("| bad input data | 2 | 1 | 1 |"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Bounded samples of the lines matched by a pattern.
+//!
+//! Patterns can match tens of thousands of lines, storing them all is wasteful when
+//! only a few examples are printed. The samples keep the first and last N lines,
+//! and a uniform random sample of N lines in between (reservoir sampling). Without
+//! sampling, only the first lines are kept up to a maximum, the others being counted.
+
+use std::collections::VecDeque;
+
+/// The default maximum number of lines kept per pattern when not sampled.
+pub const DEFAULT_MAX_LINES: usize = 10_000;
+
+/// Seed of the sampling, such that the same input produces the same report.
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// The sampled lines of a pattern.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Samples {
+    /// The number of lines of every sample, all the lines are kept if not provided.
+    limit: Option<usize>,
+    /// The maximum number of lines kept without limit, every line if not provided.
+    max_lines: Option<usize>,
+    /// The number of pushed lines.
+    pushed: usize,
+    /// The first lines, or all the lines without limit.
+    first: Vec<String>,
+    /// The reservoir of the lines between the first and last ones, with their position.
+    middle: Vec<(usize, String)>,
+    /// The last lines, with their position.
+    last: VecDeque<(usize, String)>,
+    /// The state of the xorshift generator of the reservoir.
+    state: u64,
+}
+
+impl Default for Samples {
+    fn default() -> Self {
+        Samples::new(None)
+    }
+}
+
+impl Samples {
+    /// Keep the first, last and N random lines, or all the lines without limit.
+    pub fn new(limit: Option<usize>) -> Self {
+        Samples {
+            limit,
+            max_lines: None,
+            pushed: 0,
+            first: Vec::new(),
+            middle: Vec::new(),
+            last: VecDeque::new(),
+            state: SEED,
+        }
+    }
+
+    /// Only keep the first N lines when not sampling, the others being dropped.
+    ///
+    /// Default: every line.
+    pub fn max_lines(mut self, max_lines: Option<usize>) -> Self {
+        self.max_lines = max_lines;
+        self
+    }
+
+    /// Record a new line.
+    pub fn push(&mut self, line: String) {
+        let position = self.pushed;
+        self.pushed += 1;
+
+        let Some(limit) = self.limit else {
+            if self.max_lines.is_none_or(|max| self.first.len() < max) {
+                self.first.push(line);
+            }
+            return;
+        };
+
+        if self.first.len() < limit {
+            self.first.push(line);
+            return;
+        }
+
+        self.last.push_back((position, line));
+        if self.last.len() <= limit {
+            return;
+        }
+
+        // The oldest of the last lines moves to the middle of the pattern.
+        let Some((position, line)) = self.last.pop_front() else {
+            return;
+        };
+        let seen = position - limit + 1;
+        if self.middle.len() < limit {
+            self.middle.push((position, line));
+        } else {
+            let index = (self.next_random() % seen as u64) as usize;
+            if index < limit {
+                self.middle[index] = (position, line);
+            }
+        }
+    }
+
+    /// Merge the samples of another entry into this one.
+    ///
+    /// The sampled lines of the other entry are recorded after the lines of this one,
+    /// the samples of merged entries are therefore an approximation.
+    pub fn merge(&mut self, other: Samples) {
+        let dropped = other.dropped();
+        for line in other.lines() {
+            self.push(line);
+        }
+        self.pushed += dropped;
+    }
+
+    /// The number of recorded lines not kept.
+    pub fn dropped(&self) -> usize {
+        self.pushed - self.first.len() - self.middle.len() - self.last.len()
+    }
+
+    /// The sampled lines, in the order they were recorded.
+    pub fn lines(&self) -> Vec<String> {
+        let mut middle = self.middle.clone();
+        middle.sort_by_key(|(position, _)| *position);
+
+        self.first
+            .iter()
+            .cloned()
+            .chain(middle.into_iter().map(|(_, line)| line))
+            .chain(self.last.iter().map(|(_, line)| line.clone()))
+            .collect()
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples() {
+        let mut samples = Samples::new(Some(2));
+        for line in 0..1000 {
+            samples.push(line.to_string());
+        }
+
+        let lines: Vec<usize> = samples
+            .lines()
+            .iter()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(&lines[..2], &[0, 1]);
+        assert_eq!(&lines[4..], &[998, 999]);
+        assert!(lines[2] > 1 && lines[2] < lines[3] && lines[3] < 998);
+
+        assert_eq!(samples.dropped(), 994);
+
+        let mut samples = Samples::new(None);
+        for line in 0..1000 {
+            samples.push(line.to_string());
+        }
+        assert_eq!(samples.lines().len(), 1000);
+        assert_eq!(samples.dropped(), 0);
+
+        // Only the first lines are kept up to the maximum, across merges.
+        let mut capped = Samples::new(None).max_lines(Some(10));
+        let mut other = capped.clone();
+        for line in 0..8 {
+            capped.push(line.to_string());
+            other.push(line.to_string());
+        }
+        capped.merge(other);
+        assert_eq!(capped.lines().len(), 10);
+        assert_eq!(capped.dropped(), 6);
+    }
+}
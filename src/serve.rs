@@ -0,0 +1,365 @@
+//! Scheduled triage runs, whose latest report is exposed over HTTP.
+//!
+//! Dashboards and bots poll the results instead of invoking the CLI. The triage runs every
+//! `--interval` over the last interval of logs, and a small HTTP/1.1 server answers:
+//!
+//! - `/report.json`: the JSON report of the latest successful run.
+//! - `/healthz`: `200 OK` when the latest run succeeded.
+//! - `/metrics`: the counters of the runs and of the latest report, in the Prometheus format.
+
+use std::{fmt::Write, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+};
+
+use crate::{
+    output::{OutputFormat, Render},
+    session::RegexCache,
+    summary::Summary,
+    ServeConfig, TriageSession,
+};
+
+/// The maximum size of the head of a request.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// The connections whose request head is not received within this duration are closed.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// The prefix of the exported metrics.
+const METRICS_PREFIX: &str = "sub_triage_logs";
+
+/// The state of the scheduled runs, shared with the HTTP server.
+#[derive(Debug, Default)]
+pub struct State {
+    /// The JSON report of the latest successful run.
+    report: Option<String>,
+    /// The summary of the latest successful run.
+    summary: Option<Summary>,
+    /// The number of completed runs.
+    runs: u64,
+    /// The number of failed runs.
+    failures: u64,
+    /// The error of the latest run, if it failed.
+    last_error: Option<String>,
+    /// The UNIX timestamp of the latest successful run.
+    last_success: Option<i64>,
+    /// The duration of the latest run in seconds.
+    last_duration: f64,
+}
+
+impl State {
+    /// Record a successful run.
+    fn succeeded(&mut self, report: String, summary: Summary, duration: f64) {
+        self.report = Some(report);
+        self.summary = Some(summary);
+        self.runs += 1;
+        self.last_error = None;
+        self.last_success = Some(chrono::Utc::now().timestamp());
+        self.last_duration = duration;
+    }
+
+    /// Record a failed run, the report of the previous run is still served.
+    fn failed(&mut self, error: String, duration: f64) {
+        self.runs += 1;
+        self.failures += 1;
+        self.last_error = Some(error);
+        self.last_duration = duration;
+    }
+
+    /// The metrics of the runs, in the Prometheus text format.
+    fn metrics(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, ty: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {METRICS_PREFIX}_{name} {help}");
+            let _ = writeln!(out, "# TYPE {METRICS_PREFIX}_{name} {ty}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{METRICS_PREFIX}_{name}{labels} {value}");
+            }
+        };
+        let sample = |value: String| vec![(String::new(), value)];
+
+        metric(
+            "runs_total",
+            "counter",
+            "Number of completed triage runs.",
+            &sample(self.runs.to_string()),
+        );
+        metric(
+            "run_failures_total",
+            "counter",
+            "Number of failed triage runs.",
+            &sample(self.failures.to_string()),
+        );
+        metric(
+            "last_run_duration_seconds",
+            "gauge",
+            "Duration of the latest triage run.",
+            &sample(format!("{:.3}", self.last_duration)),
+        );
+        if let Some(last_success) = self.last_success {
+            metric(
+                "last_success_timestamp_seconds",
+                "gauge",
+                "UNIX timestamp of the latest successful triage run.",
+                &sample(last_success.to_string()),
+            );
+        }
+
+        let Some(summary) = &self.summary else {
+            return out;
+        };
+        metric(
+            "categories",
+            "gauge",
+            "Number of matched patterns of the latest report.",
+            &sample(summary.categories.to_string()),
+        );
+        metric(
+            "new_categories",
+            "gauge",
+            "Number of matched patterns of the latest report that are not known issues.",
+            &sample(summary.new.to_string()),
+        );
+        metric(
+            "unknown_lines",
+            "gauge",
+            "Number of unmatched lines of the latest report.",
+            &sample(summary.unknown.to_string()),
+        );
+        metric(
+            "panics",
+            "gauge",
+            "Number of panics of the latest report.",
+            &sample(summary.panics.to_string()),
+        );
+        let levels: Vec<_> = summary
+            .levels
+            .iter()
+            .map(|(level, count)| (format!("{{level=\"{level}\"}}"), count.to_string()))
+            .collect();
+        metric(
+            "matched_lines",
+            "gauge",
+            "Number of matched lines of the latest report, per level.",
+            &levels,
+        );
+
+        out
+    }
+
+    /// The response to a GET request of the provided path.
+    fn respond(&self, path: &str) -> Response {
+        // The query string is not used.
+        let path = path.split('?').next().unwrap_or(path);
+        match path {
+            "/report.json" => match &self.report {
+                Some(report) => Response::new(200, "application/json", report.clone()),
+                None => Response::new(503, "text/plain", "No completed run yet\n".to_string()),
+            },
+            "/healthz" => match (&self.last_error, &self.report) {
+                (Some(error), _) => Response::new(503, "text/plain", format!("{error}\n")),
+                (None, Some(_)) => Response::new(200, "text/plain", "ok\n".to_string()),
+                (None, None) => {
+                    Response::new(503, "text/plain", "No completed run yet\n".to_string())
+                }
+            },
+            "/metrics" => Response::new(200, "text/plain; version=0.0.4", self.metrics()),
+            _ => Response::new(404, "text/plain", "Not found\n".to_string()),
+        }
+    }
+}
+
+/// A response of the HTTP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &'static str, body: String) -> Self {
+        Response {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    /// The response serialized as HTTP/1.1, the connection is closed after it.
+    fn to_http(&self) -> String {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Service Unavailable",
+        };
+        format!(
+            "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )
+    }
+}
+
+/// Read the head of the request, until its end or [`MAX_REQUEST_SIZE`].
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    Ok(request)
+}
+
+/// Answer the request of the connection.
+///
+/// The slow clients are disconnected after [`REQUEST_TIMEOUT`], such that they do not hold
+/// their connection forever.
+async fn handle(mut stream: TcpStream, state: Arc<RwLock<State>>) -> std::io::Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "Request timed out"))??;
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => state.read().await.respond(path),
+        (Some(_), Some(_)) => Response::new(405, "text/plain", "Method not allowed\n".to_string()),
+        _ => Response::new(400, "text/plain", "Bad request\n".to_string()),
+    };
+
+    stream.write_all(response.to_http().as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serve the state on the listener, until the process exits.
+async fn serve(listener: TcpListener, state: Arc<RwLock<State>>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                log::warn!("Failed to accept a connection: {err}");
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, state).await {
+                log::debug!("Failed to answer {peer}: {err}");
+            }
+        });
+    }
+}
+
+/// Run the triage every `--interval`, and serve the latest report on `--listen`.
+///
+/// Without `--start-time` and `--last-day`, every run queries the last interval of logs.
+pub async fn run(opts: ServeConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(&opts.listen)
+        .await
+        .map_err(|err| format!("Cannot listen on {}: {err}", opts.listen))?;
+    log::info!("Serving the triage reports on http://{}", opts.listen);
+
+    let state = Arc::new(RwLock::new(State::default()));
+    tokio::spawn(serve(listener, state.clone()));
+
+    let interval = opts
+        .config
+        .run_interval()
+        .to_std()
+        .map_err(|err| format!("Invalid interval: {err}"))?;
+    let regex_cache = RegexCache::default();
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+
+        let session =
+            TriageSession::new(opts.config.repeated_run()).regex_cache(regex_cache.clone());
+        let now = std::time::Instant::now();
+        // The matching is CPU bound, the run does not block the workers of the server.
+        let handle = tokio::runtime::Handle::current();
+        let result = tokio::task::spawn_blocking(move || {
+            handle
+                .block_on(async {
+                    let report = session.run().await?;
+                    let json = report.render(OutputFormat::Json)?;
+                    Ok::<_, Box<dyn std::error::Error>>((json, report.summary))
+                })
+                .map_err(|err| err.to_string())
+        })
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|result| result);
+        let duration = now.elapsed().as_secs_f64();
+
+        match result {
+            Ok((json, summary)) => {
+                log::info!("Triage run completed in {duration:.1}s: {summary}");
+                state.write().await.succeeded(json, summary, duration);
+            }
+            Err(err) => {
+                log::error!("Triage run failed: {err}");
+                state.write().await.failed(err, duration);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_respond() {
+        let mut state = State::default();
+        assert_eq!(state.respond("/healthz").status, 503);
+        assert_eq!(state.respond("/report.json").status, 503);
+        assert_eq!(state.respond("/unknown").status, 404);
+
+        let summary = Summary {
+            categories: 2,
+            levels: [("error".to_string(), 3), ("warn".to_string(), 5)].into(),
+            ..Default::default()
+        };
+        state.succeeded("{}".to_string(), summary, 1.5);
+        assert_eq!(state.respond("/healthz").status, 200);
+        assert_eq!(
+            state.respond("/report.json?pretty"),
+            Response::new(200, "application/json", "{}".to_string())
+        );
+        let metrics = state.respond("/metrics").body;
+        assert!(metrics.contains("sub_triage_logs_runs_total 1\n"));
+        assert!(metrics.contains("sub_triage_logs_matched_lines{level=\"error\"} 3\n"));
+
+        // The report of the previous run is still served after a failure.
+        state.failed("Loki is unreachable".to_string(), 0.5);
+        assert_eq!(state.respond("/healthz").status, 503);
+        assert_eq!(state.respond("/report.json").status, 200);
+        assert!(state
+            .respond("/metrics")
+            .body
+            .contains("sub_triage_logs_run_failures_total 1\n"));
+
+        let http = Response::new(200, "text/plain", "ok\n".to_string()).to_http();
+        assert!(http.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(http.ends_with("Content-Length: 3\r\nConnection: close\r\n\r\nok\n"));
+    }
+}
@@ -0,0 +1,1144 @@
+//! Triage sessions matching the warnings and errors against the log statements.
+
+use std::{
+    borrow::Cow,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use crate::{
+    blame, cache, checkpoint, coverage, dump, epoch, fetch_git, filter, first_error, github,
+    grafana, histogram, history, input, known, level, matcher, metadata::Metadata, parse, progress,
+    query, record::RecordReader, report, report::TriageReport, stats::Stats, subsystem, suggest,
+    suppress, threshold, version, Config,
+};
+
+/// Triage the warnings and errors of a node or chain.
+///
+/// The session fetches the log statements of the source repositories, matches the
+/// log lines (from files or Loki) against them and returns the report.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let options = sub_triage_logs::Config {
+///     file: Some("node.log".into()),
+///     ..Default::default()
+/// };
+/// let report = sub_triage_logs::TriageSession::new(options).run().await?;
+/// println!("{}", report.summary);
+/// # Ok(())
+/// # }
+/// ```
+pub struct TriageSession {
+    opts: Config,
+    query_filter: String,
+    regex_cache: Option<RegexCache>,
+}
+
+impl TriageSession {
+    /// Construct a new session from the provided options.
+    pub fn new(opts: Config) -> Self {
+        TriageSession {
+            opts,
+            query_filter: String::new(),
+            regex_cache: None,
+        }
+    }
+
+    /// Set the LogQL filter of the queries (ie ``|~ "litep2p"``), appended to the selected
+    /// levels. The local files and the command sources are not filtered.
+    ///
+    /// Default: empty.
+    pub fn query_filter(mut self, query_filter: String) -> Self {
+        self.query_filter = query_filter;
+        self
+    }
+
+    /// Reuse the regexes built by the previous sessions of the cache (ie the previous runs
+    /// of `--watch`), instead of fetching and extracting them again.
+    ///
+    /// Default: the regexes are built by the session.
+    pub(crate) fn regex_cache(mut self, regex_cache: RegexCache) -> Self {
+        self.regex_cache = Some(regex_cache);
+        self
+    }
+
+    /// Run the triage and build the report.
+    ///
+    /// The report options (ie sorting, known issues) are already applied.
+    pub async fn run(self) -> Result<TriageReport, Box<dyn std::error::Error>> {
+        Triage::new(self.opts, &self.query_filter, self.regex_cache)
+            .await?
+            .run()
+            .await
+    }
+}
+
+/// The regexes of the repeated runs are rebuilt after this duration.
+const REGEX_REFRESH: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// The regexes shared by the repeated runs of `--watch` and `serve`.
+///
+/// The regexes are built by the first run, and rebuilt once older than [`REGEX_REFRESH`],
+/// such that the runs follow the regex branch without fetching it every interval.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RegexCache {
+    built: Arc<tokio::sync::Mutex<Option<(std::time::Instant, fetch_git::Extraction)>>>,
+}
+
+impl RegexCache {
+    /// The cached regexes, built if missing or outdated.
+    async fn get(
+        &self,
+        opts: Config,
+        stats: &mut Stats,
+    ) -> Result<fetch_git::Extraction, Box<dyn std::error::Error>> {
+        let mut built = self.built.lock().await;
+        match &*built {
+            Some((at, extraction)) if at.elapsed() < REGEX_REFRESH => return Ok(extraction.clone()),
+            Some(_) => log::info!("Rebuilding the regexes"),
+            None => {}
+        }
+
+        let extraction = Triage::build_regexes(opts, stats).await?;
+        *built = Some((std::time::Instant::now(), extraction.clone()));
+        Ok(extraction)
+    }
+}
+
+enum QueryType {
+    /// The triage is running for a provided file, or the standard input for `-`.
+    File(String),
+    /// The triage is running for multiple files processed in parallel.
+    Files(Vec<PathBuf>),
+    /// The triage is running for the queries of every chain against the log source.
+    Grafana(Vec<ChainQueries>),
+    /// The triage is running for the output of a single command (ie `kubectl logs`).
+    Command(String),
+}
+
+/// The chunked queries of a chain.
+#[derive(Clone)]
+struct ChainQueries {
+    /// The queried chain.
+    chain: String,
+    /// The queries of the warnings and errors.
+    queries: Vec<String>,
+    /// The queries of the node versions, only built when grouping by version or reporting
+    /// the time to the first errors.
+    versions: Vec<String>,
+    /// The queries of the session changes, only built when detecting them.
+    sessions: Vec<String>,
+}
+
+struct Triage {
+    /// Statistics about processing lines.
+    stats: Stats,
+
+    /// The results of matching the lines.
+    matches: matcher::Matches,
+
+    /// Matches the lines against the regexes, shared with the worker tasks.
+    matcher: Arc<matcher::Matcher>,
+
+    /// The query type.
+    query_type: QueryType,
+
+    /// The maximum number of files processed in parallel.
+    jobs: usize,
+
+    /// Strip the ANSI escape sequences of the local files.
+    strip_ansi: bool,
+
+    /// The triaged log levels.
+    levels: level::Levels,
+
+    /// Provide the raw lines from the query.
+    raw: bool,
+
+    /// The interval of the per-pattern histograms.
+    histogram: Option<chrono::Duration>,
+
+    /// The order of the patterns in the report.
+    sort: report::SortOrder,
+
+    /// Only report the first N patterns.
+    top: Option<usize>,
+
+    /// Only report the patterns matched at least N times.
+    min_count: usize,
+
+    /// Print the unknown lines.
+    show_unknown: bool,
+
+    /// Writes the matched and unknown lines to the files of a directory.
+    dump: Option<dump::Dump>,
+
+    /// Links the patterns to their lines in Grafana Explore.
+    explore: Option<grafana::Explore>,
+
+    /// The repositories of the regexes, with their resolved commit.
+    repos: Vec<fetch_git::Repo>,
+
+    /// The provenance of the report.
+    metadata: Metadata,
+
+    /// When the run started, for the runtime of the report.
+    started: std::time::Instant,
+
+    /// The log statements of the run, only provided for coverage reports.
+    coverage: Option<coverage::Coverage>,
+
+    /// Suggests the closest patterns of the unknown lines.
+    suggester: Option<suggest::Suggester>,
+    /// Report the time to the first error of every node.
+    time_to_first_error: bool,
+    /// The start of the queried window, if provided.
+    start_time: Option<chrono::NaiveDateTime>,
+
+    /// Color the text report.
+    color: bool,
+
+    /// The queried time range, for the rates per hour of the patterns.
+    range: Option<chrono::Duration>,
+
+    /// Flags the patterns spiking over this factor of their median count.
+    spike_factor: Option<f64>,
+
+    /// Aggregate the pattern counts in an extra table.
+    group_by: Option<report::GroupBy>,
+
+    /// Classifies the patterns in subsystems.
+    subsystems: subsystem::Subsystems,
+
+    /// The annotations of the known issues.
+    known_issues: Option<known::KnownIssues>,
+
+    /// Hides the accepted patterns until their expiry date.
+    suppressions: Option<suppress::Suppressions>,
+
+    /// Flags the patterns exceeding their threshold.
+    thresholds: Option<threshold::Thresholds>,
+
+    /// Hide the patterns marked as known.
+    hide_known: bool,
+
+    /// Excludes the ignored patterns, applied after matching.
+    filter: filter::PatternFilter,
+
+    /// Attribute the matched log statements with git blame.
+    blame: Option<blame::Blame>,
+
+    /// The persistent query cache.
+    cache: Option<cache::QueryCache>,
+
+    /// Runs the queries against Loki.
+    runner: query::QueryRunner,
+
+    /// Converts the output of the queries to lines.
+    source: Box<dyn query::LogSource>,
+
+    /// How the progress is reported.
+    progress: progress::ProgressMode,
+
+    /// Print an intermediate report after every query chunk.
+    incremental: bool,
+
+    /// Persists the completed chunks of the run.
+    checkpoint: Option<checkpoint::CheckpointFile>,
+
+    /// The checkpoint of the interrupted run being resumed, whose chunks are skipped.
+    resumed: Option<checkpoint::Checkpoint>,
+
+    /// Pushes annotations of the error spikes.
+    grafana: Option<grafana::Grafana>,
+
+    /// Records the pattern counts of the run, with the context of the run.
+    history: Option<(history::HistoryStore, history::RunInfo)>,
+}
+
+impl Triage {
+    fn build_query(
+        opts: Config,
+        source: &dyn query::LogSource,
+        query_filter: &str,
+    ) -> Result<QueryType, Box<dyn std::error::Error>> {
+        if let Some(files) = opts.input_files()? {
+            return Ok(QueryType::Files(files));
+        }
+
+        if let Some(file) = Config::input_file(opts.file.clone()) {
+            let path = PathBuf::from(&file);
+            if path.is_dir() {
+                return Ok(QueryType::Files(input::list_dir(&path)?));
+            }
+
+            Ok(QueryType::File(file))
+        } else if let Some(query) = opts.command_query()? {
+            if !query_filter.is_empty() {
+                log::warn!(
+                    "The {:?} source reads every line, the filter is ignored",
+                    opts.source
+                );
+            }
+            Ok(QueryType::Command(query))
+        } else {
+            let queries = opts
+                .chain
+                .iter()
+                .map(|chain| {
+                    Ok(ChainQueries {
+                        chain: chain.clone(),
+                        queries: source.queries(
+                            chain,
+                            &query::Selection {
+                                levels: level::Levels::new(opts.levels.clone()).query_levels(),
+                                filter: query_filter.to_string(),
+                                ..Default::default()
+                            },
+                        )?,
+                        // The startup lines also provide the restarts of the nodes.
+                        versions: if opts.by_version || opts.time_to_first_error {
+                            source.queries(
+                                chain,
+                                &query::Selection {
+                                    levels: vec!["INFO".to_string()],
+                                    exclude_common_errors: false,
+                                    filter: version::QUERY_FILTER.to_string(),
+                                },
+                            )?
+                        } else {
+                            Vec::new()
+                        },
+                        sessions: if opts.session_changes {
+                            source.queries(
+                                chain,
+                                &query::Selection {
+                                    levels: vec!["INFO".to_string()],
+                                    exclude_common_errors: false,
+                                    filter: epoch::QUERY_FILTER.to_string(),
+                                },
+                            )?
+                        } else {
+                            Vec::new()
+                        },
+                    })
+                })
+                .collect::<Result<_, query::QueryError>>()?;
+
+            Ok(QueryType::Grafana(queries))
+        }
+    }
+
+    async fn build_regexes(
+        opts: Config,
+        stats: &mut Stats,
+    ) -> Result<fetch_git::Extraction, Box<dyn std::error::Error>> {
+        // Loaded first, such that an invalid file is reported before fetching the repositories.
+        let extra_regexes = match &opts.extra_regex_file {
+            Some(path) => fetch_git::load_extra_regexes(path)?,
+            None => Vec::new(),
+        };
+        if opts.skip_regex_build {
+            return Ok(fetch_git::Extraction {
+                regexes: extra_regexes,
+                ..Default::default()
+            });
+        }
+
+        let fetching = std::time::Instant::now();
+        let cache = opts.tarball_cache();
+        let github = github::GitHub::new(opts.github_token.clone());
+        let filter = fetch_git::PathFilter {
+            include: opts.regex_include_path.clone(),
+            exclude: opts.regex_exclude_path.clone(),
+        };
+        let repo = fetch_git::Repo::new(
+            "polkadot-sdk".into(),
+            opts.regex_repo.ok_or("Missing regex repo")?,
+            opts.regex_branch.ok_or("Missing regex branch")?,
+        );
+        let sources = fetch_git::fetch(
+            repo.url.clone(),
+            repo.branch.clone(),
+            &github,
+            &filter,
+            cache.as_ref(),
+        )
+        .await?;
+        let repo = repo.with_commit(sources.commit);
+
+        let litep2p = fetch_git::Repo::new(
+            "litep2p".into(),
+            "https://github.com/paritytech/litep2p/".into(),
+            "master".into(),
+        );
+        let litep2p_sources = fetch_git::fetch(
+            litep2p.url.clone(),
+            litep2p.branch.clone(),
+            &github,
+            &fetch_git::PathFilter::default(),
+            cache.as_ref(),
+        )
+        .await?;
+        let litep2p = litep2p.with_commit(litep2p_sources.commit);
+        stats.phase("fetch", fetching);
+
+        let extracting = std::time::Instant::now();
+        let mut extraction = fetch_git::extract_regexes(sources.files, &repo, &opts.log_macros);
+        let litep2p = fetch_git::extract_regexes(litep2p_sources.files, &litep2p, &opts.log_macros);
+        stats.phase("extract", extracting);
+        extraction.repos.extend(litep2p.repos);
+        extraction.regexes.extend(litep2p.regexes);
+        extraction.skipped.extend(litep2p.skipped);
+        extraction.regexes.extend(extra_regexes);
+
+        Ok(extraction)
+    }
+
+    async fn new(
+        mut opts: Config,
+        query_filter: &str,
+        regex_cache: Option<RegexCache>,
+    ) -> Result<Triage, Box<dyn std::error::Error>> {
+        log::info!("Running WarnErr query");
+        let started = std::time::Instant::now();
+        let (checkpoint, resumed) = Self::checkpoint(&mut opts, query_filter)?;
+        let mut metadata = Metadata::new(&opts)?;
+        // The command sources read every line, only the queries exclude the lines.
+        let excludes = matches!(
+            opts.source,
+            query::Source::Loki | query::Source::Elasticsearch
+        );
+        if opts.show_exclusions && opts.is_query() && excludes {
+            metadata.exclusions = opts.excluded_lines()?;
+        }
+
+        // The samples are printed like the raw lines.
+        let raw = opts.raw || opts.samples.is_some();
+        let samples = opts.samples;
+        let max_lines = (opts.max_lines > 0).then_some(opts.max_lines);
+        let histogram = opts.histogram;
+        let (sort, top, min_count, show_unknown) =
+            (opts.sort, opts.top, opts.min_count, opts.show_unknown);
+        let hide_known = opts.hide_known;
+        let dump = opts
+            .dump_dir
+            .clone()
+            .map(|dir| dump::Dump::new(dir, opts.dump_compress));
+        let explore = opts.explore()?;
+        let color = opts.color();
+        let spike_factor = opts.spike_factor;
+        // The queried time range, including the default one (ie `--last-day`).
+        let window = if opts.is_query() {
+            Some(query::time_window(
+                &opts.start_time,
+                &opts.end_time,
+                opts.last_day,
+            )?)
+        } else {
+            None
+        };
+        let range = window.map(|(start, end)| end - start);
+        let group_by = opts.group_by;
+        let filter = filter::PatternFilter::new(
+            &opts.ignore_pattern,
+            opts.ignore_file.as_deref(),
+            &opts.only_pattern,
+        )?;
+        let known_issues = opts
+            .known_issues
+            .as_deref()
+            .map(known::KnownIssues::load)
+            .transpose()?;
+        let subsystems = match &opts.subsystem_file {
+            Some(path) => subsystem::Subsystems::load(path)?,
+            None => subsystem::Subsystems::default(),
+        };
+        let suppressions = opts
+            .suppressions
+            .as_deref()
+            .map(suppress::Suppressions::load)
+            .transpose()?;
+        let thresholds = match (opts.highlight_threshold, &opts.threshold_file) {
+            (None, None) => None,
+            (default, None) => Some(threshold::Thresholds::new(default)),
+            (default, Some(path)) => Some(threshold::Thresholds::new(default).load(path)?),
+        };
+        let blame = if opts.blame.is_empty() {
+            None
+        } else {
+            Some(blame::Blame::new(&opts.blame)?)
+        };
+        let jobs = opts.jobs.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|jobs| jobs.get())
+                .unwrap_or(1)
+        });
+        let cache = opts.open_cache()?;
+        let runner = opts.query_runner();
+        let progress = opts.progress.unwrap_or_else(progress::ProgressMode::detect);
+        let incremental = opts.incremental;
+        let grafana = opts.grafana();
+        let history = match &opts.store {
+            // The dry runs do not record their empty counts.
+            Some(url) if !opts.dry_run => {
+                let run = history::RunInfo {
+                    start_time: opts.start_time.clone(),
+                    end_time: opts.end_time.clone(),
+                    chain: opts.is_query().then(|| opts.chain.join(",")),
+                };
+                Some((history::HistoryStore::open(url)?, run))
+            }
+            _ => None,
+        };
+        let node_prefix = opts.node_prefix()?;
+        let by_version = opts.by_version;
+        let strip_ansi = !opts.keep_ansi;
+        let levels = level::Levels::new(opts.levels.clone());
+        let source = opts.log_source()?;
+        let query_type = Self::build_query(opts.clone(), source.as_ref(), query_filter)?;
+        // Overlapping query chunks (and retries) can return the same lines twice.
+        let drop_duplicates = matches!(query_type, QueryType::Grafana(_));
+        let coverage_report = opts.coverage_report;
+        let time_to_first_error = opts.time_to_first_error;
+        let session_changes = opts.session_changes;
+        // The queries measure from the start of the window, even without `--start-time`.
+        let start_time = window.map(|(start, _)| start).or_else(|| {
+            opts.start_time
+                .as_deref()
+                .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.naive_utc())
+        });
+        let suggest_patterns = opts.suggest_patterns;
+        let mut stats = Stats::new();
+        let fetch_git::Extraction {
+            repos,
+            regexes,
+            skipped,
+        } = match regex_cache {
+            Some(regex_cache) => regex_cache.get(opts, &mut stats).await?,
+            None => Self::build_regexes(opts, &mut stats).await?,
+        };
+        metadata.patterns = regexes.len();
+        let coverage = coverage_report.then(|| coverage::Coverage::new(&regexes, skipped));
+        let suggester = suggest_patterns.then(|| suggest::Suggester::new(&regexes));
+
+        // Hardcoded currently for peerset.
+        let dedup_info = vec![
+            // Litep2p peerset.
+            matcher::DeduplicationInfo {
+                log_line: "banned, disconnecting, reason:".to_string(),
+                dedup_after: "banned, disconnecting, reason:".to_string(),
+            },
+            // Libp2p peerset (old backend)
+            matcher::DeduplicationInfo {
+                log_line: "Banned, disconnecting.".to_string(),
+                dedup_after: "Reason:".to_string(),
+            },
+            // Error importing block deduplication.
+            matcher::DeduplicationInfo {
+                log_line: "Error importing block".to_string(),
+                dedup_after: ":".to_string(),
+            },
+        ];
+
+        Ok(Triage {
+            stats,
+            matches: Default::default(),
+            matcher: Arc::new(
+                matcher::Matcher::new(regexes, dedup_info)
+                    .with_node_prefix(node_prefix)
+                    .with_versions(by_version)
+                    .with_first_errors(time_to_first_error)
+                    .with_session_changes(session_changes)
+                    .with_samples(samples)
+                    .with_max_lines(max_lines)
+                    // The suggestions are computed from the unknown lines.
+                    .with_lines(
+                        raw || dump.is_some(),
+                        show_unknown || suggest_patterns || dump.is_some(),
+                    )
+                    .with_drop_duplicates(drop_duplicates)
+                    .with_peers(group_by == Some(report::GroupBy::Peer)),
+            ),
+            query_type,
+            jobs: jobs.max(1),
+            strip_ansi,
+            levels,
+            raw,
+            histogram,
+            sort,
+            top,
+            min_count,
+            show_unknown,
+            dump,
+            explore,
+            repos,
+            metadata,
+            started,
+            coverage,
+            suggester,
+            time_to_first_error,
+            start_time,
+            color,
+            range,
+            spike_factor,
+            group_by,
+            subsystems,
+            known_issues,
+            suppressions,
+            thresholds,
+            hide_known,
+            filter,
+            blame,
+            cache,
+            runner,
+            source,
+            progress,
+            incremental,
+            checkpoint,
+            resumed,
+            grafana,
+            history,
+        })
+    }
+
+    async fn run(&mut self) -> Result<TriageReport, Box<dyn std::error::Error>> {
+        match &self.query_type {
+            QueryType::File(file) => {
+                let reader = input::reader(file)?;
+                process_stream(
+                    &self.matcher,
+                    &self.levels,
+                    None,
+                    reader,
+                    self.strip_ansi,
+                    &mut self.matches,
+                )?;
+            }
+            QueryType::Files(files) => {
+                let progress = progress::Progress::new(self.progress, "files", files.len());
+                let matches = Self::process_files(
+                    self.matcher.clone(),
+                    &self.levels,
+                    files,
+                    self.jobs,
+                    self.strip_ansi,
+                    progress,
+                )
+                .await?;
+                self.matches.merge(matches);
+            }
+            QueryType::Command(query) => {
+                let query = query.clone();
+                let mut progress = progress::Progress::new(self.progress, "chunks", 1);
+                // The output of the commands covers the whole time range, it is streamed
+                // instead of being cached.
+                let mut stream = self.runner.stream(&query)?;
+                while let Some(mut attempt) = stream.next_attempt()? {
+                    // Every level is read from the command.
+                    let mut matches = matcher::Matches::default();
+                    process_stream(
+                        &self.matcher,
+                        &self.levels,
+                        None,
+                        &mut attempt,
+                        false,
+                        &mut matches,
+                    )?;
+                    // The lines of the failed attempts are discarded.
+                    if stream.end(attempt)? {
+                        self.matches.merge(matches);
+                    }
+                }
+                progress.inc(self.matches.total, self.matches.matched());
+                progress.finish();
+            }
+            QueryType::Grafana(chains) => {
+                let chains = chains.clone();
+                let total = chains
+                    .iter()
+                    .map(|chain| chain.queries.len() + chain.versions.len() + chain.sessions.len())
+                    .sum();
+                let mut progress = progress::Progress::new(self.progress, "chunks", total);
+                let (chunks, mut completed) = (total, 0);
+                let mut checkpoint = self.resumed.take().unwrap_or_default();
+
+                let multiple_chains = chains.len() > 1;
+                for chain in chains {
+                    log::info!("Querying chain {}", chain.chain);
+                    let mut matches = checkpoint.take_chain(&chain.chain);
+
+                    for query in chain.versions {
+                        completed += 1;
+                        if checkpoint.is_completed(&query) {
+                            progress.inc(0, 0);
+                            continue;
+                        }
+
+                        let mut chunk = Self::begin_chunk(&mut matches);
+                        let bytes = self.fetch(&query)?;
+                        let result = String::from_utf8_lossy(&bytes);
+                        self.matcher
+                            .detect_versions(None, result.lines(), &mut chunk);
+                        progress.inc(result.lines().count(), 0);
+                        self.end_chunk(&chain.chain, &query, &mut matches, chunk);
+                    }
+
+                    for query in chain.sessions {
+                        completed += 1;
+                        if checkpoint.is_completed(&query) {
+                            progress.inc(0, 0);
+                            continue;
+                        }
+
+                        let mut chunk = Self::begin_chunk(&mut matches);
+                        let bytes = self.fetch(&query)?;
+                        let result = String::from_utf8_lossy(&bytes);
+                        self.matcher
+                            .detect_session_changes(result.lines(), &mut chunk);
+                        progress.inc(result.lines().count(), 0);
+                        self.end_chunk(&chain.chain, &query, &mut matches, chunk);
+                    }
+
+                    // Run the queries.
+                    for query in chain.queries {
+                        completed += 1;
+                        if checkpoint.is_completed(&query) {
+                            progress.inc(0, 0);
+                            continue;
+                        }
+
+                        let mut chunk = Self::begin_chunk(&mut matches);
+                        self.process_query(&query, &mut chunk)?;
+                        progress.inc(chunk.total, chunk.matched());
+                        self.end_chunk(&chain.chain, &query, &mut matches, chunk);
+
+                        if self.incremental {
+                            progress.println(&format!(
+                                "Partial report of {} after {completed}/{chunks} chunks: {}",
+                                chain.chain,
+                                report::partial(&[&self.matches, &matches])
+                            ));
+                        }
+                    }
+
+                    if multiple_chains {
+                        matches.tag_chain(&chain.chain);
+                    }
+                    self.matches.merge(matches);
+                }
+                progress.finish();
+
+                if let Some(file) = &self.checkpoint {
+                    file.remove()?;
+                }
+            }
+        }
+
+        self.stats.total = self.matches.total;
+        self.stats.empty_lines = self.matches.empty_lines;
+        self.stats.unknown = self.matches.unknown;
+        self.stats.duplicates = self.matches.duplicates;
+
+        if !self.filter.is_empty() {
+            let filter = &self.filter;
+            let patterns = self.matches.found_lines.len();
+            self.matches
+                .found_lines
+                .retain(|(pattern, _), _| filter.keep(pattern));
+            log::info!(
+                "Filtered out {} patterns",
+                patterns - self.matches.found_lines.len()
+            );
+        }
+
+        if let Some(dump) = &self.dump {
+            dump.write(&self.matches)?;
+        }
+        if let Some(grafana) = &self.grafana {
+            grafana.annotate(self.spikes()).await;
+        }
+
+        self.report()
+    }
+
+    /// Annotate the spikes of every pattern, tagged with the chains of the lines.
+    fn spikes(&self) -> Vec<grafana::Annotation> {
+        let interval = self.histogram.unwrap_or(grafana::DEFAULT_SPIKE_INTERVAL);
+        let factor = self.spike_factor.unwrap_or(histogram::DEFAULT_SPIKE_FACTOR);
+
+        self.matches
+            .found_lines
+            .iter()
+            .flat_map(|((pattern, details), entry)| {
+                grafana::Annotation::spikes(pattern, &details.ty, &entry.timeline, interval, factor)
+                    .into_iter()
+                    .map(|annotation| {
+                        entry
+                            .chains
+                            .keys()
+                            .fold(annotation, |annotation, chain| annotation.tag(chain))
+                    })
+            })
+            .collect()
+    }
+
+    /// The checkpoint file of the run, with the checkpoint of the interrupted run when
+    /// resuming.
+    ///
+    /// The time range of the checkpointed runs is resolved once, such that the queries of
+    /// the relative time ranges (ie `--last-day`) are the same when the run is resumed: the
+    /// time range of the interrupted run is queried again.
+    fn checkpoint(
+        opts: &mut Config,
+        query_filter: &str,
+    ) -> Result<
+        (
+            Option<checkpoint::CheckpointFile>,
+            Option<checkpoint::Checkpoint>,
+        ),
+        Box<dyn std::error::Error>,
+    > {
+        let Some(file) = opts.checkpoint_file(query_filter) else {
+            return Ok((None, None));
+        };
+
+        let resumed = if opts.resume { file.load()? } else { None };
+        let (start_time, end_time) =
+            match resumed.as_ref().and_then(|resumed| resumed.range.clone()) {
+                Some(range) => range,
+                None => query::time_range(&opts.start_time, &opts.end_time, opts.last_day)?,
+            };
+        match &resumed {
+            Some(resumed) => log::info!(
+                "Resuming the run of {start_time} - {end_time}, skipping {} completed chunks",
+                resumed.completed.len()
+            ),
+            None if opts.resume => {
+                log::warn!("No checkpoint of the run to resume, running every chunk")
+            }
+            None => {}
+        }
+        opts.start_time = Some(start_time);
+        opts.end_time = Some(end_time);
+        opts.last_day = false;
+
+        if resumed.is_none() {
+            let range = opts.start_time.as_deref().zip(opts.end_time.as_deref());
+            if let Err(err) = file.start(range) {
+                log::warn!("Failed to checkpoint the run: {err}");
+                return Ok((None, None));
+            }
+        }
+        Ok((Some(file), resumed))
+    }
+
+    /// The matches of a new chunk, deduplicating its lines against the chain.
+    fn begin_chunk(matches: &mut matcher::Matches) -> matcher::Matches {
+        matcher::Matches {
+            fingerprints: std::mem::take(&mut matches.fingerprints),
+            ..Default::default()
+        }
+    }
+
+    /// Merge the matches of the completed chunk into the chain, and persist them.
+    ///
+    /// Only the matches of the chunk are persisted, failing to do so does not interrupt the
+    /// run.
+    fn end_chunk(
+        &self,
+        chain: &str,
+        query: &str,
+        matches: &mut matcher::Matches,
+        mut chunk: matcher::Matches,
+    ) {
+        matches.fingerprints = std::mem::take(&mut chunk.fingerprints);
+        chunk.fingerprints = matches.fingerprints.take_recent();
+        if let Some(file) = &self.checkpoint {
+            if let Err(err) = file.complete(chain, query, &chunk) {
+                log::warn!("Failed to save the checkpoint of the run: {err}");
+            }
+        }
+        matches.merge(chunk);
+    }
+
+    /// Match the lines of the query, streamed while being recorded in the cache.
+    ///
+    /// The cached results and the outputs of the sources not streaming their lines are
+    /// fetched first.
+    fn process_query(
+        &mut self,
+        query: &str,
+        matches: &mut matcher::Matches,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Loki only filters the levels, not the targets.
+        let levels = self.levels.clone();
+        let keep = |line: &str| !levels.has_targets() || levels.matches(line);
+
+        let cached = self
+            .cache
+            .as_ref()
+            .is_some_and(|cache| cache.is_cached(query));
+        if cached || !self.source.streams() {
+            let bytes = self.fetch(query)?;
+            let result = String::from_utf8_lossy(&bytes);
+            self.matcher
+                .process_lines(result.lines().filter(|line| keep(line)), matches);
+            return Ok(());
+        }
+
+        // The lines and the recording of every attempt are discarded unless it completes,
+        // the failed and split attempts being partial.
+        let mut stream = self.runner.stream(query)?;
+        let mut parts = Vec::new();
+        while let Some(mut attempt) = stream.next_attempt()? {
+            let mut writer = self
+                .cache
+                .as_ref()
+                .map(|cache| cache.writer(attempt.query()))
+                .transpose()?;
+            let mark = matches.fingerprints.mark();
+            let mut lines = matcher::Matches {
+                fingerprints: std::mem::take(&mut matches.fingerprints),
+                ..Default::default()
+            };
+
+            let reader = BufReader::new(cache::Recorded::new(&mut attempt, writer.as_mut()));
+            let mut error = None;
+            let records = RecordReader::new(reader)
+                .stitch(false)
+                .map_while(|record| record.map_err(|err| error = Some(err)).ok())
+                .filter(|record| keep(record));
+            self.matcher.process_lines(records, &mut lines);
+            matches.fingerprints = std::mem::take(&mut lines.fingerprints);
+            if let Some(err) = error {
+                return Err(err.into());
+            }
+
+            if stream.end(attempt)? {
+                matches.merge(lines);
+                parts.extend(writer);
+            } else {
+                matches.fingerprints.rollback(mark);
+            }
+        }
+
+        if let Some(cache) = &mut self.cache {
+            cache.finish_parts(query, parts)?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the result of the query, from the cache when allowed.
+    fn fetch(&mut self, query: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if let Some(bytes) = self.cache.as_ref().and_then(|cache| cache.cached(query)) {
+            return Ok(bytes);
+        }
+
+        let bytes = self.source.fetch(&self.runner, query)?;
+        if let Some(cache) = &mut self.cache {
+            cache.record(query, &bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Process the files in parallel, with at most `jobs` files at a time.
+    ///
+    /// Each file is memory mapped (or decompressed) and matched in a blocking task, the results
+    /// are merged in the order of the provided files.
+    async fn process_files(
+        matcher: Arc<matcher::Matcher>,
+        levels: &level::Levels,
+        files: &[PathBuf],
+        jobs: usize,
+        strip_ansi: bool,
+        mut progress: progress::Progress,
+    ) -> Result<matcher::Matches, Box<dyn std::error::Error>> {
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(jobs));
+
+        let handles: Vec<_> = files
+            .iter()
+            .cloned()
+            .map(|file| {
+                let matcher = matcher.clone();
+                let levels = levels.clone();
+                let semaphore = semaphore.clone();
+
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await?;
+
+                    tokio::task::spawn_blocking(move || {
+                        log::info!("Processing file {}", file.display());
+
+                        let reader = input::open_reader(&file)?;
+                        let source = file.file_name().map(|name| name.to_string_lossy());
+
+                        let mut matches = matcher::Matches::default();
+                        process_stream(
+                            &matcher,
+                            &levels,
+                            source.as_deref(),
+                            reader,
+                            strip_ansi,
+                            &mut matches,
+                        )?;
+                        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(matches)
+                    })
+                    .await?
+                })
+            })
+            .collect();
+
+        let mut matches = matcher::Matches::default();
+        for handle in handles {
+            let result = handle.await?;
+            let result = result.map_err(|err| -> Box<dyn std::error::Error> { err })?;
+            progress.inc(result.total, result.matched());
+            matches.merge(result);
+        }
+        progress.finish();
+
+        Ok(matches)
+    }
+
+    fn report(&mut self) -> Result<TriageReport, Box<dyn std::error::Error>> {
+        let mut report =
+            TriageReport::new(&self.matches, self.blame.as_mut(), self.raw, self.histogram);
+        self.stats.levels = report.summary.levels.clone();
+
+        // Every pattern is recorded, regardless of the report options.
+        if let Some((store, run)) = &mut self.history {
+            // Local files do not have a queried time range, the matched lines are used instead.
+            let timestamps = || {
+                self.matches
+                    .found_lines
+                    .values()
+                    .flat_map(|entry| entry.timeline.bounds())
+            };
+            let format =
+                |time: &chrono::NaiveDateTime| time.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+            let mut run = run.clone();
+            run.start_time = run.start_time.or_else(|| timestamps().min().map(format));
+            run.end_time = run.end_time.or_else(|| timestamps().max().map(format));
+
+            store.record(&run, &report)?;
+        }
+
+        report.classify(&self.subsystems);
+        if let Some(suppressions) = &self.suppressions {
+            report.suppress(suppressions, chrono::Utc::now().date_naive());
+        }
+        if let Some(known_issues) = &self.known_issues {
+            report.annotate(known_issues);
+            if self.hide_known {
+                report.hide_known();
+            }
+        }
+        if let Some(thresholds) = &self.thresholds {
+            report.highlight(thresholds);
+        }
+        // The groups cover every pattern, like the summary.
+        match self.group_by {
+            Some(report::GroupBy::Target) => report.group_by_target(),
+            Some(report::GroupBy::Peer) => report.group_by_peer(),
+            Some(report::GroupBy::Subsystem) => report.group_by_subsystem(),
+            Some(report::GroupBy::File) => report.group_by_file(),
+            None => {}
+        }
+        // Without a provided time range, the rates are computed over the matched lines.
+        let timestamps = || {
+            self.matches
+                .found_lines
+                .values()
+                .flat_map(|entry| entry.timeline.bounds())
+        };
+        let range = self.range.or_else(|| {
+            timestamps()
+                .min()
+                .zip(timestamps().max())
+                .map(|(first, last)| *last - *first)
+        });
+        if let Some(range) = range {
+            report.rates(range);
+        }
+        if let Some(factor) = self.spike_factor {
+            let interval = self.histogram.unwrap_or(grafana::DEFAULT_SPIKE_INTERVAL);
+            report.detect_spikes(&self.matches, interval, factor);
+        }
+        report.correlate_sessions(&self.matches);
+        report.select(self.sort, self.min_count, self.top);
+        if let Some(suggester) = &self.suggester {
+            report.unknown_clusters = suggester.suggest(&report.unknown_lines);
+        }
+        if !self.show_unknown {
+            report.hide_unknown_lines();
+        }
+        if self.time_to_first_error {
+            report.first_errors = first_error::report(&self.matches, self.start_time);
+        }
+        if let Some(explore) = &self.explore {
+            for pattern in &mut report.patterns {
+                let (regex, key) = self.matcher.split_pattern(&pattern.pattern);
+                pattern.explore = Some(explore.link(regex, key));
+            }
+        }
+        // Local files do not have a queried time range, the matched lines are used instead.
+        let format = |time: &chrono::NaiveDateTime| time.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        let mut metadata = self.metadata.clone();
+        metadata.start_time = metadata
+            .start_time
+            .or_else(|| timestamps().min().map(format));
+        metadata.end_time = metadata.end_time.or_else(|| timestamps().max().map(format));
+        metadata.runtime = self.started.elapsed().as_secs_f64();
+        report.metadata = Some(metadata);
+        report.repos = self.repos.clone();
+        report.coverage = self
+            .coverage
+            .as_ref()
+            .map(|coverage| coverage.report(&self.matches));
+        report.color = self.color;
+
+        Ok(report)
+    }
+}
+
+/// Match the records of the stream with the selected levels, detecting the versions and
+/// the starts of the nodes, and the session changes, in the same pass.
+///
+/// Only the current record is kept in memory, besides the matches.
+fn process_stream(
+    matcher: &matcher::Matcher,
+    levels: &level::Levels,
+    source: Option<&str>,
+    reader: impl BufRead,
+    strip_ansi: bool,
+    matches: &mut matcher::Matches,
+) -> std::io::Result<()> {
+    let mut error = None;
+    let mut versions = matcher::Matches::default();
+    let records = RecordReader::new(reader)
+        .map_while(|record| record.map_err(|err| error = Some(err)).ok())
+        .map(|record| match strip_ansi {
+            true => match parse::strip_ansi(&record) {
+                Cow::Borrowed(_) => record,
+                Cow::Owned(stripped) => stripped,
+            },
+            false => record,
+        })
+        .inspect(|record| {
+            matcher.detect_versions(source, std::iter::once(record.as_str()), &mut versions);
+            matcher.detect_session_changes(std::iter::once(record.as_str()), &mut versions);
+        })
+        .filter(|record| levels.matches(record));
+    matcher.process_source(source, records, matches);
+    matches.versions.extend(versions.versions);
+    for (node, starts) in versions.starts {
+        matches.starts.entry(node).or_default().extend(starts);
+    }
+    matches.session_changes.extend(versions.session_changes);
+
+    error.map_or(Ok(()), Err)
+}
@@ -0,0 +1,47 @@
+//! Statistics of a run, logged when dropped.
+
+use std::{collections::BTreeMap, time::Duration};
+
+#[derive(Debug)]
+pub(crate) struct Stats {
+    pub(crate) total: usize,
+    pub(crate) empty_lines: usize,
+    /// The matched lines per level (ie `warn`, `error`).
+    pub(crate) levels: BTreeMap<String, usize>,
+    pub(crate) unknown: usize,
+    pub(crate) duplicates: usize,
+    /// The duration of the phases of the run (ie fetching the sources, extracting the regexes).
+    pub(crate) phases: Vec<(&'static str, Duration)>,
+    now: std::time::Instant,
+}
+
+impl Stats {
+    pub(crate) fn new() -> Self {
+        Stats {
+            total: 0,
+            empty_lines: 0,
+            levels: BTreeMap::new(),
+            unknown: 0,
+            duplicates: 0,
+            phases: Vec::new(),
+            now: std::time::Instant::now(),
+        }
+    }
+
+    /// Record the duration of the phase started at the provided instant.
+    pub(crate) fn phase(&mut self, name: &'static str, started: std::time::Instant) {
+        let elapsed = started.elapsed();
+        log::info!("Phase {name} took {elapsed:?}");
+        self.phases.push((name, elapsed));
+    }
+}
+
+impl Drop for Stats {
+    fn drop(&mut self) {
+        log::info!(
+            "Statistics: Execution took {}s {:?}",
+            self.now.elapsed().as_secs(),
+            self
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Classification of the log statements in subsystems, from the paths of their source files.
+//!
+//! The built-in mapping covers the layout of `polkadot-sdk` and `litep2p`, and is extended
+//! with a YAML file:
+//!
+//! ```yaml
+//! - prefix: polkadot/node/core/dispute-coordinator
+//!   subsystem: disputes
+//! - prefix: src/transport
+//!   repo: litep2p
+//!   subsystem: litep2p-transport
+//! ```
+
+use std::path::Path;
+
+/// The built-in subsystems of the `polkadot-sdk` paths.
+const BUILTIN: &[(&str, &str)] = &[
+    ("substrate/client/network/sync", "sync"),
+    ("substrate/client/network", "networking"),
+    (
+        "substrate/client/authority-discovery",
+        "authority-discovery",
+    ),
+    ("substrate/client/consensus/babe", "babe"),
+    ("substrate/client/consensus/aura", "aura"),
+    ("substrate/client/consensus/beefy", "beefy"),
+    ("substrate/client/consensus/grandpa", "grandpa"),
+    ("substrate/client/consensus", "consensus"),
+    ("substrate/client/transaction-pool", "transaction-pool"),
+    ("substrate/client/executor", "executor"),
+    ("substrate/client/db", "database"),
+    ("substrate/client/state-db", "database"),
+    ("substrate/client/rpc", "rpc"),
+    ("substrate/client/rpc-servers", "rpc"),
+    ("substrate/client/telemetry", "telemetry"),
+    ("substrate/client/service", "service"),
+    ("substrate/client", "substrate-client"),
+    ("substrate/frame", "frame"),
+    ("substrate/primitives", "primitives"),
+    ("polkadot/node/core/approval-voting", "approval-voting"),
+    (
+        "polkadot/node/network/approval-distribution",
+        "approval-voting",
+    ),
+    ("polkadot/node/core/dispute-coordinator", "disputes"),
+    ("polkadot/node/network/dispute-distribution", "disputes"),
+    ("polkadot/node/core/av-store", "availability"),
+    (
+        "polkadot/node/network/availability-distribution",
+        "availability",
+    ),
+    (
+        "polkadot/node/network/availability-recovery",
+        "availability",
+    ),
+    ("polkadot/node/core/backing", "backing"),
+    ("polkadot/node/network/statement-distribution", "backing"),
+    ("polkadot/node/core/candidate-validation", "pvf"),
+    ("polkadot/node/core/pvf", "pvf"),
+    ("polkadot/node/core/provisioner", "provisioner"),
+    ("polkadot/node/core/chain-selection", "chain-selection"),
+    (
+        "polkadot/node/network/collator-protocol",
+        "collator-protocol",
+    ),
+    ("polkadot/node/network/gossip-support", "gossip-support"),
+    ("polkadot/node/network", "parachain-networking"),
+    ("polkadot/node/overseer", "overseer"),
+    ("polkadot/node", "parachain-node"),
+    ("polkadot/runtime", "polkadot-runtime"),
+    ("polkadot/xcm", "xcm"),
+    ("cumulus/", "cumulus"),
+    ("bridges/", "bridges"),
+];
+
+/// The subsystem of the source files starting with a prefix.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct SubsystemPrefix {
+    /// The prefix of the source file paths, relative to the repository.
+    pub prefix: String,
+    /// The name of the subsystem.
+    pub subsystem: String,
+    /// The repository of the source files, any repository if not provided.
+    #[serde(default)]
+    pub repo: Option<String>,
+}
+
+/// The mapping of the source file paths to their subsystem.
+#[derive(Debug, Clone)]
+pub struct Subsystems {
+    prefixes: Vec<SubsystemPrefix>,
+}
+
+impl Default for Subsystems {
+    /// The built-in subsystems.
+    fn default() -> Self {
+        let mut prefixes: Vec<_> = BUILTIN
+            .iter()
+            .map(|(prefix, subsystem)| SubsystemPrefix {
+                prefix: prefix.to_string(),
+                subsystem: subsystem.to_string(),
+                repo: Some("polkadot-sdk".to_string()),
+            })
+            .collect();
+        prefixes.push(SubsystemPrefix {
+            prefix: String::new(),
+            subsystem: "litep2p".to_string(),
+            repo: Some("litep2p".to_string()),
+        });
+
+        Subsystems { prefixes }
+    }
+}
+
+impl Subsystems {
+    /// Load the built-in subsystems, extended with the prefixes of the provided YAML file.
+    ///
+    /// The prefixes of the file take precedence over the built-in prefixes of the same length.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut subsystems = Subsystems::default();
+        let content = std::fs::read_to_string(path)?;
+        let prefixes: Vec<SubsystemPrefix> = serde_yaml::from_str(&content)
+            .map_err(|err| format!("Invalid subsystems file {}: {err}", path.display()))?;
+        subsystems.prefixes.splice(0..0, prefixes);
+
+        Ok(subsystems)
+    }
+
+    /// The subsystem of the source file of the repository.
+    ///
+    /// The longest matching prefix wins, such that the subsystems can be refined.
+    pub fn classify(&self, repo: &str, file: &str) -> Option<&str> {
+        self.prefixes
+            .iter()
+            .filter(|prefix| prefix.repo.as_deref().is_none_or(|name| name == repo))
+            .filter(|prefix| file.starts_with(&prefix.prefix))
+            // The last maximum is returned, which is the first prefix once reversed.
+            .rev()
+            .max_by_key(|prefix| prefix.prefix.len())
+            .map(|prefix| prefix.subsystem.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subsystems() {
+        let mut subsystems = Subsystems::default();
+        assert_eq!(
+            subsystems.classify(
+                "polkadot-sdk",
+                "substrate/client/network/sync/src/engine.rs"
+            ),
+            Some("sync")
+        );
+        assert_eq!(
+            subsystems.classify("polkadot-sdk", "substrate/client/network/src/service.rs"),
+            Some("networking")
+        );
+        assert_eq!(
+            subsystems.classify("litep2p", "src/protocol/libp2p/kademlia/mod.rs"),
+            Some("litep2p")
+        );
+        assert_eq!(subsystems.classify("extra", "extra.yaml"), None);
+
+        subsystems.prefixes.splice(
+            0..0,
+            serde_yaml::from_str::<Vec<SubsystemPrefix>>(
+                r#"
+- prefix: substrate/client/network
+  subsystem: network-team
+- prefix: extra.yaml
+  subsystem: custom
+"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            subsystems.classify("polkadot-sdk", "substrate/client/network/src/service.rs"),
+            Some("network-team")
+        );
+        assert_eq!(
+            subsystems.classify(
+                "polkadot-sdk",
+                "substrate/client/network/sync/src/engine.rs"
+            ),
+            Some("sync")
+        );
+        assert_eq!(subsystems.classify("extra", "extra.yaml"), Some("custom"));
+    }
+}
@@ -0,0 +1,253 @@
+//! Suggestions of the closest patterns of the unknown lines.
+//!
+//! Most unknown lines are printed by log statements whose regex extraction failed, or by
+//! macros that are not searched. The unknown lines are clustered by their message, with
+//! the variable parts (ie numbers, hashes, peers) replaced by their placeholders, and every
+//! cluster is compared against the words of the extracted patterns.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::OnceLock,
+};
+
+use crate::{
+    fetch_git::RegexDetails,
+    normalize,
+    parse::{self, LogLine},
+};
+
+/// The maximum number of clusters reported, the most frequent first.
+const MAX_CLUSTERS: usize = 20;
+
+/// The maximum number of candidate patterns per cluster.
+const MAX_CANDIDATES: usize = 3;
+
+/// The minimum similarity of the candidate patterns.
+const MIN_SIMILARITY: f64 = 0.3;
+
+/// The masked variable parts of the clustered messages, that are not normalized values.
+const MASK: &str = "{}";
+
+/// The levels preceding the message of the plain text lines.
+const LEVELS: [&str; 5] = ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
+
+/// A pattern close to an unknown line cluster.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Candidate {
+    /// The regex of the log statement.
+    pub pattern: String,
+    /// Link to the log statement in the source repository.
+    pub permalink: String,
+    /// The similarity of the words of the cluster and the pattern, between 0 and 1.
+    pub similarity: f64,
+}
+
+/// The unknown lines sharing the same message, once the variable parts are masked.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UnknownCluster {
+    /// The message of the lines with the variable parts masked (ie `Imported {} blocks`).
+    pub template: String,
+    /// The number of unknown lines of the cluster.
+    pub count: usize,
+    /// The closest patterns, the most similar first.
+    pub candidates: Vec<Candidate>,
+}
+
+impl UnknownCluster {
+    /// The regex matching the lines of the cluster, with the variable parts as `.*?`.
+    pub fn regex(&self) -> String {
+        static VARIABLES: OnceLock<regex::Regex> = OnceLock::new();
+
+        let variables = VARIABLES.get_or_init(|| {
+            let alternatives: Vec<_> = normalize::PLACEHOLDERS
+                .iter()
+                .chain([&MASK])
+                .map(|variable| regex::escape(variable))
+                .collect();
+            regex::Regex::new(&alternatives.join("|")).expect("Regex is valid; qed")
+        });
+
+        let mut regex = String::new();
+        let mut last = 0;
+        for variable in variables.find_iter(&self.template) {
+            regex.push_str(&regex::escape(&self.template[last..variable.start()]));
+            regex.push_str(".*?");
+            last = variable.end();
+        }
+        regex.push_str(&regex::escape(&self.template[last..]));
+        regex
+    }
+}
+
+/// Suggests the closest patterns of the unknown lines.
+#[derive(Debug, Clone, Default)]
+pub struct Suggester {
+    /// The words of every pattern, with the pattern and its permalink.
+    patterns: Vec<(BTreeSet<String>, String, String)>,
+}
+
+impl Suggester {
+    /// Construct the suggester of the provided regexes.
+    pub fn new(regexes: &[(regex::Regex, RegexDetails)]) -> Self {
+        let patterns = regexes
+            .iter()
+            .map(|(regex, details)| {
+                (
+                    words(regex.as_str()),
+                    regex.as_str().to_string(),
+                    details.permalink.clone(),
+                )
+            })
+            .filter(|(words, _, _)| !words.is_empty())
+            .collect();
+
+        Suggester { patterns }
+    }
+
+    /// Cluster the unknown lines and find the closest patterns of the most frequent clusters.
+    pub fn suggest(&self, lines: &[String]) -> Vec<UnknownCluster> {
+        let mut clusters: HashMap<String, usize> = HashMap::new();
+        for line in lines {
+            *clusters.entry(template(line)).or_default() += 1;
+        }
+        let mut clusters: Vec<_> = clusters.into_iter().collect();
+        clusters.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        clusters.truncate(MAX_CLUSTERS);
+
+        clusters
+            .into_iter()
+            .map(|(template, count)| UnknownCluster {
+                candidates: self.candidates(&template),
+                template,
+                count,
+            })
+            .collect()
+    }
+
+    /// The patterns closest to the template, the most similar first.
+    fn candidates(&self, template: &str) -> Vec<Candidate> {
+        let words = words(template);
+        let mut candidates: Vec<_> = self
+            .patterns
+            .iter()
+            .map(|(pattern_words, pattern, permalink)| Candidate {
+                pattern: pattern.clone(),
+                permalink: permalink.clone(),
+                similarity: jaccard(&words, pattern_words),
+            })
+            .filter(|candidate| candidate.similarity >= MIN_SIMILARITY)
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.similarity
+                .total_cmp(&a.similarity)
+                .then_with(|| a.pattern.cmp(&b.pattern))
+        });
+        candidates.truncate(MAX_CANDIDATES);
+        candidates
+    }
+}
+
+/// The message of the line with the variable parts masked.
+///
+/// The timestamp, level, thread and target prefix of the plain text lines is dropped
+/// (ie `2024-03-29 16:00:00.000  WARN tokio-runtime-worker sync: `).
+fn template(line: &str) -> String {
+    let parsed = LogLine::parse(line);
+    let mut message = parsed.message.as_ref();
+    if parsed.level.is_none() && parse::parse_timestamp(message).is_some() {
+        let level = message
+            .match_indices(' ')
+            .map(|(index, _)| &message[index + 1..])
+            .find(|rest| LEVELS.iter().any(|level| rest.starts_with(level)));
+        if let Some((_, rest)) = level.and_then(|rest| rest.split_once(": ")) {
+            message = rest;
+        }
+    }
+
+    normalize::normalize(message)
+        .split_whitespace()
+        .map(|word| {
+            if word.chars().any(|ch| ch.is_ascii_digit()) {
+                MASK
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The lowercase words of the text, ignoring the placeholders and the variable parts.
+pub(crate) fn words(text: &str) -> BTreeSet<String> {
+    normalize::literals(text)
+        .into_iter()
+        .flat_map(|part| part.split(|ch: char| !ch.is_alphanumeric()))
+        .filter(|word| word.len() > 1 && word.chars().all(char::is_alphabetic))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The number of common words over the number of distinct words.
+pub(crate) fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest() {
+        let details = |line| RegexDetails {
+            repo: "test".to_string(),
+            file: "test.rs".to_string(),
+            start: 0,
+            end: 0,
+            line,
+            column: 1,
+            permalink: format!("https://github.com/test/blob/master/test.rs#L{line}"),
+            description: None,
+            target: None,
+            owners: Vec::new(),
+            ty: "warn".to_string(),
+        };
+        let suggester = Suggester::new(&[
+            (
+                regex::Regex::new(r"Failed to dial .*? parachain validator").unwrap(),
+                details(1),
+            ),
+            (
+                regex::Regex::new(r"Block import failed: .*?").unwrap(),
+                details(2),
+            ),
+        ]);
+        let lines = [
+            "2024-03-29 16:00:01.000  WARN tokio-runtime-worker parachain: Failed to dial peer 12D3KooWA of parachain validator: timeout",
+            "2024-03-29 16:00:02.000  WARN tokio-runtime-worker parachain: Failed to dial peer 12D3KooWB of parachain validator: timeout",
+            "2024-03-29 16:00:03.000 ERROR tokio-runtime-worker sync: Unrelated message 42",
+        ]
+        .map(String::from);
+
+        let clusters = suggester.suggest(&lines);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(
+            clusters[0].template,
+            "Failed to dial peer {} of parachain validator: timeout"
+        );
+        assert_eq!(clusters[0].count, 2);
+        assert_eq!(clusters[0].candidates.len(), 1);
+        assert!(clusters[0].candidates[0].permalink.ends_with("#L1"));
+        assert_eq!(clusters[1].template, "Unrelated message <num>");
+        assert!(clusters[1].candidates.is_empty());
+
+        assert_eq!(
+            clusters[0].regex(),
+            "Failed to dial peer .*? of parachain validator: timeout"
+        );
+        assert_eq!(clusters[1].regex(), "Unrelated message .*?");
+    }
+}
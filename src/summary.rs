@@ -0,0 +1,80 @@
+//! Single-line summary of a triage run.
+
+use std::collections::BTreeMap;
+
+/// Summary printed as the last line of every run.
+///
+/// The line is stable and parseable, such that wrapper scripts can grep for `RESULT`
+/// instead of parsing the full report.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Summary {
+    /// Number of distinct categories (patterns) that matched at least one line.
+    pub categories: usize,
+    /// Number of categories that are not known issues.
+    pub new: usize,
+    /// Number of lines matched by error log statements.
+    pub errors: usize,
+    /// Number of lines matched by warning log statements.
+    pub warns: usize,
+    /// Number of lines that could not be matched against any pattern.
+    pub unknown: usize,
+    /// Number of panics detected.
+    pub panics: usize,
+    /// Number of matched lines per level of their log statements (ie `error`, `warn`, `info`).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub levels: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    /// The totals of every level, errors and warnings first (ie `3 errors, 5 warnings, 2 info`).
+    pub fn level_totals(&self) -> String {
+        let name = |level: &str| match level {
+            "error" => "errors".to_string(),
+            "warn" => "warnings".to_string(),
+            level => level.to_string(),
+        };
+        let mut levels: Vec<_> = self.levels.iter().collect();
+        levels.sort_by_key(|(level, _)| match level.as_str() {
+            "error" => 0,
+            "warn" => 1,
+            _ => 2,
+        });
+
+        if levels.is_empty() {
+            return "0 errors, 0 warnings".to_string();
+        }
+        levels
+            .into_iter()
+            .map(|(level, count)| format!("{count} {}", name(level)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "RESULT categories={} new={} errors={} warns={} unknown={} panics={}",
+            self.categories, self.new, self.errors, self.warns, self.unknown, self.panics
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_totals() {
+        let mut summary = Summary::default();
+        assert_eq!(summary.level_totals(), "0 errors, 0 warnings");
+
+        summary.levels = BTreeMap::from([
+            ("info".to_string(), 2),
+            ("warn".to_string(), 5),
+            ("error".to_string(), 3),
+        ]);
+        assert_eq!(summary.level_totals(), "3 errors, 5 warnings, 2 info");
+    }
+}
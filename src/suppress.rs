@@ -0,0 +1,134 @@
+//! Suppressions of the accepted errors, loaded from a YAML file.
+//!
+//! ```yaml
+//! - pattern: "Failed to dial"
+//!   reason: "The bootnodes of versi are rotated every week"
+//!   link: https://github.com/paritytech/polkadot-sdk/issues/1234
+//!   expires: 2024-06-30
+//! ```
+//!
+//! The suppressed patterns are hidden from the report until their expiry date. The
+//! expired suppressions are reported again, such that they are revisited instead of
+//! hiding the patterns forever.
+
+use std::path::Path;
+
+use chrono::NaiveDate;
+
+/// The suppression of the patterns containing a substring.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Suppression {
+    /// The substring of the reported pattern.
+    pub pattern: String,
+    /// Why the pattern is accepted.
+    pub reason: String,
+    /// The tracking issue of the pattern.
+    pub link: String,
+    /// The last day of the suppression.
+    pub expires: NaiveDate,
+}
+
+impl Suppression {
+    /// Check if the suppression expired before the provided day.
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        self.expires < today
+    }
+}
+
+/// The suppressions of the accepted patterns.
+#[derive(Debug, Clone, Default)]
+pub struct Suppressions {
+    suppressions: Vec<Suppression>,
+}
+
+impl Suppressions {
+    /// Load the suppressions from the provided YAML file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let suppressions = serde_yaml::from_str(&content)
+            .map_err(|err| format!("Invalid suppressions file {}: {err}", path.display()))?;
+
+        Ok(Suppressions { suppressions })
+    }
+
+    /// Find the suppression of the provided pattern on the provided day.
+    ///
+    /// The first active suppression whose substring is contained in the pattern is
+    /// returned, the first expired one otherwise.
+    pub fn find(&self, pattern: &str, today: NaiveDate) -> Option<&Suppression> {
+        let mut matching = self
+            .suppressions
+            .iter()
+            .filter(|suppression| pattern.contains(&suppression.pattern));
+        let first = matching.next()?;
+        if !first.is_expired(today) {
+            return Some(first);
+        }
+
+        Some(
+            matching
+                .find(|suppression| !suppression.is_expired(today))
+                .unwrap_or(first),
+        )
+    }
+
+    /// The suppressions expired before the provided day.
+    pub fn expired(&self, today: NaiveDate) -> impl Iterator<Item = &Suppression> {
+        self.suppressions
+            .iter()
+            .filter(move |suppression| suppression.is_expired(today))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suppressions() {
+        let suppressions = serde_yaml::from_str(
+            r#"
+- pattern: "Failed to dial"
+  reason: "Rotated bootnodes"
+  link: https://github.com/paritytech/polkadot-sdk/issues/1234
+  expires: 2024-06-30
+- pattern: "Slow block import"
+  reason: "Known slow disks"
+  link: https://github.com/paritytech/polkadot-sdk/issues/5678
+  expires: 2024-03-01
+- pattern: "Slow block"
+  reason: "Slow disks until the migration"
+  link: https://github.com/paritytech/polkadot-sdk/issues/9012
+  expires: 2024-04-30
+"#,
+        )
+        .unwrap();
+        let suppressions = Suppressions { suppressions };
+        let today = NaiveDate::from_ymd_opt(2024, 3, 30).unwrap();
+
+        let suppression = suppressions
+            .find("Failed to dial .* (Timeout)", today)
+            .unwrap();
+        assert_eq!(suppression.reason, "Rotated bootnodes");
+        assert!(!suppression.is_expired(today));
+        // The suppressions are active on their expiry day.
+        assert!(!suppression.is_expired(suppression.expires));
+
+        let expired: Vec<_> = suppressions
+            .expired(today)
+            .map(|suppression| suppression.pattern.as_str())
+            .collect();
+        assert_eq!(expired, vec!["Slow block import"]);
+        assert!(suppressions.find("Invalid justification", today).is_none());
+
+        // The active suppressions are preferred over the expired ones.
+        let suppression = suppressions.find("Slow block import .*", today).unwrap();
+        assert_eq!(suppression.reason, "Slow disks until the migration");
+        let before = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+        let suppression = suppressions.find("Slow block import .*", before).unwrap();
+        assert_eq!(suppression.reason, "Known slow disks");
+        let after = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let suppression = suppressions.find("Slow block import .*", after).unwrap();
+        assert_eq!(suppression.reason, "Known slow disks");
+    }
+}
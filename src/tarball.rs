@@ -0,0 +1,140 @@
+//! Disk cache of the fetched repository tarballs.
+//!
+//! The tarball of `polkadot-sdk` weighs ~200MB, downloading it for every run wastes time
+//! and bandwidth. The tarballs are stored per repository and branch, and reused when the
+//! latest commit of the branch did not change, or when GitHub answers the conditional
+//! request of the stored `ETag` with `304 Not Modified`.
+
+use std::path::PathBuf;
+
+use reqwest::{header, StatusCode};
+
+use crate::{
+    cache::QueryCache,
+    github::{self, GitHub},
+};
+
+/// The downloaded version of a cached tarball.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct Metadata {
+    /// The `ETag` of the tarball response.
+    etag: Option<String>,
+    /// The latest commit of the branch when the tarball was downloaded.
+    sha: Option<String>,
+}
+
+/// Disk cache of the repository tarballs.
+#[derive(Debug, Clone)]
+pub struct TarballCache {
+    /// The directory of the cached tarballs.
+    dir: PathBuf,
+}
+
+impl TarballCache {
+    /// Construct the cache of the provided directory.
+    pub fn new(dir: PathBuf) -> Self {
+        TarballCache { dir }
+    }
+
+    /// Download the tarball of the repository branch, unless the cached one is up to date.
+    pub async fn download(
+        &self,
+        github: &GitHub,
+        repo_url: &str,
+        branch: &str,
+        archive_url: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let key = QueryCache::key(&format!("{repo_url}#{branch}"));
+        let tarball_path = self.dir.join(format!("{key}.tar.gz"));
+        let metadata_path = self.dir.join(format!("{key}.json"));
+        let cached = std::fs::read(&metadata_path)
+            .ok()
+            .and_then(|metadata| serde_json::from_slice::<Metadata>(&metadata).ok())
+            .zip(std::fs::read(&tarball_path).ok());
+
+        let sha = latest_commit(github, repo_url, branch).await;
+        if let (Some((metadata, tarball)), Some(sha)) = (&cached, &sha) {
+            if metadata.sha.as_ref() == Some(sha) {
+                log::info!("Using cached tarball of {repo_url} {branch} at {sha}");
+                return Ok(tarball.clone());
+            }
+        }
+
+        let mut request = github.get(archive_url);
+        if let Some(etag) = cached
+            .as_ref()
+            .and_then(|(metadata, _)| metadata.etag.as_ref())
+        {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some((metadata, tarball)) = cached {
+                log::info!("Using cached tarball of {repo_url} {branch}, not modified");
+                self.store(&metadata_path, &Metadata { sha, ..metadata }, None);
+                return Ok(tarball);
+            }
+        }
+
+        let response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string);
+        let tarball = response.bytes().await?.to_vec();
+        self.store(
+            &metadata_path,
+            &Metadata { etag, sha },
+            Some((&tarball_path, &tarball)),
+        );
+
+        Ok(tarball)
+    }
+
+    /// Store the tarball and its metadata.
+    ///
+    /// The run does not depend on the cache, failures are only logged.
+    fn store(
+        &self,
+        metadata_path: &std::path::Path,
+        metadata: &Metadata,
+        tarball: Option<(&std::path::Path, &[u8])>,
+    ) {
+        let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+            std::fs::create_dir_all(&self.dir)?;
+            if let Some((path, tarball)) = tarball {
+                std::fs::write(path, tarball)?;
+            }
+            std::fs::write(metadata_path, serde_json::to_vec(metadata)?)?;
+            Ok(())
+        })();
+        if let Err(err) = result {
+            log::warn!("Failed to cache the tarball: {err}");
+        }
+    }
+}
+
+/// The SHA of the latest commit of the branch, when the GitHub API is reachable.
+async fn latest_commit(github: &GitHub, repo_url: &str, branch: &str) -> Option<String> {
+    let url = github::commit_url(repo_url, branch)?;
+    let result = async {
+        github
+            .get(&url)
+            .header(header::ACCEPT, "application/vnd.github.sha")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await
+    }
+    .await;
+
+    match result {
+        Ok(sha) => Some(sha.trim().to_string()),
+        Err(err) => {
+            log::debug!("Failed to query the latest commit of {repo_url} {branch}: {err}");
+            None
+        }
+    }
+}
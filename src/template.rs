@@ -0,0 +1,78 @@
+//! Custom report layouts, rendered from handlebars templates.
+//!
+//! The templates receive the report as context, with the same fields as `--format json`:
+//!
+//! ```text
+//! {{#each patterns}}
+//! * {{count}} x `{{pattern}}` ({{level}}, {{permalink}})
+//! {{/each}}
+//! ```
+
+use std::path::Path;
+
+use handlebars::Handlebars;
+
+/// The name of the registered template.
+const TEMPLATE: &str = "report";
+
+/// A report layout provided with `--template`.
+#[derive(Debug, Clone)]
+pub struct Template {
+    registry: Handlebars<'static>,
+}
+
+impl Template {
+    /// Compile the provided template.
+    ///
+    /// The values are not HTML-escaped, since the reports are plain text or markdown.
+    pub fn new(template: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut registry = Handlebars::new();
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_template_string(TEMPLATE, template)?;
+
+        Ok(Template { registry })
+    }
+
+    /// Read and compile the template of the file.
+    ///
+    /// The template is compiled before the run, such that syntax errors are reported
+    /// before querying the logs.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let template = std::fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read the template {}: {err}", path.display()))?;
+
+        Template::new(&template)
+            .map_err(|err| format!("Invalid template {}: {err}", path.display()).into())
+    }
+
+    /// Render the report with the template.
+    pub fn render(
+        &self,
+        report: &impl serde::Serialize,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(self.registry.render(TEMPLATE, report)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{matcher::Matches, TriageReport};
+
+    #[test]
+    fn test_template() {
+        let mut report = TriageReport::new(&Matches::default(), None, false, None);
+        report.summary.errors = 3;
+        report.unknown_lines = vec!["<unknown> & co".to_string()];
+
+        let template = Template::new(
+            "{{summary.errors}} errors{{#each unknown_lines}}, unknown: {{this}}{{/each}}{{#unless patterns}}, no patterns{{/unless}}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&report).unwrap(),
+            "3 errors, unknown: <unknown> & co, no patterns"
+        );
+        assert!(Template::new("{{#each patterns}}").is_err());
+    }
+}
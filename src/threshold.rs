@@ -0,0 +1,89 @@
+//! Thresholds flagging the patterns that need attention, optionally per pattern from a
+//! YAML file.
+//!
+//! ```yaml
+//! - pattern: "Failed to dial"
+//!   threshold: 10000
+//! - pattern: "Invalid justification"
+//!   threshold: 0
+//! ```
+
+use std::path::Path;
+
+/// The threshold of the patterns containing a substring.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct PatternThreshold {
+    /// The substring of the reported pattern.
+    pub pattern: String,
+    /// The number of lines above which the pattern needs attention.
+    pub threshold: usize,
+}
+
+/// The thresholds of the patterns.
+#[derive(Debug, Clone, Default)]
+pub struct Thresholds {
+    /// The threshold of the patterns without their own threshold.
+    default: Option<usize>,
+    patterns: Vec<PatternThreshold>,
+}
+
+impl Thresholds {
+    /// Create the thresholds applying the default threshold to every pattern.
+    pub fn new(default: Option<usize>) -> Self {
+        Thresholds {
+            default,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Load the per-pattern thresholds from the provided YAML file.
+    pub fn load(mut self, path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)?;
+        self.patterns = serde_yaml::from_str(&content)
+            .map_err(|err| format!("Invalid threshold file {}: {err}", path.display()))?;
+
+        Ok(self)
+    }
+
+    /// The threshold of the provided pattern.
+    ///
+    /// The first threshold whose substring is contained in the pattern is returned, the
+    /// default threshold otherwise.
+    pub fn threshold(&self, pattern: &str) -> Option<usize> {
+        self.patterns
+            .iter()
+            .find(|threshold| pattern.contains(&threshold.pattern))
+            .map(|threshold| threshold.threshold)
+            .or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thresholds() {
+        let patterns = serde_yaml::from_str(
+            r#"
+- pattern: "Failed to dial"
+  threshold: 10000
+- pattern: "Invalid justification"
+  threshold: 0
+"#,
+        )
+        .unwrap();
+        let thresholds = Thresholds {
+            default: Some(100),
+            patterns,
+        };
+
+        assert_eq!(thresholds.threshold("Failed to dial .*"), Some(10000));
+        assert_eq!(
+            thresholds.threshold(".* banned, reason: Invalid justification"),
+            Some(0)
+        );
+        assert_eq!(thresholds.threshold("Slow block import"), Some(100));
+        assert_eq!(Thresholds::new(None).threshold("Slow block import"), None);
+    }
+}
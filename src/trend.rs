@@ -0,0 +1,89 @@
+//! Print the evolution of a pattern across the runs recorded in the history store.
+
+use clap::Parser as ClapParser;
+
+use crate::{histogram, history::HistoryStore, output::OutputFormat};
+
+#[derive(Debug, ClapParser, Clone)]
+pub struct Config {
+    /// The history store of the recorded runs, ie `sqlite://triage.db`.
+    #[clap(long)]
+    store: String,
+
+    /// The text contained in the followed patterns (ie `Failed to prove`).
+    ///
+    /// The counts of every pattern containing the text are summed.
+    #[clap(long)]
+    pattern: String,
+
+    /// Only consider the runs of this chain.
+    #[clap(long)]
+    chain: Option<String>,
+
+    /// The number of most recent runs to print.
+    #[clap(long, default_value_t = 10)]
+    runs: usize,
+
+    /// The format of the printed trend.
+    #[clap(long, value_enum, default_value_t)]
+    format: OutputFormat,
+}
+
+pub struct Trend;
+
+impl Trend {
+    pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+        let store = HistoryStore::open(&config.store)?;
+        let points = store.trend(&config.pattern, config.chain.as_deref(), config.runs)?;
+        if points.is_empty() {
+            return Err("No runs recorded in the history store".into());
+        }
+
+        if config.format == OutputFormat::Json {
+            println!("{}", serde_json::to_string_pretty(&points)?);
+            return Ok(());
+        }
+
+        let counts: Vec<_> = points.iter().map(|point| point.count).collect();
+        println!(
+            "Trend of `{}` over {} runs: {}",
+            config.pattern,
+            points.len(),
+            histogram::sparkline(&counts)
+        );
+        println!();
+
+        let markdown = config.format == OutputFormat::Markdown;
+        if markdown {
+            println!("| Run | Recorded at | Time range | Chain | Count | Patterns |");
+            println!("|---|---|---|---|---|---|");
+        } else {
+            println!(
+                "{0: <6} | {1: <20} | {2: <43} | {3: <20} | {4: <10} | Patterns",
+                "Run", "Recorded at", "Time range", "Chain", "Count"
+            );
+        }
+
+        for point in &points {
+            let range = format!(
+                "{} - {}",
+                point.start_time.as_deref().unwrap_or("?"),
+                point.end_time.as_deref().unwrap_or("?")
+            );
+            let chain = point.chain.as_deref().unwrap_or("-");
+            if markdown {
+                println!(
+                    "| {} | {} | {} | {} | {} | {} |",
+                    point.run, point.recorded_at, range, chain, point.count, point.patterns
+                );
+            } else {
+                println!(
+                    "{0: <6} | {1: <20} | {2: <43} | {3: <20} | {4: <10} | {5}",
+                    point.run, point.recorded_at, range, chain, point.count, point.patterns
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+//! Interactive triage of the unknown lines, growing the extra regex file.
+//!
+//! The unknown lines are clustered like with `--suggest-patterns`. Every cluster is
+//! presented with its closest patterns and a suggested regex, that is accepted, edited or
+//! skipped. The accepted regexes are appended to `--extra-regex-file`, such that the next
+//! runs classify the lines of the cluster.
+
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    path::Path,
+};
+
+use crate::{
+    fetch_git::{ExtraLevel, ExtraRegex},
+    suggest::UnknownCluster,
+    TriageSession, TriageUnknownConfig,
+};
+
+/// The terminal of the process, answering the questions when the standard input is piped.
+const TERMINAL: &str = "/dev/tty";
+
+/// The label of the accepted regexes, until another one is provided.
+const DEFAULT_LABEL: &str = "triage";
+
+/// Asks the questions of the triage.
+struct Prompt<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    /// Ask the question, the empty answer is the default one. `None` once the input is closed.
+    fn ask(&mut self, question: &str, default: &str) -> std::io::Result<Option<String>> {
+        if default.is_empty() {
+            write!(self.output, "{question}: ")?;
+        } else {
+            write!(self.output, "{question} [{default}]: ")?;
+        }
+        self.output.flush()?;
+
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        let answer = answer.trim();
+        if answer.is_empty() {
+            Ok(Some(default.to_string()))
+        } else {
+            Ok(Some(answer.to_string()))
+        }
+    }
+
+    /// Ask for a valid regex, starting from the provided one.
+    fn ask_regex(&mut self, pattern: &str) -> std::io::Result<Option<regex::Regex>> {
+        loop {
+            let Some(answer) = self.ask("  Regex", pattern)? else {
+                return Ok(None);
+            };
+            match regex::Regex::new(&answer) {
+                Ok(regex) => return Ok(Some(regex)),
+                Err(err) => writeln!(self.output, "  Invalid regex: {err}")?,
+            }
+        }
+    }
+
+    /// Ask for the level of the regex.
+    fn ask_level(&mut self) -> std::io::Result<Option<ExtraLevel>> {
+        loop {
+            let Some(answer) = self.ask("  Level (warn/error)", "warn")? else {
+                return Ok(None);
+            };
+            match answer.to_lowercase().as_str() {
+                "warn" => return Ok(Some(ExtraLevel::Warn)),
+                "error" => return Ok(Some(ExtraLevel::Error)),
+                _ => writeln!(self.output, "  Unknown level {answer:?}")?,
+            }
+        }
+    }
+}
+
+/// Append the regex to the extra regex file.
+fn append(path: &Path, entry: &ExtraRegex) -> Result<(), Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if !content.is_empty() && !content.ends_with('\n') {
+        writeln!(file)?;
+    }
+    file.write_all(serde_yaml::to_string(&[entry])?.as_bytes())?;
+    Ok(())
+}
+
+/// Walk through the clusters, and append the accepted regexes to the file.
+///
+/// The clusters matched by a regex accepted earlier are skipped. Returns the number of
+/// accepted regexes, once every cluster is reviewed or the triage is quit.
+pub fn review(
+    clusters: &[UnknownCluster],
+    path: &Path,
+    input: impl BufRead,
+    output: impl Write,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut prompt = Prompt { input, output };
+    let mut accepted: Vec<regex::Regex> = Vec::new();
+    let mut label = DEFAULT_LABEL.to_string();
+
+    for (index, cluster) in clusters.iter().enumerate() {
+        if accepted
+            .iter()
+            .any(|regex| regex.is_match(&cluster.template))
+        {
+            continue;
+        }
+
+        writeln!(prompt.output)?;
+        writeln!(
+            prompt.output,
+            "[{}/{}] {} lines: {}",
+            index + 1,
+            clusters.len(),
+            cluster.count,
+            cluster.template
+        )?;
+        for candidate in &cluster.candidates {
+            writeln!(
+                prompt.output,
+                "  Close pattern ({:.0}%): {} {}",
+                candidate.similarity * 100.0,
+                candidate.pattern,
+                candidate.permalink
+            )?;
+        }
+        let suggested = cluster.regex();
+        writeln!(prompt.output, "  Suggested regex: {suggested}")?;
+
+        let regex = loop {
+            let Some(action) = prompt.ask("  Accept, edit, skip or quit (a/e/s/q)", "s")? else {
+                return Ok(accepted.len());
+            };
+            match action.as_str() {
+                "a" => match regex::Regex::new(&suggested) {
+                    Ok(regex) => break Some(regex),
+                    // Ie too large, once compiled.
+                    Err(err) => writeln!(prompt.output, "  Invalid regex: {err}")?,
+                },
+                "e" => match prompt.ask_regex(&suggested)? {
+                    Some(regex) => break Some(regex),
+                    None => return Ok(accepted.len()),
+                },
+                "s" => break None,
+                "q" => return Ok(accepted.len()),
+                _ => writeln!(prompt.output, "  Unknown answer {action:?}")?,
+            }
+        };
+        let Some(regex) = regex else {
+            continue;
+        };
+
+        let Some(level) = prompt.ask_level()? else {
+            return Ok(accepted.len());
+        };
+        let Some(answer) = prompt.ask("  Label", &label)? else {
+            return Ok(accepted.len());
+        };
+        label = answer;
+
+        append(
+            path,
+            &ExtraRegex {
+                pattern: regex.as_str().to_string(),
+                level,
+                label: label.clone(),
+                description: None,
+                target: None,
+            },
+        )?;
+        writeln!(prompt.output, "  Appended to {}", path.display())?;
+        accepted.push(regex);
+    }
+
+    Ok(accepted.len())
+}
+
+/// The input of the answers: the standard input when it is a terminal, the terminal of the
+/// process otherwise (ie when the logs are piped).
+fn answers() -> Result<Box<dyn BufRead>, Box<dyn std::error::Error>> {
+    if std::io::stdin().is_terminal() {
+        return Ok(Box::new(std::io::stdin().lock()));
+    }
+
+    let terminal = std::fs::File::open(TERMINAL).map_err(|err| {
+        format!("triage-unknown asks its questions on a terminal, cannot open {TERMINAL}: {err}")
+    })?;
+    Ok(Box::new(std::io::BufReader::new(terminal)))
+}
+
+/// Triage the logs, then walk through the clusters of the unknown lines on the terminal.
+pub async fn run(opts: TriageUnknownConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = opts.config;
+    let path = config.extra_regex_file.clone().ok_or(
+        "triage-unknown requires --extra-regex-file, where the accepted regexes are appended",
+    )?;
+    if !path.exists() {
+        std::fs::File::create(&path)
+            .map_err(|err| format!("Cannot create {}: {err}", path.display()))?;
+    }
+    config.suggest_patterns = true;
+    let answers = answers()?;
+
+    let report = TriageSession::new(config).run().await?;
+    if report.unknown_clusters.is_empty() {
+        println!("No unknown lines to triage");
+        return Ok(());
+    }
+    println!(
+        "{} unknown lines in {} clusters, the most frequent first",
+        report.summary.unknown,
+        report.unknown_clusters.len()
+    );
+
+    let accepted = review(
+        &report.unknown_clusters,
+        &path,
+        answers,
+        std::io::stdout().lock(),
+    )?;
+    println!("Appended {accepted} regexes to {}", path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch_git;
+
+    #[test]
+    fn test_review() {
+        let cluster = |template: &str, count| UnknownCluster {
+            template: template.to_string(),
+            count,
+            candidates: Vec::new(),
+        };
+        let clusters = [
+            cluster("Failed to dial peer <peer>: timeout", 10),
+            cluster("Failed to dial peer <peer>: timeout after <duration>", 5),
+            cluster("Unrelated message <num>", 3),
+            cluster("Slow block import", 2),
+            cluster("Never reviewed", 1),
+        ];
+        let path = std::env::temp_dir().join(format!(
+            "sub-triage-logs-triage-unknown-{}.yaml",
+            std::process::id()
+        ));
+        std::fs::write(&path, "- pattern: Existing\n  level: warn\n  label: x").unwrap();
+
+        // Accept the first cluster, which also matches the second one, skip the third one,
+        // edit the fourth one, then quit.
+        let input = "a
+
+libp2p
+
+e
+(
+Slow .*
+error
+
+q
+";
+        let mut output = Vec::new();
+        let accepted = review(&clusters, &path, input.as_bytes(), &mut output).unwrap();
+        assert_eq!(accepted, 2);
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Suggested regex: Failed to dial peer .*?: timeout"));
+        assert!(!output.contains("[2/5]"));
+        assert!(output.contains("Invalid regex"));
+
+        let regexes = fetch_git::load_extra_regexes(&path).unwrap();
+        let patterns: Vec<_> = regexes
+            .iter()
+            .map(|(regex, details)| (regex.as_str(), details.ty.as_str(), details.repo.as_str()))
+            .collect();
+        assert_eq!(
+            patterns,
+            [
+                ("Existing", "warn", "x"),
+                ("Failed to dial peer .*?: timeout", "warn", "libp2p"),
+                ("Slow .*", "error", "libp2p"),
+            ]
+        );
+
+        // A suggested regex too large once compiled is reported, then asked again.
+        let clusters = [cluster(&"Slow block <num> ".repeat(20_000), 1)];
+        let mut output = Vec::new();
+        let accepted = review(&clusters, &path, "a\ns\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(accepted, 0);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Invalid regex"));
+        assert_eq!(output.matches("Accept, edit, skip or quit").count(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
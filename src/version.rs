@@ -0,0 +1,49 @@
+//! Extraction of the node versions printed at startup.
+//!
+//! Substrate nodes print their version when starting, ie
+//! `2024-03-29 16:00:00 ✌️  version 1.10.0-7049c3c9883`.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// Loki filter selecting the version lines.
+pub const QUERY_FILTER: &str = "|~ `version [0-9]+\\.[0-9]+`";
+
+/// The version of lines from nodes that did not print their version.
+pub const UNKNOWN: &str = "unknown";
+
+/// Extract the version of the node from its startup line.
+pub fn extract_version(line: &str) -> Option<&str> {
+    static VERSION: OnceLock<Regex> = OnceLock::new();
+
+    // Cheap check before running the regex on every line.
+    if !line.contains("version ") {
+        return None;
+    }
+
+    let regex = VERSION.get_or_init(|| {
+        Regex::new(r"\bversion\s+(\d+\.\d+\.\d+(?:-[0-9A-Za-z.]+)?)").expect("Regex is valid; qed")
+    });
+    regex
+        .captures(line)
+        .and_then(|captures| captures.get(1))
+        .map(|version| version.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_version() {
+        assert_eq!(
+            extract_version("2024-03-29 16:00:00 ✌️  version 1.10.0-7049c3c9883"),
+            Some("1.10.0-7049c3c9883")
+        );
+        assert_eq!(
+            extract_version("2024-03-29 16:00:00 Unsupported protocol version 2"),
+            None
+        );
+    }
+}
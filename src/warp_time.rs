@@ -2,69 +2,454 @@
 
 use clap::Parser as ClapParser;
 
-const MAX_LINES: usize = 1000;
+use crate::{output::OutputFormat, query};
+
+/// Lines of interest for measuring the sync phases.
+const WARP_LINES_FILTER: &str = "|~ `Warping, Downloading finality proofs|Warp sync is complete|State sync is complete|Starting gap sync|Block history|finalized #`";
+
+/// Marks the start of the warp sync.
+const WARP_START: &str = "Warping, Downloading finality proofs";
+/// Marks the end of the warp sync and the start of the state sync.
+const WARP_END: &str = "Warp sync is complete";
+/// Marks the end of the state sync.
+const STATE_END: &str = "State sync is complete";
+/// Marks the start of the gap sync.
+const GAP_START: &str = "Starting gap sync";
+/// Informant line reported while the block history is downloaded.
+const HISTORY_PROGRESS: &str = "Block history,";
+/// Marks the end of the block history download (and of the gap sync).
+const HISTORY_END: &str = "Block history download is complete";
+/// The finalized block reported by the informant (ie `finalized #1234 (0x..)`).
+const FINALIZED: &str = "finalized #";
 
 #[derive(Debug, ClapParser, Clone)]
 pub struct Config {
-    /// Provide a file for parsing.
+    /// Provide files for parsing instead of querying the Loki instance.
+    ///
+    /// Accepts glob patterns (ie `logs/*.txt`) and can be provided multiple times.
+    #[clap(long)]
+    file: Vec<String>,
+
+    /// The format of the printed timings.
+    #[clap(long, value_enum, default_value_t = OutputFormat::Markdown)]
+    format: OutputFormat,
+
+    /// The address of the Loki instance.
+    #[clap(long, default_value = "127.0.0.1:10700")]
+    address: String,
+
+    /// The chain to query.
+    #[clap(long, default_value = "versi-networking")]
+    chain: String,
+
+    /// The nodes to query.
+    ///
+    /// Can be provided multiple times to measure the warp time of each node.
+    #[clap(long)]
+    node: Vec<String>,
+
+    /// Provide the info for the last 24 hours.
+    #[clap(long)]
+    last_day: bool,
+
+    /// The start time of the query, see `warn-err --start-time`.
+    #[clap(long, value_parser = query::parse_time_arg)]
+    start_time: Option<String>,
+
+    /// The end time of the query, see `warn-err --end-time`.
+    #[clap(long, value_parser = query::parse_time_arg)]
+    end_time: Option<String>,
+
+    /// Optionally provide an organization ID.
+    #[clap(long)]
+    org_id: Option<String>,
+
+    /// The bearer token of the Loki instance.
+    ///
+    /// Passed to `logcli` through its environment, never printed with the queries.
+    #[clap(
+        long,
+        env = "LOKI_BEARER_TOKEN",
+        hide_env_values = true,
+        conflicts_with = "username"
+    )]
+    auth_token: Option<String>,
+
+    /// The username of the basic authentication of the Loki instance.
+    #[clap(long, env = "LOKI_USERNAME", requires = "password")]
+    username: Option<String>,
+
+    /// The password of the basic authentication of the Loki instance.
+    #[clap(
+        long,
+        env = "LOKI_PASSWORD",
+        hide_env_values = true,
+        requires = "username"
+    )]
+    password: Option<String>,
+
+    /// The CA certificate verifying the TLS certificate of the Loki instance.
     #[clap(long)]
-    file: String,
+    ca_cert: Option<std::path::PathBuf>,
+
+    /// Skip the verification of the TLS certificate of the Loki instance.
+    #[clap(long)]
+    insecure_skip_verify: bool,
+
+    /// Extra label matcher of the Loki stream selector, ie `namespace=versi`.
+    ///
+    /// Can be provided multiple times.
+    #[clap(long = "label", value_name = "LABEL", value_parser = query::parse_label)]
+    labels: Vec<query::Label>,
+
+    /// The maximum number of retries of a failed query, see `warn-err --retries`.
+    #[clap(long, default_value_t = query::DEFAULT_RETRIES)]
+    retries: u32,
+}
+
+/// The duration of a detected sync phase.
+#[derive(Debug, serde::Serialize)]
+struct PhaseTiming {
+    /// The name of the phase.
+    phase: String,
+    /// Duration of the phase in seconds.
+    secs: f64,
+}
+
+/// The measured durations of the sync phases for a single node.
+#[derive(Debug, serde::Serialize)]
+struct Timings {
+    /// The node (or file) of the measurement.
+    node: String,
+    /// The detected phases in the order of [`PHASES`].
+    phases: Vec<PhaseTiming>,
+    /// Total duration in seconds, from the first to the last detected event.
+    total_secs: f64,
+}
+
+impl Timings {
+    fn phase(&self, phase: &str) -> Option<f64> {
+        self.phases
+            .iter()
+            .find(|timing| timing.phase == phase)
+            .map(|timing| timing.secs)
+    }
+}
+
+/// The phases reported, in order.
+const PHASES: [&str; 5] = [
+    "Warp",
+    "State",
+    "Gap sync",
+    "Block history",
+    "First finalized",
+];
+
+/// The first occurrence of each sync event of a node.
+#[derive(Debug, Default)]
+struct Events {
+    warp_start: Option<chrono::NaiveDateTime>,
+    warp_end: Option<chrono::NaiveDateTime>,
+    state_end: Option<chrono::NaiveDateTime>,
+    gap_start: Option<chrono::NaiveDateTime>,
+    history_start: Option<chrono::NaiveDateTime>,
+    history_end: Option<chrono::NaiveDateTime>,
+    /// The finalized block number observed when the state sync completed.
+    finalized_at_state_end: Option<u64>,
+    /// The first time the finalized block advanced after the state sync.
+    first_finalized: Option<chrono::NaiveDateTime>,
+}
+
+impl Events {
+    /// Record the events of the provided line.
+    fn process_line(&mut self, line: &str) {
+        let Some(time) = WarpTime::extract_time(line) else {
+            return;
+        };
+
+        let first = |event: &mut Option<chrono::NaiveDateTime>, pattern: &str| {
+            if event.is_none() && line.contains(pattern) {
+                *event = Some(time);
+            }
+        };
+        first(&mut self.warp_start, WARP_START);
+        first(&mut self.warp_end, WARP_END);
+        first(&mut self.state_end, STATE_END);
+        first(&mut self.gap_start, GAP_START);
+        first(&mut self.history_start, HISTORY_PROGRESS);
+        first(&mut self.history_end, HISTORY_END);
+
+        if self.state_end.is_none() || self.first_finalized.is_some() {
+            return;
+        }
+        let Some(finalized) = Self::finalized_number(line) else {
+            return;
+        };
+        match self.finalized_at_state_end {
+            None => self.finalized_at_state_end = Some(finalized),
+            Some(previous) if finalized > previous => self.first_finalized = Some(time),
+            Some(_) => {}
+        }
+    }
+
+    /// Extract the finalized number of an informant line.
+    fn finalized_number(line: &str) -> Option<u64> {
+        let (_, rest) = line.split_once(FINALIZED)?;
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        rest[..end].parse().ok()
+    }
+
+    /// The (start, end) events of each phase in the order of [`PHASES`].
+    fn phases(&self) -> [(Option<chrono::NaiveDateTime>, Option<chrono::NaiveDateTime>); 5] {
+        [
+            (self.warp_start, self.warp_end),
+            (self.warp_end, self.state_end),
+            (self.gap_start, self.history_end),
+            (self.history_start, self.history_end),
+            (self.state_end, self.first_finalized),
+        ]
+    }
+}
+
+/// Aggregate statistics of a phase across nodes.
+#[derive(Debug, serde::Serialize)]
+struct PhaseStats {
+    min: f64,
+    median: f64,
+    p95: f64,
+    max: f64,
+}
+
+impl PhaseStats {
+    fn new(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+
+        // Nearest-rank percentile.
+        let percentile = |p: f64| {
+            let rank = (p * values.len() as f64).ceil() as usize;
+            values[rank.saturating_sub(1).min(values.len() - 1)]
+        };
+
+        Some(PhaseStats {
+            min: values[0],
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            max: values[values.len() - 1],
+        })
+    }
+}
+
+/// The aggregated report of all measured nodes.
+#[derive(Debug, serde::Serialize)]
+struct Report {
+    nodes: Vec<Timings>,
+    summary: Vec<(String, PhaseStats)>,
 }
 
 pub struct WarpTime;
 
 impl WarpTime {
-    fn extract_time(line: &str) -> chrono::NaiveDateTime {
+    fn extract_time(line: &str) -> Option<chrono::NaiveDateTime> {
         let mut tokens = line.split_whitespace();
-        let date = tokens.next().expect("Invalid date");
-        let time = tokens.next().expect("Invalid time");
+        let date = tokens.next()?;
+        let time = tokens.next()?;
         let current = format!("{} {}", date, time);
-        chrono::NaiveDateTime::parse_from_str(&current, "%Y-%m-%d %H:%M:%S.%f")
-            .expect("Cannot parse provided time from log line")
+        chrono::NaiveDateTime::parse_from_str(&current, "%Y-%m-%d %H:%M:%S%.f").ok()
+    }
+
+    /// Fetch the sync related lines of the node from Loki in chronological order.
+    fn query_lines(
+        config: &Config,
+        node: Option<String>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let queries = query::QueryBuilder::new()
+            .address(config.address.clone())
+            .chain(config.chain.clone())
+            .set_time(
+                config.start_time.clone(),
+                config.end_time.clone(),
+                config.last_day,
+            )
+            .exclude_common_errors(false)
+            .append_query(WARP_LINES_FILTER.to_string())
+            .org_id(config.org_id.clone())
+            .ca_cert(config.ca_cert.clone())
+            .insecure_skip_verify(config.insecure_skip_verify)
+            .node(node.into_iter().collect())
+            .labels(config.labels.clone())
+            .raw_output(true)
+            .forward(true)
+            .build_chunks()?;
+
+        let runner = query::QueryRunner::new()
+            .retries(config.retries)
+            .auth(query::LokiAuth {
+                token: config.auth_token.clone(),
+                username: config.username.clone(),
+                password: config.password.clone(),
+            });
+        let mut lines = String::new();
+        for query in queries {
+            let bytes = runner.run(&query)?;
+            lines.push_str(&String::from_utf8_lossy(&bytes));
+        }
+
+        Ok(lines)
+    }
+
+    /// Collect the lines of every input, indexed by the node (or file) name.
+    fn collect_inputs(
+        config: &Config,
+    ) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+        let mut inputs = Vec::new();
+
+        if !config.file.is_empty() {
+            for pattern in &config.file {
+                for path in glob::glob(pattern)? {
+                    let path = path?;
+                    log::info!("Running warp time: {:?}", path);
+
+                    let bytes = crate::input::open(&path)?;
+                    inputs.push((
+                        path.display().to_string(),
+                        String::from_utf8_lossy(&bytes).into_owned(),
+                    ));
+                }
+            }
+        } else if config.node.is_empty() {
+            log::info!("Running warp time for chain {:?}", config.chain);
+            inputs.push((config.chain.clone(), Self::query_lines(config, None)?));
+        } else {
+            for node in &config.node {
+                log::info!("Running warp time for node {:?}", node);
+                inputs.push((node.clone(), Self::query_lines(config, Some(node.clone()))?));
+            }
+        }
+
+        Ok(inputs)
+    }
+
+    /// Measure the sync phases from the lines of a single node.
+    fn measure(node: String, lines: &str) -> Result<Timings, String> {
+        let mut events = Events::default();
+        for line in lines.lines() {
+            events.process_line(line);
+        }
+
+        let secs = |duration: chrono::TimeDelta| duration.num_milliseconds() as f64 / 1000.0;
+
+        let mut phases = Vec::new();
+        let mut first_event = None;
+        let mut last_event = None;
+        for (phase, (start, end)) in PHASES.iter().zip(events.phases()) {
+            let (Some(start), Some(end)) = (start, end) else {
+                continue;
+            };
+
+            phases.push(PhaseTiming {
+                phase: phase.to_string(),
+                secs: secs(end.signed_duration_since(start)),
+            });
+            first_event =
+                Some(first_event.map_or(start, |first: chrono::NaiveDateTime| first.min(start)));
+            last_event = last_event.max(Some(end));
+        }
+
+        let (Some(first_event), Some(last_event)) = (first_event, last_event) else {
+            return Err(format!("Cannot find any sync phase for {node}"));
+        };
+
+        Ok(Timings {
+            node,
+            phases,
+            total_secs: secs(last_event.signed_duration_since(first_event)),
+        })
     }
 
     pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
-        log::info!("Running warp time: {:?}", config.file);
-
-        let bytes = std::fs::read(config.file)?;
-        let result = String::from_utf8_lossy(&bytes);
-
-        let mut lines = result.lines().take(MAX_LINES);
-
-        // Warping, Downloading finality proofs.
-        let start_warp = lines.find(|line| line.contains("Warping, Downloading finality proofs"));
-        let start_warp = Self::extract_time(start_warp.expect("Cannot find start warp time"));
-
-        // sync: Warp sync is complete, continuing with state sync.
-        let end_warp = lines.find(|line| line.contains("Warp sync is complete"));
-        let end_warp = Self::extract_time(end_warp.expect("Cannot find end warp time"));
-
-        // sync: State sync is complete.
-        let end_state_sync = lines.find(|line| line.contains("State sync is complete"));
-        let end_state_sync =
-            Self::extract_time(end_state_sync.expect("Cannot find end state sync time"));
-
-        let warp_time = end_warp.signed_duration_since(start_warp).to_std().unwrap();
-        let state_sync_time = end_state_sync
-            .signed_duration_since(end_warp)
-            .to_std()
-            .unwrap();
-        let total = end_state_sync
-            .signed_duration_since(start_warp)
-            .to_std()
-            .unwrap();
-
-        println!();
-        println!();
-        println!("Phase | Time");
-        println!(" -|- ");
-
-        println!("Warp  | {:?}", warp_time);
-        println!("State | {:?}", state_sync_time);
-        println!("Total | {:?}", total);
-
-        println!();
-        println!();
+        let mut nodes = Vec::new();
+        for (node, lines) in Self::collect_inputs(&config)? {
+            match Self::measure(node, &lines) {
+                Ok(timings) => nodes.push(timings),
+                Err(err) => log::error!("Skipping node: {err}"),
+            }
+        }
+
+        let summary = PHASES
+            .iter()
+            .map(|phase| {
+                let values = nodes.iter().filter_map(|timings| timings.phase(phase));
+                (phase.to_string(), values.collect())
+            })
+            .chain(std::iter::once((
+                "Total".to_string(),
+                nodes.iter().map(|timings| timings.total_secs).collect(),
+            )))
+            .filter_map(|(phase, values)| Some((phase, PhaseStats::new(values)?)))
+            .collect();
+
+        let report = Report { nodes, summary };
+
+        match config.format {
+            OutputFormat::Text | OutputFormat::Markdown => {
+                println!();
+                println!();
+                // Only the phases detected on at least one node are displayed.
+                let detected: Vec<_> = PHASES
+                    .iter()
+                    .filter(|phase| {
+                        report
+                            .nodes
+                            .iter()
+                            .any(|timings| timings.phase(phase).is_some())
+                    })
+                    .collect();
+
+                let headers: Vec<_> = detected.iter().map(|phase| phase.to_string()).collect();
+                println!("Node | {} | Total", headers.join(" | "));
+                println!(" -|{}- ", "-|".repeat(detected.len()));
+                for timings in &report.nodes {
+                    let columns: Vec<_> = detected
+                        .iter()
+                        .map(|phase| {
+                            timings
+                                .phase(phase)
+                                .map(|secs| format!("{secs:.3}s"))
+                                .unwrap_or_else(|| "-".to_string())
+                        })
+                        .collect();
+                    println!(
+                        "{} | {} | {:.3}s",
+                        timings.node,
+                        columns.join(" | "),
+                        timings.total_secs
+                    );
+                }
+
+                if report.nodes.len() > 1 {
+                    println!();
+                    println!("Phase | Min | Median | P95 | Max");
+                    println!(" -|-|-|-|- ");
+                    for (phase, stats) in &report.summary {
+                        println!(
+                            "{} | {:.3}s | {:.3}s | {:.3}s | {:.3}s",
+                            phase, stats.min, stats.median, stats.p95, stats.max
+                        );
+                    }
+                }
+
+                println!();
+                println!();
+            }
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+        }
 
         Ok(())
     }
@@ -0,0 +1,298 @@
+//! Continuous triage of the burn-ins, reporting the patterns that are new or growing.
+//!
+//! With `--watch`, the triage is repeated every `--interval` over the trailing window of logs.
+//! The counts of every pattern are kept between the runs, such that the runs after the first
+//! one only report the patterns that appeared or grew since the previous run.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    output::{escape, Render},
+    report::TriageReport,
+    session::RegexCache,
+    Config, TriageSession,
+};
+
+/// The pattern grows when its count is at least this many times its previous count.
+const GROWTH_RATIO: f64 = 1.5;
+
+/// A pattern that is new or growing compared to the previous run.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PatternChange {
+    /// The pattern.
+    pub pattern: String,
+    /// The level of the log statement (ie `warn`, `error`).
+    pub level: String,
+    /// The number of lines matched by the run.
+    pub count: usize,
+    /// The number of lines matched by the previous run, `None` for the new patterns.
+    pub previous: Option<usize>,
+    /// Link to the log statement in the source repository.
+    pub permalink: String,
+}
+
+impl PatternChange {
+    /// The change of the pattern (ie `new`, `+150%`).
+    pub fn change(&self) -> String {
+        match self.previous {
+            None => "new".to_string(),
+            Some(previous) => format!(
+                "+{:.0}%",
+                (self.count - previous) as f64 * 100.0 / previous as f64
+            ),
+        }
+    }
+}
+
+/// The patterns of a run that are new or growing compared to the previous run.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct WatchReport {
+    /// The number of the run, from 1.
+    pub run: usize,
+    /// The new patterns first, then the largest growth first.
+    pub patterns: Vec<PatternChange>,
+}
+
+impl WatchReport {
+    fn summary(&self) -> String {
+        let new = self
+            .patterns
+            .iter()
+            .filter(|pattern| pattern.previous.is_none())
+            .count();
+        format!(
+            "Run {}: {} new and {} growing patterns since the previous run",
+            self.run,
+            new,
+            self.patterns.len() - new
+        )
+    }
+}
+
+impl Render for WatchReport {
+    fn render_text(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "{}", self.summary())?;
+        if self.patterns.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{0: <10} | {1: <10} | {2: <10} | {3: <10} | Pattern",
+            "Change", "Count", "Previous", "Level"
+        )?;
+        for pattern in &self.patterns {
+            writeln!(
+                out,
+                "{0: <10} | {1: <10} | {2: <10} | {3: <10} | {4}",
+                pattern.change(),
+                pattern.count,
+                pattern.previous.unwrap_or_default(),
+                pattern.level,
+                pattern.pattern
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn render_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "{}", self.summary())?;
+        if self.patterns.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(out, "| Change | Count | Previous | Level | Pattern |")?;
+        writeln!(out, "|---|---|---|---|---|")?;
+        for pattern in &self.patterns {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | [`{}`]({}) |",
+                pattern.change(),
+                pattern.count,
+                pattern.previous.unwrap_or_default(),
+                pattern.level,
+                escape(&pattern.pattern),
+                pattern.permalink
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The state kept between the runs.
+#[derive(Debug, Default)]
+pub struct Watch {
+    /// The number of completed runs.
+    runs: usize,
+    /// The counts of the patterns of the previous run.
+    counts: BTreeMap<String, usize>,
+}
+
+impl Watch {
+    /// Compare the report of a run with the previous run, and keep its counts for the next one.
+    pub fn update(&mut self, report: &TriageReport) -> WatchReport {
+        let mut patterns: Vec<_> = report
+            .patterns
+            .iter()
+            .filter_map(|pattern| {
+                let previous = self.counts.get(&pattern.pattern).copied();
+                let growing = previous
+                    .is_none_or(|previous| pattern.count as f64 >= previous as f64 * GROWTH_RATIO);
+
+                growing.then(|| PatternChange {
+                    pattern: pattern.pattern.clone(),
+                    level: pattern.level.clone(),
+                    count: pattern.count,
+                    previous,
+                    permalink: pattern.permalink.clone(),
+                })
+            })
+            .collect();
+        let growth = |pattern: &PatternChange| {
+            pattern.previous.map_or(f64::INFINITY, |previous| {
+                pattern.count as f64 / previous as f64
+            })
+        };
+        patterns.sort_by(|a, b| {
+            growth(b)
+                .total_cmp(&growth(a))
+                .then_with(|| b.count.cmp(&a.count))
+                .then_with(|| a.pattern.cmp(&b.pattern))
+        });
+
+        self.runs += 1;
+        self.counts = report
+            .patterns
+            .iter()
+            .map(|pattern| (pattern.pattern.clone(), pattern.count))
+            .collect();
+
+        WatchReport {
+            run: self.runs,
+            patterns,
+        }
+    }
+}
+
+/// Repeat the triage every `--interval`, until the process exits.
+///
+/// The first run writes the full report, the next ones only the new or growing patterns.
+/// The failed runs are logged and skipped, the next run is compared with the last successful one.
+pub async fn run(opts: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let output = opts.report_output()?;
+    let interval = opts
+        .run_interval()
+        .to_std()
+        .map_err(|err| format!("Invalid interval: {err}"))?;
+    log::info!("Watching the logs every {}s", interval.as_secs());
+
+    let mut watch = Watch::default();
+    let regex_cache = RegexCache::default();
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+
+        let session = TriageSession::new(opts.repeated_run()).regex_cache(regex_cache.clone());
+        let report = match session.run().await {
+            Ok(report) => report,
+            Err(err) => {
+                log::error!("Triage run failed: {err}");
+                continue;
+            }
+        };
+        if opts.dry_run {
+            continue;
+        }
+
+        let changes = watch.update(&report);
+        if changes.run == 1 {
+            output.write(&report)?;
+            println!("{}", report.summary);
+        } else if !changes.patterns.is_empty() {
+            output.write(&changes)?;
+        } else {
+            log::info!("{}", changes.summary());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fetch_git::RegexDetails,
+        matcher::{Matcher, Matches},
+        output::OutputFormat,
+    };
+
+    #[test]
+    fn test_watch() {
+        let details = |ty: &str| RegexDetails {
+            repo: "test".to_string(),
+            file: "test.rs".to_string(),
+            start: 0,
+            end: 0,
+            line: 1,
+            column: 1,
+            permalink: format!("https://github.com/test/blob/master/test.rs#{ty}"),
+            description: None,
+            target: None,
+            owners: Vec::new(),
+            ty: ty.to_string(),
+        };
+        let matcher = Matcher::new(
+            vec![
+                (regex::Regex::new("Slow").unwrap(), details("warn")),
+                (regex::Regex::new("Failed").unwrap(), details("error")),
+                (regex::Regex::new("Stalled").unwrap(), details("warn")),
+            ],
+            Vec::new(),
+        );
+        let report = |lines: &[&str]| {
+            let mut matches = Matches::default();
+            matcher.process_source(None, lines.iter().copied(), &mut matches);
+            TriageReport::new(&matches, None, false, None)
+        };
+
+        let mut watch = Watch::default();
+        let first = watch.update(&report(&[
+            "2024-03-29 10:00:00.000  WARN main test: Slow",
+            "2024-03-29 10:00:01.000  WARN main test: Slow",
+            "2024-03-29 10:00:02.000 ERROR main test: Failed",
+            "2024-03-29 10:00:03.000 ERROR main test: Failed",
+        ]));
+        assert_eq!(first.run, 1);
+        assert_eq!(first.patterns.len(), 2);
+
+        // The stable pattern is not reported, the growing and the new ones are.
+        let second = watch.update(&report(&[
+            "2024-03-29 11:00:00.000  WARN main test: Slow",
+            "2024-03-29 11:00:01.000  WARN main test: Slow",
+            "2024-03-29 11:00:02.000 ERROR main test: Failed",
+            "2024-03-29 11:00:03.000 ERROR main test: Failed",
+            "2024-03-29 11:00:04.000 ERROR main test: Failed",
+            "2024-03-29 11:00:05.000  WARN main test: Stalled",
+        ]));
+        let changes: Vec<_> = second
+            .patterns
+            .iter()
+            .map(|pattern| (pattern.pattern.as_str(), pattern.change()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("Stalled", "new".to_string()),
+                ("Failed", "+50%".to_string())
+            ]
+        );
+        assert!(second
+            .render(OutputFormat::Text)
+            .unwrap()
+            .starts_with("Run 2: 1 new and 1 growing patterns since the previous run"));
+    }
+}